@@ -0,0 +1,55 @@
+//! The layout of precompiled scripts, which the compiler writes and the vm loads. Everything is
+//! little endian, and strings are a `u16` length followed by that many bytes of UTF-8.
+//!
+//! | Section           | Contents                                                                |
+//! |-------------------|-------------------------------------------------------------------------|
+//! | Header            | [`MAGIC`], `u16` version, `u16` ISA revision, `u8` fix precision, flags |
+//! | Bytecode          | `u32` length in words, then the `u16` words                             |
+//! | Stack depths      | `u16` count, then `u32` bytecode offset and `u32` max depth for each    |
+//! |                   | function                                                                |
+//! | Properties        | `u16` count, then name, `u8` index, `u8` [`ValueType`] and `u8`         |
+//! |                   | [`PropertyAccess`] for each                                             |
+//! | Event handlers    | `u16` count, then name, `u32` bytecode offset, arguments, return types  |
+//! |                   | and yield types for each                                                |
+//! | Triggers          | `u16` count, then name, arguments and field names for each              |
+//! | Messages          | `u16` count, then name and arguments for each                           |
+//! | Debug (optional)  | `u16` count, then name and `u32` bytecode offset for each function      |
+//!
+//! The version is [`FORMAT_VERSION`], the instruction set revision is
+//! [`ISA_REVISION`](crate::ISA_REVISION) and the flags are a `u8`. Arguments and return types
+//! are a `u8` count followed by a `u8` [`ValueType`] for each one. Recursive functions have no
+//! limit to how much stack they could need, so have [`UNBOUNDED_STACK_DEPTH`] as their max depth. Field names are a `u8` which is
+//! non-zero if the trigger names its arguments, followed by a name for each argument if it does.
+//! Generators are stored with the event handlers, and have the type they yield as their only yield
+//! type. Everything else has no yield types. The debug section is only there if [`FLAG_DEBUG`] is
+//! set.
+
+pub const MAGIC: [u8; 4] = *b"TAPR";
+
+/// Bump when a release changes the layout of the container. Changes between releases don't need
+/// a bump, since nothing could have been written with the unreleased layout.
+pub const FORMAT_VERSION: u16 = 1;
+
+/// The max depth of a function which could need any amount of stack
+pub const UNBOUNDED_STACK_DEPTH: u32 = u32::MAX;
+
+/// Set in the flags if there is a debug section
+pub const FLAG_DEBUG: u8 = 1 << 0;
+
+/// The type of a property or argument
+#[repr(u8)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, enumn::N)]
+pub enum ValueType {
+    Int,
+    Fix,
+    Bool,
+}
+
+/// Whether the script was compiled to read from or assign to a property
+#[repr(u8)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, enumn::N)]
+pub enum PropertyAccess {
+    ReadWrite,
+    ReadOnly,
+    WriteOnly,
+}
@@ -3,6 +3,12 @@
 
 use enumn::N;
 
+pub mod container;
+
+/// Bump when a release adds or removes an instruction, or changes its encoding or meaning, so
+/// precompiled scripts from older releases are rejected rather than misbehaving
+pub const ISA_REVISION: u16 = 1;
+
 #[repr(u8)]
 #[derive(Debug, Clone, Copy, PartialEq, Eq, N)]
 pub enum Instruction {
@@ -18,10 +24,35 @@ pub enum Instruction {
     MathsOp,
     JumpIfFalse,
     Jump,
+    /// Calls the function at the next word. `arg` is the number of arguments it takes.
     Call,
     Return,
     Spawn,
     Trigger,
+    /// Sends the message at the next word to another script. `arg` is the number of arguments,
+    /// which are on the stack above the id of the script to send it to.
+    Send,
+    /// Pops a value and suspends the thread, handing the value to rust. Only generators yield.
+    Yield,
+    /// The same as `Spawn`, but the new thread runs in the group whose id is on the stack below
+    /// the arguments rather than the spawning thread's group
+    SpawnInGroup,
+    /// Pops the id of a group, and stops its threads from running from the next run
+    PauseGroup,
+    /// Pops the id of a group, and lets its threads run again from the next run
+    ResumeGroup,
+    /// Pops a number of frames and then the id of a group, whose threads then only run once every
+    /// that many frames
+    SetGroupTimeScale,
+    /// Calls the function at the next word, reusing the current frame. `arg` is the number of
+    /// arguments to the new function, the word after the target is `[current args, shift]`
+    TailCall,
+
+    // superinstructions, produced by the peephole optimiser in the compiler
+    /// `prop[arg] += next word as i16`
+    AddPropImmediate,
+    /// `MathsOp` with the comparison in `arg`, followed by `JumpIfFalse` to the next word
+    CompareJumpIfFalse,
 }
 
 #[repr(u8)]
@@ -42,4 +73,7 @@ pub enum MathsOp {
 
     FixMul,
     FixDiv,
+
+    ShiftLeft,
+    ShiftRight,
 }
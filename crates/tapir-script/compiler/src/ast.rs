@@ -1,14 +1,19 @@
-use std::iter;
+use std::{
+    fmt::{self, Display},
+    iter,
+};
 
 use crate::{
     tokens::{FileId, Span},
     types::Type,
 };
 
+pub use fix::Fix;
 pub(crate) use metadata::Metadata;
 
 use serde::Serialize;
 
+mod fix;
 mod metadata;
 #[cfg(test)]
 mod pretty_printer;
@@ -19,8 +24,6 @@ pub struct SymbolId(pub usize);
 #[derive(Clone, Copy, Hash, PartialEq, Eq, Debug, Serialize, PartialOrd, Ord)]
 pub struct FunctionId(pub usize);
 
-pub type Fix = agb_fixnum::Num<i32, 8>;
-
 #[derive(Clone, Debug, Serialize)]
 pub struct Script<'input> {
     pub functions: Vec<Function<'input>>,
@@ -112,6 +115,22 @@ impl Script<'_> {
 #[derive(Clone, Debug, Serialize, Default)]
 pub struct FunctionModifiers {
     pub is_event_handler: Option<Span>,
+    pub is_generator: Option<GeneratorModifier>,
+}
+
+impl FunctionModifiers {
+    /// Event handlers and generators are called from rust rather than from the script
+    pub fn is_entry_point(&self) -> bool {
+        self.is_event_handler.is_some() || self.is_generator.is_some()
+    }
+}
+
+/// A `generator fn` is called from rust to produce a sequence of values, one for each `yield`.
+/// Generators don't return anything, so the function's `return_types` are empty.
+#[derive(Clone, Debug, Serialize)]
+pub struct GeneratorModifier {
+    pub span: Span,
+    pub yields: TypeWithLocation,
 }
 
 #[derive(Clone, Debug, Serialize)]
@@ -156,6 +175,69 @@ pub struct Statement<'input> {
 }
 
 impl<'input> Statement<'input> {
+    /// The expressions belonging to this statement, but not any in nested blocks
+    pub(crate) fn direct_expressions(&self) -> Box<dyn Iterator<Item = &Expression<'input>> + '_> {
+        match &self.kind {
+            StatementKind::Error
+            | StatementKind::Wait
+            | StatementKind::Continue
+            | StatementKind::Break
+            | StatementKind::Nop
+            | StatementKind::Block { .. }
+            | StatementKind::Loop { .. } => Box::new(iter::empty()),
+            StatementKind::VariableDeclaration { value, .. }
+            | StatementKind::Assignment { value, .. }
+            | StatementKind::Yield { value }
+            | StatementKind::If {
+                condition: value, ..
+            } => Box::new(iter::once(value)),
+            StatementKind::Call { arguments, .. }
+            | StatementKind::Return { values: arguments }
+            | StatementKind::Trigger { arguments, .. } => Box::new(arguments.iter()),
+            StatementKind::Spawn {
+                group, arguments, ..
+            } => Box::new(group.iter().chain(arguments)),
+            StatementKind::Send {
+                target, arguments, ..
+            } => Box::new(iter::once(target).chain(arguments)),
+            StatementKind::ControlGroup { group, control } => {
+                Box::new(iter::once(group).chain(control.frames()))
+            }
+        }
+    }
+
+    pub(crate) fn direct_expressions_mut(
+        &mut self,
+    ) -> Box<dyn Iterator<Item = &mut Expression<'input>> + '_> {
+        match &mut self.kind {
+            StatementKind::Error
+            | StatementKind::Wait
+            | StatementKind::Continue
+            | StatementKind::Break
+            | StatementKind::Nop
+            | StatementKind::Block { .. }
+            | StatementKind::Loop { .. } => Box::new(iter::empty()),
+            StatementKind::VariableDeclaration { value, .. }
+            | StatementKind::Assignment { value, .. }
+            | StatementKind::Yield { value }
+            | StatementKind::If {
+                condition: value, ..
+            } => Box::new(iter::once(value)),
+            StatementKind::Call { arguments, .. }
+            | StatementKind::Return { values: arguments }
+            | StatementKind::Trigger { arguments, .. } => Box::new(arguments.iter_mut()),
+            StatementKind::Spawn {
+                group, arguments, ..
+            } => Box::new(group.iter_mut().chain(arguments)),
+            StatementKind::Send {
+                target, arguments, ..
+            } => Box::new(iter::once(target).chain(arguments)),
+            StatementKind::ControlGroup { group, control } => {
+                Box::new(iter::once(group).chain(control.frames_mut()))
+            }
+        }
+    }
+
     pub(crate) fn expressions_mut(
         &mut self,
     ) -> Box<dyn Iterator<Item = &mut Expression<'input>> + '_> {
@@ -166,7 +248,8 @@ impl<'input> Statement<'input> {
             | StatementKind::Break
             | StatementKind::Nop => Box::new(iter::empty()),
             StatementKind::VariableDeclaration { value, .. }
-            | StatementKind::Assignment { value, .. } => Box::new(iter::once(value)),
+            | StatementKind::Assignment { value, .. }
+            | StatementKind::Yield { value } => Box::new(iter::once(value)),
             StatementKind::If {
                 condition,
                 true_block,
@@ -181,8 +264,16 @@ impl<'input> Statement<'input> {
             }
             StatementKind::Call { arguments, .. }
             | StatementKind::Return { values: arguments }
-            | StatementKind::Trigger { arguments, .. }
-            | StatementKind::Spawn { arguments, .. } => Box::new(arguments.iter_mut()),
+            | StatementKind::Trigger { arguments, .. } => Box::new(arguments.iter_mut()),
+            StatementKind::Spawn {
+                group, arguments, ..
+            } => Box::new(group.iter_mut().chain(arguments)),
+            StatementKind::Send {
+                target, arguments, ..
+            } => Box::new(iter::once(target).chain(arguments)),
+            StatementKind::ControlGroup { group, control } => {
+                Box::new(iter::once(group).chain(control.frames_mut()))
+            }
         }
     }
 }
@@ -220,17 +311,73 @@ pub enum StatementKind<'input> {
         name: &'input str,
         arguments: Vec<Expression<'input>>,
     },
+    /// `spawn group, name(arguments);` runs the new thread in `group` rather than the spawning
+    /// thread's group
     Spawn {
+        group: Option<Expression<'input>>,
         name: &'input str,
         arguments: Vec<Expression<'input>>,
     },
     Trigger {
         name: &'input str,
         arguments: Vec<Expression<'input>>,
+        /// `trigger Hit { damage: 5 };` names each argument, which then match the fields of a
+        /// struct variant in rust rather than being positional
+        field_names: Option<Vec<TriggerFieldName<'input>>>,
+    },
+    /// `send target, name(arguments);` runs the event handler `name` in the script with the id
+    /// `target` in the same `ScriptWorld`
+    Send {
+        target: Expression<'input>,
+        name: &'input str,
+        arguments: Vec<Expression<'input>>,
     },
     Return {
         values: Vec<Expression<'input>>,
     },
+    /// `yield value;` hands `value` to rust from a generator and suspends it until the next value
+    /// is asked for
+    Yield {
+        value: Expression<'input>,
+    },
+    /// `pause group;`, `resume group;` or `timescale group, frames;`, which change how the
+    /// threads in `group` run from the next frame
+    ControlGroup {
+        group: Expression<'input>,
+        control: GroupControl<'input>,
+    },
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub enum GroupControl<'input> {
+    Pause,
+    Resume,
+    /// Only run the group once every `frames` frames
+    TimeScale {
+        frames: Expression<'input>,
+    },
+}
+
+impl<'input> GroupControl<'input> {
+    pub(crate) fn frames(&self) -> Option<&Expression<'input>> {
+        match self {
+            GroupControl::Pause | GroupControl::Resume => None,
+            GroupControl::TimeScale { frames } => Some(frames),
+        }
+    }
+
+    pub(crate) fn frames_mut(&mut self) -> Option<&mut Expression<'input>> {
+        match self {
+            GroupControl::Pause | GroupControl::Resume => None,
+            GroupControl::TimeScale { frames } => Some(frames),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct TriggerFieldName<'input> {
+    pub name: &'input str,
+    pub span: Span,
 }
 
 impl<'input> StatementKind<'input> {
@@ -325,6 +472,37 @@ pub enum BinaryOperator {
     LtEq,
 
     Then,
+
+    // only created by the optimiser, so have no syntax of their own
+    ShiftLeft,
+    ShiftRight,
+}
+
+impl Display for BinaryOperator {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        use BinaryOperator as B;
+
+        let symbol = match self {
+            B::Add => "+",
+            B::Sub => "-",
+            B::Mul | B::FixMul => "*",
+            B::Div | B::FixDiv => "/",
+            B::Mod => "%",
+            B::RealDiv => "//",
+            B::RealMod => "%%",
+            B::EqEq => "==",
+            B::NeEq => "!=",
+            B::Gt => ">",
+            B::GtEq => ">=",
+            B::Lt => "<",
+            B::LtEq => "<=",
+            B::Then => "then",
+            B::ShiftLeft => "<<",
+            B::ShiftRight => ">>",
+        };
+
+        write!(f, "{symbol}")
+    }
 }
 
 impl BinaryOperator {
@@ -350,7 +528,9 @@ impl BinaryOperator {
             | B::Gt
             | B::GtEq
             | B::Lt
-            | B::LtEq => {
+            | B::LtEq
+            | B::ShiftLeft
+            | B::ShiftRight => {
                 matches!(lhs_type, Type::Fix | Type::Int)
             }
 
@@ -373,7 +553,9 @@ impl BinaryOperator {
             | B::RealDiv
             | B::RealMod
             | B::FixMul
-            | B::FixDiv => lhs_type,
+            | B::FixDiv
+            | B::ShiftLeft
+            | B::ShiftRight => lhs_type,
 
             B::EqEq | B::NeEq | B::Gt | B::GtEq | B::Lt | B::LtEq => Type::Bool,
             B::Then => rhs_type,
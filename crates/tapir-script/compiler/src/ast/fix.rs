@@ -0,0 +1,142 @@
+use std::{
+    fmt::Display,
+    ops::{Add, Div, Mul, Sub},
+};
+
+use agb_fixnum::FixedWidthUnsignedInteger;
+use num_traits::{Num, ParseFloatError};
+
+use crate::CompileSettings;
+
+/// A fixed point number with `precision` fractional bits. Each script chooses its own precision,
+/// so it is stored alongside the value rather than being part of the type. The arithmetic matches
+/// `agb_fixnum::Num<i32, precision>` exactly, so constant folding gives the same answer as the VM.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
+pub struct Fix {
+    raw: i32,
+    precision: u8,
+}
+
+impl Fix {
+    pub fn new(integer: i32, precision: u8) -> Self {
+        Self::from_raw(integer << precision, precision)
+    }
+
+    pub fn from_raw(raw: i32, precision: u8) -> Self {
+        debug_assert!(
+            (1..=CompileSettings::MAX_FIX_PRECISION).contains(&precision),
+            "Invalid fix precision {precision}"
+        );
+
+        Self { raw, precision }
+    }
+
+    pub fn from_str_radix(value: &str, radix: u32, precision: u8) -> Result<Self, ParseFloatError> {
+        let value = f64::from_str_radix(value, radix)?;
+
+        let integer = value.trunc() as i32;
+        let fractional = (value.fract() * (1u64 << 30) as f64) as i32;
+
+        Ok(Self::from_raw(
+            (integer << precision) + (fractional >> (30 - precision)),
+            precision,
+        ))
+    }
+
+    pub fn to_raw(self) -> i32 {
+        self.raw
+    }
+
+    pub fn precision(self) -> u8 {
+        self.precision
+    }
+
+    pub fn floor(self) -> i32 {
+        self.raw >> self.precision
+    }
+
+    pub fn frac(self) -> i32 {
+        self.raw & ((1 << self.precision) - 1)
+    }
+
+    pub fn is_zero(self) -> bool {
+        self.raw == 0
+    }
+
+    pub fn is_one(self) -> bool {
+        self == Self::new(1, self.precision)
+    }
+
+    fn with_raw(self, raw: i32) -> Self {
+        Self::from_raw(raw, self.precision)
+    }
+}
+
+impl Add for Fix {
+    type Output = Self;
+
+    fn add(self, rhs: Self) -> Self::Output {
+        self.with_raw(self.raw + rhs.raw)
+    }
+}
+
+impl Sub for Fix {
+    type Output = Self;
+
+    fn sub(self, rhs: Self) -> Self::Output {
+        self.with_raw(self.raw - rhs.raw)
+    }
+}
+
+impl Mul for Fix {
+    type Output = Self;
+
+    fn mul(self, rhs: Self) -> Self::Output {
+        self.with_raw(i32::upcast_multiply(
+            self.raw,
+            rhs.raw,
+            self.precision as usize,
+        ))
+    }
+}
+
+impl Div for Fix {
+    type Output = Self;
+
+    fn div(self, rhs: Self) -> Self::Output {
+        self.with_raw((self.raw << self.precision) / rhs.raw)
+    }
+}
+
+impl Display for Fix {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mask = (1 << self.precision) - 1;
+
+        let mut integral = self.floor();
+        let mut fractional = self.frac();
+
+        // print negative numbers as `-(integral + fractional)` rather than `-integral + fractional`
+        let is_negative = fractional != 0 && integral < 0;
+        if is_negative {
+            integral += 1;
+            fractional = (1 << self.precision) - fractional;
+        }
+
+        if is_negative && integral == 0 {
+            write!(f, "-")?;
+        }
+        write!(f, "{integral}")?;
+
+        if fractional != 0 {
+            write!(f, ".")?;
+        }
+
+        while fractional & mask != 0 {
+            fractional *= 10;
+            write!(f, "{}", (fractional & !mask) >> self.precision)?;
+            fractional &= mask;
+        }
+
+        Ok(())
+    }
+}
@@ -91,7 +91,7 @@ impl Metadata {
         let type_id = TypeId::of::<T>();
         self.map
             .get(&type_id)
-            .map_or(false, |(value, _)| (**value).as_any().is::<T>())
+            .is_some_and(|(value, _)| (**value).as_any().is::<T>())
     }
 
     pub fn clear(&mut self) {
@@ -1,6 +1,6 @@
 use std::fmt::{Display, Write};
 
-use super::{Expression, ExpressionKind, Function, Statement, StatementKind};
+use super::{Expression, ExpressionKind, Function, GroupControl, Statement, StatementKind};
 
 pub(super) fn pretty_print(function: &Function, output: &mut dyn Write) -> std::fmt::Result {
     if function.name == "@toplevel" {
@@ -19,6 +19,8 @@ pub(super) fn pretty_print(function: &Function, output: &mut dyn Write) -> std::
             "{}fn {}(",
             if function.modifiers.is_event_handler.is_some() {
                 "event "
+            } else if function.modifiers.is_generator.is_some() {
+                "generator "
             } else {
                 ""
             },
@@ -39,6 +41,10 @@ pub(super) fn pretty_print(function: &Function, output: &mut dyn Write) -> std::
             write!(output, ")")?;
         }
 
+        if let Some(generator) = &function.modifiers.is_generator {
+            write!(output, " -> {}", generator.yields.t)?;
+        }
+
         writeln!(output, " {{")?;
 
         pretty_print_statements(&function.statements, output, Indent(1))?;
@@ -73,6 +79,28 @@ fn pretty_print_statements(
                 write!(output, ";")?;
             }
             StatementKind::Wait => write!(output, "wait;")?,
+            StatementKind::Yield { value } => {
+                write!(output, "yield ")?;
+                pretty_print_expr(value, output, indent.increase())?;
+                write!(output, ";")?;
+            }
+            StatementKind::ControlGroup { group, control } => {
+                write!(
+                    output,
+                    "{} ",
+                    match control {
+                        GroupControl::Pause => "pause",
+                        GroupControl::Resume => "resume",
+                        GroupControl::TimeScale { .. } => "timescale",
+                    }
+                )?;
+                pretty_print_expr(group, output, indent.increase())?;
+                if let Some(frames) = control.frames() {
+                    write!(output, ", ")?;
+                    pretty_print_expr(frames, output, indent.increase())?;
+                }
+                write!(output, ";")?;
+            }
             StatementKind::Block { block } => {
                 writeln!(output, "{{")?;
                 pretty_print_statements(block, output, indent.increase())?;
@@ -110,8 +138,17 @@ fn pretty_print_statements(
                 }
                 write!(output, ");")?;
             }
-            StatementKind::Spawn { name, arguments } => {
-                write!(output, "spawn {name}(")?;
+            StatementKind::Spawn {
+                group,
+                name,
+                arguments,
+            } => {
+                write!(output, "spawn ")?;
+                if let Some(group) = group {
+                    pretty_print_expr(group, output, indent.increase())?;
+                    write!(output, ", ")?;
+                }
+                write!(output, "{name}(")?;
                 for argument in arguments {
                     pretty_print_expr(argument, output, indent.increase())?;
                     write!(output, ",")?;
@@ -126,8 +163,29 @@ fn pretty_print_statements(
                 }
                 write!(output, ");")?;
             }
-            StatementKind::Trigger { name, arguments } => {
+            StatementKind::Trigger {
+                name,
+                arguments,
+                field_names,
+            } => {
                 write!(output, "trigger {name}(")?;
+                for (i, arg) in arguments.iter().enumerate() {
+                    if let Some(field_names) = field_names {
+                        write!(output, "{}: ", field_names[i].name)?;
+                    }
+                    pretty_print_expr(arg, output, indent.increase())?;
+                    write!(output, ",")?;
+                }
+                write!(output, ");")?;
+            }
+            StatementKind::Send {
+                target,
+                name,
+                arguments,
+            } => {
+                write!(output, "send ")?;
+                pretty_print_expr(target, output, indent.increase())?;
+                write!(output, ", {name}(")?;
                 for arg in arguments {
                     pretty_print_expr(arg, output, indent.increase())?;
                     write!(output, ",")?;
@@ -160,28 +218,7 @@ fn pretty_print_expr(
         ExpressionKind::BinaryOperation { lhs, operator, rhs } => {
             write!(output, "(")?;
             pretty_print_expr(lhs, output, indent)?;
-            write!(
-                output,
-                " {} ",
-                match operator {
-                    super::BinaryOperator::Add => "+",
-                    super::BinaryOperator::Sub => "-",
-                    super::BinaryOperator::Mul => "*",
-                    super::BinaryOperator::Div => "/",
-                    super::BinaryOperator::Mod => "%",
-                    super::BinaryOperator::RealDiv => "//",
-                    super::BinaryOperator::RealMod => "%%",
-                    super::BinaryOperator::FixMul => "*",
-                    super::BinaryOperator::FixDiv => "/",
-                    super::BinaryOperator::EqEq => "==",
-                    super::BinaryOperator::NeEq => "!=",
-                    super::BinaryOperator::Gt => ">",
-                    super::BinaryOperator::GtEq => ">=",
-                    super::BinaryOperator::Lt => "<",
-                    super::BinaryOperator::LtEq => "<=",
-                    super::BinaryOperator::Then => "then",
-                }
-            )?;
+            write!(output, " {operator} ")?;
             pretty_print_expr(rhs, output, indent)?;
             write!(output, ")")?;
         }
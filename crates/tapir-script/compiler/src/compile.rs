@@ -1,8 +1,11 @@
-use std::{collections::HashMap, ops::ControlFlow, path::Path};
+use std::{collections::HashMap, path::Path};
 
+use cfg::{Cfg, Edge, Instruction, Terminator};
 use optimisations::UnusedFunction;
+use petgraph::graph::NodeIndex;
+use stack_depth::CallSite;
 use symtab_visitor::{SymTab, SymTabVisitor};
-use type_visitor::{TriggerId, TypeTable, TypeVisitor};
+use type_visitor::{MessageId, TriggerId, TypeTable, TypeVisitor};
 
 use crate::{
     ast::{self, BinaryOperator, Function, FunctionId, MaybeResolved, Statement, SymbolId},
@@ -11,13 +14,18 @@ use crate::{
     reporting::Diagnostics,
     tokens::FileId,
     types::Type,
-    EventHandler, EventHandlerArgument, Trigger,
+    EventHandler, EventHandlerArgument, FunctionInfo, SentMessage, StackDepth, Trigger,
 };
 
+pub(crate) mod analysis;
+mod cfg;
 mod loop_visitor;
 mod optimisations;
+mod peephole;
+mod stack_depth;
 mod symtab_visitor;
 mod type_visitor;
+mod unused_code_visitor;
 
 use opcodes::{MathsOp, Opcode};
 
@@ -26,14 +34,33 @@ pub struct Property {
     pub ty: Type,
     pub index: usize,
     pub name: String,
+    pub access: PropertyAccess,
+}
+
+/// What a script is allowed to do with a property
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum PropertyAccess {
+    #[default]
+    ReadWrite,
+    /// Owned by the game, like the current frame, so the script can't assign to it
+    ReadOnly,
+    /// Only set by the script, like a requested animation, so the script can't read it
+    WriteOnly,
 }
 
 pub struct CompileSettings {
     pub properties: Vec<Property>,
     pub enable_optimisations: bool,
+    /// How many fractional bits `fix` values have, between 1 and `MAX_FIX_PRECISION`. This must
+    /// match the `agb_fixnum::Num<i32, N>` used on the Rust side.
+    pub fix_precision: u8,
 }
 
 impl CompileSettings {
+    pub const DEFAULT_FIX_PRECISION: u8 = 8;
+    /// The most fractional bits a fix can have while still leaving a useful integer part
+    pub const MAX_FIX_PRECISION: u8 = 24;
+
     pub(crate) fn is_property(&self, symbol_id: SymbolId) -> bool {
         symbol_id.0 < self.properties.len()
     }
@@ -47,7 +74,7 @@ pub fn compile(
     filename: impl AsRef<Path>,
     input: &str,
     settings: &CompileSettings,
-) -> Result<Bytecode, Diagnostics> {
+) -> Result<(Bytecode, Diagnostics), Diagnostics> {
     let file_id = FileId::new(0);
 
     let mut diagnostics = Diagnostics::new(file_id, filename, input);
@@ -55,7 +82,7 @@ pub fn compile(
     let lexer = Lexer::new(input, file_id);
     let parser = grammar::ScriptParser::new();
 
-    let mut ast = match parser.parse(file_id, &mut diagnostics, lexer) {
+    let mut ast = match parser.parse(file_id, settings.fix_precision, &mut diagnostics, lexer) {
         Ok(ast) => ast,
         Err(e) => {
             diagnostics.add_lalrpop(e, file_id);
@@ -73,62 +100,159 @@ pub fn compile(
         type_visitor.visit_function(function, sym_tab_visitor.get_symtab(), &mut diagnostics);
     }
 
-    optimisations::optimise(&mut ast.functions, settings, &mut diagnostics);
+    // this needs to happen before optimisation, which removes the code we want to warn about
+    unused_code_visitor::visit_unused_code(&ast.functions, &mut diagnostics);
 
     let type_table = type_visitor.into_type_table(sym_tab_visitor.get_symtab(), &mut diagnostics);
 
-    if diagnostics.has_any() {
+    // the optimisations expect every variable to be resolved and every type to be known
+    if diagnostics.has_errors() {
+        return Err(diagnostics);
+    }
+
+    optimisations::optimise(
+        &mut ast.functions,
+        sym_tab_visitor.get_symtab().symbol_count(),
+        settings,
+        &mut diagnostics,
+    );
+
+    if diagnostics.has_errors() {
         return Err(diagnostics);
     }
 
     let mut compiler = Compiler::new(type_table);
 
-    for function in ast.functions {
+    for mut function in ast.functions {
         if function.meta.has::<UnusedFunction>() {
             continue; // don't need to compile unused functions
         }
 
-        compiler.compile_function(&function, sym_tab_visitor.get_symtab());
+        mark_tail_calls(&mut function, &compiler.type_table);
+
+        compiler.compile_function(&mut function, sym_tab_visitor.get_symtab());
     }
 
     compiler.finalise();
 
-    Ok(compiler.bytecode)
+    // anything left is a warning
+    Ok((compiler.bytecode, diagnostics))
+}
+
+/// Set on call and return statements where the call is the last thing the function does, so the
+/// called function can reuse the current stack frame rather than growing the stack.
+#[derive(Clone, Copy, Debug)]
+struct TailCall;
+
+fn mark_tail_calls(function: &mut Function, type_table: &TypeTable) {
+    // the toplevel function has no frame to reuse
+    if *function.meta.get::<FunctionId>().unwrap() == FunctionId(0) {
+        return;
+    }
+
+    mark_tail_calls_in_block(&mut function.statements, true, type_table);
+}
+
+fn mark_tail_calls_in_block(block: &mut [Statement], is_tail: bool, type_table: &TypeTable) {
+    for i in 0..block.len() {
+        let followed_by_return = matches!(
+            block.get(i + 1),
+            Some(Statement { kind: ast::StatementKind::Return { values }, .. }) if values.is_empty()
+        );
+        let in_tail_position = followed_by_return || (is_tail && i == block.len() - 1);
+
+        let statement = &mut block[i];
+        match &mut statement.kind {
+            ast::StatementKind::Call { .. } => {
+                // the called function returns straight to our caller, so mustn't leave anything
+                // on the stack for it
+                let function_id = *statement.meta.get::<FunctionId>().unwrap();
+                if in_tail_position && type_table.num_function_returns(function_id) == 0 {
+                    statement.meta.set(TailCall);
+                }
+            }
+            ast::StatementKind::Return { values } => {
+                if matches!(
+                    values.as_slice(),
+                    [ast::Expression {
+                        kind: ast::ExpressionKind::Call { .. },
+                        ..
+                    }]
+                ) {
+                    statement.meta.set(TailCall);
+                }
+            }
+            ast::StatementKind::If {
+                true_block,
+                false_block,
+                ..
+            } => {
+                mark_tail_calls_in_block(true_block, in_tail_position, type_table);
+                mark_tail_calls_in_block(false_block, in_tail_position, type_table);
+            }
+            ast::StatementKind::Block { block } => {
+                mark_tail_calls_in_block(block, in_tail_position, type_table);
+            }
+            ast::StatementKind::Loop { block } => {
+                mark_tail_calls_in_block(block, false, type_table);
+            }
+            _ => {}
+        }
+    }
 }
 
 struct Compiler<'input> {
     stack: Vec<Option<SymbolId>>,
-    loops: Vec<LoopCompliationState>,
 
     function_calls: Vec<(FunctionId, Jump)>,
     function_locations: HashMap<FunctionId, Label>,
 
+    current_function: FunctionId,
+    /// Where the current function's frame starts in `stack`, including its arguments
+    frame_base: usize,
+    /// The most the stack has grown to in the current function
+    frame_max: usize,
+    frame_depths: HashMap<FunctionId, usize>,
+    call_sites: Vec<CallSite>,
+
     type_table: TypeTable<'input>,
     bytecode: Bytecode,
 }
 
-struct LoopCompliationState {
-    loop_start: Label,
-    forward_jumps: Vec<Jump>,
-
-    stack_length: usize,
+/// Where the blocks of the function being compiled start, and what the stack looks like when they
+/// do. Every path into a block leaves the stack the same way.
+#[derive(Default)]
+struct BlockLocations {
+    labels: HashMap<NodeIndex, Label>,
+    jumps: Vec<(Jump, NodeIndex)>,
+    stacks: HashMap<NodeIndex, Vec<Option<SymbolId>>>,
 }
 
 impl<'input> Compiler<'input> {
     pub fn new(type_table: TypeTable<'input>) -> Self {
         Self {
             stack: vec![],
-            loops: vec![],
 
             function_calls: vec![],
             function_locations: HashMap::from([(FunctionId(0), Label(0))]),
-            bytecode: Bytecode::new(type_table.triggers()),
+
+            current_function: FunctionId(0),
+            frame_base: 0,
+            frame_max: 0,
+            frame_depths: HashMap::new(),
+            call_sites: vec![],
+
+            bytecode: Bytecode::new(type_table.triggers(), type_table.messages()),
             type_table,
         }
     }
 
-    pub fn compile_function(&mut self, function: &Function<'input>, symtab: &SymTab) {
+    pub fn compile_function(&mut self, function: &mut Function<'input>, symtab: &SymTab) {
         let function_id = *function.meta.get::<FunctionId>().unwrap();
+
+        self.current_function = function_id;
+        self.frame_base = self.stack.len();
+
         if function_id != FunctionId(0) {
             // the stack will be arguments, then the return pointer. However, if we're at toplevel, then
             // the stack will be empty to start with
@@ -143,10 +267,17 @@ impl<'input> Compiler<'input> {
             self.stack.push(None);
         }
 
+        self.frame_max = self.stack.len();
+
         self.function_locations
             .insert(function_id, self.bytecode.new_label());
+        self.bytecode.functions.push(FunctionInfo {
+            name: function.name.to_owned(),
+            bytecode_offset: self.bytecode.length,
+        });
 
-        if function.modifiers.is_event_handler.is_some() {
+        // generators are started from rust in the same way as event handlers
+        if function.modifiers.is_entry_point() {
             self.bytecode.event_handlers.push(EventHandler {
                 name: function.name.to_owned(),
                 bytecode_offset: self.bytecode.length,
@@ -165,230 +296,263 @@ impl<'input> Compiler<'input> {
                         }
                     })
                     .collect(),
+                return_types: function.return_types.types.iter().map(|t| t.t).collect(),
+                yields: function
+                    .modifiers
+                    .is_generator
+                    .as_ref()
+                    .map(|generator| generator.yields.t),
             });
         }
 
-        self.compile_block(
-            &function.statements,
-            symtab,
-            self.stack.len(),
-            function.arguments.len(),
-        );
+        let stack_bottom = self.stack.len();
+        let num_args = function.arguments.len();
+        // if there is no return value, then the function can get to the end without returning
+        let has_implicit_return = function.return_types.types.is_empty();
+
+        let cfg = Cfg::new(function);
+        let layout = cfg.layout();
+        let mut blocks = BlockLocations::default();
+        blocks.stacks.insert(layout[0], self.stack.clone());
+
+        for (i, &node) in layout.iter().enumerate() {
+            let next = layout.get(i + 1).copied();
+            let block = cfg.block(node);
+
+            blocks.labels.insert(node, self.bytecode.new_label());
+            self.stack = match block.terminator {
+                // everything was dropped before getting here, or this was reached by a return
+                Terminator::Exit => self.stack[..stack_bottom].to_vec(),
+                _ => blocks.stacks[&node].clone(),
+            };
 
-        // if there is no return value, then no return is required to be compiled so we should add one
-        if function.return_types.types.is_empty() {
-            self.bytecode.add_opcode(Opcode::Return {
-                args: function.arguments.len() as u8,
-                rets: 0,
-                shift: 0, // can be zero because we will have dropped after the block was compiled
-            });
+            for instruction in &block.instructions {
+                self.compile_instruction(instruction, symtab, stack_bottom);
+            }
+
+            match &block.terminator {
+                Terminator::Goto => {
+                    self.compile_jump(&mut blocks, cfg.successor(node, Edge::Always), next);
+                }
+                Terminator::Branch { condition } => {
+                    self.compile_expression(condition, symtab);
+
+                    // the condition stays on the stack whichever way this goes
+                    let false_block = cfg.successor(node, Edge::False);
+                    let if_false_jump = self.bytecode.new_jump_if_false();
+                    blocks.jumps.push((if_false_jump, false_block));
+                    blocks
+                        .stacks
+                        .entry(false_block)
+                        .or_insert_with(|| self.stack.clone());
+
+                    self.compile_jump(&mut blocks, cfg.successor(node, Edge::True), next);
+                }
+                Terminator::Yield { value: None } => {
+                    self.bytecode.add_opcode(Opcode::Wait);
+                    self.compile_jump(&mut blocks, cfg.successor(node, Edge::Always), next);
+                }
+                Terminator::Yield { value: Some(value) } => {
+                    self.compile_expression(value, symtab);
+                    self.bytecode.add_opcode(Opcode::Yield);
+                    self.stack.pop();
+                    self.compile_jump(&mut blocks, cfg.successor(node, Edge::Always), next);
+                }
+                Terminator::Return { values } => {
+                    for ret_value in values.iter() {
+                        self.compile_expression(ret_value, symtab);
+                    }
+
+                    let distance_to_bottom = self.stack.len() - stack_bottom;
+
+                    self.bytecode.add_opcode(Opcode::Return {
+                        args: num_args as u8,
+                        rets: values.len() as u8,
+                        shift: distance_to_bottom.try_into().expect("Too far to shift"),
+                    });
+                }
+                Terminator::TailCall {
+                    function,
+                    arguments,
+                } => {
+                    self.compile_tail_call(
+                        function.expect("Should've resolved function"),
+                        arguments,
+                        symtab,
+                        stack_bottom,
+                        num_args,
+                    );
+                }
+                Terminator::Exit => {
+                    if has_implicit_return {
+                        self.bytecode.add_opcode(Opcode::Return {
+                            args: num_args as u8,
+                            rets: 0,
+                            shift: 0, // can be zero because everything was dropped on the way here
+                        });
+                    }
+                }
+            }
+        }
+
+        for (jump, node) in blocks.jumps {
+            self.bytecode.patch_jump(jump, blocks.labels[&node]);
         }
+
+        self.frame_depths
+            .insert(function_id, self.frame_max - self.frame_base);
     }
 
-    fn compile_block(
+    /// Goes to the block, which doesn't need a jump if it comes next.
+    fn compile_jump(
         &mut self,
-        statements: &[Statement<'input>],
-        symtab: &SymTab,
-        stack_bottom: usize,
-        num_args: usize,
+        blocks: &mut BlockLocations,
+        target: NodeIndex,
+        next: Option<NodeIndex>,
     ) {
-        let previous_stack_size = self.stack.len();
+        let target_stack = blocks
+            .stacks
+            .entry(target)
+            .or_insert_with(|| self.stack.clone());
+        debug_assert_eq!(
+            target_stack.len(),
+            self.stack.len(),
+            "Every path into a block should leave the stack the same size"
+        );
 
-        for statement in statements {
-            if self.compile_statement(statement, symtab, stack_bottom, num_args)
-                == ControlFlow::Break(())
-            {
-                break;
-            };
+        if next != Some(target) {
+            let jump = self.bytecode.new_jump();
+            blocks.jumps.push((jump, target));
         }
-
-        self.compile_drop_to(previous_stack_size);
     }
 
-    #[must_use]
-    fn compile_statement(
+    fn compile_instruction(
         &mut self,
-        statement: &Statement<'input>,
+        instruction: &Instruction<'_, 'input>,
         symtab: &SymTab,
         stack_bottom: usize,
-        num_args: usize,
-    ) -> ControlFlow<()> {
-        match &statement.kind {
-            ast::StatementKind::Error => panic!("Should never have to compile an error"),
-            ast::StatementKind::VariableDeclaration { value, .. } => {
-                let ident: &SymbolId = statement.meta.get().expect("Should've resolved variable");
-
-                self.compile_expression(value, symtab);
-                self.stack.pop();
-                self.stack.push(Some(*ident)); // this is now on the stack at this location
-            }
-            ast::StatementKind::Assignment { value, .. } => {
-                let ident: &SymbolId = statement.meta.get().expect("Should've resolved variable");
+    ) {
+        match instruction {
+            Instruction::Assign {
+                target,
+                value,
+                is_declaration,
+                ..
+            } => {
+                let ident = target.expect("Should've resolved variable");
 
                 self.compile_expression(value, symtab);
 
-                if let Some(property) = symtab.get_property(*ident) {
+                if *is_declaration {
+                    self.stack.pop();
+                    self.stack.push(Some(ident)); // this is now on the stack at this location
+                } else if let Some(property) = symtab.get_property(ident) {
                     self.bytecode
                         .add_opcode(Opcode::SetProp(property.index as u8));
                     self.stack.pop();
                 } else {
-                    let offset = self.get_offset(*ident);
+                    let offset = self.get_offset(ident);
                     self.bytecode.add_opcode(Opcode::Move(offset as u8 - 1));
                     self.stack.pop();
                 }
             }
-            ast::StatementKind::Wait => {
-                self.bytecode.add_opcode(Opcode::Wait);
-            }
-            ast::StatementKind::Nop => {}
-            ast::StatementKind::If {
-                condition,
-                true_block,
-                false_block,
+            Instruction::Call {
+                function,
+                arguments,
             } => {
-                let stack_depth_before_if = self.stack.len();
-
-                self.compile_expression(condition, symtab);
-                let if_false_jump = self.bytecode.new_jump_if_false();
-
-                self.compile_block(true_block, symtab, stack_bottom, num_args);
-                self.compile_drop_to(stack_depth_before_if);
-
-                let if_true_jump = self.bytecode.new_jump();
-
-                let false_target = self.bytecode.new_label();
-                self.bytecode.patch_jump(if_false_jump, false_target);
-
-                // insert a fake additional stack value for the bool that will be left
-                self.stack.push(None);
-
-                self.compile_block(false_block, symtab, stack_bottom, num_args);
-
-                self.compile_drop_to(stack_depth_before_if);
-                let end_target = self.bytecode.new_label();
-                self.bytecode.patch_jump(if_true_jump, end_target);
-            }
-            ast::StatementKind::Block { block } => {
-                let stack_depth_before_block = self.stack.len();
-                self.compile_block(block, symtab, stack_bottom, num_args);
-                self.compile_drop_to(stack_depth_before_block);
-            }
-            ast::StatementKind::Return { values } => {
-                for ret_value in values {
-                    self.compile_expression(ret_value, symtab);
-                }
-
-                let distance_to_bottom = self.stack.len() - stack_bottom;
-
-                self.bytecode.add_opcode(Opcode::Return {
-                    args: num_args as u8,
-                    rets: values.len() as u8,
-                    shift: distance_to_bottom.try_into().expect("Too far to shift"),
-                });
-
-                // we should stop compiling this block
-                return ControlFlow::Break(());
-            }
-            ast::StatementKind::Call { arguments, .. } => {
-                let function_id: FunctionId = *statement.meta.get().unwrap();
+                let function_id = function.expect("Should've resolved function");
                 let number_of_returns = self.type_table.num_function_returns(function_id);
                 let stack_before_call = self.stack.len();
 
-                for argument in arguments {
+                for argument in arguments.iter() {
                     self.compile_expression(argument, symtab);
                 }
 
-                let call_jump = self.bytecode.new_call();
+                let call_jump = self.bytecode.new_call(arguments.len() as u8);
                 self.function_calls.push((function_id, call_jump));
+                self.record_call_site(function_id, stack_before_call, false);
 
                 // fixup the stack after the call instruction
                 self.stack
                     .resize(self.stack.len() - arguments.len() + number_of_returns, None);
+                self.record_stack_depth();
 
                 self.compile_drop_to(stack_before_call);
             }
-            ast::StatementKind::Spawn { arguments, .. } => {
-                let function_id: FunctionId = *statement.meta.get().unwrap();
+            Instruction::Spawn {
+                group,
+                function,
+                arguments,
+            } => {
+                let function_id = function.expect("Should've resolved function");
+                let stack_before_spawn = self.stack.len();
 
-                for argument in arguments {
+                if let Some(group) = group {
+                    self.compile_expression(group, symtab);
+                }
+
+                for argument in arguments.iter() {
                     self.compile_expression(argument, symtab);
                 }
 
-                let spawn_jump = self.bytecode.new_spawn(arguments.len() as u8);
+                let spawn_jump = self
+                    .bytecode
+                    .new_spawn(arguments.len() as u8, group.is_some());
                 self.function_calls.push((function_id, spawn_jump));
 
-                self.stack.truncate(self.stack.len() - arguments.len());
-            }
-            ast::StatementKind::Continue => {
-                let loop_state = self
-                    .loops
-                    .last()
-                    .expect("Should've worked out that there wasn't a loop for this break yet");
-                let stack_size_to_return_to = loop_state.stack_length;
-                let loop_start = loop_state.loop_start;
-
-                self.bytecode.add_opcode(Opcode::Drop(
-                    (self.stack.len() - stack_size_to_return_to) as u8,
-                ));
-
-                let jump = self.bytecode.new_jump();
-                self.bytecode.patch_jump(jump, loop_start);
-            }
-            ast::StatementKind::Break => {
-                let loop_state = self
-                    .loops
-                    .last()
-                    .expect("Should've worked out that there wasn't a loop for this break yet");
-                let stack_size_to_return_to = loop_state.stack_length;
-
-                self.bytecode.add_opcode(Opcode::Drop(
-                    (self.stack.len() - stack_size_to_return_to) as u8,
-                ));
-
-                let jump = self.bytecode.new_jump();
-                let loop_state = self.loops.last_mut().unwrap();
-                loop_state.forward_jumps.push(jump);
-
-                // we should stop compiling this block
-                return ControlFlow::Break(());
-            }
-            ast::StatementKind::Loop { block } => {
-                let loop_start = self.bytecode.new_label();
-                self.loops.push(LoopCompliationState {
-                    loop_start,
-                    forward_jumps: vec![],
-                    stack_length: self.stack.len(),
-                });
-
-                self.compile_block(block, symtab, stack_bottom, num_args);
-
-                let jump = self.bytecode.new_jump();
-                self.bytecode.patch_jump(jump, loop_start);
-
-                let loop_end = self.bytecode.new_label();
-                let loop_compilation_state = self.loops.pop().expect("There should be a loop here");
-
-                for forward_jump in loop_compilation_state.forward_jumps {
-                    self.bytecode.patch_jump(forward_jump, loop_end);
-                }
+                self.stack.truncate(stack_before_spawn);
             }
-            ast::StatementKind::Trigger { arguments, .. } => {
+            Instruction::Trigger { trigger, arguments } => {
                 let stack_before_trigger = self.stack.len();
-                for arg in arguments {
+                for arg in arguments.iter() {
                     self.compile_expression(arg, symtab);
                 }
 
-                let &TriggerId(trigger_index) =
-                    statement.meta.get().expect("Should have a trigger id");
+                let TriggerId(trigger_index) = trigger.expect("Should have a trigger id");
 
                 self.bytecode
                     .add_opcode(Opcode::Trigger(trigger_index as u8));
 
                 self.stack.truncate(stack_before_trigger);
             }
-        }
+            Instruction::Send {
+                message,
+                target,
+                arguments,
+            } => {
+                let stack_before_send = self.stack.len();
+                self.compile_expression(target, symtab);
+                for arg in arguments.iter() {
+                    self.compile_expression(arg, symtab);
+                }
+
+                let MessageId(message_index) = message.expect("Should have a message id");
+
+                self.bytecode.add_opcode(Opcode::Send {
+                    args: arguments.len() as u8,
+                    message: message_index as u16,
+                });
 
-        ControlFlow::Continue(())
+                self.stack.truncate(stack_before_send);
+            }
+            Instruction::ControlGroup { group, control } => {
+                let stack_before_control = self.stack.len();
+                self.compile_expression(group, symtab);
+
+                let opcode = match control {
+                    ast::GroupControl::Pause => Opcode::PauseGroup,
+                    ast::GroupControl::Resume => Opcode::ResumeGroup,
+                    ast::GroupControl::TimeScale { frames } => {
+                        self.compile_expression(frames, symtab);
+                        Opcode::SetGroupTimeScale
+                    }
+                };
+                self.bytecode.add_opcode(opcode);
+
+                self.stack.truncate(stack_before_control);
+            }
+            Instruction::EndScope { slots } => self.compile_drop_to(stack_bottom + slots),
+        }
     }
 
     fn compile_expression(&mut self, value: &ast::Expression<'input>, symtab: &SymTab) {
@@ -460,18 +624,65 @@ impl<'input> Compiler<'input> {
                 let function_id: FunctionId = *value.meta.get().unwrap();
 
                 let number_of_returns = 1;
+                let stack_before_call = self.stack.len();
+
                 for argument in arguments {
                     self.compile_expression(argument, symtab);
                 }
 
-                let call_jump = self.bytecode.new_call();
+                let call_jump = self.bytecode.new_call(arguments.len() as u8);
                 self.function_calls.push((function_id, call_jump));
+                self.record_call_site(function_id, stack_before_call, false);
 
                 // fixup the stack after the call instruction
                 self.stack
                     .resize(self.stack.len() - arguments.len() + number_of_returns, None);
             }
         }
+
+        self.record_stack_depth();
+    }
+
+    fn compile_tail_call(
+        &mut self,
+        function_id: FunctionId,
+        arguments: &[ast::Expression<'input>],
+        symtab: &SymTab,
+        stack_bottom: usize,
+        num_args: usize,
+    ) {
+        for argument in arguments {
+            self.compile_expression(argument, symtab);
+        }
+
+        let distance_to_bottom = self.stack.len() - stack_bottom;
+
+        let tail_call_jump = self.bytecode.new_tail_call(
+            arguments.len() as u8,
+            num_args as u8,
+            distance_to_bottom.try_into().expect("Too far to shift"),
+        );
+        self.function_calls.push((function_id, tail_call_jump));
+        // the called function's frame replaces this one
+        self.record_call_site(function_id, self.frame_base, true);
+    }
+
+    fn record_call_site(
+        &mut self,
+        function_id: FunctionId,
+        stack_before_call: usize,
+        is_tail_call: bool,
+    ) {
+        self.call_sites.push(CallSite {
+            caller: self.current_function,
+            callee: function_id,
+            offset: stack_before_call - self.frame_base,
+            is_tail_call,
+        });
+    }
+
+    fn record_stack_depth(&mut self) {
+        self.frame_max = self.frame_max.max(self.stack.len());
     }
 
     fn compile_drop_to(&mut self, desired_stack_size: usize) {
@@ -502,6 +713,18 @@ impl<'input> Compiler<'input> {
             let label = self.function_locations[fname];
             self.bytecode.patch_jump(*jump, label);
         }
+
+        let max_depths = stack_depth::max_stack_depths(&self.frame_depths, &self.call_sites);
+        self.bytecode.stack_depths = max_depths
+            .into_iter()
+            .map(|(function_id, max_depth)| StackDepth {
+                bytecode_offset: self.function_locations[&function_id].0 as usize,
+                max_depth,
+            })
+            .collect();
+        self.bytecode
+            .stack_depths
+            .sort_by_key(|stack_depth| stack_depth.bytecode_offset);
     }
 }
 
@@ -525,10 +748,47 @@ pub mod opcodes {
         MathsOp(MathsOp),
         JumpIfFalse(u16),
         Jump(u16),
-        Call(u16),
-        Spawn { args: u8, target: u16 },
-        Return { args: u8, rets: u8, shift: u8 },
+        Call {
+            args: u8,
+            target: u16,
+        },
+        /// If `in_group`, the id of the group to spawn into is below the arguments
+        Spawn {
+            args: u8,
+            target: u16,
+            in_group: bool,
+        },
+        Return {
+            args: u8,
+            rets: u8,
+            shift: u8,
+        },
         Trigger(u8),
+        /// Pops `args` arguments and then the id of the script to send `message` to
+        Send {
+            args: u8,
+            message: u16,
+        },
+        Yield,
+        PauseGroup,
+        ResumeGroup,
+        SetGroupTimeScale,
+        TailCall {
+            args: u8,
+            frame_args: u8,
+            shift: u8,
+            target: u16,
+        },
+
+        // superinstructions, only created by the peephole optimiser
+        AddPropImmediate {
+            prop: u8,
+            value: i16,
+        },
+        CompareJumpIfFalse {
+            op: MathsOp,
+            target: u16,
+        },
     }
 
     impl Display for Opcode {
@@ -542,33 +802,40 @@ pub mod opcodes {
                 Opcode::SetProp(i) => write!(f, "setprop\t{i}"),
                 Opcode::Wait => write!(f, "wait"),
                 Opcode::Move(i) => write!(f, "move\t{i}"),
-                Opcode::MathsOp(maths_op) => write!(
-                    f,
-                    "{}",
-                    match maths_op {
-                        MathsOp::Add => "add",
-                        MathsOp::Sub => "sub",
-                        MathsOp::Mul => "mul",
-                        MathsOp::RealMod => "realmod",
-                        MathsOp::RealDiv => "realdiv",
-                        MathsOp::EqEq => "==",
-                        MathsOp::NeEq => "!=",
-                        MathsOp::Gt => ">",
-                        MathsOp::GtEq => ">=",
-                        MathsOp::Lt => "<",
-                        MathsOp::LtEq => "<=",
-                        MathsOp::FixMul => "fmul",
-                        MathsOp::FixDiv => "fdiv",
-                    }
-                ),
+                Opcode::MathsOp(maths_op) => write!(f, "{maths_op}"),
                 Opcode::JumpIfFalse(target) => write!(f, "jif\t{target}"),
                 Opcode::Jump(target) => write!(f, "j\t{target}"),
-                Opcode::Call(target) => write!(f, "call\t{target}"),
+                Opcode::Call { args, target } => write!(f, "call\t{args} {target}"),
                 Opcode::Return { args, rets, shift } => {
                     write!(f, "ret\targs={args} rets={rets} shift={shift}")
                 }
-                Opcode::Spawn { args, target } => write!(f, "spawn\t{args} {target}"),
+                Opcode::Spawn {
+                    args,
+                    target,
+                    in_group: false,
+                } => write!(f, "spawn\t{args} {target}"),
+                Opcode::Spawn {
+                    args,
+                    target,
+                    in_group: true,
+                } => write!(f, "spawng\t{args} {target}"),
                 Opcode::Trigger(index) => write!(f, "trigger\t{index}"),
+                Opcode::Send { args, message } => write!(f, "send\t{args} {message}"),
+                Opcode::Yield => write!(f, "yield"),
+                Opcode::PauseGroup => write!(f, "pauseg"),
+                Opcode::ResumeGroup => write!(f, "resumeg"),
+                Opcode::SetGroupTimeScale => write!(f, "timescaleg"),
+                Opcode::TailCall {
+                    args,
+                    frame_args,
+                    shift,
+                    target,
+                } => write!(
+                    f,
+                    "tailcall\t{target} args={args} frame_args={frame_args} shift={shift}"
+                ),
+                Opcode::AddPropImmediate { prop, value } => write!(f, "addpropi\t{prop} {value}"),
+                Opcode::CompareJumpIfFalse { op, target } => write!(f, "cmpjif\t{op} {target}"),
             }
         }
     }
@@ -589,6 +856,44 @@ pub mod opcodes {
         LtEq,
         FixMul,
         FixDiv,
+
+        ShiftLeft,
+        ShiftRight,
+    }
+
+    impl MathsOp {
+        pub fn is_comparison(self) -> bool {
+            matches!(
+                self,
+                Self::EqEq | Self::NeEq | Self::Gt | Self::GtEq | Self::Lt | Self::LtEq
+            )
+        }
+    }
+
+    impl Display for MathsOp {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            write!(
+                f,
+                "{}",
+                match self {
+                    MathsOp::Add => "add",
+                    MathsOp::Sub => "sub",
+                    MathsOp::Mul => "mul",
+                    MathsOp::RealMod => "realmod",
+                    MathsOp::RealDiv => "realdiv",
+                    MathsOp::EqEq => "==",
+                    MathsOp::NeEq => "!=",
+                    MathsOp::Gt => ">",
+                    MathsOp::GtEq => ">=",
+                    MathsOp::Lt => "<",
+                    MathsOp::LtEq => "<=",
+                    MathsOp::FixMul => "fmul",
+                    MathsOp::FixDiv => "fdiv",
+                    MathsOp::ShiftLeft => "shl",
+                    MathsOp::ShiftRight => "shr",
+                }
+            )
+        }
     }
 
     impl From<MathsOp> for bytecode::MathsOp {
@@ -603,7 +908,10 @@ pub mod opcodes {
                 };
             }
 
-            arm!(Add, Sub, Mul, RealMod, RealDiv, EqEq, NeEq, Gt, GtEq, Lt, LtEq, FixMul, FixDiv)
+            arm!(
+                Add, Sub, Mul, RealMod, RealDiv, EqEq, NeEq, Gt, GtEq, Lt, LtEq, FixMul, FixDiv,
+                ShiftLeft, ShiftRight
+            )
         }
     }
 
@@ -612,10 +920,13 @@ pub mod opcodes {
             match self {
                 Self::JumpIfFalse(_)
                 | Self::Jump(_)
-                | Self::Call(_)
+                | Self::Call { .. }
                 | Self::Return { .. }
-                | Self::Spawn { .. } => 2,
-                Self::Push32(_) => 3,
+                | Self::Spawn { .. }
+                | Self::Send { .. }
+                | Self::AddPropImmediate { .. }
+                | Self::CompareJumpIfFalse { .. } => 2,
+                Self::Push32(_) | Self::TailCall { .. } => 3,
                 _ => 1,
             }
         }
@@ -641,6 +952,8 @@ pub mod opcodes {
                 BinaryOperator::LtEq => LtEq,
                 BinaryOperator::FixMul => FixMul,
                 BinaryOperator::FixDiv => FixDiv,
+                BinaryOperator::ShiftLeft => ShiftLeft,
+                BinaryOperator::ShiftRight => ShiftRight,
                 BinaryOperator::Then => panic!("Shouldn't be compiling then binops"),
             }
         }
@@ -654,15 +967,21 @@ pub struct Bytecode {
 
     pub event_handlers: Vec<EventHandler>,
     pub triggers: Vec<Trigger>,
+    pub messages: Vec<SentMessage>,
+    pub stack_depths: Vec<StackDepth>,
+    pub functions: Vec<FunctionInfo>,
 }
 
 impl Bytecode {
-    pub fn new(triggers: Vec<Trigger>) -> Self {
+    pub fn new(triggers: Vec<Trigger>, messages: Vec<SentMessage>) -> Self {
         Self {
             data: vec![],
             length: 0,
             event_handlers: vec![],
             triggers,
+            messages,
+            stack_depths: vec![],
+            functions: vec![],
         }
     }
 
@@ -689,13 +1008,27 @@ impl Bytecode {
         Jump(self.data.len() - 1)
     }
 
-    fn new_call(&mut self) -> Jump {
-        self.add_opcode(Opcode::Call(0));
+    fn new_call(&mut self, args: u8) -> Jump {
+        self.add_opcode(Opcode::Call { args, target: 0 });
+        Jump(self.data.len() - 1)
+    }
+
+    fn new_spawn(&mut self, args: u8, in_group: bool) -> Jump {
+        self.add_opcode(Opcode::Spawn {
+            args,
+            target: 0,
+            in_group,
+        });
         Jump(self.data.len() - 1)
     }
 
-    fn new_spawn(&mut self, args: u8) -> Jump {
-        self.add_opcode(Opcode::Spawn { args, target: 0 });
+    fn new_tail_call(&mut self, args: u8, frame_args: u8, shift: u8) -> Jump {
+        self.add_opcode(Opcode::TailCall {
+            args,
+            frame_args,
+            shift,
+            target: 0,
+        });
         Jump(self.data.len() - 1)
     }
 
@@ -703,8 +1036,9 @@ impl Bytecode {
         match &mut self.data[jump.0] {
             Opcode::Jump(target)
             | Opcode::JumpIfFalse(target)
-            | Opcode::Call(target)
-            | Opcode::Spawn { target, .. } => *target = label.0,
+            | Opcode::Call { target, .. }
+            | Opcode::Spawn { target, .. }
+            | Opcode::TailCall { target, .. } => *target = label.0,
             opcode => panic!("Tried to patch {opcode:?} which isn't a jump"),
         }
     }
@@ -758,12 +1092,20 @@ impl Bytecode {
                     one_arg!(Jump, 0);
                     result.push(target);
                 }
-                Opcode::Call(target) => {
-                    one_arg!(Call, 0);
+                Opcode::Call { args, target } => {
+                    one_arg!(Call, args);
                     result.push(target);
                 }
-                Opcode::Spawn { args, target } => {
-                    one_arg!(Spawn, args);
+                Opcode::Spawn {
+                    args,
+                    target,
+                    in_group,
+                } => {
+                    if in_group {
+                        one_arg!(SpawnInGroup, args);
+                    } else {
+                        one_arg!(Spawn, args);
+                    }
                     result.push(target);
                 }
                 Opcode::Return { args, rets, shift } => {
@@ -773,6 +1115,40 @@ impl Bytecode {
                 Opcode::Trigger(index) => {
                     one_arg!(Trigger, index);
                 }
+                Opcode::Send { args, message } => {
+                    one_arg!(Send, args);
+                    result.push(message);
+                }
+                Opcode::Yield => {
+                    one_arg!(Yield, 0);
+                }
+                Opcode::PauseGroup => {
+                    one_arg!(PauseGroup, 0);
+                }
+                Opcode::ResumeGroup => {
+                    one_arg!(ResumeGroup, 0);
+                }
+                Opcode::SetGroupTimeScale => {
+                    one_arg!(SetGroupTimeScale, 0);
+                }
+                Opcode::TailCall {
+                    args,
+                    frame_args,
+                    shift,
+                    target,
+                } => {
+                    one_arg!(TailCall, args);
+                    result.push(target);
+                    result.push(u16::from_be_bytes([frame_args, shift]));
+                }
+                Opcode::AddPropImmediate { prop, value } => {
+                    one_arg!(AddPropImmediate, prop);
+                    result.push(value as u16);
+                }
+                Opcode::CompareJumpIfFalse { op, target } => {
+                    one_arg!(CompareJumpIfFalse, bytecode::MathsOp::from(op));
+                    result.push(target);
+                }
             }
         }
 
@@ -791,6 +1167,8 @@ mod test {
 
     use insta::{assert_snapshot, glob};
 
+    use crate::{LintLevel, Severity};
+
     use super::*;
 
     #[test]
@@ -803,17 +1181,207 @@ mod test {
                     ty: Type::Int,
                     index: 0,
                     name: "int_prop".to_string(),
+                    access: PropertyAccess::ReadWrite,
                 }],
                 enable_optimisations: false,
+                fix_precision: CompileSettings::DEFAULT_FIX_PRECISION,
             };
 
-            let bytecode = compile(path, &input, &compiler_settings).unwrap();
+            let (bytecode, _) = compile(path, &input, &compiler_settings).unwrap();
             let decompiled = print_opcodes(&bytecode.data);
 
             assert_snapshot!(decompiled);
         });
     }
 
+    #[test]
+    fn peephole_snapshot_tests() {
+        glob!("snapshot_tests", "peephole/*.tapir", |path| {
+            let input = fs::read_to_string(path).unwrap();
+
+            let compiler_settings = CompileSettings {
+                properties: vec![Property {
+                    ty: Type::Int,
+                    index: 0,
+                    name: "int_prop".to_string(),
+                    access: PropertyAccess::ReadWrite,
+                }],
+                enable_optimisations: true,
+                fix_precision: CompileSettings::DEFAULT_FIX_PRECISION,
+            };
+
+            let (mut bytecode, _) = compile(path, &input, &compiler_settings).unwrap();
+            bytecode.fuse_superinstructions();
+            let mut decompiled = print_opcodes(&bytecode.data);
+
+            for event_handler in &bytecode.event_handlers {
+                writeln!(
+                    &mut decompiled,
+                    "event {}: {:08}",
+                    event_handler.name, event_handler.bytecode_offset
+                )
+                .unwrap();
+            }
+
+            assert_snapshot!(decompiled);
+        });
+    }
+
+    #[test]
+    fn stack_depth_snapshot_tests() {
+        glob!("snapshot_tests", "stack_depth/*.tapir", |path| {
+            let input = fs::read_to_string(path).unwrap();
+
+            let compiler_settings = CompileSettings {
+                properties: vec![Property {
+                    ty: Type::Int,
+                    index: 0,
+                    name: "int_prop".to_string(),
+                    access: PropertyAccess::ReadWrite,
+                }],
+                enable_optimisations: false,
+                fix_precision: CompileSettings::DEFAULT_FIX_PRECISION,
+            };
+
+            let (bytecode, _) = compile(path, &input, &compiler_settings).unwrap();
+            let mut decompiled = print_opcodes(&bytecode.data);
+
+            for stack_depth in &bytecode.stack_depths {
+                match stack_depth.max_depth {
+                    Some(max_depth) => writeln!(
+                        &mut decompiled,
+                        "depth {:08}: {max_depth}",
+                        stack_depth.bytecode_offset
+                    ),
+                    None => writeln!(
+                        &mut decompiled,
+                        "depth {:08}: unbounded",
+                        stack_depth.bytecode_offset
+                    ),
+                }
+                .unwrap();
+            }
+
+            assert_snapshot!(decompiled);
+        });
+    }
+
+    #[test]
+    fn warnings_snapshot_tests() {
+        glob!("snapshot_tests", "warnings/*.tapir", |path| {
+            let input = fs::read_to_string(path).unwrap();
+
+            let compiler_settings = CompileSettings {
+                properties: vec![Property {
+                    ty: Type::Int,
+                    index: 0,
+                    name: "int_prop".to_string(),
+                    access: PropertyAccess::ReadWrite,
+                }],
+                enable_optimisations: true,
+                fix_precision: CompileSettings::DEFAULT_FIX_PRECISION,
+            };
+
+            let (_, mut warnings) =
+                compile(path.file_name().unwrap(), &input, &compiler_settings).unwrap();
+
+            assert_snapshot!(warnings.pretty_string(false));
+        });
+    }
+
+    #[test]
+    fn warnings_can_be_allowed_and_denied() {
+        let input = "var unused = 5;\nvar x = 3;\nvar x = 4;\nint_prop = x;";
+        let compiler_settings = CompileSettings {
+            properties: vec![Property {
+                ty: Type::Int,
+                index: 0,
+                name: "int_prop".to_string(),
+                access: PropertyAccess::ReadWrite,
+            }],
+            enable_optimisations: false,
+            fix_precision: CompileSettings::DEFAULT_FIX_PRECISION,
+        };
+
+        let (_, mut warnings) = compile("input.tapir", input, &compiler_settings).unwrap();
+        assert!(warnings.has_any());
+        assert!(!warnings.has_errors());
+
+        warnings.allow("unused_variable");
+        assert_eq!(warnings.summaries().len(), 1);
+
+        warnings.deny("shadowing");
+        assert!(warnings.has_errors());
+
+        warnings.allow("warnings");
+        assert!(!warnings.has_any());
+    }
+
+    #[test]
+    fn the_last_lint_level_wins() {
+        let input = "var unused = 5;\nvar x = 3;\nvar x = 4;\nint_prop = x;";
+        let compiler_settings = CompileSettings {
+            properties: vec![Property {
+                ty: Type::Int,
+                index: 0,
+                name: "int_prop".to_string(),
+                access: PropertyAccess::ReadWrite,
+            }],
+            enable_optimisations: false,
+            fix_precision: CompileSettings::DEFAULT_FIX_PRECISION,
+        };
+
+        let (_, mut warnings) = compile("input.tapir", input, &compiler_settings).unwrap();
+        warnings.set_lint_levels([
+            (LintLevel::Allow, "unused_variable"),
+            (LintLevel::Deny, "unused_variable"),
+            (LintLevel::Deny, "shadowing"),
+            (LintLevel::Allow, "shadowing"),
+        ]);
+        // both of the unused variables, but not the shadowing
+        assert_eq!(
+            warnings
+                .summaries()
+                .into_iter()
+                .map(|(_, severity, _)| severity)
+                .collect::<Vec<_>>(),
+            [Severity::Error, Severity::Error]
+        );
+
+        let (_, mut warnings) = compile("input.tapir", input, &compiler_settings).unwrap();
+        warnings.set_lint_levels([
+            (LintLevel::Allow, "warnings"),
+            (LintLevel::Deny, "shadowing"),
+        ]);
+        assert_eq!(warnings.summaries().len(), 1);
+        assert!(warnings.has_errors());
+    }
+
+    #[test]
+    fn divide_by_zero_is_only_an_error_in_code_which_runs() {
+        let compiler_settings = CompileSettings {
+            properties: vec![Property {
+                ty: Type::Int,
+                index: 0,
+                name: "int_prop".to_string(),
+                access: PropertyAccess::ReadWrite,
+            }],
+            enable_optimisations: true,
+            fix_precision: CompileSettings::DEFAULT_FIX_PRECISION,
+        };
+
+        let never_runs = "var b = 0;\nvar i = 0;\nloop {\n    if i >= 0 { break; }\n    int_prop = int_prop // b;\n}";
+        assert!(compile("never_runs.tapir", never_runs, &compiler_settings).is_ok());
+
+        let runs = "var b = 0;\nint_prop = int_prop // b;";
+        let Err(mut diagnostics) = compile("runs.tapir", runs, &compiler_settings) else {
+            panic!("Expected dividing by zero to be an error");
+        };
+        assert!(diagnostics
+            .pretty_string(false)
+            .contains("Divide by zero not allowed"));
+    }
+
     fn print_opcodes(opcodes: &[Opcode]) -> String {
         let mut result = String::new();
 
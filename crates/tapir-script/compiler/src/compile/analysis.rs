@@ -0,0 +1,369 @@
+use std::{collections::HashMap, ops::Range, path::Path};
+
+use serde::Serialize;
+
+use crate::{
+    ast::{
+        Expression, ExpressionKind, FunctionId, MaybeResolved, Statement, StatementKind, SymbolId,
+    },
+    grammar,
+    lexer::Lexer,
+    reporting::{Diagnostics, Severity},
+    tokens::{FileId, Span},
+    types::Type,
+};
+
+use super::{
+    loop_visitor, symtab_visitor::SymTabVisitor, type_visitor::TypeVisitor, unused_code_visitor,
+    CompileSettings,
+};
+
+/// Everything an editor needs to know about a script. Unlike `compile`, this
+/// always succeeds and contains as much information as could be worked out
+/// even if the script has errors.
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct Analysis {
+    pub diagnostics: Vec<AnalysisDiagnostic>,
+    pub symbols: Vec<AnalysisSymbol>,
+    pub functions: Vec<AnalysisFunction>,
+    pub references: Vec<AnalysisReference>,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct AnalysisDiagnostic {
+    pub range: Range<usize>,
+    pub severity: Severity,
+    pub message: String,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct AnalysisSymbol {
+    pub name: String,
+    pub ty: Type,
+    pub kind: SymbolKind,
+    /// `None` for properties, since they are declared in rust
+    pub declaration: Option<Range<usize>>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+pub enum SymbolKind {
+    Property,
+    Argument,
+    Variable,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct AnalysisFunction {
+    pub name: String,
+    pub declaration: Range<usize>,
+    pub arguments: Vec<(String, Type)>,
+    pub return_types: Vec<Type>,
+    pub is_event_handler: bool,
+    /// The type of the values a `generator fn` yields, or `None` for any other function
+    pub yields: Option<Type>,
+}
+
+/// A place in the source which refers to a symbol or a function. The target is an
+/// index into `Analysis::symbols` or `Analysis::functions` respectively.
+#[derive(Clone, Debug, Serialize)]
+pub struct AnalysisReference {
+    pub range: Range<usize>,
+    pub target: ReferenceTarget,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+pub enum ReferenceTarget {
+    Symbol(usize),
+    Function(usize),
+}
+
+impl Analysis {
+    /// The innermost reference which contains the given byte offset
+    pub fn reference_at(&self, offset: usize) -> Option<&AnalysisReference> {
+        self.references
+            .iter()
+            .filter(|reference| reference.range.start <= offset && offset <= reference.range.end)
+            .min_by_key(|reference| reference.range.len())
+    }
+}
+
+pub fn analyse(filename: impl AsRef<Path>, input: &str, settings: &CompileSettings) -> Analysis {
+    let file_id = FileId::new(0);
+
+    let mut diagnostics = Diagnostics::new(file_id, filename, input);
+
+    let lexer = Lexer::new(input, file_id);
+    let parser = grammar::ScriptParser::new();
+
+    let mut ast = match parser.parse(file_id, settings.fix_precision, &mut diagnostics, lexer) {
+        Ok(ast) => ast,
+        Err(e) => {
+            diagnostics.add_lalrpop(e, file_id);
+            return Analysis {
+                diagnostics: analysis_diagnostics(&mut diagnostics),
+                ..Default::default()
+            };
+        }
+    };
+
+    let mut sym_tab_visitor = SymTabVisitor::new(settings, &mut ast.functions, &mut diagnostics);
+    let mut type_visitor = TypeVisitor::new(settings, &ast.functions);
+
+    for function in &mut ast.functions {
+        sym_tab_visitor.visit_function(function, &mut diagnostics);
+        loop_visitor::visit_loop_check(function, &mut diagnostics);
+
+        type_visitor.visit_function(function, sym_tab_visitor.get_symtab(), &mut diagnostics);
+    }
+
+    unused_code_visitor::visit_unused_code(&ast.functions, &mut diagnostics);
+
+    let symtab = sym_tab_visitor.get_symtab();
+    let type_table = type_visitor.into_type_table(symtab, &mut diagnostics);
+
+    let argument_symbols = ast
+        .functions
+        .iter()
+        .flat_map(|function| &function.arguments)
+        .filter_map(|argument| match argument.name {
+            MaybeResolved::Resolved(symbol_id) => Some(symbol_id),
+            MaybeResolved::Unresolved(_) => None,
+        })
+        .collect::<Vec<_>>();
+
+    let symbols = symtab
+        .all_symbols()
+        .map(|(name, symbol_id)| AnalysisSymbol {
+            name: name.to_string(),
+            ty: type_table.type_for_symbol(symbol_id),
+            kind: if settings.is_property(symbol_id) {
+                SymbolKind::Property
+            } else if argument_symbols.contains(&symbol_id) {
+                SymbolKind::Argument
+            } else {
+                SymbolKind::Variable
+            },
+            declaration: symtab.declaration_for_symbol(symbol_id).map(range),
+        })
+        .collect();
+
+    let mut function_indices = HashMap::new();
+    let mut functions = vec![];
+
+    for function in &ast.functions {
+        if function.name == "@toplevel" {
+            continue;
+        }
+
+        function_indices.insert(*function.meta.get::<FunctionId>().unwrap(), functions.len());
+        functions.push(AnalysisFunction {
+            name: function.name.to_string(),
+            declaration: range(function.span),
+            arguments: function
+                .arguments
+                .iter()
+                .map(|argument| {
+                    let name = match argument.name {
+                        MaybeResolved::Resolved(symbol_id) => {
+                            symtab.name_for_symbol(symbol_id).into_owned()
+                        }
+                        MaybeResolved::Unresolved(name) => name.to_string(),
+                    };
+
+                    (name, argument.t.t)
+                })
+                .collect(),
+            return_types: function.return_types.types.iter().map(|t| t.t).collect(),
+            is_event_handler: function.modifiers.is_event_handler.is_some(),
+            yields: function
+                .modifiers
+                .is_generator
+                .as_ref()
+                .map(|generator| generator.yields.t),
+        });
+    }
+
+    let mut reference_collector = ReferenceCollector {
+        function_indices,
+        references: vec![],
+    };
+
+    for function in &ast.functions {
+        if let Some(&function_id) = function.meta.get::<FunctionId>() {
+            reference_collector.function(function_id, function.span);
+        }
+
+        for argument in &function.arguments {
+            if let MaybeResolved::Resolved(symbol_id) = argument.name {
+                reference_collector.symbol(symbol_id, argument.span);
+            }
+        }
+
+        reference_collector.visit_block(&function.statements);
+    }
+
+    Analysis {
+        diagnostics: analysis_diagnostics(&mut diagnostics),
+        symbols,
+        functions,
+        references: reference_collector.references,
+    }
+}
+
+fn analysis_diagnostics(diagnostics: &mut Diagnostics) -> Vec<AnalysisDiagnostic> {
+    diagnostics
+        .summaries()
+        .into_iter()
+        .map(|(span, severity, message)| AnalysisDiagnostic {
+            range: range(span),
+            severity,
+            message,
+        })
+        .collect()
+}
+
+fn range(span: Span) -> Range<usize> {
+    span.start..span.end
+}
+
+struct ReferenceCollector {
+    function_indices: HashMap<FunctionId, usize>,
+    references: Vec<AnalysisReference>,
+}
+
+impl ReferenceCollector {
+    fn symbol(&mut self, symbol_id: SymbolId, span: Span) {
+        self.references.push(AnalysisReference {
+            range: range(span),
+            target: ReferenceTarget::Symbol(symbol_id.0),
+        });
+    }
+
+    fn function(&mut self, function_id: FunctionId, span: Span) {
+        // the top level function can't be referenced
+        if let Some(&index) = self.function_indices.get(&function_id) {
+            self.references.push(AnalysisReference {
+                range: range(span),
+                target: ReferenceTarget::Function(index),
+            });
+        }
+    }
+
+    fn visit_block(&mut self, block: &[Statement]) {
+        for statement in block {
+            match &statement.kind {
+                StatementKind::VariableDeclaration { .. } | StatementKind::Assignment { .. } => {
+                    if let Some(&symbol_id) = statement.meta.get::<SymbolId>() {
+                        self.symbol(symbol_id, statement.span);
+                    }
+                }
+                StatementKind::Call { .. } | StatementKind::Spawn { .. } => {
+                    if let Some(&function_id) = statement.meta.get::<FunctionId>() {
+                        self.function(function_id, statement.span);
+                    }
+                }
+                StatementKind::If {
+                    true_block,
+                    false_block,
+                    ..
+                } => {
+                    self.visit_block(true_block);
+                    self.visit_block(false_block);
+                }
+                StatementKind::Block { block } | StatementKind::Loop { block } => {
+                    self.visit_block(block);
+                }
+                StatementKind::Error
+                | StatementKind::Wait
+                | StatementKind::Continue
+                | StatementKind::Break
+                | StatementKind::Nop
+                | StatementKind::Trigger { .. }
+                | StatementKind::Send { .. }
+                | StatementKind::Yield { .. }
+                | StatementKind::ControlGroup { .. }
+                | StatementKind::Return { .. } => {}
+            }
+
+            // nested blocks are visited above, so only look at the expressions directly in this statement
+            match &statement.kind {
+                StatementKind::VariableDeclaration { value, .. }
+                | StatementKind::Assignment { value, .. }
+                | StatementKind::Yield { value } => self.visit_expression(value),
+                StatementKind::If { condition, .. } => self.visit_expression(condition),
+                StatementKind::Call { arguments, .. }
+                | StatementKind::Trigger { arguments, .. }
+                | StatementKind::Return { values: arguments } => {
+                    for argument in arguments {
+                        self.visit_expression(argument);
+                    }
+                }
+                StatementKind::Send {
+                    target, arguments, ..
+                } => {
+                    self.visit_expression(target);
+                    for argument in arguments {
+                        self.visit_expression(argument);
+                    }
+                }
+                StatementKind::Spawn { .. } | StatementKind::ControlGroup { .. } => {
+                    for expression in statement.direct_expressions() {
+                        self.visit_expression(expression);
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    fn visit_expression(&mut self, expression: &Expression) {
+        for expr in expression.all_inner() {
+            match &expr.kind {
+                ExpressionKind::Variable(_) => {
+                    if let Some(&symbol_id) = expr.meta.get::<SymbolId>() {
+                        self.symbol(symbol_id, expr.span);
+                    }
+                }
+                ExpressionKind::Call { .. } => {
+                    if let Some(&function_id) = expr.meta.get::<FunctionId>() {
+                        self.function(function_id, expr.span);
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::fs;
+
+    use insta::{assert_ron_snapshot, glob};
+
+    use crate::{CompileSettings, Property, PropertyAccess, Type};
+
+    use super::*;
+
+    #[test]
+    fn analysis_snapshot_tests() {
+        glob!("snapshot_tests", "analysis/*.tapir", |path| {
+            let input = fs::read_to_string(path).unwrap();
+
+            let compile_settings = CompileSettings {
+                properties: vec![Property {
+                    ty: Type::Int,
+                    index: 0,
+                    name: "int_prop".to_string(),
+                    access: PropertyAccess::ReadWrite,
+                }],
+                enable_optimisations: false,
+                fix_precision: CompileSettings::DEFAULT_FIX_PRECISION,
+            };
+
+            let analysis = analyse(path.file_name().unwrap(), &input, &compile_settings);
+
+            assert_ron_snapshot!(analysis);
+        });
+    }
+}
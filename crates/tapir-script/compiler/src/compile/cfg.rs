@@ -0,0 +1,786 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    fmt::{self, Display},
+    mem,
+};
+
+use petgraph::{graph::NodeIndex, visit::EdgeRef, Direction, Graph};
+
+use petgraph::visit::Dfs;
+
+use crate::ast::{
+    Expression, ExpressionKind, Function, FunctionId, GroupControl, Metadata, Statement,
+    StatementKind, SymbolId,
+};
+
+use super::{
+    type_visitor::{MessageId, TriggerId},
+    TailCall,
+};
+
+/// A control flow graph for a single function. Each basic block is a list of instructions which
+/// always run one after the other, followed by a terminator which decides where to go next.
+///
+/// The instructions borrow the expressions and statement metadata from the AST mutably, so the
+/// optimisations can work out what to do using the graph and then rewrite the AST in place.
+/// Constant propagation and dead code elimination are dataflow analyses over it, and code
+/// generation lowers it to bytecode one block at a time.
+pub struct Cfg<'a, 'input> {
+    graph: Graph<BasicBlock<'a, 'input>, Edge>,
+    entry: NodeIndex,
+    exit: NodeIndex,
+    /// Every block other than the exit, in the order the code for them appears in the function
+    order: Vec<NodeIndex>,
+}
+
+#[derive(Default)]
+pub struct BasicBlock<'a, 'input> {
+    pub instructions: Vec<Instruction<'a, 'input>>,
+    pub terminator: Terminator<'a, 'input>,
+}
+
+pub enum Instruction<'a, 'input> {
+    /// Both `var x = value;` and `x = value;`. The target is `None` if it couldn't be resolved,
+    /// which will already be an error
+    Assign {
+        target: Option<SymbolId>,
+        value: &'a mut Expression<'input>,
+        /// Whether this is a `var`, which is where the variable gets its slot on the stack
+        is_declaration: bool,
+        /// The metadata of the statement, so passes can annotate it
+        meta: &'a mut Metadata,
+    },
+    /// The function is `None` if it couldn't be resolved, which will already be an error
+    Call {
+        function: Option<FunctionId>,
+        arguments: &'a mut [Expression<'input>],
+    },
+    Spawn {
+        group: Option<&'a mut Expression<'input>>,
+        function: Option<FunctionId>,
+        arguments: &'a mut [Expression<'input>],
+    },
+    /// The trigger is `None` until the type visitor has worked it out
+    Trigger {
+        trigger: Option<TriggerId>,
+        arguments: &'a mut [Expression<'input>],
+    },
+    /// The message is `None` until the type visitor has worked it out
+    Send {
+        message: Option<MessageId>,
+        target: &'a mut Expression<'input>,
+        arguments: &'a mut [Expression<'input>],
+    },
+    ControlGroup {
+        group: &'a mut Expression<'input>,
+        control: &'a mut GroupControl<'input>,
+    },
+    /// The end of a block of statements, after which only the first `slots` values the function
+    /// put on the stack are still needed. These are its variables which are still in scope, along
+    /// with the condition of any `if` this is inside, which stays on the stack until the end of
+    /// the `if`.
+    EndScope { slots: usize },
+}
+
+#[derive(Default)]
+pub enum Terminator<'a, 'input> {
+    /// Carries on to the only successor
+    #[default]
+    Goto,
+    /// Goes to the `Edge::True` or `Edge::False` successor depending on the condition
+    Branch {
+        condition: &'a mut Expression<'input>,
+    },
+    /// A `wait`, or a `yield` in a generator along with the value it yields. Anything else could
+    /// run, and change any property, before this resumes at the only successor
+    Yield {
+        value: Option<&'a mut Expression<'input>>,
+    },
+    /// Goes to the exit block
+    Return {
+        values: &'a mut [Expression<'input>],
+    },
+    /// A call which is the last thing the function does, so the called function replaces this
+    /// one and returns straight to its caller. Goes to the exit block
+    TailCall {
+        function: Option<FunctionId>,
+        arguments: &'a mut [Expression<'input>],
+    },
+    /// The end of the function, only used by the exit block
+    Exit,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Edge {
+    Always,
+    True,
+    False,
+}
+
+impl<'a, 'input> Cfg<'a, 'input> {
+    pub fn new(function: &'a mut Function<'input>) -> Self {
+        let mut graph = Graph::new();
+        let entry = graph.add_node(BasicBlock::default());
+        let exit = graph.add_node(BasicBlock {
+            instructions: vec![],
+            terminator: Terminator::Exit,
+        });
+
+        let mut builder = CfgBuilder {
+            graph,
+            current: entry,
+            instructions: vec![],
+            slots: 0,
+            loops: vec![],
+            exit,
+            order: vec![entry],
+        };
+
+        builder.lower_block(&mut function.statements);
+        builder.finish(Terminator::Goto, &[(exit, Edge::Always)]);
+
+        Self {
+            graph: builder.graph,
+            entry,
+            exit,
+            order: builder.order,
+        }
+    }
+
+    pub fn block(&self, node: NodeIndex) -> &BasicBlock<'a, 'input> {
+        &self.graph[node]
+    }
+
+    pub fn blocks_mut(&mut self) -> impl Iterator<Item = (NodeIndex, &mut BasicBlock<'a, 'input>)> {
+        self.graph.node_indices().zip(self.graph.node_weights_mut())
+    }
+
+    /// Where the block goes along the given edge. Panics if it doesn't have that edge.
+    pub fn successor(&self, node: NodeIndex, edge: Edge) -> NodeIndex {
+        self.graph
+            .edges_directed(node, Direction::Outgoing)
+            .find(|candidate| *candidate.weight() == edge)
+            .map(|candidate| candidate.target())
+            .expect("Should have a successor for this edge")
+    }
+
+    /// The blocks which can be reached from the entry in the order their code appears in the
+    /// function, followed by the exit block. Every block other than the entry and the exit comes
+    /// after at least one of its predecessors.
+    pub fn layout(&self) -> Vec<NodeIndex> {
+        let mut reachable = Dfs::new(&self.graph, self.entry);
+        let mut is_reachable = vec![false; self.graph.node_count()];
+        while let Some(node) = reachable.next(&self.graph) {
+            is_reachable[node.index()] = true;
+        }
+
+        self.order
+            .iter()
+            .copied()
+            .filter(|node| is_reachable[node.index()])
+            .chain([self.exit])
+            .collect()
+    }
+
+    /// Runs the analysis until it settles, and returns the state at the start of every block
+    /// which can be reached from the entry.
+    pub fn solve_forward<A: ForwardAnalysis>(&self, analysis: &A) -> HashMap<NodeIndex, A::State> {
+        let mut states = HashMap::from([(self.entry, analysis.entry_state())]);
+        let mut worklist = VecDeque::from([self.entry]);
+
+        while let Some(node) = worklist.pop_front() {
+            let mut state = states[&node].clone();
+            analysis.transfer(&self.graph[node], &mut state);
+
+            for successor in self.graph.neighbors_directed(node, Direction::Outgoing) {
+                let new_state = match states.get(&successor) {
+                    Some(existing) => analysis.join(existing, &state),
+                    None => state.clone(),
+                };
+
+                if states.get(&successor) != Some(&new_state) {
+                    states.insert(successor, new_state);
+                    if !worklist.contains(&successor) {
+                        worklist.push_back(successor);
+                    }
+                }
+            }
+        }
+
+        states
+    }
+
+    /// Runs the analysis until it settles, and returns the state at the end of every block. This
+    /// includes blocks which never reach the exit, such as the body of a loop without a `break`.
+    pub fn solve_backward<A: BackwardAnalysis>(
+        &self,
+        analysis: &A,
+    ) -> HashMap<NodeIndex, A::State> {
+        let mut start_states: HashMap<NodeIndex, A::State> = HashMap::new();
+        let mut end_states = HashMap::new();
+        let mut worklist: VecDeque<_> = self.graph.node_indices().rev().collect();
+
+        while let Some(node) = worklist.pop_front() {
+            let end_state = if node == self.exit {
+                analysis.exit_state()
+            } else {
+                self.graph
+                    .neighbors_directed(node, Direction::Outgoing)
+                    .filter_map(|successor| start_states.get(&successor))
+                    .fold(analysis.initial_state(), |state, successor_state| {
+                        analysis.join(&state, successor_state)
+                    })
+            };
+
+            let mut state = end_state.clone();
+            analysis.transfer(&self.graph[node], &mut state);
+            end_states.insert(node, end_state);
+
+            if start_states.get(&node) != Some(&state) {
+                start_states.insert(node, state);
+                for predecessor in self.graph.neighbors_directed(node, Direction::Incoming) {
+                    if !worklist.contains(&predecessor) {
+                        worklist.push_back(predecessor);
+                    }
+                }
+            }
+        }
+
+        end_states
+    }
+}
+
+/// A dataflow analysis where information flows from the entry of the function towards the exit.
+pub trait ForwardAnalysis {
+    type State: Clone + PartialEq;
+
+    /// What is known at the very start of the function
+    fn entry_state(&self) -> Self::State;
+    /// What is known where two paths through the function meet
+    fn join(&self, a: &Self::State, b: &Self::State) -> Self::State;
+    /// Updates the state with the effect of running the block, including its terminator
+    fn transfer(&self, block: &BasicBlock, state: &mut Self::State);
+}
+
+/// A dataflow analysis where information flows from the exit of the function towards the entry.
+pub trait BackwardAnalysis {
+    type State: Clone + PartialEq;
+
+    /// What is known at the very end of the function
+    fn exit_state(&self) -> Self::State;
+    /// What is known about a block before anything has been worked out, which must leave the
+    /// other state unchanged when joined with it
+    fn initial_state(&self) -> Self::State;
+    /// What is known where two paths through the function split
+    fn join(&self, a: &Self::State, b: &Self::State) -> Self::State;
+    /// Updates the state at the end of the block to the state at its start, going backwards
+    /// through the terminator and then the instructions
+    fn transfer(&self, block: &BasicBlock, state: &mut Self::State);
+}
+
+struct CfgBuilder<'a, 'input> {
+    graph: Graph<BasicBlock<'a, 'input>, Edge>,
+    current: NodeIndex,
+    instructions: Vec<Instruction<'a, 'input>>,
+    /// How many values the function has on the stack at this point, see [`Instruction::EndScope`]
+    slots: usize,
+    loops: Vec<LoopTargets>,
+    exit: NodeIndex,
+    order: Vec<NodeIndex>,
+}
+
+struct LoopTargets {
+    start: NodeIndex,
+    end: NodeIndex,
+    slots: usize,
+}
+
+impl<'a, 'input> CfgBuilder<'a, 'input> {
+    fn new_block(&mut self) -> NodeIndex {
+        self.graph.add_node(BasicBlock::default())
+    }
+
+    /// Makes the block the current one, which is where anything lowered next ends up.
+    fn switch_to(&mut self, block: NodeIndex) {
+        self.current = block;
+        self.order.push(block);
+    }
+
+    /// Ends the current block. Anything lowered after this needs a new current block.
+    fn finish(&mut self, terminator: Terminator<'a, 'input>, successors: &[(NodeIndex, Edge)]) {
+        let block = &mut self.graph[self.current];
+        block.instructions = mem::take(&mut self.instructions);
+        block.terminator = terminator;
+
+        for &(successor, edge) in successors {
+            self.graph.add_edge(self.current, successor, edge);
+        }
+    }
+
+    /// Drops everything but the first `slots` values from the stack, without changing what is in
+    /// scope for the statements lowered after this.
+    fn drop_to(&mut self, slots: usize) {
+        if slots >= self.slots {
+            return;
+        }
+
+        // the end of several scopes at once only needs one drop
+        if let Some(Instruction::EndScope { slots: previous }) = self.instructions.last_mut() {
+            *previous = slots;
+        } else {
+            self.instructions.push(Instruction::EndScope { slots });
+        }
+    }
+
+    fn lower_block(&mut self, block: &'a mut [Statement<'input>]) {
+        let slots = self.slots;
+
+        for statement in block {
+            self.lower_statement(statement);
+        }
+
+        self.drop_to(slots);
+        self.slots = slots;
+    }
+
+    fn lower_statement(&mut self, statement: &'a mut Statement<'input>) {
+        let Statement { kind, meta, .. } = statement;
+
+        match kind {
+            StatementKind::Error | StatementKind::Nop => {}
+            StatementKind::VariableDeclaration { value, .. } => {
+                self.instructions.push(Instruction::Assign {
+                    target: meta.get().copied(),
+                    value,
+                    is_declaration: true,
+                    meta,
+                });
+                self.slots += 1;
+            }
+            StatementKind::Assignment { value, .. } => {
+                self.instructions.push(Instruction::Assign {
+                    target: meta.get().copied(),
+                    value,
+                    is_declaration: false,
+                    meta,
+                });
+            }
+            StatementKind::Call { arguments, .. } => {
+                let function = meta.get().copied();
+
+                if meta.has::<TailCall>() {
+                    let exit = self.exit;
+                    self.finish(
+                        Terminator::TailCall {
+                            function,
+                            arguments,
+                        },
+                        &[(exit, Edge::Always)],
+                    );
+                    let next = self.new_block();
+                    self.switch_to(next);
+                } else {
+                    self.instructions.push(Instruction::Call {
+                        function,
+                        arguments,
+                    });
+                }
+            }
+            StatementKind::Spawn {
+                group, arguments, ..
+            } => {
+                self.instructions.push(Instruction::Spawn {
+                    group: group.as_mut(),
+                    function: meta.get().copied(),
+                    arguments,
+                });
+            }
+            StatementKind::Trigger { arguments, .. } => {
+                self.instructions.push(Instruction::Trigger {
+                    trigger: meta.get().copied(),
+                    arguments,
+                });
+            }
+            StatementKind::Send {
+                target, arguments, ..
+            } => {
+                self.instructions.push(Instruction::Send {
+                    message: meta.get().copied(),
+                    target,
+                    arguments,
+                });
+            }
+            StatementKind::ControlGroup { group, control } => {
+                self.instructions
+                    .push(Instruction::ControlGroup { group, control });
+            }
+            StatementKind::Wait => {
+                let resume = self.new_block();
+                self.finish(Terminator::Yield { value: None }, &[(resume, Edge::Always)]);
+                self.switch_to(resume);
+            }
+            StatementKind::Yield { value } => {
+                let resume = self.new_block();
+                self.finish(
+                    Terminator::Yield { value: Some(value) },
+                    &[(resume, Edge::Always)],
+                );
+                self.switch_to(resume);
+            }
+            StatementKind::If {
+                condition,
+                true_block,
+                false_block,
+            } => {
+                let true_start = self.new_block();
+                let false_start = self.new_block();
+                let end = self.new_block();
+
+                self.finish(
+                    Terminator::Branch { condition },
+                    &[(true_start, Edge::True), (false_start, Edge::False)],
+                );
+
+                // the condition stays on the stack in both branches
+                let slots = self.slots;
+                self.slots += 1;
+
+                self.switch_to(true_start);
+                self.lower_block(true_block);
+                self.drop_to(slots);
+                self.finish(Terminator::Goto, &[(end, Edge::Always)]);
+
+                self.switch_to(false_start);
+                self.lower_block(false_block);
+                self.drop_to(slots);
+                self.finish(Terminator::Goto, &[(end, Edge::Always)]);
+
+                self.slots = slots;
+                self.switch_to(end);
+            }
+            StatementKind::Block { block } => self.lower_block(block),
+            StatementKind::Loop { block } => {
+                let start = self.new_block();
+                let end = self.new_block();
+
+                self.finish(Terminator::Goto, &[(start, Edge::Always)]);
+
+                self.switch_to(start);
+                self.loops.push(LoopTargets {
+                    start,
+                    end,
+                    slots: self.slots,
+                });
+                self.lower_block(block);
+                self.loops.pop();
+                self.finish(Terminator::Goto, &[(start, Edge::Always)]);
+
+                self.switch_to(end);
+            }
+            StatementKind::Break | StatementKind::Continue => {
+                let loop_targets = self
+                    .loops
+                    .last()
+                    .expect("Should've checked that this is in a loop");
+                let target = if matches!(kind, StatementKind::Break) {
+                    loop_targets.end
+                } else {
+                    loop_targets.start
+                };
+
+                self.drop_to(loop_targets.slots);
+                self.finish(Terminator::Goto, &[(target, Edge::Always)]);
+                // anything after this is unreachable, but still needs somewhere to go
+                let next = self.new_block();
+                self.switch_to(next);
+            }
+            StatementKind::Return { values } => {
+                let exit = self.exit;
+                let terminator = if meta.has::<TailCall>() {
+                    let [Expression {
+                        kind: ExpressionKind::Call { arguments, .. },
+                        meta: call_meta,
+                        ..
+                    }] = values.as_mut_slice()
+                    else {
+                        panic!("Tail calls should only return a single call");
+                    };
+
+                    Terminator::TailCall {
+                        function: call_meta.get().copied(),
+                        arguments,
+                    }
+                } else {
+                    Terminator::Return { values }
+                };
+
+                self.finish(terminator, &[(exit, Edge::Always)]);
+                let next = self.new_block();
+                self.switch_to(next);
+            }
+        }
+    }
+}
+
+impl Display for Cfg<'_, '_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for node in self.graph.node_indices() {
+            let block = &self.graph[node];
+
+            let name = if node == self.entry {
+                " (entry)"
+            } else if node == self.exit {
+                " (exit)"
+            } else {
+                ""
+            };
+            writeln!(f, "bb{}{name}:", node.index())?;
+
+            for instruction in &block.instructions {
+                write!(f, "    ")?;
+                match instruction {
+                    Instruction::Assign { target, value, .. } => {
+                        write!(
+                            f,
+                            "{} = {}",
+                            DisplaySymbol(*target),
+                            DisplayExpression(value)
+                        )?;
+                    }
+                    Instruction::Call {
+                        function,
+                        arguments,
+                    } => {
+                        write!(
+                            f,
+                            "call {}({})",
+                            DisplayFunction(*function),
+                            DisplayList(arguments)
+                        )?;
+                    }
+                    Instruction::Spawn {
+                        group,
+                        function,
+                        arguments,
+                    } => {
+                        write!(f, "spawn ")?;
+                        if let Some(group) = group {
+                            write!(f, "{}, ", DisplayExpression(group))?;
+                        }
+                        write!(
+                            f,
+                            "{}({})",
+                            DisplayFunction(*function),
+                            DisplayList(arguments)
+                        )?;
+                    }
+                    Instruction::Trigger { arguments, .. } => {
+                        write!(f, "trigger({})", DisplayList(arguments))?;
+                    }
+                    Instruction::Send {
+                        target, arguments, ..
+                    } => {
+                        write!(
+                            f,
+                            "send {}, ({})",
+                            DisplayExpression(target),
+                            DisplayList(arguments)
+                        )?;
+                    }
+                    Instruction::ControlGroup { group, control } => match control {
+                        GroupControl::Pause => write!(f, "pause {}", DisplayExpression(group))?,
+                        GroupControl::Resume => write!(f, "resume {}", DisplayExpression(group))?,
+                        GroupControl::TimeScale { frames } => write!(
+                            f,
+                            "timescale {}, {}",
+                            DisplayExpression(group),
+                            DisplayExpression(frames)
+                        )?,
+                    },
+                    Instruction::EndScope { slots } => write!(f, "end scope, keeping {slots}")?,
+                }
+                writeln!(f)?;
+            }
+
+            let successor = |edge: Edge| self.successor(node, edge).index();
+
+            write!(f, "    ")?;
+            match &block.terminator {
+                Terminator::Goto => write!(f, "goto bb{}", successor(Edge::Always))?,
+                Terminator::Branch { condition } => write!(
+                    f,
+                    "branch {} ? bb{} : bb{}",
+                    DisplayExpression(condition),
+                    successor(Edge::True),
+                    successor(Edge::False)
+                )?,
+                Terminator::Yield { value: None } => {
+                    write!(f, "yield, resume bb{}", successor(Edge::Always))?
+                }
+                Terminator::Yield { value: Some(value) } => write!(
+                    f,
+                    "yield {}, resume bb{}",
+                    DisplayExpression(value),
+                    successor(Edge::Always)
+                )?,
+                Terminator::Return { values } => write!(f, "return {}", DisplayList(values))?,
+                Terminator::TailCall {
+                    function,
+                    arguments,
+                } => write!(
+                    f,
+                    "tail call {}({})",
+                    DisplayFunction(*function),
+                    DisplayList(arguments)
+                )?,
+                Terminator::Exit => write!(f, "exit")?,
+            }
+            writeln!(f)?;
+        }
+
+        Ok(())
+    }
+}
+
+struct DisplayExpression<'a, 'input>(&'a Expression<'input>);
+
+impl Display for DisplayExpression<'_, '_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let expression = self.0;
+        match &expression.kind {
+            ExpressionKind::Integer(i) => write!(f, "{i}"),
+            ExpressionKind::Fix(num) => write!(f, "{num}"),
+            ExpressionKind::Bool(b) => write!(f, "{b}"),
+            ExpressionKind::Variable(_) => {
+                write!(f, "{}", DisplaySymbol(expression.meta.get().copied()))
+            }
+            ExpressionKind::BinaryOperation { lhs, operator, rhs } => write!(
+                f,
+                "({} {operator} {})",
+                DisplayExpression(lhs),
+                DisplayExpression(rhs)
+            ),
+            ExpressionKind::Call { arguments, .. } => write!(
+                f,
+                "{}({})",
+                DisplayFunction(expression.meta.get().copied()),
+                DisplayList(arguments)
+            ),
+            ExpressionKind::Error => write!(f, "ERROR"),
+            ExpressionKind::Nop => write!(f, "NOP"),
+        }
+    }
+}
+
+struct DisplaySymbol(Option<SymbolId>);
+
+impl Display for DisplaySymbol {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.0 {
+            Some(symbol_id) => write!(f, "%{}", symbol_id.0),
+            None => write!(f, "%?"),
+        }
+    }
+}
+
+struct DisplayFunction(Option<FunctionId>);
+
+impl Display for DisplayFunction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.0 {
+            Some(function_id) => write!(f, "fn{}", function_id.0),
+            None => write!(f, "fn?"),
+        }
+    }
+}
+
+struct DisplayList<'a, 'input>(&'a [Expression<'input>]);
+
+impl Display for DisplayList<'_, '_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, expression) in self.0.iter().enumerate() {
+            if i != 0 {
+                write!(f, ", ")?;
+            }
+
+            write!(f, "{}", DisplayExpression(expression))?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{fmt::Write, fs};
+
+    use insta::{assert_snapshot, glob};
+
+    use crate::{
+        compile::{
+            loop_visitor::visit_loop_check, symtab_visitor::SymTabVisitor,
+            type_visitor::TypeVisitor,
+        },
+        grammar,
+        lexer::Lexer,
+        reporting::Diagnostics,
+        tokens::FileId,
+        CompileSettings, Property, PropertyAccess, Type,
+    };
+
+    use super::*;
+
+    #[test]
+    fn cfg_snapshot_tests() {
+        glob!("snapshot_tests", "cfg/*.tapir", |path| {
+            let input = fs::read_to_string(path).unwrap();
+
+            let lexer = Lexer::new(&input, FileId::new(0));
+            let parser = grammar::ScriptParser::new();
+            let file_id = FileId::new(0);
+
+            let mut diagnostics = Diagnostics::new(file_id, path.file_name().unwrap(), &input);
+
+            let mut script = parser
+                .parse(
+                    file_id,
+                    CompileSettings::DEFAULT_FIX_PRECISION,
+                    &mut diagnostics,
+                    lexer,
+                )
+                .unwrap();
+
+            let compile_settings = CompileSettings {
+                properties: vec![Property {
+                    ty: Type::Int,
+                    index: 0,
+                    name: "int_prop".to_owned(),
+                    access: PropertyAccess::ReadWrite,
+                }],
+                enable_optimisations: true,
+                fix_precision: CompileSettings::DEFAULT_FIX_PRECISION,
+            };
+
+            let mut symtab_visitor =
+                SymTabVisitor::new(&compile_settings, &mut script.functions, &mut diagnostics);
+            let mut type_visitor = TypeVisitor::new(&compile_settings, &script.functions);
+
+            let mut output = String::new();
+            for function in &mut script.functions {
+                visit_loop_check(function, &mut diagnostics);
+                symtab_visitor.visit_function(function, &mut diagnostics);
+                type_visitor.visit_function(
+                    function,
+                    symtab_visitor.get_symtab(),
+                    &mut diagnostics,
+                );
+
+                writeln!(&mut output, "fn {}:", function.name).unwrap();
+                write!(&mut output, "{}", Cfg::new(function)).unwrap();
+                writeln!(&mut output).unwrap();
+            }
+
+            assert_snapshot!(output);
+        });
+    }
+}
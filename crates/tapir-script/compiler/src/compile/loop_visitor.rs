@@ -29,8 +29,11 @@ fn visit_block_in_loop(
             | StatementKind::VariableDeclaration { .. }
             | StatementKind::Assignment { .. }
             | StatementKind::Wait
+            | StatementKind::Yield { .. }
             | StatementKind::Nop
             | StatementKind::Trigger { .. }
+            | StatementKind::Send { .. }
+            | StatementKind::ControlGroup { .. }
             | StatementKind::Call { .. }
             | StatementKind::Spawn { .. } => {}
             StatementKind::Return { .. } => {
@@ -87,7 +90,7 @@ mod test {
 
     use insta::{assert_ron_snapshot, assert_snapshot, glob};
 
-    use crate::{grammar, lexer::Lexer, tokens::FileId};
+    use crate::{grammar, lexer::Lexer, tokens::FileId, CompileSettings};
 
     use super::*;
 
@@ -102,7 +105,14 @@ mod test {
 
             let mut diagnostics = Diagnostics::new(file_id, path.file_name().unwrap(), &input);
 
-            let mut script = parser.parse(file_id, &mut diagnostics, lexer).unwrap();
+            let mut script = parser
+                .parse(
+                    file_id,
+                    CompileSettings::DEFAULT_FIX_PRECISION,
+                    &mut diagnostics,
+                    lexer,
+                )
+                .unwrap();
 
             for function in &mut script.functions {
                 visit_loop_check(function, &mut diagnostics);
@@ -125,7 +135,14 @@ mod test {
 
             let mut diagnostics = Diagnostics::new(file_id, path.file_name().unwrap(), &input);
 
-            let mut script = parser.parse(file_id, &mut diagnostics, lexer).unwrap();
+            let mut script = parser
+                .parse(
+                    file_id,
+                    CompileSettings::DEFAULT_FIX_PRECISION,
+                    &mut diagnostics,
+                    lexer,
+                )
+                .unwrap();
 
             for function in &mut script.functions {
                 visit_loop_check(function, &mut diagnostics);
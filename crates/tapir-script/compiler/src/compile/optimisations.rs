@@ -1,26 +1,38 @@
+mod common_subexpression_visitor;
 mod constant_folding_visitor;
 mod constant_propagation_visitor;
 mod dead_code_elimination_visitor;
+mod inline_visitor;
+mod loop_invariant_visitor;
+mod strength_reduction_visitor;
 mod unused_function_visitor;
 
 use std::ops::{BitOr, BitOrAssign};
 
-use constant_folding_visitor::constant_fold;
+use common_subexpression_visitor::eliminate_common_subexpressions;
+use constant_folding_visitor::{constant_fold, report_divide_by_zero};
 use constant_propagation_visitor::constant_propagation;
 use dead_code_elimination_visitor::dead_code_eliminate;
+use inline_visitor::inline_functions;
+use loop_invariant_visitor::hoist_loop_invariants;
+use strength_reduction_visitor::strength_reduce;
 use unused_function_visitor::unused_function_visitor;
 
-pub(super) use unused_function_visitor::UnusedFunction;
+pub(super) use unused_function_visitor::{reachable_functions, UnusedFunction};
 
 use crate::{
-    ast::{ExpressionKind, Function},
+    ast::{Expression, ExpressionKind, Fix, Function, SymbolId},
     reporting::Diagnostics,
 };
 
 use super::CompileSettings;
 
+/// `symbol_count` is the number of symbols in the symbol table. Any variables the optimisations
+/// create get ids after these, so they can't clash with a property or another variable, even one
+/// which the functions never mention.
 pub fn optimise(
     functions: &mut [Function],
+    symbol_count: usize,
     compile_settings: &CompileSettings,
     diagnostics: &mut Diagnostics,
 ) {
@@ -28,8 +40,13 @@ pub fn optimise(
         return;
     }
 
+    let mut next_symbol = symbol_count;
+
     loop {
-        let mut did_something = unused_function_visitor(functions);
+        // inlining first means that constants can flow through the inlined bodies, and that
+        // functions which are no longer called can be removed
+        let mut did_something = inline_functions(functions, compile_settings, &mut next_symbol)
+            | unused_function_visitor(functions);
 
         for function in functions.iter_mut() {
             if function.meta.has::<UnusedFunction>() {
@@ -37,18 +54,66 @@ pub fn optimise(
             }
 
             while constant_propagation(function, compile_settings)
-                | constant_fold(function, diagnostics)
+                | constant_fold(function)
                 | dead_code_eliminate(function, compile_settings)
                 == ConstantOptimisationResult::DidSomething
             {
                 did_something = ConstantOptimisationResult::DidSomething;
             }
+
+            // after the constant optimisations so they don't hide constants from them
+            did_something |= hoist_loop_invariants(function, compile_settings, &mut next_symbol);
+            did_something |= strength_reduce(function);
+            did_something |=
+                eliminate_common_subexpressions(function, compile_settings, &mut next_symbol);
         }
 
         if did_something == ConstantOptimisationResult::DidNothing {
             break;
         }
     }
+
+    // only now that the code which never runs has been removed
+    for function in functions.iter() {
+        if !function.meta.has::<UnusedFunction>() {
+            report_divide_by_zero(function, diagnostics);
+        }
+    }
+}
+
+fn has_call(expression: &Expression) -> bool {
+    expression
+        .all_inner()
+        .any(|expression| matches!(expression.kind, ExpressionKind::Call { .. }))
+}
+
+/// Whether both expressions always calculate the same value, assuming no variables change
+fn is_same_expression(a: &Expression, b: &Expression) -> bool {
+    match (&a.kind, &b.kind) {
+        (ExpressionKind::Integer(a), ExpressionKind::Integer(b)) => a == b,
+        (ExpressionKind::Fix(a), ExpressionKind::Fix(b)) => a == b,
+        (ExpressionKind::Bool(a), ExpressionKind::Bool(b)) => a == b,
+        (ExpressionKind::Variable(_), ExpressionKind::Variable(_)) => {
+            a.meta.get::<SymbolId>() == b.meta.get::<SymbolId>()
+        }
+        (
+            ExpressionKind::BinaryOperation {
+                lhs: a_lhs,
+                operator: a_operator,
+                rhs: a_rhs,
+            },
+            ExpressionKind::BinaryOperation {
+                lhs: b_lhs,
+                operator: b_operator,
+                rhs: b_rhs,
+            },
+        ) => {
+            a_operator == b_operator
+                && is_same_expression(a_lhs, b_lhs)
+                && is_same_expression(a_rhs, b_rhs)
+        }
+        _ => false,
+    }
 }
 
 #[derive(Clone, Copy, PartialEq, Eq)]
@@ -77,10 +142,10 @@ impl BitOr for ConstantOptimisationResult {
     }
 }
 
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, Debug, PartialEq)]
 enum Constant {
     Int(i32),
-    Fix(agb_fixnum::Num<i32, 8>),
+    Fix(Fix),
     Bool(bool),
 }
 
@@ -141,11 +206,19 @@ mod test {
 
                 let mut diagnostics = Diagnostics::new(file_id, path.file_name().unwrap(), &input);
 
-                let mut script = parser.parse(file_id, &mut diagnostics, lexer).unwrap();
+                let mut script = parser
+                    .parse(
+                        file_id,
+                        CompileSettings::DEFAULT_FIX_PRECISION,
+                        &mut diagnostics,
+                        lexer,
+                    )
+                    .unwrap();
 
                 let compile_settings = CompileSettings {
                     properties: Vec::new(),
                     enable_optimisations: true,
+                    fix_precision: CompileSettings::DEFAULT_FIX_PRECISION,
                 };
 
                 let mut symtab_visitor =
@@ -162,7 +235,12 @@ mod test {
                     );
                 }
 
-                optimise(&mut script.functions, &compile_settings, &mut diagnostics);
+                optimise(
+                    &mut script.functions,
+                    symtab_visitor.get_symtab().symbol_count(),
+                    &compile_settings,
+                    &mut diagnostics,
+                );
 
                 let pretty_printed = script.pretty_print();
 
@@ -0,0 +1,332 @@
+use crate::{
+    ast::{Expression, ExpressionKind, Function, Metadata, Statement, StatementKind, SymbolId},
+    CompileSettings,
+};
+
+use super::{has_call, is_same_expression, ConstantOptimisationResult};
+
+/// Reuses the value of a pure expression or property load which gets calculated more than once
+/// in the same basic block by storing it in a new variable. Each call handles at most one
+/// expression per basic block, so should be run until it stops doing anything.
+pub fn eliminate_common_subexpressions(
+    function: &mut Function,
+    compile_settings: &CompileSettings,
+    next_symbol: &mut usize,
+) -> ConstantOptimisationResult {
+    eliminate_in_block(&mut function.statements, compile_settings, next_symbol)
+}
+
+fn eliminate_in_block(
+    block: &mut Vec<Statement>,
+    compile_settings: &CompileSettings,
+    next_symbol: &mut usize,
+) -> ConstantOptimisationResult {
+    let mut result = ConstantOptimisationResult::DidNothing;
+
+    for statement in block.iter_mut() {
+        match &mut statement.kind {
+            StatementKind::If {
+                true_block,
+                false_block,
+                ..
+            } => {
+                result |= eliminate_in_block(true_block, compile_settings, next_symbol);
+                result |= eliminate_in_block(false_block, compile_settings, next_symbol);
+            }
+            StatementKind::Block { block } | StatementKind::Loop { block } => {
+                result |= eliminate_in_block(block, compile_settings, next_symbol);
+            }
+            _ => {}
+        }
+    }
+
+    let mut start = 0;
+    while start < block.len() {
+        let mut end = basic_block_end(block, start);
+
+        if let Some(candidate) = find_candidate(&block[start..end], compile_settings) {
+            let symbol_id = SymbolId(*next_symbol);
+            *next_symbol += 1;
+
+            let first = start + candidate.first;
+            replace_occurrences(&mut block[first..end], &candidate, symbol_id);
+
+            let mut meta = Metadata::new();
+            meta.set(symbol_id);
+
+            block.insert(
+                first,
+                Statement {
+                    span: candidate.expression.span,
+                    kind: StatementKind::VariableDeclaration {
+                        ident: "@common",
+                        value: candidate.expression,
+                    },
+                    meta,
+                },
+            );
+
+            end += 1;
+            result = ConstantOptimisationResult::DidSomething;
+        }
+
+        start = end;
+    }
+
+    result
+}
+
+/// The index after the last statement which is in the same basic block as `start`. Waits and
+/// calls don't end a basic block here, but they do invalidate any property reads.
+fn basic_block_end(block: &[Statement], start: usize) -> usize {
+    for (i, statement) in block.iter().enumerate().skip(start) {
+        match statement.kind {
+            // the condition of an if is evaluated as part of this basic block
+            StatementKind::If { .. }
+            | StatementKind::Return { .. }
+            | StatementKind::Break
+            | StatementKind::Continue => return i + 1,
+            // blocks and loops contain their own basic blocks
+            StatementKind::Block { .. } | StatementKind::Loop { .. } => return i.max(start + 1),
+            _ => {}
+        }
+    }
+
+    block.len()
+}
+
+struct Candidate<'input> {
+    expression: Expression<'input>,
+    /// The statement which contains the first use of this expression
+    first: usize,
+    reads_property: bool,
+    symbols: Vec<SymbolId>,
+}
+
+struct AvailableExpression<'input> {
+    candidate: Candidate<'input>,
+    uses: usize,
+    is_valid: bool,
+}
+
+fn find_candidate<'input>(
+    basic_block: &[Statement<'input>],
+    compile_settings: &CompileSettings,
+) -> Option<Candidate<'input>> {
+    let mut available: Vec<AvailableExpression> = vec![];
+
+    for (i, statement) in basic_block.iter().enumerate() {
+        // anything in a statement with a call could be evaluated after the call changes a property,
+        // so these are left alone entirely
+        let statement_has_call = statement.direct_expressions().any(has_call);
+
+        if !statement_has_call {
+            for expression in statement.direct_expressions() {
+                count_uses(expression, i, &mut available, compile_settings);
+            }
+        }
+
+        for expression in &mut available {
+            if invalidated_by(statement, &expression.candidate) {
+                expression.is_valid = false;
+            }
+        }
+    }
+
+    available
+        .into_iter()
+        .find(|expression| expression.uses > 1)
+        .map(|expression| expression.candidate)
+}
+
+fn count_uses<'input>(
+    expression: &Expression<'input>,
+    statement_index: usize,
+    available: &mut Vec<AvailableExpression<'input>>,
+    compile_settings: &CompileSettings,
+) {
+    if is_candidate(expression, compile_settings) {
+        if let Some(existing) = available.iter_mut().find(|existing| {
+            existing.is_valid && is_same_expression(&existing.candidate.expression, expression)
+        }) {
+            // the inner expressions will get replaced along with this one, so don't count them
+            existing.uses += 1;
+            return;
+        }
+
+        let symbols: Vec<SymbolId> = expression
+            .all_inner()
+            .filter(|inner| matches!(inner.kind, ExpressionKind::Variable(_)))
+            .map(|inner| *inner.meta.get().unwrap())
+            .collect();
+
+        available.push(AvailableExpression {
+            candidate: Candidate {
+                expression: expression.clone(),
+                first: statement_index,
+                reads_property: symbols
+                    .iter()
+                    .any(|&symbol_id| compile_settings.is_property(symbol_id)),
+                symbols,
+            },
+            uses: 1,
+            is_valid: true,
+        });
+    }
+
+    if let ExpressionKind::BinaryOperation { lhs, rhs, .. } = &expression.kind {
+        count_uses(lhs, statement_index, available, compile_settings);
+        count_uses(rhs, statement_index, available, compile_settings);
+    }
+}
+
+/// Local variables and constants are already as cheap as reading the new variable would be
+fn is_candidate(expression: &Expression, compile_settings: &CompileSettings) -> bool {
+    match &expression.kind {
+        ExpressionKind::Variable(_) => {
+            compile_settings.is_property(*expression.meta.get().unwrap())
+        }
+        ExpressionKind::BinaryOperation { .. } => !has_call(expression),
+        _ => false,
+    }
+}
+
+/// Whether the value of the candidate could be different after this statement has run
+fn invalidated_by(statement: &Statement, candidate: &Candidate) -> bool {
+    let assigns_to_candidate = matches!(statement.kind, StatementKind::Assignment { .. })
+        && candidate
+            .symbols
+            .contains(statement.meta.get().expect("Should've resolved variable"));
+
+    // much like constant propagation, properties could change across waits, yields and calls
+    let could_change_properties = matches!(
+        statement.kind,
+        StatementKind::Wait
+            | StatementKind::Yield { .. }
+            | StatementKind::Call { .. }
+            | StatementKind::Spawn { .. }
+    ) || statement.direct_expressions().any(has_call);
+
+    assigns_to_candidate || (candidate.reads_property && could_change_properties)
+}
+
+fn replace_occurrences(basic_block: &mut [Statement], candidate: &Candidate, symbol_id: SymbolId) {
+    for statement in basic_block {
+        if !statement.direct_expressions().any(has_call) {
+            for expression in statement.direct_expressions_mut() {
+                replace_in_expression(expression, candidate, symbol_id);
+            }
+        }
+
+        if invalidated_by(statement, candidate) {
+            break;
+        }
+    }
+}
+
+fn replace_in_expression(expression: &mut Expression, candidate: &Candidate, symbol_id: SymbolId) {
+    if is_same_expression(expression, &candidate.expression) {
+        let mut meta = Metadata::new();
+        meta.set(symbol_id);
+
+        *expression = Expression {
+            span: expression.span,
+            kind: ExpressionKind::Variable("@common"),
+            meta,
+        };
+
+        return;
+    }
+
+    if let ExpressionKind::BinaryOperation { lhs, rhs, .. } = &mut expression.kind {
+        replace_in_expression(lhs, candidate, symbol_id);
+        replace_in_expression(rhs, candidate, symbol_id);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::fs;
+
+    use insta::{assert_snapshot, glob};
+
+    use crate::{
+        compile::{
+            loop_visitor::visit_loop_check, symtab_visitor::SymTabVisitor,
+            type_visitor::TypeVisitor,
+        },
+        grammar,
+        lexer::Lexer,
+        reporting::Diagnostics,
+        tokens::FileId,
+        CompileSettings, Property, PropertyAccess, Type,
+    };
+
+    use super::*;
+
+    #[test]
+    fn common_subexpression_snapshot_tests() {
+        glob!("snapshot_tests", "common_subexpression/*.tapir", |path| {
+            let input = fs::read_to_string(path).unwrap();
+
+            let lexer = Lexer::new(&input, FileId::new(0));
+            let parser = grammar::ScriptParser::new();
+            let file_id = FileId::new(0);
+
+            let mut diagnostics = Diagnostics::new(file_id, path.file_name().unwrap(), &input);
+
+            let mut script = parser
+                .parse(
+                    file_id,
+                    CompileSettings::DEFAULT_FIX_PRECISION,
+                    &mut diagnostics,
+                    lexer,
+                )
+                .unwrap();
+
+            let compile_settings = CompileSettings {
+                properties: vec![
+                    Property {
+                        ty: Type::Int,
+                        index: 0,
+                        name: "int_prop".to_owned(),
+                        access: PropertyAccess::ReadWrite,
+                    },
+                    Property {
+                        ty: Type::Int,
+                        index: 1,
+                        name: "other_prop".to_owned(),
+                        access: PropertyAccess::ReadWrite,
+                    },
+                ],
+                enable_optimisations: true,
+                fix_precision: CompileSettings::DEFAULT_FIX_PRECISION,
+            };
+
+            let mut symtab_visitor =
+                SymTabVisitor::new(&compile_settings, &mut script.functions, &mut diagnostics);
+            let mut type_visitor = TypeVisitor::new(&compile_settings, &script.functions);
+
+            for function in &mut script.functions {
+                visit_loop_check(function, &mut diagnostics);
+                symtab_visitor.visit_function(function, &mut diagnostics);
+                type_visitor.visit_function(
+                    function,
+                    symtab_visitor.get_symtab(),
+                    &mut diagnostics,
+                );
+            }
+
+            let mut next_symbol = symtab_visitor.get_symtab().symbol_count();
+            for function in &mut script.functions {
+                while eliminate_common_subexpressions(function, &compile_settings, &mut next_symbol)
+                    == ConstantOptimisationResult::DidSomething
+                {}
+            }
+
+            let pretty_printed = script.pretty_print();
+
+            assert_snapshot!(pretty_printed);
+        });
+    }
+}
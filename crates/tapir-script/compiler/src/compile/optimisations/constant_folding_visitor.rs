@@ -1,32 +1,83 @@
 use std::{mem, ops::BitOr};
 
 use crate::{
-    ast::{BinaryOperator, Expression, ExpressionKind, Function},
+    ast::{BinaryOperator, Expression, ExpressionKind, Fix, Function, Statement, StatementKind},
     reporting::{CompilerErrorKind, Diagnostics},
 };
 
 use super::ConstantOptimisationResult;
 
-pub fn constant_fold(
-    function: &mut Function,
-    diagnostics: &mut Diagnostics,
-) -> ConstantOptimisationResult {
+pub fn constant_fold(function: &mut Function) -> ConstantOptimisationResult {
     function
         .statements
         .iter_mut()
         .flat_map(|statement| statement.expressions_mut())
-        .map(|expr| fold(expr, diagnostics))
+        .map(fold)
         .reduce(BitOr::bitor)
         .unwrap_or(ConstantOptimisationResult::DidNothing)
 }
 
+/// Dividing by zero is left alone by folding, and only reported once optimising has finished.
+/// Otherwise constants propagated into code which never runs, like after a `break` which is
+/// always taken, would be errors.
+pub fn report_divide_by_zero(function: &Function, diagnostics: &mut Diagnostics) {
+    report_divide_by_zero_in_block(&function.statements, diagnostics);
+}
+
+fn report_divide_by_zero_in_block(block: &[Statement], diagnostics: &mut Diagnostics) {
+    for statement in block {
+        for expression in statement
+            .direct_expressions()
+            .flat_map(|expression| expression.all_inner())
+        {
+            if let ExpressionKind::BinaryOperation { operator, rhs, .. } = &expression.kind {
+                if is_division(*operator) && is_zero(rhs) {
+                    diagnostics.add_message(CompilerErrorKind::DivideByZero.into_message(rhs.span));
+                }
+            }
+        }
+
+        match &statement.kind {
+            StatementKind::If {
+                true_block,
+                false_block,
+                ..
+            } => {
+                report_divide_by_zero_in_block(true_block, diagnostics);
+                report_divide_by_zero_in_block(false_block, diagnostics);
+            }
+            StatementKind::Block { block } | StatementKind::Loop { block } => {
+                report_divide_by_zero_in_block(block, diagnostics);
+            }
+            _ => {}
+        }
+    }
+}
+
+fn is_division(operator: BinaryOperator) -> bool {
+    use BinaryOperator as B;
+
+    matches!(
+        operator,
+        B::Div | B::RealDiv | B::Mod | B::RealMod | B::FixDiv
+    )
+}
+
+fn is_zero(expression: &Expression) -> bool {
+    match expression.kind {
+        ExpressionKind::Integer(i) => i == 0,
+        ExpressionKind::Fix(n) => n.is_zero(),
+        _ => false,
+    }
+}
+
 #[rustfmt::skip]
-fn fold(exp: &mut Expression, diagnostics: &mut Diagnostics) -> ConstantOptimisationResult {
+fn fold(exp: &mut Expression) -> ConstantOptimisationResult {
     let ExpressionKind::BinaryOperation { lhs, operator, rhs } = &mut exp.kind else {
         return ConstantOptimisationResult::DidNothing;
     };
 
-    let did_something = fold(lhs, diagnostics) | fold(rhs, diagnostics);
+    let did_something = fold(lhs) | fold(rhs);
 
     use BinaryOperator as B;
     use ExpressionKind as E;
@@ -60,8 +111,8 @@ fn fold(exp: &mut Expression, diagnostics: &mut Diagnostics) -> ConstantOptimisa
         (E::Integer(lhs), B::Add,              E::Integer(rhs)) => E::Integer(lhs + rhs),
         (E::Integer(lhs), B::Sub,              E::Integer(rhs)) => E::Integer(lhs - rhs),
         (E::Integer(lhs), B::Mul,              E::Integer(rhs)) => E::Integer(lhs * rhs),
-        (E::Integer(lhs), B::Div | B::RealDiv, E::Integer(rhs)) => E::Integer(lhs / rhs), // FIXME: div_floor
-        (E::Integer(lhs), B::Mod | B::RealMod, E::Integer(rhs)) => E::Integer(lhs.rem_euclid(rhs)),
+        (E::Integer(lhs), B::Div | B::RealDiv, E::Integer(rhs)) if rhs != 0 => E::Integer(lhs / rhs), // FIXME: div_floor
+        (E::Integer(lhs), B::Mod | B::RealMod, E::Integer(rhs)) if rhs != 0 => E::Integer(lhs.rem_euclid(rhs)),
 
         // ===================
         // Integer comparisons
@@ -89,7 +140,15 @@ fn fold(exp: &mut Expression, diagnostics: &mut Diagnostics) -> ConstantOptimisa
         (E::Fix(lhs), B::Add,    E::Fix(rhs)) => E::Fix(lhs + rhs),
         (E::Fix(lhs), B::Sub,    E::Fix(rhs)) => E::Fix(lhs - rhs),
         (E::Fix(lhs), B::FixMul, E::Fix(rhs)) => E::Fix(lhs * rhs),
-        (E::Fix(lhs), B::FixDiv, E::Fix(rhs)) => E::Fix(lhs / rhs),
+        (E::Fix(lhs), B::FixDiv, E::Fix(rhs)) if !rhs.is_zero() => E::Fix(lhs / rhs),
+
+        // ========================================
+        // Shifts left behind by strength reduction
+        // ========================================
+        (E::Integer(lhs), B::ShiftLeft,  E::Integer(rhs)) => E::Integer(lhs << rhs),
+        (E::Integer(lhs), B::ShiftRight, E::Integer(rhs)) => E::Integer(lhs >> rhs),
+        (E::Fix(lhs),     B::ShiftLeft,  E::Integer(rhs)) => E::Fix(Fix::from_raw(lhs.to_raw() << rhs, lhs.precision())),
+        (E::Fix(lhs),     B::ShiftRight, E::Integer(rhs)) => E::Fix(Fix::from_raw(lhs.to_raw() >> rhs, lhs.precision())),
 
         // ===================
         // add / subtract zero
@@ -97,35 +156,23 @@ fn fold(exp: &mut Expression, diagnostics: &mut Diagnostics) -> ConstantOptimisa
         (E::Integer(0), B::Add | B::Sub, any) => take_side!(rhs, any),
         (any, B::Add | B::Sub, E::Integer(0)) => take_side!(lhs, any),
 
-        (any, B::Add | B::Sub, E::Fix(n)) if n == 0.into() => take_side!(lhs, any),
-        (E::Fix(n), B::Add | B::Sub, any) if n == 0.into() => take_side!(rhs, any),
+        (any, B::Add | B::Sub, E::Fix(n)) if n.is_zero() => take_side!(lhs, any),
+        (E::Fix(n), B::Add | B::Sub, any) if n.is_zero() => take_side!(rhs, any),
 
         // ==============================================
         // mulitply by zero
         // (5 + foo(x)) * 0 -> (5 + foo(x)) then 0
         // ==============================================
         (any, B::Mul, E::Integer(0)) => replace_op!(any, B::Then, E::Integer(0)),
-        (any, B::FixMul | B::Mul, E::Fix(n)) if n == 0.into() => replace_op!(any, B::Then, E::Integer(0)),
+        (any, B::FixMul | B::Mul, E::Fix(n)) if n.is_zero() => replace_op!(any, B::Then, E::Integer(0)),
 
         // ======================
         // multiply / divide by 1
         // ======================
         (any, B::Mul | B::Div | B::RealDiv, E::Integer(1)) => take_side!(lhs, any),
         (E::Integer(1), B::Mul, any) => take_side!(rhs, any),
-        (any, B::FixDiv | B::FixMul | B::RealDiv, E::Fix(n)) if n == 1.into() => take_side!(lhs, any),
-        (E::Fix(n), B::FixDiv | B::FixMul, any) if n == 1.into() => take_side!(rhs, any),
-
-        // ===========
-        // Divide by 0
-        // ===========
-        (_, B::Div | B::RealDiv | B::Mod | B::RealMod, E::Integer(0)) => {
-            diagnostics.add_message(CompilerErrorKind::DivideByZero.into_message(rhs.span));
-            E::Error
-        }
-        (_, B::Div | B::RealDiv | B::Mod | B::RealMod, E::Fix(n)) if n == 0.into() => {
-            diagnostics.add_message(CompilerErrorKind::DivideByZero.into_message(rhs.span));
-            E::Error
-        }
+        (any, B::FixDiv | B::FixMul | B::RealDiv, E::Fix(n)) if n.is_one() => take_side!(lhs, any),
+        (E::Fix(n), B::FixDiv | B::FixMul, any) if n.is_one() => take_side!(rhs, any),
 
         // ==========================
         //         Association
@@ -192,7 +239,7 @@ mod test {
         lexer::Lexer,
         reporting::Diagnostics,
         tokens::FileId,
-        CompileSettings, Property, Type,
+        CompileSettings, Property, PropertyAccess, Type,
     };
 
     use super::*;
@@ -208,7 +255,14 @@ mod test {
 
             let mut diagnostics = Diagnostics::new(file_id, path.file_name().unwrap(), &input);
 
-            let mut script = parser.parse(file_id, &mut diagnostics, lexer).unwrap();
+            let mut script = parser
+                .parse(
+                    file_id,
+                    CompileSettings::DEFAULT_FIX_PRECISION,
+                    &mut diagnostics,
+                    lexer,
+                )
+                .unwrap();
 
             let compile_settings = CompileSettings {
                 properties: vec![
@@ -216,19 +270,23 @@ mod test {
                         ty: Type::Int,
                         index: 0,
                         name: "int_prop".to_owned(),
+                        access: PropertyAccess::ReadWrite,
                     },
                     Property {
                         ty: Type::Fix,
                         index: 1,
                         name: "fix_prop".to_owned(),
+                        access: PropertyAccess::ReadWrite,
                     },
                     Property {
                         ty: Type::Bool,
                         index: 2,
                         name: "bool_prop".to_owned(),
+                        access: PropertyAccess::ReadWrite,
                     },
                 ],
                 enable_optimisations: true,
+                fix_precision: CompileSettings::DEFAULT_FIX_PRECISION,
             };
 
             let mut symtab_visitor =
@@ -244,9 +302,7 @@ mod test {
                     &mut diagnostics,
                 );
 
-                while constant_fold(function, &mut diagnostics)
-                    == ConstantOptimisationResult::DidSomething
-                {}
+                while constant_fold(function) == ConstantOptimisationResult::DidSomething {}
             }
 
             let pretty_printed = script.pretty_print();
@@ -1,153 +1,218 @@
-use std::ops::BitOr;
-
-use constant_propagation_map::ConstantPropagationMap;
+use std::collections::HashMap;
 
 use crate::{
-    ast::{Expression, ExpressionKind, Function, Statement, StatementKind},
+    ast::{Expression, ExpressionKind, Function, SymbolId},
+    compile::cfg::{BasicBlock, Cfg, ForwardAnalysis, Instruction, Terminator},
     CompileSettings,
 };
 
-use super::ConstantOptimisationResult;
-
-mod constant_propagation_map;
+use super::{has_call, Constant, ConstantOptimisationResult};
 
+/// Replaces variables with their values wherever every path to that point leaves them with the
+/// same constant value.
 pub fn constant_propagation(
     function: &mut Function,
     compile_settings: &CompileSettings,
 ) -> ConstantOptimisationResult {
-    let mut constant_symbol = ConstantPropagationMap::default();
-    constant_propagation_block(
-        &mut function.statements,
-        &mut constant_symbol,
-        compile_settings,
-    )
-}
+    let mut cfg = Cfg::new(function);
+    let analysis = ConstantPropagation { compile_settings };
+    let mut states = cfg.solve_forward(&analysis);
 
-fn constant_propagation_block(
-    block: &mut [Statement],
-    constant_symbols: &mut ConstantPropagationMap,
-    compile_settings: &CompileSettings,
-) -> ConstantOptimisationResult {
-    block
-        .iter_mut()
-        .map(|statement| match &mut statement.kind {
-            StatementKind::Error
-            | StatementKind::Continue
-            | StatementKind::Break
-            | StatementKind::Nop => ConstantOptimisationResult::DidNothing,
-            StatementKind::Wait => {
-                constant_symbols.poison_properties(compile_settings);
-
-                ConstantOptimisationResult::DidNothing
-            }
-            StatementKind::Assignment { value, .. }
-            | StatementKind::VariableDeclaration { value, .. } => {
-                let did_propagate =
-                    constant_propagation_expr(value, constant_symbols, compile_settings);
+    let mut result = ConstantOptimisationResult::DidNothing;
 
-                let symbol_id = statement.meta.get().unwrap();
-                constant_symbols.set(*symbol_id, &value.kind);
+    for (node, block) in cfg.blocks_mut() {
+        // no point propagating into unreachable code
+        let Some(constants) = states.get_mut(&node) else {
+            continue;
+        };
 
-                did_propagate
+        for instruction in &mut block.instructions {
+            for expression in instruction_expressions(instruction) {
+                result |= analysis.propagate(expression, constants);
             }
-            StatementKind::If {
-                condition,
-                true_block,
-                false_block,
-            } => {
-                let mut did_propagate =
-                    constant_propagation_expr(condition, constant_symbols, compile_settings);
-                let mut true_block_symbols = constant_symbols.snapshot();
-                did_propagate |= constant_propagation_block(
-                    true_block,
-                    &mut true_block_symbols,
-                    compile_settings,
-                );
-                let mut false_block_symbols = constant_symbols.snapshot();
-                did_propagate |= constant_propagation_block(
-                    false_block,
-                    &mut false_block_symbols,
-                    compile_settings,
-                );
 
-                constant_symbols.apply_poisons(&true_block_symbols);
-                constant_symbols.apply_poisons(&false_block_symbols);
+            analysis.apply(instruction, constants);
+        }
 
-                did_propagate
-            }
-            StatementKind::Loop { block } => {
-                let mut loop_block_symbols = ConstantPropagationMap::default();
-                let did_propagate =
-                    constant_propagation_block(block, &mut loop_block_symbols, compile_settings);
-                constant_symbols.apply_poisons(&loop_block_symbols);
-                did_propagate
-            }
-            StatementKind::Block { block } => {
-                constant_propagation_block(block, constant_symbols, compile_settings)
-            }
-            StatementKind::Call { arguments, .. } => {
-                let did_propagate = arguments
-                    .iter_mut()
-                    .map(|expr| constant_propagation_expr(expr, constant_symbols, compile_settings))
-                    .reduce(BitOr::bitor)
-                    .unwrap_or(ConstantOptimisationResult::DidNothing);
-                constant_symbols.poison_properties(compile_settings);
-                did_propagate
+        match &mut block.terminator {
+            Terminator::Branch { condition }
+            | Terminator::Yield {
+                value: Some(condition),
+            } => {
+                result |= analysis.propagate(condition, constants);
             }
-            StatementKind::Spawn {
+            Terminator::Return { values }
+            | Terminator::TailCall {
                 arguments: values, ..
+            } => {
+                for value in values.iter_mut() {
+                    result |= analysis.propagate(value, constants);
+                }
             }
-            | StatementKind::Trigger {
-                arguments: values, ..
+            Terminator::Goto | Terminator::Yield { value: None } | Terminator::Exit => {}
+        }
+    }
+
+    result
+}
+
+/// Which symbols have a known constant value. Anything missing could have any value.
+type Constants = HashMap<SymbolId, Constant>;
+
+struct ConstantPropagation<'a> {
+    compile_settings: &'a CompileSettings,
+}
+
+impl ForwardAnalysis for ConstantPropagation<'_> {
+    type State = Constants;
+
+    fn entry_state(&self) -> Self::State {
+        Constants::new()
+    }
+
+    fn join(&self, a: &Self::State, b: &Self::State) -> Self::State {
+        a.iter()
+            .filter(|&(symbol_id, value)| b.get(symbol_id) == Some(value))
+            .map(|(&symbol_id, &value)| (symbol_id, value))
+            .collect()
+    }
+
+    fn transfer(&self, block: &BasicBlock, state: &mut Self::State) {
+        for instruction in &block.instructions {
+            self.apply(instruction, state);
+        }
+
+        match &block.terminator {
+            // properties could be changed by rust code, or by a different thread
+            Terminator::Yield { .. } => self.poison_properties(state),
+            Terminator::Branch { condition } if has_call(condition) => {
+                self.poison_properties(state);
             }
-            | StatementKind::Return { values } => values
-                .iter_mut()
-                .map(|expr| constant_propagation_expr(expr, constant_symbols, compile_settings))
-                .reduce(BitOr::bitor)
-                .unwrap_or(ConstantOptimisationResult::DidNothing),
-        })
-        .reduce(BitOr::bitor)
-        .unwrap_or(ConstantOptimisationResult::DidNothing)
+            _ => {}
+        }
+    }
 }
 
-fn constant_propagation_expr(
-    expression: &mut Expression,
-    constant_symbols: &mut ConstantPropagationMap,
-    compile_settings: &CompileSettings,
-) -> ConstantOptimisationResult {
-    match &mut expression.kind {
-        ExpressionKind::Integer(_)
-        | ExpressionKind::Fix(_)
-        | ExpressionKind::Bool(_)
-        | ExpressionKind::Error
-        | ExpressionKind::Nop => ConstantOptimisationResult::DidNothing,
-        ExpressionKind::BinaryOperation {
-            ref mut lhs,
-            ref mut rhs,
-            ..
-        } => {
-            constant_propagation_expr(lhs, constant_symbols, compile_settings)
-                | constant_propagation_expr(rhs, constant_symbols, compile_settings)
+impl ConstantPropagation<'_> {
+    /// Updates the state with the effect of the instruction, assuming its expressions have
+    /// already had any constants propagated into them
+    fn apply(&self, instruction: &Instruction, state: &mut Constants) {
+        let calls_function = match instruction {
+            Instruction::Call { .. } => true,
+            Instruction::Assign { value, .. } => has_call(value),
+            Instruction::Trigger { arguments, .. } => arguments.iter().any(has_call),
+            Instruction::Spawn {
+                group, arguments, ..
+            } => group.as_deref().is_some_and(has_call) || arguments.iter().any(has_call),
+            // messages are only delivered at the next run, so can't change anything here
+            Instruction::Send {
+                target, arguments, ..
+            } => has_call(target) || arguments.iter().any(has_call),
+            // neither can changes to groups, which only take effect at the next run
+            Instruction::ControlGroup { group, control } => {
+                has_call(group) || control.frames().is_some_and(has_call)
+            }
+            Instruction::EndScope { .. } => false,
+        };
+
+        if calls_function {
+            self.poison_properties(state);
         }
-        ExpressionKind::Call { arguments, .. } => {
-            let did_propagate = arguments
-                .iter_mut()
-                .map(|expr| constant_propagation_expr(expr, constant_symbols, compile_settings))
-                .reduce(BitOr::bitor)
-                .unwrap_or(ConstantOptimisationResult::DidNothing);
-            constant_symbols.poison_properties(compile_settings);
-            did_propagate
+
+        if let Instruction::Assign {
+            target: Some(target),
+            value,
+            ..
+        } = instruction
+        {
+            match constant_value(value, state) {
+                Some(constant) => state.insert(*target, constant),
+                None => state.remove(target),
+            };
         }
-        ExpressionKind::Variable(_) => {
-            let symbol = expression.meta.get().expect("Variable should have symbol");
-            if let Some(constant) = constant_symbols.get(*symbol) {
-                expression.kind = constant.into();
-                ConstantOptimisationResult::DidSomething
-            } else {
-                ConstantOptimisationResult::DidNothing
+    }
+
+    fn propagate(
+        &self,
+        expression: &mut Expression,
+        constants: &mut Constants,
+    ) -> ConstantOptimisationResult {
+        match &mut expression.kind {
+            ExpressionKind::Integer(_)
+            | ExpressionKind::Fix(_)
+            | ExpressionKind::Bool(_)
+            | ExpressionKind::Error
+            | ExpressionKind::Nop => ConstantOptimisationResult::DidNothing,
+            ExpressionKind::BinaryOperation { lhs, rhs, .. } => {
+                self.propagate(lhs, constants) | self.propagate(rhs, constants)
+            }
+            ExpressionKind::Call { arguments, .. } => {
+                let mut result = ConstantOptimisationResult::DidNothing;
+                for argument in arguments {
+                    result |= self.propagate(argument, constants);
+                }
+
+                // the function could change any property before the rest of the expression runs
+                self.poison_properties(constants);
+                result
+            }
+            ExpressionKind::Variable(_) => {
+                // unresolved variables will already be an error
+                let constant = expression
+                    .meta
+                    .get()
+                    .and_then(|symbol_id| constants.get(symbol_id));
+                if let Some(&constant) = constant {
+                    expression.kind = constant.into();
+                    ConstantOptimisationResult::DidSomething
+                } else {
+                    ConstantOptimisationResult::DidNothing
+                }
             }
         }
     }
+
+    fn poison_properties(&self, state: &mut Constants) {
+        state.retain(|&symbol_id, _| !self.compile_settings.is_property(symbol_id));
+    }
+}
+
+fn constant_value(expression: &Expression, constants: &Constants) -> Option<Constant> {
+    match &expression.kind {
+        ExpressionKind::Variable(_) => expression
+            .meta
+            .get()
+            .and_then(|symbol_id| constants.get(symbol_id))
+            .copied(),
+        kind => kind.try_into().ok(),
+    }
+}
+
+fn instruction_expressions<'b, 'input>(
+    instruction: &'b mut Instruction<'_, 'input>,
+) -> impl Iterator<Item = &'b mut Expression<'input>> {
+    let (first, rest): (
+        Option<&'b mut Expression<'input>>,
+        &'b mut [Expression<'input>],
+    ) = match instruction {
+        Instruction::Assign { value, .. } => (Some(&mut **value), &mut []),
+        Instruction::Call { arguments, .. } | Instruction::Trigger { arguments, .. } => {
+            (None, &mut **arguments)
+        }
+        Instruction::Spawn {
+            group, arguments, ..
+        } => (group.as_deref_mut(), &mut **arguments),
+        Instruction::Send {
+            target, arguments, ..
+        } => (Some(&mut **target), &mut **arguments),
+        Instruction::ControlGroup { group, control } => (
+            Some(&mut **group),
+            control.frames_mut().map_or(&mut [], std::slice::from_mut),
+        ),
+        Instruction::EndScope { .. } => (None, &mut []),
+    };
+
+    first.into_iter().chain(rest.iter_mut())
 }
 
 #[cfg(test)]
@@ -165,7 +230,7 @@ mod test {
         lexer::Lexer,
         reporting::Diagnostics,
         tokens::FileId,
-        CompileSettings, Property, Type,
+        CompileSettings, Property, PropertyAccess, Type,
     };
 
     use super::*;
@@ -181,15 +246,24 @@ mod test {
 
             let mut diagnostics = Diagnostics::new(file_id, path.file_name().unwrap(), &input);
 
-            let mut script = parser.parse(file_id, &mut diagnostics, lexer).unwrap();
+            let mut script = parser
+                .parse(
+                    file_id,
+                    CompileSettings::DEFAULT_FIX_PRECISION,
+                    &mut diagnostics,
+                    lexer,
+                )
+                .unwrap();
 
             let compile_settings = CompileSettings {
                 properties: vec![Property {
                     ty: Type::Int,
                     index: 0,
                     name: "int_prop".to_owned(),
+                    access: PropertyAccess::ReadWrite,
                 }],
                 enable_optimisations: true,
+                fix_precision: CompileSettings::DEFAULT_FIX_PRECISION,
             };
 
             let mut symtab_visitor =
@@ -2,76 +2,18 @@ use std::collections::HashSet;
 
 use crate::{
     ast::{Expression, ExpressionKind, Function, Statement, StatementKind, SymbolId},
+    compile::cfg::{BackwardAnalysis, BasicBlock, Cfg, Instruction, Terminator},
     CompileSettings,
 };
 
-use super::ConstantOptimisationResult;
-
-#[derive(Default, Clone)]
-struct UsedAssignmentsSet {
-    used: HashSet<SymbolId>,
-    used_declaration: HashSet<SymbolId>,
-}
-
-enum DeclarationUsage {
-    ValueUsed,
-    DeclarationUsed,
-    Unused,
-}
-
-impl UsedAssignmentsSet {
-    fn poison_properties(&mut self, compile_settings: &CompileSettings) {
-        self.used.extend(compile_settings.property_symbols());
-    }
-
-    fn used(&mut self, symbol: SymbolId) {
-        self.used.insert(symbol);
-        self.used_declaration.insert(symbol);
-    }
-
-    fn remove(&mut self, symbol: SymbolId) -> bool {
-        self.used.remove(&symbol)
-    }
-
-    fn remove_declaration(&mut self, symbol: SymbolId) -> DeclarationUsage {
-        match (
-            self.used.remove(&symbol),
-            self.used_declaration.remove(&symbol),
-        ) {
-            (true, true) => DeclarationUsage::ValueUsed,
-            (true, false) => unreachable!(),
-            (false, true) => DeclarationUsage::DeclarationUsed,
-            (false, false) => DeclarationUsage::Unused,
-        }
-    }
-
-    fn combine(self, other: UsedAssignmentsSet) -> UsedAssignmentsSet {
-        UsedAssignmentsSet {
-            used: self.used.union(&other.used).copied().collect(),
-            used_declaration: self.used_declaration.union(&other.used).copied().collect(),
-        }
-    }
-
-    fn absorb(&mut self, other: UsedAssignmentsSet) {
-        self.used.extend(other.used);
-        self.used_declaration.extend(other.used_declaration);
-    }
-}
+use super::{has_call, ConstantOptimisationResult};
 
 pub fn dead_code_eliminate(
     function: &mut Function,
     compile_settings: &CompileSettings,
 ) -> ConstantOptimisationResult {
     eliminate_after_control_flow_diverge(&mut function.statements);
-
-    let mut constant_symbol = UsedAssignmentsSet::default();
-    constant_symbol.poison_properties(compile_settings);
-    annotate_dead_statements(
-        &mut function.statements,
-        &mut constant_symbol,
-        compile_settings,
-        true,
-    );
+    annotate_dead_statements(function, compile_settings);
 
     sweep_dead_statements(&mut function.statements)
         | sweep_unconditional_if(&mut function.statements)
@@ -127,6 +69,9 @@ fn sweep_unconditional_if(block: &mut Vec<Statement>) -> ConstantOptimisationRes
         | StatementKind::Call { .. }
         | StatementKind::Spawn { .. }
         | StatementKind::Trigger { .. }
+        | StatementKind::Send { .. }
+        | StatementKind::ControlGroup { .. }
+        | StatementKind::Yield { .. }
         | StatementKind::Return { .. } => true,
     });
 
@@ -150,6 +95,9 @@ fn eliminate_after_control_flow_diverge(block: &mut Vec<Statement>) -> bool {
             | StatementKind::VariableDeclaration { .. }
             | StatementKind::Call { .. }
             | StatementKind::Trigger { .. }
+            | StatementKind::Send { .. }
+            | StatementKind::ControlGroup { .. }
+            | StatementKind::Yield { .. }
             | StatementKind::Spawn { .. } => {}
             StatementKind::If {
                 true_block,
@@ -183,6 +131,9 @@ fn sweep_dead_statements(block: &mut [Statement]) -> ConstantOptimisationResult
             | StatementKind::Wait
             | StatementKind::Call { .. }
             | StatementKind::Trigger { .. }
+            | StatementKind::Send { .. }
+            | StatementKind::ControlGroup { .. }
+            | StatementKind::Yield { .. }
             | StatementKind::Spawn { .. }
             | StatementKind::Return { .. } => {}
             StatementKind::Assignment { value, .. }
@@ -219,98 +170,193 @@ fn sweep_dead_statements(block: &mut [Statement]) -> ConstantOptimisationResult
 #[derive(Debug, Clone, Copy)]
 struct DeadStatement;
 
-fn annotate_dead_statements(
-    block: &mut [Statement],
-    used_symbols: &mut UsedAssignmentsSet,
-    compile_settings: &CompileSettings,
-    mark_as_dead: bool,
-) {
-    for statement in block.iter_mut().rev() {
-        match &mut statement.kind {
-            StatementKind::Error
-            | StatementKind::Continue
-            | StatementKind::Break
-            | StatementKind::Nop => {}
-            StatementKind::Wait => {
-                used_symbols.poison_properties(compile_settings);
+/// Marks every assignment whose value is never read with [`DeadStatement`].
+fn annotate_dead_statements(function: &mut Function, compile_settings: &CompileSettings) {
+    // a `var` is where the variable gets its slot on the stack, so it has to stay as long as
+    // anything could still read the variable, even if that will read a later assignment
+    let read_anywhere = function
+        .statements
+        .iter_mut()
+        .flat_map(Statement::expressions_mut)
+        .flat_map(|expression| expression.all_inner())
+        .filter(|expression| matches!(expression.kind, ExpressionKind::Variable(_)))
+        .filter_map(|expression| expression.meta.get().copied())
+        .collect();
+
+    let mut cfg = Cfg::new(function);
+    let analysis = Liveness {
+        compile_settings,
+        read_anywhere,
+    };
+    let mut live_at_end = cfg.solve_backward(&analysis);
+
+    for (node, block) in cfg.blocks_mut() {
+        let live = live_at_end
+            .get_mut(&node)
+            .expect("Should have solved every block");
+
+        analysis.apply_terminator(&block.terminator, live);
+        for instruction in block.instructions.iter_mut().rev() {
+            if analysis.apply(instruction, live) == Assignment::Dead {
+                if let Instruction::Assign { meta, .. } = instruction {
+                    meta.set(DeadStatement);
+                }
             }
-            StatementKind::Assignment { value, .. } => {
-                let symbol = statement.meta.get().unwrap();
-                let symbol_is_used = used_symbols.remove(*symbol);
-                if !symbol_is_used {
-                    if mark_as_dead {
-                        statement.meta.set(DeadStatement);
-                    }
-                } else {
-                    dead_code_visit_expression(value, used_symbols, compile_settings);
+        }
+    }
+}
+
+/// Which symbols could be read before they are next assigned to
+type Live = HashSet<SymbolId>;
+
+struct Liveness<'a> {
+    compile_settings: &'a CompileSettings,
+    read_anywhere: HashSet<SymbolId>,
+}
+
+#[derive(PartialEq, Eq)]
+enum Assignment {
+    Dead,
+    Live,
+}
+
+impl BackwardAnalysis for Liveness<'_> {
+    type State = Live;
+
+    fn exit_state(&self) -> Self::State {
+        // whatever called the script can see the properties after it finishes
+        self.compile_settings.property_symbols().collect()
+    }
+
+    fn initial_state(&self) -> Self::State {
+        Live::new()
+    }
+
+    fn join(&self, a: &Self::State, b: &Self::State) -> Self::State {
+        a.union(b).copied().collect()
+    }
+
+    fn transfer(&self, block: &BasicBlock, state: &mut Self::State) {
+        self.apply_terminator(&block.terminator, state);
+        for instruction in block.instructions.iter().rev() {
+            self.apply(instruction, state);
+        }
+    }
+}
+
+impl Liveness<'_> {
+    /// Updates the state at the end of the terminator to the state at its start
+    fn apply_terminator(&self, terminator: &Terminator, live: &mut Live) {
+        match terminator {
+            Terminator::Goto | Terminator::Exit => {}
+            Terminator::Branch { condition } => self.read(condition, live),
+            // properties could be read by rust code, or by a different thread
+            Terminator::Yield { value } => {
+                self.read_properties(live);
+                if let Some(value) = value {
+                    self.read(value, live);
                 }
             }
-            StatementKind::VariableDeclaration { value, .. } => {
-                let symbol = statement.meta.get().unwrap();
-                let symbol_is_used = used_symbols.remove_declaration(*symbol);
-                match symbol_is_used {
-                    DeclarationUsage::ValueUsed | DeclarationUsage::DeclarationUsed => {
-                        dead_code_visit_expression(value, used_symbols, compile_settings);
-                    }
-                    DeclarationUsage::Unused => {
-                        if mark_as_dead {
-                            statement.meta.set(DeadStatement);
-                        }
-                    }
+            Terminator::Return { values } => {
+                for value in values.iter() {
+                    self.read(value, live);
                 }
             }
-            StatementKind::If {
-                true_block,
-                false_block,
-                condition,
+            Terminator::TailCall { arguments, .. } => {
+                self.read_properties(live);
+                for argument in arguments.iter() {
+                    self.read(argument, live);
+                }
+            }
+        }
+    }
+
+    /// Updates the state after the instruction to the state before it, and returns whether the
+    /// instruction is an assignment whose value is never read
+    fn apply(&self, instruction: &Instruction, live: &mut Live) -> Assignment {
+        match instruction {
+            Instruction::Assign {
+                target: Some(target),
+                value,
+                is_declaration,
+                ..
             } => {
-                let mut true_block_used_symbols = used_symbols.clone();
-                let mut false_block_used_symbols = used_symbols.clone();
-                annotate_dead_statements(
-                    true_block,
-                    &mut true_block_used_symbols,
-                    compile_settings,
-                    true,
-                );
-                annotate_dead_statements(
-                    false_block,
-                    &mut false_block_used_symbols,
-                    compile_settings,
-                    true,
-                );
-                *used_symbols = true_block_used_symbols.combine(false_block_used_symbols);
-                dead_code_visit_expression(condition, used_symbols, compile_settings);
+                let is_read_later =
+                    live.remove(target) || (*is_declaration && self.read_anywhere.contains(target));
+
+                if is_read_later {
+                    self.read(value, live);
+                    Assignment::Live
+                } else {
+                    // any calls will still be made, and they could read properties
+                    if has_call(value) {
+                        self.read(value, live);
+                    }
+                    Assignment::Dead
+                }
             }
-            StatementKind::Block { block } => {
-                annotate_dead_statements(block, used_symbols, compile_settings, true);
+            Instruction::Assign {
+                target: None,
+                value,
+                ..
+            } => {
+                self.read(value, live);
+                Assignment::Live
             }
-            StatementKind::Loop { block } => {
-                let mut analyse_existing = used_symbols.clone();
-                annotate_dead_statements(block, &mut analyse_existing, compile_settings, false);
-                used_symbols.absorb(analyse_existing);
-                let mut analyse_existing = used_symbols.clone();
-                annotate_dead_statements(block, &mut analyse_existing, compile_settings, true);
-                used_symbols.absorb(analyse_existing);
+            Instruction::Call { arguments, .. } => {
+                self.read_properties(live);
+                for argument in arguments.iter() {
+                    self.read(argument, live);
+                }
+                Assignment::Live
             }
-            StatementKind::Call { arguments, .. } => {
-                for expr in arguments {
-                    dead_code_visit_expression(expr, used_symbols, compile_settings);
+            Instruction::Spawn {
+                group, arguments, ..
+            } => {
+                for expression in group.iter().map(|group| &**group).chain(arguments.iter()) {
+                    self.read(expression, live);
                 }
-                used_symbols.poison_properties(compile_settings);
+                Assignment::Live
             }
-            StatementKind::Spawn {
-                arguments: values, ..
+            Instruction::Trigger { arguments, .. } => {
+                for argument in arguments.iter() {
+                    self.read(argument, live);
+                }
+                Assignment::Live
             }
-            | StatementKind::Trigger {
-                arguments: values, ..
+            Instruction::Send {
+                target, arguments, ..
+            } => {
+                for expression in [&**target].into_iter().chain(arguments.iter()) {
+                    self.read(expression, live);
+                }
+                Assignment::Live
             }
-            | StatementKind::Return { values } => {
-                for expr in values {
-                    dead_code_visit_expression(expr, used_symbols, compile_settings);
+            Instruction::ControlGroup { group, control } => {
+                self.read(group, live);
+                if let Some(frames) = control.frames() {
+                    self.read(frames, live);
                 }
+                Assignment::Live
+            }
+            Instruction::EndScope { .. } => Assignment::Live,
+        }
+    }
+
+    fn read(&self, expression: &Expression, live: &mut Live) {
+        for inner in expression.all_inner() {
+            match &inner.kind {
+                ExpressionKind::Variable(_) => live.extend(inner.meta.get::<SymbolId>()),
+                // the function could read any property
+                ExpressionKind::Call { .. } => self.read_properties(live),
+                _ => {}
             }
         }
     }
+
+    fn read_properties(&self, live: &mut Live) {
+        live.extend(self.compile_settings.property_symbols());
+    }
 }
 
 fn extract_side_effects<'input>(
@@ -343,36 +389,6 @@ fn extract_side_effects<'input>(
     }
 }
 
-fn dead_code_visit_expression(
-    expression: &Expression,
-    used_symbols: &mut UsedAssignmentsSet,
-    compile_settings: &CompileSettings,
-) {
-    match &expression.kind {
-        ExpressionKind::Integer(_)
-        | ExpressionKind::Fix(_)
-        | ExpressionKind::Bool(_)
-        | ExpressionKind::Error
-        | ExpressionKind::Nop => {}
-        ExpressionKind::BinaryOperation {
-            ref lhs, ref rhs, ..
-        } => {
-            dead_code_visit_expression(lhs, used_symbols, compile_settings);
-            dead_code_visit_expression(rhs, used_symbols, compile_settings);
-        }
-        ExpressionKind::Call { arguments, .. } => {
-            for expression in arguments {
-                dead_code_visit_expression(expression, used_symbols, compile_settings);
-            }
-            used_symbols.poison_properties(compile_settings);
-        }
-        ExpressionKind::Variable(_) => {
-            let symbol = expression.meta.get().unwrap();
-            used_symbols.used(*symbol);
-        }
-    }
-}
-
 #[cfg(test)]
 mod test {
     use std::fs;
@@ -388,7 +404,7 @@ mod test {
         lexer::Lexer,
         reporting::Diagnostics,
         tokens::FileId,
-        CompileSettings, Property, Type,
+        CompileSettings, Property, PropertyAccess, Type,
     };
 
     use super::*;
@@ -404,7 +420,14 @@ mod test {
 
             let mut diagnostics = Diagnostics::new(file_id, path.file_name().unwrap(), &input);
 
-            let mut script = parser.parse(file_id, &mut diagnostics, lexer).unwrap();
+            let mut script = parser
+                .parse(
+                    file_id,
+                    CompileSettings::DEFAULT_FIX_PRECISION,
+                    &mut diagnostics,
+                    lexer,
+                )
+                .unwrap();
 
             let compile_settings = CompileSettings {
                 properties: vec![
@@ -412,19 +435,23 @@ mod test {
                         ty: Type::Int,
                         index: 0,
                         name: "int_prop".to_owned(),
+                        access: PropertyAccess::ReadWrite,
                     },
                     Property {
                         ty: Type::Fix,
                         index: 1,
                         name: "fix_prop".to_owned(),
+                        access: PropertyAccess::ReadWrite,
                     },
                     Property {
                         ty: Type::Bool,
                         index: 2,
                         name: "bool_prop".to_owned(),
+                        access: PropertyAccess::ReadWrite,
                     },
                 ],
                 enable_optimisations: true,
+                fix_precision: CompileSettings::DEFAULT_FIX_PRECISION,
             };
 
             let mut symtab_visitor =
@@ -0,0 +1,540 @@
+use std::{collections::HashMap, mem};
+
+use petgraph::algo::has_path_connecting;
+
+use crate::{
+    ast::{
+        Expression, ExpressionKind, Function, FunctionId, MaybeResolved, Metadata, Statement,
+        StatementKind, SymbolId,
+    },
+    CompileSettings,
+};
+
+use super::{
+    has_call, unused_function_visitor::call_graph, ConstantOptimisationResult, UnusedFunction,
+};
+
+/// Functions bigger than this, counted in statements and expressions, are left as calls
+const MAX_INLINE_SIZE: usize = 16;
+
+/// Set on the block which replaced a call to this function
+#[derive(Debug, Clone, Copy)]
+pub struct InlinedFrom(#[allow(dead_code)] pub FunctionId);
+
+struct InlineCandidate<'input> {
+    parameters: Vec<SymbolId>,
+    body: InlineBody<'input>,
+}
+
+enum InlineBody<'input> {
+    /// A function with no return values and no early return, which can replace a call statement
+    Statements(Vec<Statement<'input>>),
+    /// A function which is just `return expr;`, which can replace a call expression
+    Expression(Expression<'input>),
+}
+
+pub fn inline_functions(
+    functions: &mut [Function],
+    compile_settings: &CompileSettings,
+    next_symbol: &mut usize,
+) -> ConstantOptimisationResult {
+    let candidates = inline_candidates(functions);
+    if candidates.is_empty() {
+        return ConstantOptimisationResult::DidNothing;
+    }
+
+    let mut inliner = Inliner {
+        candidates: &candidates,
+        compile_settings,
+        next_symbol,
+        result: ConstantOptimisationResult::DidNothing,
+    };
+
+    for function in functions.iter_mut() {
+        if function.meta.has::<UnusedFunction>() {
+            continue; // no point inlining into functions which aren't called
+        }
+
+        inliner.block(&mut function.statements);
+    }
+
+    inliner.result
+}
+
+fn inline_candidates<'input>(
+    functions: &[Function<'input>],
+) -> HashMap<FunctionId, InlineCandidate<'input>> {
+    let call_graph = call_graph(functions);
+
+    functions
+        .iter()
+        .filter_map(|function| {
+            let function_id: FunctionId = *function.meta.get().unwrap();
+
+            if function_id == FunctionId(0)
+                || function.modifiers.is_entry_point()
+                || function.meta.has::<UnusedFunction>()
+                || block_size(&function.statements) > MAX_INLINE_SIZE
+            {
+                return None;
+            }
+
+            let is_recursive = call_graph
+                .neighbors(function_id)
+                .any(|callee| has_path_connecting(&call_graph, callee, function_id, None));
+            if is_recursive {
+                return None;
+            }
+
+            let body = match (&function.statements[..], function.return_types.types.len()) {
+                (
+                    [Statement {
+                        kind: StatementKind::Return { values },
+                        ..
+                    }],
+                    1,
+                ) => InlineBody::Expression(values[0].clone()),
+                (statements, 0) => {
+                    // a trailing `return;` does nothing, but any other return would need a jump
+                    let statements = match statements {
+                        [rest @ .., Statement {
+                            kind: StatementKind::Return { .. },
+                            ..
+                        }] => rest,
+                        statements => statements,
+                    };
+
+                    if contains_return(statements) {
+                        return None;
+                    }
+
+                    InlineBody::Statements(statements.to_vec())
+                }
+                _ => return None,
+            };
+
+            let parameters = function
+                .arguments
+                .iter()
+                .map(|argument| match argument.name {
+                    MaybeResolved::Resolved(symbol_id) => symbol_id,
+                    MaybeResolved::Unresolved(_) => {
+                        panic!("Should have been resolved by the symbol visitor")
+                    }
+                })
+                .collect();
+
+            Some((function_id, InlineCandidate { parameters, body }))
+        })
+        .collect()
+}
+
+struct Inliner<'a, 'input> {
+    candidates: &'a HashMap<FunctionId, InlineCandidate<'input>>,
+    compile_settings: &'a CompileSettings,
+    next_symbol: &'a mut usize,
+    result: ConstantOptimisationResult,
+}
+
+impl<'input> Inliner<'_, 'input> {
+    fn block(&mut self, block: &mut [Statement<'input>]) {
+        for statement in block {
+            self.statement(statement);
+        }
+    }
+
+    fn statement(&mut self, statement: &mut Statement<'input>) {
+        match &mut statement.kind {
+            StatementKind::Error
+            | StatementKind::Wait
+            | StatementKind::Continue
+            | StatementKind::Break
+            | StatementKind::Nop => {}
+            StatementKind::VariableDeclaration { value, .. }
+            | StatementKind::Assignment { value, .. }
+            | StatementKind::Yield { value } => self.expression(value),
+            StatementKind::If {
+                condition,
+                true_block,
+                false_block,
+            } => {
+                self.expression(condition);
+                self.block(true_block);
+                self.block(false_block);
+            }
+            StatementKind::Block { block } | StatementKind::Loop { block } => self.block(block),
+            StatementKind::Call { arguments, .. }
+            | StatementKind::Trigger { arguments, .. }
+            | StatementKind::Return { values: arguments } => {
+                for argument in arguments {
+                    self.expression(argument);
+                }
+            }
+            StatementKind::Send {
+                target, arguments, ..
+            } => {
+                self.expression(target);
+                for argument in arguments {
+                    self.expression(argument);
+                }
+            }
+            StatementKind::Spawn { .. } | StatementKind::ControlGroup { .. } => {
+                for expression in statement.direct_expressions_mut() {
+                    self.expression(expression);
+                }
+            }
+        }
+
+        let StatementKind::Call { arguments, .. } = &mut statement.kind else {
+            return;
+        };
+
+        let function_id: FunctionId = *statement.meta.get().unwrap();
+        let Some(InlineCandidate {
+            parameters,
+            body: InlineBody::Statements(body),
+        }) = self.candidates.get(&function_id)
+        else {
+            return;
+        };
+
+        let mut renames = HashMap::new();
+
+        // the arguments get evaluated in order before the body, exactly like a call would
+        let mut block: Vec<_> = parameters
+            .iter()
+            .zip(mem::take(arguments))
+            .map(|(parameter, argument)| {
+                let mut meta = Metadata::new();
+                meta.set(self.rename(*parameter, &mut renames));
+
+                Statement {
+                    span: argument.span,
+                    kind: StatementKind::VariableDeclaration {
+                        ident: "@argument",
+                        value: argument,
+                    },
+                    meta,
+                }
+            })
+            .collect();
+
+        let mut body = body.clone();
+        self.rename_block(&mut body, &mut renames);
+        for statement in &mut body {
+            for expression in statement.expressions_mut() {
+                self.rename_expression(expression, &mut renames);
+            }
+        }
+
+        block.extend(body);
+
+        statement.kind = StatementKind::Block { block };
+        statement.meta.clear();
+        statement.meta.set(InlinedFrom(function_id));
+
+        self.result = ConstantOptimisationResult::DidSomething;
+    }
+
+    fn expression(&mut self, expression: &mut Expression<'input>) {
+        match &mut expression.kind {
+            ExpressionKind::BinaryOperation { lhs, rhs, .. } => {
+                self.expression(lhs);
+                self.expression(rhs);
+            }
+            ExpressionKind::Call { arguments, .. } => {
+                for argument in arguments {
+                    self.expression(argument);
+                }
+            }
+            _ => {}
+        }
+
+        let ExpressionKind::Call { arguments, .. } = &mut expression.kind else {
+            return;
+        };
+
+        let function_id: FunctionId = *expression.meta.get().unwrap();
+        let Some(InlineCandidate {
+            parameters,
+            body: InlineBody::Expression(body),
+        }) = self.candidates.get(&function_id)
+        else {
+            return;
+        };
+
+        if !self.can_substitute(parameters, arguments, body) {
+            return;
+        }
+
+        let substitutions: HashMap<_, _> = parameters
+            .iter()
+            .copied()
+            .zip(mem::take(arguments))
+            .collect();
+
+        let mut inlined = body.clone();
+        substitute(&mut inlined, &substitutions);
+
+        *expression = inlined;
+        self.result = ConstantOptimisationResult::DidSomething;
+    }
+
+    /// Substituting the arguments straight into the body avoids needing somewhere to store them,
+    /// but is only allowed if it can't change what gets evaluated or the order it happens in.
+    fn can_substitute(
+        &self,
+        parameters: &[SymbolId],
+        arguments: &[Expression],
+        body: &Expression,
+    ) -> bool {
+        let body_has_call = has_call(body);
+
+        parameters
+            .iter()
+            .zip(arguments)
+            .all(|(parameter, argument)| {
+                if has_call(argument) {
+                    return false;
+                }
+
+                // a call in the body could change a property between when the argument would've
+                // been evaluated and when it gets used
+                if body_has_call && self.reads_property(argument) {
+                    return false;
+                }
+
+                let uses = body
+                    .all_inner()
+                    .filter(|expression| {
+                        matches!(expression.kind, ExpressionKind::Variable(_))
+                            && expression.meta.get() == Some(parameter)
+                    })
+                    .count();
+
+                // don't duplicate work if the parameter is used more than once
+                uses <= 1
+                    || matches!(
+                        argument.kind,
+                        ExpressionKind::Integer(_)
+                            | ExpressionKind::Fix(_)
+                            | ExpressionKind::Bool(_)
+                            | ExpressionKind::Variable(_)
+                    )
+            })
+    }
+
+    fn reads_property(&self, expression: &Expression) -> bool {
+        expression.all_inner().any(|expression| {
+            matches!(expression.kind, ExpressionKind::Variable(_))
+                && self
+                    .compile_settings
+                    .is_property(*expression.meta.get().unwrap())
+        })
+    }
+
+    /// Every local variable in the inlined body needs a new symbol, since the same function
+    /// could get inlined more than once into the same caller.
+    fn rename(
+        &mut self,
+        symbol_id: SymbolId,
+        renames: &mut HashMap<SymbolId, SymbolId>,
+    ) -> SymbolId {
+        if self.compile_settings.is_property(symbol_id) {
+            return symbol_id;
+        }
+
+        *renames.entry(symbol_id).or_insert_with(|| {
+            *self.next_symbol += 1;
+            SymbolId(*self.next_symbol - 1)
+        })
+    }
+
+    fn rename_block(
+        &mut self,
+        block: &mut [Statement<'input>],
+        renames: &mut HashMap<SymbolId, SymbolId>,
+    ) {
+        for statement in block {
+            match &mut statement.kind {
+                StatementKind::VariableDeclaration { .. } | StatementKind::Assignment { .. } => {
+                    let symbol_id = *statement.meta.get().unwrap();
+                    statement.meta.set(self.rename(symbol_id, renames));
+                }
+                StatementKind::If {
+                    true_block,
+                    false_block,
+                    ..
+                } => {
+                    self.rename_block(true_block, renames);
+                    self.rename_block(false_block, renames);
+                }
+                StatementKind::Block { block } | StatementKind::Loop { block } => {
+                    self.rename_block(block, renames);
+                }
+                _ => {}
+            }
+        }
+    }
+
+    fn rename_expression(
+        &mut self,
+        expression: &mut Expression<'input>,
+        renames: &mut HashMap<SymbolId, SymbolId>,
+    ) {
+        match &mut expression.kind {
+            ExpressionKind::Variable(_) => {
+                let symbol_id = *expression.meta.get().unwrap();
+                expression.meta.set(self.rename(symbol_id, renames));
+            }
+            ExpressionKind::BinaryOperation { lhs, rhs, .. } => {
+                self.rename_expression(lhs, renames);
+                self.rename_expression(rhs, renames);
+            }
+            ExpressionKind::Call { arguments, .. } => {
+                for argument in arguments {
+                    self.rename_expression(argument, renames);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+fn substitute<'input>(
+    expression: &mut Expression<'input>,
+    substitutions: &HashMap<SymbolId, Expression<'input>>,
+) {
+    match &mut expression.kind {
+        ExpressionKind::Variable(_) => {
+            if let Some(substitution) = substitutions.get(expression.meta.get().unwrap()) {
+                *expression = substitution.clone();
+            }
+        }
+        ExpressionKind::BinaryOperation { lhs, rhs, .. } => {
+            substitute(lhs, substitutions);
+            substitute(rhs, substitutions);
+        }
+        ExpressionKind::Call { arguments, .. } => {
+            for argument in arguments {
+                substitute(argument, substitutions);
+            }
+        }
+        _ => {}
+    }
+}
+
+fn contains_return(block: &[Statement]) -> bool {
+    block.iter().any(|statement| match &statement.kind {
+        StatementKind::Return { .. } => true,
+        StatementKind::If {
+            true_block,
+            false_block,
+            ..
+        } => contains_return(true_block) || contains_return(false_block),
+        StatementKind::Block { block } | StatementKind::Loop { block } => contains_return(block),
+        _ => false,
+    })
+}
+
+fn block_size(block: &[Statement]) -> usize {
+    block
+        .iter()
+        .map(|statement| {
+            let nested = match &statement.kind {
+                StatementKind::If {
+                    true_block,
+                    false_block,
+                    ..
+                } => block_size(true_block) + block_size(false_block),
+                StatementKind::Block { block } | StatementKind::Loop { block } => block_size(block),
+                _ => 0,
+            };
+
+            let expressions: usize = statement
+                .direct_expressions()
+                .map(|expression| expression.all_inner().count())
+                .sum();
+
+            1 + nested + expressions
+        })
+        .sum()
+}
+
+#[cfg(test)]
+mod test {
+    use std::fs;
+
+    use insta::{assert_snapshot, glob};
+
+    use crate::{
+        compile::{
+            loop_visitor::visit_loop_check, optimisations::optimise, symtab_visitor::SymTabVisitor,
+            type_visitor::TypeVisitor,
+        },
+        grammar,
+        lexer::Lexer,
+        reporting::Diagnostics,
+        tokens::FileId,
+        CompileSettings, Property, PropertyAccess, Type,
+    };
+
+    #[test]
+    fn inline_snapshot_tests() {
+        glob!("snapshot_tests", "inline/*.tapir", |path| {
+            let input = fs::read_to_string(path).unwrap();
+
+            let lexer = Lexer::new(&input, FileId::new(0));
+            let parser = grammar::ScriptParser::new();
+            let file_id = FileId::new(0);
+
+            let mut diagnostics = Diagnostics::new(file_id, path.file_name().unwrap(), &input);
+
+            let mut script = parser
+                .parse(
+                    file_id,
+                    CompileSettings::DEFAULT_FIX_PRECISION,
+                    &mut diagnostics,
+                    lexer,
+                )
+                .unwrap();
+
+            let compile_settings = CompileSettings {
+                properties: vec![Property {
+                    ty: Type::Int,
+                    index: 0,
+                    name: "int_prop".to_owned(),
+                    access: PropertyAccess::ReadWrite,
+                }],
+                enable_optimisations: true,
+                fix_precision: CompileSettings::DEFAULT_FIX_PRECISION,
+            };
+
+            let mut symtab_visitor =
+                SymTabVisitor::new(&compile_settings, &mut script.functions, &mut diagnostics);
+            let mut type_visitor = TypeVisitor::new(&compile_settings, &script.functions);
+
+            for function in &mut script.functions {
+                visit_loop_check(function, &mut diagnostics);
+                symtab_visitor.visit_function(function, &mut diagnostics);
+                type_visitor.visit_function(
+                    function,
+                    symtab_visitor.get_symtab(),
+                    &mut diagnostics,
+                );
+            }
+
+            // the whole optimiser, to check that constants flow through the inlined bodies
+            optimise(
+                &mut script.functions,
+                symtab_visitor.get_symtab().symbol_count(),
+                &compile_settings,
+                &mut diagnostics,
+            );
+
+            let pretty_printed = script.pretty_print();
+
+            assert_snapshot!(pretty_printed);
+        });
+    }
+}
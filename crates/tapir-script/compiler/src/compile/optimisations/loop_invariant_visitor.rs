@@ -0,0 +1,347 @@
+use std::collections::HashSet;
+
+use crate::{
+    ast::{
+        BinaryOperator, Expression, ExpressionKind, Function, Metadata, Statement, StatementKind,
+        SymbolId,
+    },
+    CompileSettings,
+};
+
+use super::{has_call, is_same_expression, ConstantOptimisationResult};
+
+/// Moves pure expressions which calculate the same value on every iteration of a loop to just
+/// before it, so they only get calculated once. Each call hoists at most one expression out of
+/// each loop, so should be run until it stops doing anything.
+pub fn hoist_loop_invariants(
+    function: &mut Function,
+    compile_settings: &CompileSettings,
+    next_symbol: &mut usize,
+) -> ConstantOptimisationResult {
+    hoist_in_block(&mut function.statements, compile_settings, next_symbol)
+}
+
+fn hoist_in_block(
+    block: &mut Vec<Statement>,
+    compile_settings: &CompileSettings,
+    next_symbol: &mut usize,
+) -> ConstantOptimisationResult {
+    let mut result = ConstantOptimisationResult::DidNothing;
+
+    let mut i = 0;
+    while i < block.len() {
+        let mut hoisted = None;
+
+        match &mut block[i].kind {
+            StatementKind::If {
+                true_block,
+                false_block,
+                ..
+            } => {
+                result |= hoist_in_block(true_block, compile_settings, next_symbol);
+                result |= hoist_in_block(false_block, compile_settings, next_symbol);
+            }
+            StatementKind::Block { block } => {
+                result |= hoist_in_block(block, compile_settings, next_symbol);
+            }
+            StatementKind::Loop { block: body } => {
+                // inner loops first, so anything hoisted from them can carry on out of this one
+                result |= hoist_in_block(body, compile_settings, next_symbol);
+
+                let effects = LoopEffects::new(body);
+
+                if let Some(index) = body.iter().position(|statement| {
+                    is_hoisted_from_inner_loop(statement, &effects, compile_settings)
+                }) {
+                    // no need for another variable, the whole declaration can move out again
+                    hoisted = Some(body.remove(index));
+                } else if let Some(expression) = find_invariant(body, &effects, compile_settings) {
+                    let symbol_id = SymbolId(*next_symbol);
+                    *next_symbol += 1;
+
+                    for inner in body.iter_mut().flat_map(Statement::expressions_mut) {
+                        replace_in_expression(inner, &expression, symbol_id);
+                    }
+
+                    let mut meta = Metadata::new();
+                    meta.set(symbol_id);
+
+                    hoisted = Some(Statement {
+                        span: expression.span,
+                        kind: StatementKind::VariableDeclaration {
+                            ident: "@invariant",
+                            value: expression,
+                        },
+                        meta,
+                    });
+                }
+            }
+            _ => {}
+        }
+
+        if let Some(hoisted) = hoisted {
+            block.insert(i, hoisted);
+            i += 1;
+
+            result = ConstantOptimisationResult::DidSomething;
+        }
+
+        i += 1;
+    }
+
+    result
+}
+
+/// What the body of a loop could change while it runs
+#[derive(Default)]
+struct LoopEffects {
+    /// Symbols which are assigned to, or declared fresh each iteration
+    modified: HashSet<SymbolId>,
+    /// Whether there is a wait or call, after which properties could have any value
+    could_change_properties: bool,
+}
+
+impl LoopEffects {
+    fn new(body: &[Statement]) -> Self {
+        let mut effects = Self::default();
+        collect_effects(body, &mut effects);
+        effects
+    }
+}
+
+fn is_hoisted_from_inner_loop(
+    statement: &Statement,
+    effects: &LoopEffects,
+    compile_settings: &CompileSettings,
+) -> bool {
+    matches!(
+        &statement.kind,
+        StatementKind::VariableDeclaration {
+            ident: "@invariant",
+            value,
+        } if is_invariant(value, effects, compile_settings) && !could_fail(value)
+    )
+}
+
+fn find_invariant<'input>(
+    body: &mut [Statement<'input>],
+    effects: &LoopEffects,
+    compile_settings: &CompileSettings,
+) -> Option<Expression<'input>> {
+    body.iter_mut()
+        .flat_map(Statement::expressions_mut)
+        .find_map(|expression| largest_invariant(expression, effects, compile_settings))
+}
+
+fn collect_effects(block: &[Statement], effects: &mut LoopEffects) {
+    for statement in block {
+        match &statement.kind {
+            StatementKind::VariableDeclaration { .. } | StatementKind::Assignment { .. } => {
+                effects
+                    .modified
+                    .insert(*statement.meta.get().expect("Should've resolved variable"));
+            }
+            StatementKind::Wait
+            | StatementKind::Yield { .. }
+            | StatementKind::Call { .. }
+            | StatementKind::Spawn { .. } => {
+                effects.could_change_properties = true;
+            }
+            StatementKind::If {
+                true_block,
+                false_block,
+                ..
+            } => {
+                collect_effects(true_block, effects);
+                collect_effects(false_block, effects);
+            }
+            StatementKind::Block { block } | StatementKind::Loop { block } => {
+                collect_effects(block, effects);
+            }
+            _ => {}
+        }
+
+        if statement.direct_expressions().any(has_call) {
+            effects.could_change_properties = true;
+        }
+    }
+}
+
+fn largest_invariant<'input>(
+    expression: &Expression<'input>,
+    effects: &LoopEffects,
+    compile_settings: &CompileSettings,
+) -> Option<Expression<'input>> {
+    match &expression.kind {
+        // variables and constants on their own are already as cheap as the hoisted variable
+        ExpressionKind::BinaryOperation { .. }
+            if is_invariant(expression, effects, compile_settings) && !could_fail(expression) =>
+        {
+            Some(expression.clone())
+        }
+        ExpressionKind::BinaryOperation { lhs, rhs, .. } => {
+            largest_invariant(lhs, effects, compile_settings)
+                .or_else(|| largest_invariant(rhs, effects, compile_settings))
+        }
+        ExpressionKind::Call { arguments, .. } => arguments
+            .iter()
+            .find_map(|argument| largest_invariant(argument, effects, compile_settings)),
+        _ => None,
+    }
+}
+
+fn is_invariant(
+    expression: &Expression,
+    effects: &LoopEffects,
+    compile_settings: &CompileSettings,
+) -> bool {
+    !has_call(expression)
+        && expression.all_inner().all(|inner| {
+            let ExpressionKind::Variable(_) = inner.kind else {
+                return true;
+            };
+
+            let symbol_id = *inner.meta.get().expect("Should've resolved variable");
+            let could_change = effects.modified.contains(&symbol_id)
+                || (effects.could_change_properties && compile_settings.is_property(symbol_id));
+
+            !could_change
+        })
+}
+
+/// The expression might not get evaluated on every path through the loop, so hoisting it mustn't
+/// introduce a division by zero which couldn't happen before
+fn could_fail(expression: &Expression) -> bool {
+    expression.all_inner().any(|inner| match &inner.kind {
+        ExpressionKind::BinaryOperation {
+            operator:
+                BinaryOperator::Div
+                | BinaryOperator::Mod
+                | BinaryOperator::RealDiv
+                | BinaryOperator::RealMod
+                | BinaryOperator::FixDiv,
+            rhs,
+            ..
+        } => match rhs.kind {
+            ExpressionKind::Integer(n) => n == 0,
+            ExpressionKind::Fix(n) => n.is_zero(),
+            _ => true,
+        },
+        _ => false,
+    })
+}
+
+fn replace_in_expression(expression: &mut Expression, invariant: &Expression, symbol_id: SymbolId) {
+    if is_same_expression(expression, invariant) {
+        let mut meta = Metadata::new();
+        meta.set(symbol_id);
+
+        *expression = Expression {
+            span: expression.span,
+            kind: ExpressionKind::Variable("@invariant"),
+            meta,
+        };
+
+        return;
+    }
+
+    match &mut expression.kind {
+        ExpressionKind::BinaryOperation { lhs, rhs, .. } => {
+            replace_in_expression(lhs, invariant, symbol_id);
+            replace_in_expression(rhs, invariant, symbol_id);
+        }
+        ExpressionKind::Call { arguments, .. } => {
+            for argument in arguments {
+                replace_in_expression(argument, invariant, symbol_id);
+            }
+        }
+        _ => {}
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::fs;
+
+    use insta::{assert_snapshot, glob};
+
+    use crate::{
+        compile::{
+            loop_visitor::visit_loop_check, symtab_visitor::SymTabVisitor,
+            type_visitor::TypeVisitor,
+        },
+        grammar,
+        lexer::Lexer,
+        reporting::Diagnostics,
+        tokens::FileId,
+        CompileSettings, Property, PropertyAccess, Type,
+    };
+
+    use super::*;
+
+    #[test]
+    fn loop_invariant_snapshot_tests() {
+        glob!("snapshot_tests", "loop_invariant/*.tapir", |path| {
+            let input = fs::read_to_string(path).unwrap();
+
+            let lexer = Lexer::new(&input, FileId::new(0));
+            let parser = grammar::ScriptParser::new();
+            let file_id = FileId::new(0);
+
+            let mut diagnostics = Diagnostics::new(file_id, path.file_name().unwrap(), &input);
+
+            let mut script = parser
+                .parse(
+                    file_id,
+                    CompileSettings::DEFAULT_FIX_PRECISION,
+                    &mut diagnostics,
+                    lexer,
+                )
+                .unwrap();
+
+            let compile_settings = CompileSettings {
+                properties: vec![
+                    Property {
+                        ty: Type::Int,
+                        index: 0,
+                        name: "int_prop".to_owned(),
+                        access: PropertyAccess::ReadWrite,
+                    },
+                    Property {
+                        ty: Type::Fix,
+                        index: 1,
+                        name: "fix_prop".to_owned(),
+                        access: PropertyAccess::ReadWrite,
+                    },
+                ],
+                enable_optimisations: true,
+                fix_precision: CompileSettings::DEFAULT_FIX_PRECISION,
+            };
+
+            let mut symtab_visitor =
+                SymTabVisitor::new(&compile_settings, &mut script.functions, &mut diagnostics);
+            let mut type_visitor = TypeVisitor::new(&compile_settings, &script.functions);
+
+            for function in &mut script.functions {
+                visit_loop_check(function, &mut diagnostics);
+                symtab_visitor.visit_function(function, &mut diagnostics);
+                type_visitor.visit_function(
+                    function,
+                    symtab_visitor.get_symtab(),
+                    &mut diagnostics,
+                );
+            }
+
+            let mut next_symbol = symtab_visitor.get_symtab().symbol_count();
+            for function in &mut script.functions {
+                while hoist_loop_invariants(function, &compile_settings, &mut next_symbol)
+                    == ConstantOptimisationResult::DidSomething
+                {}
+            }
+
+            let pretty_printed = script.pretty_print();
+
+            assert_snapshot!(pretty_printed);
+        });
+    }
+}
@@ -0,0 +1,145 @@
+use std::ops::BitOr;
+
+use crate::ast::{BinaryOperator, Expression, ExpressionKind, Fix, Function};
+
+use super::ConstantOptimisationResult;
+
+/// Replaces multiplications by powers of two with shifts, which are much cheaper on the GBA.
+/// Should run after constant folding, which has already turned fix multiplications by whole
+/// numbers into integer multiplications.
+pub fn strength_reduce(function: &mut Function) -> ConstantOptimisationResult {
+    function
+        .statements
+        .iter_mut()
+        .flat_map(|statement| statement.expressions_mut())
+        .map(reduce)
+        .fold(ConstantOptimisationResult::DidNothing, BitOr::bitor)
+}
+
+fn reduce(expression: &mut Expression) -> ConstantOptimisationResult {
+    match &mut expression.kind {
+        ExpressionKind::BinaryOperation { lhs, operator, rhs } => {
+            let did_something = reduce(lhs) | reduce(rhs);
+
+            let Some((shift_operator, amount)) = shift_for(*operator, &rhs.kind) else {
+                return did_something;
+            };
+
+            *operator = shift_operator;
+            rhs.kind = ExpressionKind::Integer(amount);
+
+            ConstantOptimisationResult::DidSomething
+        }
+        ExpressionKind::Call { arguments, .. } => arguments
+            .iter_mut()
+            .map(reduce)
+            .fold(ConstantOptimisationResult::DidNothing, BitOr::bitor),
+        _ => ConstantOptimisationResult::DidNothing,
+    }
+}
+
+fn shift_for(operator: BinaryOperator, rhs: &ExpressionKind) -> Option<(BinaryOperator, i32)> {
+    match (operator, rhs) {
+        // x * 4 => x << 2, which works for fix values multiplied by integers too
+        (BinaryOperator::Mul, &ExpressionKind::Integer(n)) if n > 1 && n.count_ones() == 1 => {
+            Some((BinaryOperator::ShiftLeft, n.trailing_zeros() as i32))
+        }
+        // x * 0.25 => x >> 2, since the fixed point multiplication shifts back down afterwards
+        (BinaryOperator::FixMul, &ExpressionKind::Fix(n)) => {
+            let raw = n.to_raw();
+            let one = Fix::new(1, n.precision()).to_raw();
+
+            (raw > 0 && raw < one && raw.count_ones() == 1).then(|| {
+                (
+                    BinaryOperator::ShiftRight,
+                    (one.trailing_zeros() - raw.trailing_zeros()) as i32,
+                )
+            })
+        }
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::fs;
+
+    use insta::{assert_snapshot, glob};
+
+    use crate::{
+        compile::{
+            loop_visitor::visit_loop_check, optimisations::constant_folding_visitor::constant_fold,
+            symtab_visitor::SymTabVisitor, type_visitor::TypeVisitor,
+        },
+        grammar,
+        lexer::Lexer,
+        reporting::Diagnostics,
+        tokens::FileId,
+        CompileSettings, Property, PropertyAccess, Type,
+    };
+
+    use super::*;
+
+    #[test]
+    fn strength_reduction_snapshot_tests() {
+        glob!("snapshot_tests", "strength_reduction/*.tapir", |path| {
+            let input = fs::read_to_string(path).unwrap();
+
+            let lexer = Lexer::new(&input, FileId::new(0));
+            let parser = grammar::ScriptParser::new();
+            let file_id = FileId::new(0);
+
+            let mut diagnostics = Diagnostics::new(file_id, path.file_name().unwrap(), &input);
+
+            let mut script = parser
+                .parse(
+                    file_id,
+                    CompileSettings::DEFAULT_FIX_PRECISION,
+                    &mut diagnostics,
+                    lexer,
+                )
+                .unwrap();
+
+            let compile_settings = CompileSettings {
+                properties: vec![
+                    Property {
+                        ty: Type::Int,
+                        index: 0,
+                        name: "int_prop".to_owned(),
+                        access: PropertyAccess::ReadWrite,
+                    },
+                    Property {
+                        ty: Type::Fix,
+                        index: 1,
+                        name: "fix_prop".to_owned(),
+                        access: PropertyAccess::ReadWrite,
+                    },
+                ],
+                enable_optimisations: true,
+                fix_precision: CompileSettings::DEFAULT_FIX_PRECISION,
+            };
+
+            let mut symtab_visitor =
+                SymTabVisitor::new(&compile_settings, &mut script.functions, &mut diagnostics);
+            let mut type_visitor = TypeVisitor::new(&compile_settings, &script.functions);
+
+            for function in &mut script.functions {
+                visit_loop_check(function, &mut diagnostics);
+                symtab_visitor.visit_function(function, &mut diagnostics);
+                type_visitor.visit_function(
+                    function,
+                    symtab_visitor.get_symtab(),
+                    &mut diagnostics,
+                );
+
+                while constant_fold(function) == ConstantOptimisationResult::DidSomething {}
+
+                strength_reduce(function);
+            }
+
+            let pretty_printed = script.pretty_print();
+
+            assert_snapshot!(pretty_printed);
+        });
+    }
+}
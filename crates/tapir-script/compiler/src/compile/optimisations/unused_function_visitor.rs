@@ -1,3 +1,5 @@
+use std::collections::HashSet;
+
 use petgraph::prelude::*;
 
 use serde::Serialize;
@@ -10,21 +12,55 @@ use super::ConstantOptimisationResult;
 pub struct UnusedFunction;
 
 pub fn unused_function_visitor(functions: &mut [Function]) -> ConstantOptimisationResult {
-    let mut call_graph = DiGraphMap::new();
-
-    let mut roots = vec![FunctionId(0)];
+    let called_functions = reachable_functions(functions);
 
+    let mut did_something = ConstantOptimisationResult::DidNothing;
     for function in functions.iter_mut() {
+        let function_id: FunctionId = *function.meta.get().unwrap();
+
+        if !called_functions.contains(&function_id) && !function.meta.set(UnusedFunction) {
+            // we've newly set a meta field
+            did_something = ConstantOptimisationResult::DidSomething;
+        }
+    }
+
+    did_something
+}
+
+/// Every function which could be called starting from the top level function or an event handler
+pub fn reachable_functions(functions: &[Function]) -> HashSet<FunctionId> {
+    let call_graph = call_graph(functions);
+
+    let roots = std::iter::once(FunctionId(0)).chain(
+        functions
+            .iter()
+            .filter(|function| {
+                function.modifiers.is_entry_point() && !function.meta.has::<UnusedFunction>()
+            })
+            .map(|function| *function.meta.get::<FunctionId>().unwrap()),
+    );
+
+    let mut called_functions = Dfs::empty(&call_graph);
+
+    for root in roots {
+        called_functions.move_to(root);
+        while called_functions.next(&call_graph).is_some() {}
+    }
+
+    called_functions.discovered.into_iter().collect()
+}
+
+/// An edge from each function to every function it calls or spawns
+pub fn call_graph(functions: &[Function]) -> DiGraphMap<FunctionId, ()> {
+    let mut call_graph = DiGraphMap::new();
+
+    for function in functions {
         if function.meta.has::<UnusedFunction>() {
             continue; // don't need to inspect this since we already know it is unused
         }
 
         let function_id: FunctionId = *function.meta.get().unwrap();
 
-        if function.modifiers.is_event_handler.is_some() {
-            roots.push(function_id);
-        }
-
         call_graph.add_node(function_id);
 
         visit_block(function_id, &function.statements, &mut call_graph);
@@ -63,7 +99,8 @@ pub fn unused_function_visitor(functions: &mut [Function]) -> ConstantOptimisati
                     | StatementKind::Nop => continue,
 
                     StatementKind::VariableDeclaration { value, .. }
-                    | StatementKind::Assignment { value, .. } => {
+                    | StatementKind::Assignment { value, .. }
+                    | StatementKind::Yield { value } => {
                         visit_expr!(value);
                     }
                     StatementKind::Loop { block } | StatementKind::Block { block } => {
@@ -78,43 +115,32 @@ pub fn unused_function_visitor(functions: &mut [Function]) -> ConstantOptimisati
                         visit_block(calling_function, true_block, call_graph);
                         visit_block(calling_function, false_block, call_graph);
                     }
-                    StatementKind::Spawn { arguments, .. }
-                    | StatementKind::Call { arguments, .. } => {
+                    StatementKind::Spawn { .. } | StatementKind::Call { .. } => {
                         if let Some(called_id) = statement.meta.get() {
                             call_graph.add_edge(calling_function, *called_id, ());
                         }
 
-                        visit_exprs!(arguments);
+                        visit_exprs!(statement.direct_expressions());
                     }
                     StatementKind::Return { values: arguments }
                     | StatementKind::Trigger { arguments, .. } => {
                         visit_exprs!(arguments);
                     }
+                    StatementKind::Send {
+                        target, arguments, ..
+                    } => {
+                        visit_expr!(target);
+                        visit_exprs!(arguments);
+                    }
+                    StatementKind::ControlGroup { .. } => {
+                        visit_exprs!(statement.direct_expressions());
+                    }
                 }
             }
         }
     }
 
-    let mut called_functions = Dfs::empty(&call_graph);
-
-    for root in roots {
-        called_functions.move_to(root);
-        while called_functions.next(&call_graph).is_some() {}
-    }
-
-    let called_functions = called_functions.discovered;
-
-    let mut did_something = ConstantOptimisationResult::DidNothing;
-    for function in functions.iter_mut() {
-        let function_id: FunctionId = *function.meta.get().unwrap();
-
-        if !called_functions.contains(&function_id) && !function.meta.set(UnusedFunction) {
-            // we've newly set a meta field
-            did_something = ConstantOptimisationResult::DidSomething;
-        }
-    }
-
-    did_something
+    call_graph
 }
 
 #[cfg(test)]
@@ -148,11 +174,19 @@ mod test {
 
             let mut diagnostics = Diagnostics::new(file_id, path.file_name().unwrap(), &input);
 
-            let mut script = parser.parse(file_id, &mut diagnostics, lexer).unwrap();
+            let mut script = parser
+                .parse(
+                    file_id,
+                    CompileSettings::DEFAULT_FIX_PRECISION,
+                    &mut diagnostics,
+                    lexer,
+                )
+                .unwrap();
 
             let compile_settings = CompileSettings {
                 properties: Vec::new(),
                 enable_optimisations: true,
+                fix_precision: CompileSettings::DEFAULT_FIX_PRECISION,
             };
 
             let mut symtab_visitor =
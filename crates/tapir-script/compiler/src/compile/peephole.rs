@@ -0,0 +1,139 @@
+use std::collections::{HashMap, HashSet};
+
+use super::{
+    opcodes::{MathsOp, Opcode},
+    Bytecode,
+};
+
+impl Bytecode {
+    /// Replaces common sequences of opcodes with a single superinstruction which does the same
+    /// thing. Every instruction costs a decode and dispatch in the VM, which is expensive on the
+    /// GBA, so doing more per instruction makes things noticeably faster.
+    ///
+    /// This has to run after all the jumps have been patched, since it moves code around.
+    pub(crate) fn fuse_superinstructions(&mut self) {
+        let jump_targets = self.jump_targets();
+        let offsets: Vec<usize> = self
+            .data
+            .iter()
+            .scan(0, |offset, opcode| {
+                let this_offset = *offset;
+                *offset += opcode.size();
+                Some(this_offset)
+            })
+            .collect();
+
+        let mut offset = 0;
+        let mut new_offsets = HashMap::new();
+        let mut fused = Vec::with_capacity(self.data.len());
+
+        let mut i = 0;
+        while i < self.data.len() {
+            new_offsets.insert(offsets[i], offset);
+
+            let (opcode, consumed) = match fuse(&self.data[i..]) {
+                // can't fuse over a jump target, since something needs to land in the middle
+                Some((opcode, consumed))
+                    if (i + 1..i + consumed).all(|j| !jump_targets.contains(&offsets[j])) =>
+                {
+                    (opcode, consumed)
+                }
+                _ => (self.data[i], 1),
+            };
+
+            fused.push(opcode);
+            offset += opcode.size();
+            i += consumed;
+        }
+
+        // allow for jumping to the very end of the bytecode
+        new_offsets.insert(self.length, offset);
+
+        for opcode in &mut fused {
+            match opcode {
+                Opcode::Jump(target)
+                | Opcode::JumpIfFalse(target)
+                | Opcode::Call { target, .. }
+                | Opcode::Spawn { target, .. }
+                | Opcode::TailCall { target, .. }
+                | Opcode::CompareJumpIfFalse { target, .. } => {
+                    *target = new_offsets[&(*target as usize)] as u16;
+                }
+                _ => {}
+            }
+        }
+
+        for event_handler in &mut self.event_handlers {
+            event_handler.bytecode_offset = new_offsets[&event_handler.bytecode_offset];
+        }
+
+        for stack_depth in &mut self.stack_depths {
+            stack_depth.bytecode_offset = new_offsets[&stack_depth.bytecode_offset];
+        }
+
+        for function in &mut self.functions {
+            function.bytecode_offset = new_offsets[&function.bytecode_offset];
+        }
+
+        self.data = fused;
+        self.length = offset;
+    }
+
+    fn jump_targets(&self) -> HashSet<usize> {
+        let jumps = self.data.iter().filter_map(|opcode| match *opcode {
+            Opcode::Jump(target)
+            | Opcode::JumpIfFalse(target)
+            | Opcode::Call { target, .. }
+            | Opcode::Spawn { target, .. }
+            | Opcode::TailCall { target, .. } => Some(target as usize),
+            _ => None,
+        });
+
+        let event_handlers = self
+            .event_handlers
+            .iter()
+            .map(|event_handler| event_handler.bytecode_offset);
+
+        let function_starts = self
+            .stack_depths
+            .iter()
+            .map(|stack_depth| stack_depth.bytecode_offset);
+
+        jumps.chain(event_handlers).chain(function_starts).collect()
+    }
+}
+
+/// Returns the superinstruction for the sequence at the start of `opcodes` along with how many
+/// opcodes it replaces, if there is one.
+fn fuse(opcodes: &[Opcode]) -> Option<(Opcode, usize)> {
+    match *opcodes {
+        // prop = prop + n;
+        [Opcode::GetProp(prop), push, Opcode::MathsOp(MathsOp::Add), Opcode::SetProp(set_prop), ..]
+        | [push, Opcode::GetProp(prop), Opcode::MathsOp(MathsOp::Add), Opcode::SetProp(set_prop), ..]
+            if prop == set_prop =>
+        {
+            let value = immediate(push)?;
+            Some((Opcode::AddPropImmediate { prop, value }, 4))
+        }
+        // prop = prop - n;
+        [Opcode::GetProp(prop), push, Opcode::MathsOp(MathsOp::Sub), Opcode::SetProp(set_prop), ..]
+            if prop == set_prop =>
+        {
+            let value = immediate(push)?.checked_neg()?;
+            Some((Opcode::AddPropImmediate { prop, value }, 4))
+        }
+        // if a < b { ... }
+        [Opcode::MathsOp(op), Opcode::JumpIfFalse(target), ..] if op.is_comparison() => {
+            Some((Opcode::CompareJumpIfFalse { op, target }, 2))
+        }
+        _ => None,
+    }
+}
+
+fn immediate(opcode: Opcode) -> Option<i16> {
+    match opcode {
+        Opcode::Push8(value) => Some(value.into()),
+        Opcode::Push32(value) => value.try_into().ok(),
+        _ => None,
+    }
+}
@@ -0,0 +1,93 @@
+use std::collections::HashMap;
+
+use petgraph::{algo::tarjan_scc, prelude::DiGraphMap};
+
+use crate::ast::FunctionId;
+
+/// A call from one function to another, recorded while compiling
+#[derive(Clone, Copy, Debug)]
+pub struct CallSite {
+    pub caller: FunctionId,
+    pub callee: FunctionId,
+    /// How much of the caller's frame is below the callee's frame
+    pub offset: usize,
+    /// Tail calls replace the caller's frame, so have an offset of 0
+    pub is_tail_call: bool,
+}
+
+#[derive(Clone, Copy, Debug)]
+struct CallEdge {
+    offset: usize,
+    grows_stack: bool,
+}
+
+/// Works out the most stack each function could need, including everything it calls. A function
+/// gets `None` if it can recurse without going through a tail call, since then there is no limit.
+///
+/// `frame_depths` is the most stack each function uses itself, including its arguments and
+/// return address.
+pub fn max_stack_depths(
+    frame_depths: &HashMap<FunctionId, usize>,
+    call_sites: &[CallSite],
+) -> HashMap<FunctionId, Option<usize>> {
+    let mut graph = DiGraphMap::<FunctionId, CallEdge>::new();
+    for &function_id in frame_depths.keys() {
+        graph.add_node(function_id);
+    }
+
+    for call_site in call_sites {
+        let mut edge = CallEdge {
+            offset: call_site.offset,
+            grows_stack: !call_site.is_tail_call,
+        };
+
+        // only the deepest call from a function to the same callee matters
+        if let Some(existing) = graph.edge_weight(call_site.caller, call_site.callee) {
+            edge.offset = edge.offset.max(existing.offset);
+            edge.grows_stack |= existing.grows_stack;
+        }
+
+        graph.add_edge(call_site.caller, call_site.callee, edge);
+    }
+
+    let mut depths = HashMap::new();
+
+    // this returns the components in reverse topological order, so callees are always done first
+    for component in tarjan_scc(&graph) {
+        // calls within a component can repeat forever. That's fine for tail calls, which replace
+        // the current frame, but anything else grows the stack every time round.
+        let is_recursive = component.iter().any(|&caller| {
+            graph
+                .edges(caller)
+                .any(|(_, callee, edge)| edge.grows_stack && component.contains(&callee))
+        });
+
+        let depth = if is_recursive {
+            None
+        } else {
+            component
+                .iter()
+                .map(|function_id| {
+                    let calls = graph
+                        .edges(*function_id)
+                        .filter(|(_, callee, _)| !component.contains(callee))
+                        .map(|(_, callee, edge)| Some(edge.offset + depths[&callee]?))
+                        .collect::<Option<Vec<_>>>()?;
+
+                    Some(
+                        calls
+                            .into_iter()
+                            .fold(frame_depths[function_id], usize::max),
+                    )
+                })
+                .collect::<Option<Vec<_>>>()
+                .map(|depths| depths.into_iter().max().unwrap_or(0))
+        };
+
+        for function_id in component {
+            depths.insert(function_id, depth);
+        }
+    }
+
+    depths
+}
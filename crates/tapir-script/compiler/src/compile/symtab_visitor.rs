@@ -1,21 +1,26 @@
-use std::{borrow::Cow, collections::HashMap};
+use std::{
+    borrow::Cow,
+    collections::{HashMap, HashSet},
+};
 
 use crate::{
     ast::{
         Expression, ExpressionKind, Function, FunctionId, MaybeResolved, Statement, StatementKind,
         SymbolId,
     },
-    reporting::{CompilerErrorKind, Diagnostics},
+    reporting::{CompilerErrorKind, CompilerWarningKind, Diagnostics},
     tokens::Span,
 };
 
-use super::{CompileSettings, Property};
+use super::{CompileSettings, Property, PropertyAccess};
 
 pub struct SymTabVisitor<'input> {
     symtab: SymTab<'input>,
 
     symbol_names: NameTable<'input>,
     function_names: HashMap<&'input str, FunctionId>,
+
+    read_symbols: HashSet<SymbolId>,
 }
 
 impl<'input> SymTabVisitor<'input> {
@@ -48,6 +53,8 @@ impl<'input> SymTabVisitor<'input> {
                 .iter()
                 .map(|f| (f.name, *f.meta.get::<FunctionId>().unwrap()))
                 .collect(),
+
+            read_symbols: HashSet::new(),
         }
     }
 
@@ -62,20 +69,67 @@ impl<'input> SymTabVisitor<'input> {
     ) {
         self.symbol_names.push_scope();
 
+        let first_symbol = self.symtab.symbol_names.len();
+
         for argument in &mut function.arguments {
             let MaybeResolved::Unresolved(name) = argument.name else {
                 panic!("Should not have resolved arguments yet");
             };
 
-            let symbol_id = self.symtab.new_symbol(name, argument.span);
-            self.symbol_names.insert(name, symbol_id);
-
+            let symbol_id = self.declare(name, argument.span, diagnostics);
             argument.name = MaybeResolved::Resolved(symbol_id);
         }
 
         self.visit_block(&mut function.statements, diagnostics);
 
         self.symbol_names.pop_scope();
+
+        // symbols can't escape the function they are declared in, so we know all the reads by now
+        for symbol_id in (first_symbol..self.symtab.symbol_names.len()).map(SymbolId) {
+            let name = self.symtab.name_for_symbol(symbol_id);
+            if self.read_symbols.contains(&symbol_id) || name.starts_with('_') {
+                continue;
+            }
+
+            diagnostics.add_message(
+                CompilerWarningKind::UnusedVariable {
+                    name: name.into_owned(),
+                }
+                .into_message(self.symtab.span_for_symbol(symbol_id)),
+            );
+        }
+    }
+
+    fn declare(
+        &mut self,
+        name: &'input str,
+        span: Span,
+        diagnostics: &mut Diagnostics,
+    ) -> SymbolId {
+        if let Some(shadowed) = self.symbol_names.get(name) {
+            diagnostics.add_message(
+                CompilerWarningKind::ShadowedVariable {
+                    name: name.to_string(),
+                    original_declaration: self.symtab.declaration_for_symbol(shadowed),
+                }
+                .into_message(span),
+            );
+        }
+
+        let symbol_id = self.symtab.new_symbol(name, span);
+        self.symbol_names.insert(name, symbol_id);
+
+        symbol_id
+    }
+
+    fn has_property_access(&self, symbol_id: SymbolId, access: PropertyAccess) -> bool {
+        self.symtab
+            .get_property(symbol_id)
+            .is_some_and(|property| property.access == access)
+    }
+
+    fn closest_function(&self, name: &str) -> Option<String> {
+        closest_match(name, self.function_names.keys().copied())
     }
 
     fn visit_block(&mut self, ast: &mut [Statement<'input>], diagnostics: &mut Diagnostics) {
@@ -89,9 +143,7 @@ impl<'input> SymTabVisitor<'input> {
                 } => {
                     self.visit_expr(value, diagnostics);
 
-                    let symbol_id = self.symtab.new_symbol(ident, statement.span);
-                    self.symbol_names.insert(ident, symbol_id);
-
+                    let symbol_id = self.declare(ident, statement.span, diagnostics);
                     statement.meta.set(symbol_id);
                 }
                 StatementKind::Assignment {
@@ -102,13 +154,28 @@ impl<'input> SymTabVisitor<'input> {
 
                     if let Some(symbol_id) = self.symbol_names.get(ident) {
                         statement.meta.set(symbol_id);
+
+                        if self.has_property_access(symbol_id, PropertyAccess::ReadOnly) {
+                            diagnostics.add_message(
+                                CompilerErrorKind::AssignmentToReadOnlyProperty {
+                                    name: ident.to_string(),
+                                }
+                                .into_message(statement.span),
+                            );
+                        }
                     } else {
                         diagnostics.add_message(
-                            CompilerErrorKind::UnknownVariable(ident.to_string())
-                                .into_message(statement.span),
+                            CompilerErrorKind::UnknownVariable {
+                                name: ident.to_string(),
+                                suggestion: self.symbol_names.closest_match(ident),
+                            }
+                            .into_message(statement.span),
                         );
                     }
                 }
+                StatementKind::Yield { value } => {
+                    self.visit_expr(value, diagnostics);
+                }
                 StatementKind::If {
                     ref mut condition,
                     ref mut true_block,
@@ -139,6 +206,7 @@ impl<'input> SymTabVisitor<'input> {
                 | StatementKind::Spawn {
                     ref mut arguments,
                     name,
+                    ..
                 } => {
                     if let Some(function) = self.function_names.get(name) {
                         statement.meta.set(*function);
@@ -146,6 +214,7 @@ impl<'input> SymTabVisitor<'input> {
                         diagnostics.add_message(
                             CompilerErrorKind::UnknownFunction {
                                 name: name.to_string(),
+                                suggestion: self.closest_function(name),
                             }
                             .into_message(statement.span),
                         );
@@ -154,6 +223,13 @@ impl<'input> SymTabVisitor<'input> {
                     for argument in arguments {
                         self.visit_expr(argument, diagnostics);
                     }
+
+                    if let StatementKind::Spawn {
+                        group: Some(group), ..
+                    } = &mut statement.kind
+                    {
+                        self.visit_expr(group, diagnostics);
+                    }
                 }
                 StatementKind::Trigger {
                     ref mut arguments, ..
@@ -162,6 +238,22 @@ impl<'input> SymTabVisitor<'input> {
                         self.visit_expr(argument, diagnostics);
                     }
                 }
+                StatementKind::Send {
+                    ref mut target,
+                    ref mut arguments,
+                    ..
+                } => {
+                    self.visit_expr(target, diagnostics);
+                    for argument in arguments {
+                        self.visit_expr(argument, diagnostics);
+                    }
+                }
+                StatementKind::ControlGroup { group, control } => {
+                    self.visit_expr(group, diagnostics);
+                    if let Some(frames) = control.frames_mut() {
+                        self.visit_expr(frames, diagnostics);
+                    }
+                }
                 StatementKind::Loop { ref mut block } => {
                     self.visit_block(block, diagnostics);
                 }
@@ -171,15 +263,28 @@ impl<'input> SymTabVisitor<'input> {
         self.symbol_names.pop_scope();
     }
 
-    fn visit_expr(&self, expr: &mut Expression<'_>, diagnostics: &mut Diagnostics) {
+    fn visit_expr(&mut self, expr: &mut Expression<'_>, diagnostics: &mut Diagnostics) {
         match &mut expr.kind {
             ExpressionKind::Variable(ident) => {
                 if let Some(symbol_id) = self.symbol_names.get(ident) {
                     expr.meta.set(symbol_id);
+                    self.read_symbols.insert(symbol_id);
+
+                    if self.has_property_access(symbol_id, PropertyAccess::WriteOnly) {
+                        diagnostics.add_message(
+                            CompilerErrorKind::ReadFromWriteOnlyProperty {
+                                name: ident.to_string(),
+                            }
+                            .into_message(expr.span),
+                        );
+                    }
                 } else {
                     diagnostics.add_message(
-                        CompilerErrorKind::UnknownVariable(ident.to_string())
-                            .into_message(expr.span),
+                        CompilerErrorKind::UnknownVariable {
+                            name: ident.to_string(),
+                            suggestion: self.symbol_names.closest_match(ident),
+                        }
+                        .into_message(expr.span),
                     );
                 }
             }
@@ -201,6 +306,7 @@ impl<'input> SymTabVisitor<'input> {
                     diagnostics.add_message(
                         CompilerErrorKind::UnknownFunction {
                             name: name.to_string(),
+                            suggestion: self.closest_function(name),
                         }
                         .into_message(expr.span),
                     );
@@ -254,6 +360,16 @@ impl<'input> NameTable<'input> {
         None
     }
 
+    /// The name in scope which is most similar to `name`, if there is one which is close enough
+    pub fn closest_match(&self, name: &str) -> Option<String> {
+        closest_match(
+            name,
+            self.names
+                .iter()
+                .flat_map(|nametab| nametab.keys().map(|name| name.as_ref())),
+        )
+    }
+
     pub fn push_scope(&mut self) {
         self.names.push(HashMap::new())
     }
@@ -264,6 +380,43 @@ impl<'input> NameTable<'input> {
     }
 }
 
+/// Finds the candidate with the smallest edit distance to `name`, so long as it is close enough
+/// that it is likely to be a typo. Ties are broken alphabetically so the result is deterministic.
+fn closest_match<'a>(name: &str, candidates: impl Iterator<Item = &'a str>) -> Option<String> {
+    let max_distance = (name.chars().count() / 3).max(1);
+
+    candidates
+        .filter(|candidate| *candidate != name && !candidate.starts_with('@'))
+        .map(|candidate| (edit_distance(name, candidate), candidate))
+        .filter(|(distance, _)| *distance <= max_distance)
+        .min()
+        .map(|(_, candidate)| candidate.to_string())
+}
+
+/// The Levenshtein distance between two strings
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b = b.chars().collect::<Vec<_>>();
+    let mut previous_row = (0..=b.len()).collect::<Vec<_>>();
+
+    for (i, a_char) in a.chars().enumerate() {
+        let mut current_row = vec![i + 1];
+
+        for (j, b_char) in b.iter().enumerate() {
+            let substitution_cost = usize::from(a_char != *b_char);
+
+            current_row.push(
+                (previous_row[j] + substitution_cost)
+                    .min(previous_row[j + 1] + 1)
+                    .min(current_row[j] + 1),
+            );
+        }
+
+        previous_row = current_row;
+    }
+
+    previous_row[b.len()]
+}
+
 pub struct SymTab<'input> {
     properties: Vec<Property>,
 
@@ -299,7 +452,15 @@ impl<'input> SymTab<'input> {
             .expect("Symbol should have a span")
     }
 
-    #[cfg(test)]
+    pub(crate) fn declaration_for_symbol(&self, symbol_id: SymbolId) -> Option<Span> {
+        self.symbol_names[symbol_id.0].1
+    }
+
+    /// Properties and every variable and argument declared in the script
+    pub(crate) fn symbol_count(&self) -> usize {
+        self.symbol_names.len()
+    }
+
     pub fn all_symbols(&self) -> impl Iterator<Item = (&'_ str, SymbolId)> + '_ {
         self.symbol_names
             .iter()
@@ -322,6 +483,30 @@ mod test {
 
     use super::*;
 
+    #[test]
+    fn edit_distance_examples() {
+        assert_eq!(edit_distance("counter", "counter"), 0);
+        assert_eq!(edit_distance("countr", "counter"), 1);
+        assert_eq!(edit_distance("conuter", "counter"), 2);
+        assert_eq!(edit_distance("", "abc"), 3);
+        assert_eq!(edit_distance("kitten", "sitting"), 3);
+    }
+
+    #[test]
+    fn closest_match_ignores_distant_names() {
+        let candidates = ["counter", "speed", "x"];
+
+        assert_eq!(
+            closest_match("countr", candidates.into_iter()).as_deref(),
+            Some("counter")
+        );
+        assert_eq!(
+            closest_match("y", candidates.into_iter()).as_deref(),
+            Some("x")
+        );
+        assert_eq!(closest_match("health", candidates.into_iter()), None);
+    }
+
     #[test]
     fn symtab_success_snapshot_tests() {
         glob!("snapshot_tests", "symtab_visitor/*_success.tapir", |path| {
@@ -333,7 +518,14 @@ mod test {
 
             let mut diagnostics = Diagnostics::new(file_id, path.file_name().unwrap(), &input);
 
-            let mut script = parser.parse(file_id, &mut diagnostics, lexer).unwrap();
+            let mut script = parser
+                .parse(
+                    file_id,
+                    CompileSettings::DEFAULT_FIX_PRECISION,
+                    &mut diagnostics,
+                    lexer,
+                )
+                .unwrap();
 
             let mut visitor = SymTabVisitor::new(
                 &CompileSettings {
@@ -341,8 +533,10 @@ mod test {
                         ty: Type::Int,
                         index: 0,
                         name: "int_prop".to_string(),
+                        access: PropertyAccess::ReadWrite,
                     }],
                     enable_optimisations: false,
+                    fix_precision: CompileSettings::DEFAULT_FIX_PRECISION,
                 },
                 &mut script.functions,
                 &mut diagnostics,
@@ -370,17 +564,38 @@ mod test {
             let mut diagnostics = Diagnostics::new(file_id, path.file_name().unwrap(), &input);
 
             let mut script = parser
-                .parse(FileId::new(0), &mut diagnostics, lexer)
+                .parse(
+                    FileId::new(0),
+                    CompileSettings::DEFAULT_FIX_PRECISION,
+                    &mut diagnostics,
+                    lexer,
+                )
                 .unwrap();
 
             let mut visitor = SymTabVisitor::new(
                 &CompileSettings {
-                    properties: vec![Property {
-                        ty: Type::Int,
-                        index: 0,
-                        name: "int_prop".to_string(),
-                    }],
+                    properties: vec![
+                        Property {
+                            ty: Type::Int,
+                            index: 0,
+                            name: "int_prop".to_string(),
+                            access: PropertyAccess::ReadWrite,
+                        },
+                        Property {
+                            ty: Type::Int,
+                            index: 1,
+                            name: "readonly_prop".to_string(),
+                            access: PropertyAccess::ReadOnly,
+                        },
+                        Property {
+                            ty: Type::Int,
+                            index: 2,
+                            name: "writeonly_prop".to_string(),
+                            access: PropertyAccess::WriteOnly,
+                        },
+                    ],
                     enable_optimisations: false,
+                    fix_precision: CompileSettings::DEFAULT_FIX_PRECISION,
                 },
                 &mut script.functions,
                 &mut diagnostics,
@@ -390,6 +605,9 @@ mod test {
                 visitor.visit_function(function, &mut diagnostics);
             }
 
+            // warnings have their own tests
+            diagnostics.allow("warnings");
+
             assert_snapshot!(diagnostics.pretty_string(false));
         });
     }
@@ -4,35 +4,59 @@ use serde::Serialize;
 
 use crate::{
     ast::{
-        self, BinaryOperator, Expression, Function, FunctionId, FunctionModifiers, FunctionReturn,
-        MaybeResolved, SymbolId,
+        self, BinaryOperator, Expression, Function, FunctionArgument, FunctionId,
+        FunctionModifiers, FunctionReturn, GeneratorModifier, MaybeResolved, SymbolId,
     },
-    reporting::{CompilerErrorKind, Diagnostics},
+    reporting::{CompilerErrorKind, Diagnostics, TypeOrigin},
     tokens::Span,
     types::{FunctionType, Type},
-    Trigger,
+    SentMessage, Trigger,
 };
 
 use super::{loop_visitor::LoopContainsNoBreak, symtab_visitor::SymTab, CompileSettings};
 
 pub struct TypeVisitor<'input> {
     type_table: Vec<Option<Type>>,
+    /// Where each variable was first given its type, for better error messages
+    type_origins: HashMap<SymbolId, Span>,
     functions: HashMap<FunctionId, FunctionInfo>,
 
-    trigger_types: HashMap<&'input str, TriggerInfo>,
+    trigger_types: HashMap<&'input str, TriggerInfo<'input>>,
+
+    /// The event handlers in this script by name, since messages sent to them must match
+    event_handlers: HashMap<&'input str, FunctionId>,
+    messages: HashMap<&'input str, MessageInfo>,
+    /// The spans of the generators in this script by name, since messages can't be sent to them
+    generators: HashMap<&'input str, Span>,
+
+    /// Set while visiting a generator, to check its `yield`s
+    current_generator: Option<GeneratorModifier>,
 }
 
 #[derive(Clone, Copy, Debug)]
 pub struct TriggerId(pub usize);
 
+#[derive(Clone, Copy, Debug)]
+pub struct MessageId(pub usize);
+
 struct FunctionInfo {
     span: Span,
+    argument_spans: Vec<Span>,
     ty: FunctionType,
     modifiers: FunctionModifiers,
 }
 
 #[derive(Serialize, Clone, Debug)]
-struct TriggerInfo {
+struct TriggerInfo<'input> {
+    span: Span,
+    ty: Vec<Type>,
+    index: usize,
+    field_names: Option<Vec<&'input str>>,
+    argument_spans: Vec<Span>,
+}
+
+#[derive(Serialize, Clone, Debug)]
+struct MessageInfo {
     span: Span,
     ty: Vec<Type>,
     index: usize,
@@ -41,8 +65,18 @@ struct TriggerInfo {
 impl<'input> TypeVisitor<'input> {
     pub fn new(settings: &CompileSettings, functions: &[Function<'input>]) -> Self {
         let mut resolved_functions = HashMap::new();
+        let mut event_handlers = HashMap::new();
+        let mut generators = HashMap::new();
 
         for function in functions {
+            if function.modifiers.is_event_handler.is_some() {
+                event_handlers.insert(function.name, *function.meta.get().unwrap());
+            }
+
+            if function.modifiers.is_generator.is_some() {
+                generators.insert(function.name, function.span);
+            }
+
             let function_type = FunctionType {
                 args: function.arguments.iter().map(|t| t.t.t).collect(),
                 rets: function.return_types.types.iter().map(|t| t.t).collect(),
@@ -52,6 +86,7 @@ impl<'input> TypeVisitor<'input> {
                 *function.meta.get().unwrap(),
                 FunctionInfo {
                     span: function.span,
+                    argument_spans: function.arguments.iter().map(argument_span).collect(),
                     ty: function_type,
                     modifiers: function.modifiers.clone(),
                 },
@@ -64,9 +99,16 @@ impl<'input> TypeVisitor<'input> {
                 .iter()
                 .map(|prop| Some(prop.ty))
                 .collect(),
+            type_origins: HashMap::new(),
 
             functions: resolved_functions,
             trigger_types: HashMap::new(),
+
+            event_handlers,
+            messages: HashMap::new(),
+            generators,
+
+            current_generator: None,
         }
     }
 
@@ -75,6 +117,7 @@ impl<'input> TypeVisitor<'input> {
         symbol_id: SymbolId,
         ty: Type,
         span: Span,
+        symtab: &SymTab,
         diagnostics: &mut Diagnostics,
     ) {
         if self.type_table.len() <= symbol_id.0 {
@@ -87,10 +130,18 @@ impl<'input> TypeVisitor<'input> {
                 return;
             }
 
+            let expected_because = match self.type_origins.get(&symbol_id) {
+                Some(origin) => Some(TypeOrigin::Declaration(*origin)),
+                None => symtab
+                    .get_property(symbol_id)
+                    .map(|property| TypeOrigin::Property(property.name.clone())),
+            };
+
             diagnostics.add_message(
                 CompilerErrorKind::TypeError {
                     expected: self.type_table[symbol_id.0].unwrap(),
                     actual: ty,
+                    expected_because,
                 }
                 .into_message(span),
             );
@@ -98,6 +149,10 @@ impl<'input> TypeVisitor<'input> {
             return;
         }
 
+        if self.type_table[symbol_id.0].is_none() {
+            self.type_origins.insert(symbol_id, span);
+        }
+
         self.type_table[symbol_id.0] = Some(ty);
     }
 
@@ -132,30 +187,16 @@ impl<'input> TypeVisitor<'input> {
                 panic!("Should've resolved the symbol by now")
             };
 
-            self.resolve_type(symbol_id, argument.t.t, argument.span, diagnostics);
+            self.resolve_type(
+                symbol_id,
+                argument.t.t,
+                argument_span(argument),
+                symtab,
+                diagnostics,
+            );
         }
 
-        if let FunctionModifiers {
-            is_event_handler: Some(event_span),
-        } = &function.modifiers
-        {
-            if !function.return_types.types.is_empty() {
-                diagnostics.add_message(
-                    CompilerErrorKind::EventFunctionsShouldNotHaveAReturnType {
-                        return_type_span: function.return_types.span,
-                        function_name: function.name.to_string(),
-                        event_span: *event_span,
-                    }
-                    .into_message(function.span),
-                );
-
-                // we've added an error, so compilation will fail
-                // but we also want to warn about invalid returns etc, so
-                // change to what the user intended and warn about everything else too
-                function.return_types.types.clear();
-                function.return_types.span = *event_span;
-            }
-        }
+        self.current_generator = function.modifiers.is_generator.clone();
 
         let block_analysis_result = self.visit_block(
             &mut function.statements,
@@ -186,8 +227,41 @@ impl<'input> TypeVisitor<'input> {
     ) -> BlockAnalysisResult {
         for statement in ast.iter_mut() {
             match &mut statement.kind {
-                ast::StatementKind::Wait
-                | ast::StatementKind::Break
+                ast::StatementKind::Wait => {
+                    if let Some(generator) = &self.current_generator {
+                        diagnostics.add_message(
+                            CompilerErrorKind::WaitInGenerator {
+                                generator_span: generator.span,
+                            }
+                            .into_message(statement.span),
+                        );
+                    }
+                }
+                ast::StatementKind::Yield { value } => {
+                    let value_type = self.type_for_expression(value, symtab, diagnostics);
+
+                    match &self.current_generator {
+                        None => diagnostics.add_message(
+                            CompilerErrorKind::YieldOutsideGenerator.into_message(statement.span),
+                        ),
+                        Some(generator)
+                            if value_type != generator.yields.t
+                                && value_type != Type::Error
+                                && generator.yields.t != Type::Error =>
+                        {
+                            diagnostics.add_message(
+                                CompilerErrorKind::IncorrectYieldType {
+                                    expected: generator.yields.t,
+                                    actual: value_type,
+                                    yield_type_span: generator.yields.span,
+                                }
+                                .into_message(value.span),
+                            );
+                        }
+                        Some(_) => {}
+                    }
+                }
+                ast::StatementKind::Break
                 | ast::StatementKind::Continue
                 | ast::StatementKind::Nop
                 | ast::StatementKind::Error => {}
@@ -197,16 +271,15 @@ impl<'input> TypeVisitor<'input> {
                         .get()
                         .expect("Should've been resolved by symbol resolution");
                     let expr_type = self.type_for_expression(value, symtab, diagnostics);
-                    self.resolve_type(*ident, expr_type, statement.span, diagnostics);
+                    self.resolve_type(*ident, expr_type, statement.span, symtab, diagnostics);
                 }
                 ast::StatementKind::Assignment { value, .. } => {
-                    let ident: &SymbolId = statement
-                        .meta
-                        .get()
-                        .expect("Should've been resolved by symbol resolution");
-
                     let expr_type = self.type_for_expression(value, symtab, diagnostics);
-                    self.resolve_type(*ident, expr_type, statement.span, diagnostics);
+
+                    // if this didn't resolve, then symbol resolution will already have reported it
+                    if let Some(ident) = statement.meta.get::<SymbolId>() {
+                        self.resolve_type(*ident, expr_type, statement.span, symtab, diagnostics);
+                    }
                 }
                 ast::StatementKind::If {
                     condition,
@@ -288,7 +361,15 @@ impl<'input> TypeVisitor<'input> {
                         diagnostics,
                     );
                 }
-                ast::StatementKind::Spawn { name, arguments } => {
+                ast::StatementKind::Spawn {
+                    group,
+                    name,
+                    arguments,
+                } => {
+                    if let Some(group) = group {
+                        self.check_group(group, symtab, diagnostics);
+                    }
+
                     self.type_for_call(
                         statement.span,
                         name,
@@ -317,16 +398,55 @@ impl<'input> TypeVisitor<'input> {
                         return BlockAnalysisResult::AllBranchesReturn;
                     }
                 }
-                ast::StatementKind::Trigger { name, arguments } => {
+                ast::StatementKind::Trigger {
+                    name,
+                    arguments,
+                    field_names,
+                } => {
                     let trigger_arguments = arguments
                         .iter_mut()
                         .map(|arg| self.type_for_expression(arg, symtab, diagnostics))
                         .collect::<Vec<_>>();
 
+                    if let Some(field_names) = field_names {
+                        for (i, field_name) in field_names.iter().enumerate() {
+                            if let Some(first) = field_names[..i]
+                                .iter()
+                                .find(|other| other.name == field_name.name)
+                            {
+                                diagnostics.add_message(
+                                    CompilerErrorKind::DuplicateTriggerField {
+                                        name: field_name.name.to_owned(),
+                                        first_span: first.span,
+                                    }
+                                    .into_message(field_name.span),
+                                );
+                            }
+                        }
+                    }
+
+                    let trigger_field_names = field_names
+                        .as_ref()
+                        .map(|field_names| field_names.iter().map(|field| field.name).collect());
+
                     let trigger_index;
 
                     if let Some(trigger_info) = self.trigger_types.get(name) {
-                        if trigger_info.ty.len() != trigger_arguments.len()
+                        if trigger_info.field_names != trigger_field_names {
+                            diagnostics.add_message(
+                                CompilerErrorKind::TriggerIncorrectFields {
+                                    name: name.to_owned(),
+                                    first_definition_span: trigger_info.span,
+                                    first_definition_fields: owned_field_names(
+                                        &trigger_info.field_names,
+                                    ),
+                                    second_definition_fields: owned_field_names(
+                                        &trigger_field_names,
+                                    ),
+                                }
+                                .into_message(statement.span),
+                            );
+                        } else if trigger_info.ty.len() != trigger_arguments.len()
                             || trigger_info.ty.iter().zip(&trigger_arguments).any(
                                 |(expected, actual)| {
                                     actual != expected
@@ -350,24 +470,137 @@ impl<'input> TypeVisitor<'input> {
                     } else {
                         trigger_index = self.trigger_types.len();
 
+                        // named arguments point at their name, since that's what has to match rust
+                        let argument_spans = match field_names {
+                            Some(field_names) => {
+                                field_names.iter().map(|field| field.span).collect()
+                            }
+                            None => arguments.iter().map(|argument| argument.span).collect(),
+                        };
+
                         self.trigger_types.insert(
                             name,
                             TriggerInfo {
                                 span: statement.span,
                                 ty: trigger_arguments,
                                 index: trigger_index,
+                                field_names: trigger_field_names,
+                                argument_spans,
                             },
                         );
                     }
 
                     statement.meta.set(TriggerId(trigger_index));
                 }
+                ast::StatementKind::Send {
+                    target,
+                    name,
+                    arguments,
+                } => {
+                    let target_type = self.type_for_expression(target, symtab, diagnostics);
+                    if !matches!(target_type, Type::Int | Type::Error) {
+                        diagnostics.add_message(
+                            CompilerErrorKind::InvalidTypeForSendTarget { got: target_type }
+                                .into_message(target.span),
+                        );
+                    }
+
+                    let message_arguments = arguments
+                        .iter_mut()
+                        .map(|arg| self.type_for_expression(arg, symtab, diagnostics))
+                        .collect::<Vec<_>>();
+
+                    let message_index;
+
+                    if let Some(message_info) = self.messages.get(name) {
+                        if !types_match(&message_info.ty, &message_arguments) {
+                            diagnostics.add_message(
+                                CompilerErrorKind::MessageIncorrectArgs {
+                                    name: name.to_string(),
+                                    first_definition_span: message_info.span,
+                                    first_definition_args: message_info.ty.clone(),
+                                    second_definition_args: message_arguments,
+                                }
+                                .into_message(statement.span),
+                            );
+                        }
+
+                        message_index = message_info.index;
+                    } else {
+                        // only the first send needs checking, since the rest must match it
+                        if let Some(&generator_span) = self.generators.get(name) {
+                            diagnostics.add_message(
+                                CompilerErrorKind::CannotSendToGenerator {
+                                    name: name.to_string(),
+                                    generator_span,
+                                }
+                                .into_message(statement.span),
+                            );
+                        } else if let Some(event_handler) = self
+                            .event_handlers
+                            .get(name)
+                            .map(|function_id| &self.functions[function_id])
+                        {
+                            if !types_match(&event_handler.ty.args, &message_arguments) {
+                                diagnostics.add_message(
+                                    CompilerErrorKind::MessageEventHandlerMismatch {
+                                        name: name.to_string(),
+                                        event_handler_span: event_handler.span,
+                                        event_handler_args: event_handler.ty.args.clone(),
+                                        message_args: message_arguments.clone(),
+                                    }
+                                    .into_message(statement.span),
+                                );
+                            }
+                        }
+
+                        message_index = self.messages.len();
+                        self.messages.insert(
+                            name,
+                            MessageInfo {
+                                span: statement.span,
+                                ty: message_arguments,
+                                index: message_index,
+                            },
+                        );
+                    }
+
+                    statement.meta.set(MessageId(message_index));
+                }
+                ast::StatementKind::ControlGroup { group, control } => {
+                    self.check_group(group, symtab, diagnostics);
+
+                    if let Some(frames) = control.frames_mut() {
+                        let frames_type = self.type_for_expression(frames, symtab, diagnostics);
+                        if !matches!(frames_type, Type::Int | Type::Error) {
+                            diagnostics.add_message(
+                                CompilerErrorKind::InvalidTypeForTimeScale { got: frames_type }
+                                    .into_message(frames.span),
+                            );
+                        }
+                    }
+                }
             }
         }
 
         BlockAnalysisResult::ContainsNonReturningBranch
     }
 
+    /// Groups are identified by ints, the same as the targets of `send`
+    fn check_group(
+        &mut self,
+        group: &mut Expression<'input>,
+        symtab: &SymTab,
+        diagnostics: &mut Diagnostics,
+    ) {
+        let group_type = self.type_for_expression(group, symtab, diagnostics);
+        if !matches!(group_type, Type::Int | Type::Error) {
+            diagnostics.add_message(
+                CompilerErrorKind::InvalidTypeForGroup { got: group_type }.into_message(group.span),
+            );
+        }
+    }
+
     pub fn into_type_table(
         self,
         symtab: &SymTab,
@@ -378,6 +611,9 @@ impl<'input> TypeVisitor<'input> {
             if let Some(ty) = ty {
                 types.push(ty);
             } else {
+                // keep the indices lined up with the symbol ids
+                types.push(Type::Error);
+
                 diagnostics.add_message(
                     CompilerErrorKind::UnknownType(
                         symtab.name_for_symbol(SymbolId(i)).into_owned(),
@@ -396,6 +632,7 @@ impl<'input> TypeVisitor<'input> {
                 .collect(),
 
             triggers: self.trigger_types,
+            messages: self.messages,
         }
     }
 
@@ -410,10 +647,11 @@ impl<'input> TypeVisitor<'input> {
             ast::ExpressionKind::Fix(_) => Type::Fix,
             ast::ExpressionKind::Bool(_) => Type::Bool,
             ast::ExpressionKind::Variable(_) => {
-                let symbol_id: &SymbolId = expression
-                    .meta
-                    .get()
-                    .expect("Should have a symbol id from symbol resolution");
+                let Some(symbol_id) = expression.meta.get::<SymbolId>() else {
+                    // symbol resolution will already have reported this
+                    return Type::Error;
+                };
+
                 self.get_type(*symbol_id, expression.span, symtab, diagnostics)
             }
             ast::ExpressionKind::BinaryOperation { lhs, operator, rhs } => {
@@ -427,8 +665,13 @@ impl<'input> TypeVisitor<'input> {
 
                 if lhs_type != rhs_type && *operator != BinaryOperator::Then {
                     diagnostics.add_message(
-                        CompilerErrorKind::BinaryOperatorTypeError { lhs_type, rhs_type }
-                            .into_message(expression.span),
+                        CompilerErrorKind::BinaryOperatorTypeError {
+                            lhs_type,
+                            rhs_type,
+                            lhs_span: lhs.span,
+                            rhs_span: rhs.span,
+                        }
+                        .into_message(expression.span),
                     );
 
                     return Type::Error;
@@ -503,6 +746,16 @@ impl<'input> TypeVisitor<'input> {
                 .into_message(span),
             );
 
+            return vec![Type::Error];
+        } else if function_info.modifiers.is_generator.is_some() {
+            diagnostics.add_message(
+                CompilerErrorKind::CannotCallGenerator {
+                    function_span: function_info.span,
+                    function_name: name.to_string(),
+                }
+                .into_message(span),
+            );
+
             return vec![Type::Error];
         } else if argument_types.len() != function_info.ty.args.len() {
             diagnostics.add_message(
@@ -511,18 +764,22 @@ impl<'input> TypeVisitor<'input> {
                     actual: argument_types.len(),
                     function_span: function_info.span,
                     function_name: name.to_string(),
+                    argument_spans: function_info.argument_spans.clone(),
                 }
                 .into_message(span),
             );
         } else {
-            for ((actual, actual_span), expected) in
-                argument_types.iter().zip(&function_info.ty.args)
+            for (((actual, actual_span), expected), declaration) in argument_types
+                .iter()
+                .zip(&function_info.ty.args)
+                .zip(&function_info.argument_spans)
             {
                 if actual != expected && *actual != Type::Error && *expected != Type::Error {
                     diagnostics.add_message(
                         CompilerErrorKind::TypeError {
                             expected: *expected,
                             actual: *actual,
+                            expected_because: Some(TypeOrigin::Declaration(*declaration)),
                         }
                         .into_message(*actual_span),
                     );
@@ -534,6 +791,23 @@ impl<'input> TypeVisitor<'input> {
     }
 }
 
+/// Covers both the name and the type, so `x: int`
+fn argument_span(argument: &FunctionArgument) -> Span {
+    Span::new(
+        argument.span.file_id,
+        argument.span.start,
+        argument.t.span.end,
+    )
+}
+
+/// Whether the arguments have the expected types, ignoring any which are already errors
+fn types_match(expected: &[Type], actual: &[Type]) -> bool {
+    expected.len() == actual.len()
+        && expected.iter().zip(actual).all(|(expected, actual)| {
+            expected == actual || *expected == Type::Error || *actual == Type::Error
+        })
+}
+
 #[derive(Clone, Copy, PartialEq, Eq)]
 enum BlockAnalysisResult {
     AllBranchesReturn,
@@ -545,11 +819,11 @@ pub struct TypeTable<'input> {
     types: Vec<Type>,
     num_function_returns: HashMap<FunctionId, usize>,
 
-    triggers: HashMap<&'input str, TriggerInfo>,
+    triggers: HashMap<&'input str, TriggerInfo<'input>>,
+    messages: HashMap<&'input str, MessageInfo>,
 }
 
 impl TypeTable<'_> {
-    #[cfg(test)]
     pub fn type_for_symbol(&self, symbol_id: SymbolId) -> Type {
         self.types[symbol_id.0]
     }
@@ -560,22 +834,49 @@ impl TypeTable<'_> {
 
     pub fn triggers(&self) -> Vec<Trigger> {
         let mut result = vec![];
-        result.resize_with(self.triggers.len(), || Trigger {
-            name: String::new(),
-            arguments: vec![],
-        });
+        result.resize_with(self.triggers.len(), || None);
 
         for (name, info) in &self.triggers {
-            result[info.index] = Trigger {
+            result[info.index] = Some(Trigger {
                 name: name.to_string(),
                 arguments: info.ty.clone(),
-            };
+                field_names: owned_field_names(&info.field_names),
+                span: info.span,
+                argument_spans: info.argument_spans.clone(),
+            });
         }
 
         result
+            .into_iter()
+            .map(|trigger| trigger.expect("Trigger indices should be contiguous"))
+            .collect()
+    }
+
+    pub fn messages(&self) -> Vec<SentMessage> {
+        let mut result = vec![];
+        result.resize_with(self.messages.len(), || None);
+
+        for (name, info) in &self.messages {
+            result[info.index] = Some(SentMessage {
+                name: name.to_string(),
+                arguments: info.ty.clone(),
+                span: info.span,
+            });
+        }
+
+        result
+            .into_iter()
+            .map(|message| message.expect("Message indices should be contiguous"))
+            .collect()
     }
 }
 
+fn owned_field_names(field_names: &Option<Vec<&str>>) -> Option<Vec<String>> {
+    field_names
+        .as_ref()
+        .map(|field_names| field_names.iter().map(|&name| name.to_owned()).collect())
+}
+
 #[cfg(test)]
 mod test {
     use std::fs;
@@ -583,7 +884,7 @@ mod test {
     use insta::{assert_ron_snapshot, assert_snapshot, glob};
 
     use crate::{
-        compile::{loop_visitor, symtab_visitor::SymTabVisitor, Property},
+        compile::{loop_visitor, symtab_visitor::SymTabVisitor, Property, PropertyAccess},
         grammar,
         lexer::Lexer,
         tokens::FileId,
@@ -604,7 +905,12 @@ mod test {
             let mut diagnostics = Diagnostics::new(file_id, path.file_name().unwrap(), &input);
 
             let mut script = parser
-                .parse(FileId::new(0), &mut diagnostics, lexer)
+                .parse(
+                    FileId::new(0),
+                    CompileSettings::DEFAULT_FIX_PRECISION,
+                    &mut diagnostics,
+                    lexer,
+                )
                 .unwrap();
 
             let settings = CompileSettings {
@@ -612,8 +918,10 @@ mod test {
                     ty: Type::Int,
                     index: 0,
                     name: "int_prop".to_string(),
+                    access: PropertyAccess::ReadWrite,
                 }],
                 enable_optimisations: false,
+                fix_precision: CompileSettings::DEFAULT_FIX_PRECISION,
             };
             let mut symtab_visitor =
                 SymTabVisitor::new(&settings, &mut script.functions, &mut diagnostics);
@@ -631,7 +939,7 @@ mod test {
                 );
             }
 
-            assert!(!diagnostics.has_any(), "{} failed", path.display());
+            assert!(!diagnostics.has_errors(), "{} failed", path.display());
 
             let symtab = symtab_visitor.get_symtab();
             let type_table = type_visitor.into_type_table(symtab, &mut diagnostics);
@@ -656,15 +964,24 @@ mod test {
 
             let mut diagnostics = Diagnostics::new(file_id, path.file_name().unwrap(), &input);
 
-            let mut script = parser.parse(file_id, &mut diagnostics, lexer).unwrap();
+            let mut script = parser
+                .parse(
+                    file_id,
+                    CompileSettings::DEFAULT_FIX_PRECISION,
+                    &mut diagnostics,
+                    lexer,
+                )
+                .unwrap();
 
             let settings = CompileSettings {
                 properties: vec![Property {
                     ty: Type::Int,
                     index: 0,
                     name: "int_prop".to_string(),
+                    access: PropertyAccess::ReadWrite,
                 }],
                 enable_optimisations: false,
+                fix_precision: CompileSettings::DEFAULT_FIX_PRECISION,
             };
             let mut symtab_visitor =
                 SymTabVisitor::new(&settings, &mut script.functions, &mut diagnostics);
@@ -679,6 +996,9 @@ mod test {
 
             type_visitor.into_type_table(symtab_visitor.get_symtab(), &mut diagnostics);
 
+            // warnings have their own tests
+            diagnostics.allow("warnings");
+
             let err_str = diagnostics.pretty_string(false);
 
             assert_snapshot!(err_str);
@@ -0,0 +1,173 @@
+use crate::{
+    ast::{Function, FunctionId, Statement, StatementKind},
+    reporting::{CompilerWarningKind, Diagnostics},
+    tokens::Span,
+};
+
+use super::optimisations::reachable_functions;
+
+/// Warns about code which will never do anything. The optimiser would otherwise silently remove
+/// it, but it is more likely to be a mistake than something the author meant to write.
+pub fn visit_unused_code(functions: &[Function], diagnostics: &mut Diagnostics) {
+    let reachable = reachable_functions(functions);
+
+    for function in functions {
+        let function_id: FunctionId = *function.meta.get().unwrap();
+
+        if !reachable.contains(&function_id) {
+            diagnostics.add_message(
+                CompilerWarningKind::UnusedFunction {
+                    name: function.name.to_string(),
+                }
+                .into_message(function.span),
+            );
+        }
+
+        if function.modifiers.is_event_handler.is_some() && function.statements.is_empty() {
+            diagnostics.add_message(
+                CompilerWarningKind::EmptyEventHandler {
+                    name: function.name.to_string(),
+                }
+                .into_message(function.span),
+            );
+        }
+
+        visit_block(&function.statements, diagnostics);
+    }
+}
+
+/// Returns true if control flow can never get past the end of this block
+fn visit_block(block: &[Statement], diagnostics: &mut Diagnostics) -> bool {
+    for (i, statement) in block.iter().enumerate() {
+        if !visit_statement(statement, diagnostics) {
+            continue;
+        }
+
+        if let (Some(first), Some(last)) = (block.get(i + 1), block.last()) {
+            diagnostics.add_message(
+                CompilerWarningKind::UnreachableCode {
+                    diverging_statement: statement.span,
+                }
+                .into_message(Span::new(
+                    first.span.file_id,
+                    first.span.start,
+                    last.span.end,
+                )),
+            );
+        }
+
+        // everything after here is unreachable, so there's no point warning about it again
+        return true;
+    }
+
+    false
+}
+
+fn visit_statement(statement: &Statement, diagnostics: &mut Diagnostics) -> bool {
+    match &statement.kind {
+        StatementKind::Return { .. } | StatementKind::Break | StatementKind::Continue => true,
+        StatementKind::If {
+            true_block,
+            false_block,
+            ..
+        } => {
+            let true_block_diverges = visit_block(true_block, diagnostics);
+            let false_block_diverges = visit_block(false_block, diagnostics);
+
+            true_block_diverges && false_block_diverges
+        }
+        StatementKind::Block { block } => visit_block(block, diagnostics),
+        StatementKind::Loop { block } => {
+            visit_block(block, diagnostics);
+
+            // the only way out of a loop is to break out of it
+            !contains_break(block)
+        }
+        StatementKind::Error
+        | StatementKind::VariableDeclaration { .. }
+        | StatementKind::Assignment { .. }
+        | StatementKind::Wait
+        | StatementKind::Nop
+        | StatementKind::Call { .. }
+        | StatementKind::Spawn { .. }
+        | StatementKind::Trigger { .. }
+        | StatementKind::Send { .. }
+        | StatementKind::ControlGroup { .. }
+        | StatementKind::Yield { .. } => false,
+    }
+}
+
+/// Whether there is a `break` which would exit the loop with this body
+fn contains_break(block: &[Statement]) -> bool {
+    block.iter().any(|statement| match &statement.kind {
+        StatementKind::Break => true,
+        StatementKind::If {
+            true_block,
+            false_block,
+            ..
+        } => contains_break(true_block) || contains_break(false_block),
+        StatementKind::Block { block } => contains_break(block),
+        // a break in here would exit the inner loop, not ours
+        StatementKind::Loop { .. } => false,
+        _ => false,
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use std::fs;
+
+    use insta::{assert_snapshot, glob};
+
+    use crate::{
+        compile::{loop_visitor::visit_loop_check, symtab_visitor::SymTabVisitor},
+        grammar,
+        lexer::Lexer,
+        tokens::FileId,
+        CompileSettings,
+    };
+
+    use super::*;
+
+    #[test]
+    fn unused_code_snapshot_tests() {
+        glob!("snapshot_tests", "unused_code/*.tapir", |path| {
+            let input = fs::read_to_string(path).unwrap();
+
+            let file_id = FileId::new(0);
+            let lexer = Lexer::new(&input, file_id);
+            let parser = grammar::ScriptParser::new();
+
+            let mut diagnostics = Diagnostics::new(file_id, path.file_name().unwrap(), &input);
+
+            let mut script = parser
+                .parse(
+                    file_id,
+                    CompileSettings::DEFAULT_FIX_PRECISION,
+                    &mut diagnostics,
+                    lexer,
+                )
+                .unwrap();
+
+            let compile_settings = CompileSettings {
+                properties: Vec::new(),
+                enable_optimisations: false,
+                fix_precision: CompileSettings::DEFAULT_FIX_PRECISION,
+            };
+
+            let mut symtab_visitor =
+                SymTabVisitor::new(&compile_settings, &mut script.functions, &mut diagnostics);
+
+            for function in &mut script.functions {
+                symtab_visitor.visit_function(function, &mut diagnostics);
+                visit_loop_check(function, &mut diagnostics);
+            }
+
+            // only interested in the warnings from this visitor
+            let mut diagnostics = Diagnostics::new(file_id, path.file_name().unwrap(), &input);
+            visit_unused_code(&script.functions, &mut diagnostics);
+
+            assert_snapshot!(diagnostics.pretty_string(false));
+        });
+    }
+}
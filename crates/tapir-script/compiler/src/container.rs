@@ -0,0 +1,139 @@
+//! Writes compiled scripts out in the container format described in [`bytecode::container`], so
+//! they can be shipped and loaded without the compiler.
+
+use bytecode::container::{
+    PropertyAccess as ContainerPropertyAccess, ValueType, FLAG_DEBUG, FORMAT_VERSION, MAGIC,
+    UNBOUNDED_STACK_DEPTH,
+};
+
+use crate::{CompileResult, PropertyAccess, Type};
+
+impl CompileResult {
+    /// Serializes the script so the vm can load it later. The debug section holds the names of
+    /// every function and where they start, which is only useful for tooling, so leave it out of
+    /// release builds.
+    pub fn to_container(&self, include_debug_info: bool) -> Vec<u8> {
+        let mut writer = Writer::default();
+
+        writer.bytes(&MAGIC);
+        writer.bytes(&FORMAT_VERSION.to_le_bytes());
+        writer.bytes(&bytecode::ISA_REVISION.to_le_bytes());
+        writer.u8(self.fix_precision);
+        writer.u8(if include_debug_info { FLAG_DEBUG } else { 0 });
+
+        writer.u32(self.bytecode.len());
+        for &word in &self.bytecode {
+            writer.bytes(&word.to_le_bytes());
+        }
+
+        writer.u16(self.stack_depths.len());
+        for stack_depth in &self.stack_depths {
+            writer.u32(stack_depth.bytecode_offset);
+            writer.u32(
+                stack_depth
+                    .max_depth
+                    .unwrap_or(UNBOUNDED_STACK_DEPTH as usize),
+            );
+        }
+
+        writer.u16(self.properties.len());
+        for property in &self.properties {
+            writer.string(&property.name);
+            writer.u8(property.index as u8);
+            writer.value_type(property.ty);
+            writer.u8(match property.access {
+                PropertyAccess::ReadWrite => ContainerPropertyAccess::ReadWrite,
+                PropertyAccess::ReadOnly => ContainerPropertyAccess::ReadOnly,
+                PropertyAccess::WriteOnly => ContainerPropertyAccess::WriteOnly,
+            } as u8);
+        }
+
+        writer.u16(self.event_handlers.len());
+        for event_handler in &self.event_handlers {
+            writer.string(&event_handler.name);
+            writer.u32(event_handler.bytecode_offset);
+            writer.types(event_handler.arguments.iter().map(|argument| argument.ty));
+            writer.types(event_handler.return_types.iter().copied());
+            writer.types(event_handler.yields.into_iter());
+        }
+
+        writer.u16(self.triggers.len());
+        for trigger in &self.triggers {
+            writer.string(&trigger.name);
+            writer.types(trigger.arguments.iter().copied());
+
+            match &trigger.field_names {
+                Some(field_names) => {
+                    writer.u8(1);
+                    for field_name in field_names {
+                        writer.string(field_name);
+                    }
+                }
+                None => writer.u8(0),
+            }
+        }
+
+        writer.u16(self.messages.len());
+        for message in &self.messages {
+            writer.string(&message.name);
+            writer.types(message.arguments.iter().copied());
+        }
+
+        if include_debug_info {
+            writer.u16(self.functions.len());
+            for function in &self.functions {
+                writer.string(&function.name);
+                writer.u32(function.bytecode_offset);
+            }
+        }
+
+        writer.data
+    }
+}
+
+#[derive(Default)]
+struct Writer {
+    data: Vec<u8>,
+}
+
+impl Writer {
+    fn bytes(&mut self, bytes: &[u8]) {
+        self.data.extend_from_slice(bytes);
+    }
+
+    fn u8(&mut self, value: u8) {
+        self.data.push(value);
+    }
+
+    fn u16(&mut self, value: usize) {
+        let value = u16::try_from(value).expect("Value too large for the container format");
+        self.bytes(&value.to_le_bytes());
+    }
+
+    fn u32(&mut self, value: usize) {
+        let value = u32::try_from(value).expect("Value too large for the container format");
+        self.bytes(&value.to_le_bytes());
+    }
+
+    fn string(&mut self, value: &str) {
+        self.u16(value.len());
+        self.bytes(value.as_bytes());
+    }
+
+    fn value_type(&mut self, ty: Type) {
+        self.u8(match ty {
+            Type::Int => ValueType::Int,
+            Type::Fix => ValueType::Fix,
+            Type::Bool => ValueType::Bool,
+            Type::Error => unreachable!("Should not have errors after compiling"),
+        } as u8);
+    }
+
+    fn types(&mut self, types: impl ExactSizeIterator<Item = Type>) {
+        let count = u8::try_from(types.len()).expect("Too many arguments or return types");
+        self.u8(count);
+        for ty in types {
+            self.value_type(ty);
+        }
+    }
+}
@@ -0,0 +1,703 @@
+use std::{collections::HashMap, fmt::Write, path::Path};
+
+use crate::{
+    ast::{
+        BinaryOperator, Expression, ExpressionKind, ExternFunctionDefinition, Function,
+        FunctionArgument, FunctionReturn, GroupControl, MaybeResolved, Statement, StatementKind,
+    },
+    grammar,
+    lexer::{Lexer, TriviaLexer, TriviaToken},
+    reporting::Diagnostics,
+    tokens::{FileId, Token},
+    CompileSettings,
+};
+
+const INDENT: &str = "    ";
+
+/// Formats a script in the canonical style. Comments and blank lines are kept, but
+/// indentation, spacing and parentheses are rewritten.
+///
+/// Scripts which fail to parse are returned as errors rather than formatted, since
+/// there is no way to know what the user intended.
+pub fn format(filename: impl AsRef<Path>, input: &str) -> Result<String, Diagnostics> {
+    let file_id = FileId::new(0);
+
+    let mut diagnostics = Diagnostics::new(file_id, filename, input);
+
+    let lexer = Lexer::new(input, file_id);
+    let parser = grammar::ScriptParser::new();
+
+    let ast = match parser.parse(
+        file_id,
+        CompileSettings::DEFAULT_FIX_PRECISION,
+        &mut diagnostics,
+        lexer,
+    ) {
+        Ok(ast) => ast,
+        Err(e) => {
+            diagnostics.add_lalrpop(e, file_id);
+            return Err(diagnostics);
+        }
+    };
+
+    if diagnostics.has_any() {
+        return Err(diagnostics);
+    }
+
+    let mut formatter = Formatter::new(input, file_id);
+
+    // The AST doesn't keep the order of the top level items, so put them back in source order
+    let mut items = vec![];
+    let (top_level, functions) = ast
+        .functions
+        .split_first()
+        .expect("the top level function always exists");
+
+    items.extend(top_level.statements.iter().map(TopLevelItem::Statement));
+    items.extend(functions.iter().map(TopLevelItem::Function));
+    items.extend(
+        ast.extern_functions
+            .iter()
+            .map(TopLevelItem::ExternFunction),
+    );
+
+    items.sort_by_key(|item| formatter.item_start(item));
+
+    let mut previous_was_function = false;
+    for item in &items {
+        let is_function = matches!(item, TopLevelItem::Function(_));
+        let start = formatter.item_start(item);
+
+        // functions get a blank line either side, which goes before any comments attached to them
+        let force_separator = is_function || previous_was_function;
+        let wrote_comments = formatter.comments_before(start, 0, force_separator);
+        formatter.separator(start, force_separator && !wrote_comments);
+
+        match item {
+            TopLevelItem::Statement(statement) => formatter.statement(statement, 0),
+            TopLevelItem::Function(function) => formatter.function(function),
+            TopLevelItem::ExternFunction(function) => formatter.extern_function(function),
+        }
+
+        previous_was_function = is_function;
+    }
+
+    formatter.comments_before(input.len(), 0, previous_was_function);
+
+    Ok(formatter.output)
+}
+
+enum TopLevelItem<'a, 'input> {
+    Statement(&'a Statement<'input>),
+    Function(&'a Function<'input>),
+    ExternFunction(&'a ExternFunctionDefinition<'input>),
+}
+
+struct Comment<'input> {
+    start: usize,
+    end: usize,
+    text: &'input str,
+}
+
+struct Formatter<'input> {
+    input: &'input str,
+    output: String,
+
+    tokens: Vec<(usize, Token<'input>, usize)>,
+    comments: Vec<Comment<'input>>,
+    next_comment: usize,
+
+    /// Maps the start of each `{` to the span of its matching `}`
+    matching_braces: HashMap<usize, (usize, usize)>,
+
+    /// Where in the input the last thing written to the output ended
+    previous_end: usize,
+    /// True at the start of the file or a block, where we never want a blank line
+    at_block_start: bool,
+    /// The indentation of the statement being written, which anything it has to continue onto
+    /// another line is indented past
+    indent: usize,
+}
+
+impl<'input> Formatter<'input> {
+    fn new(input: &'input str, file_id: FileId) -> Self {
+        let mut tokens = vec![];
+        let mut comments = vec![];
+
+        // The script has already parsed successfully, so there are no lexer errors
+        for (start, token, end) in TriviaLexer::new(input, file_id).flatten() {
+            match token {
+                TriviaToken::Token(token) => tokens.push((start, token, end)),
+                TriviaToken::Comment(text) => comments.push(Comment {
+                    start,
+                    end,
+                    text: text.trim_end(),
+                }),
+                TriviaToken::Whitespace(_) => {}
+            }
+        }
+
+        let mut matching_braces = HashMap::new();
+        let mut open_braces = vec![];
+        for &(start, ref token, end) in &tokens {
+            match token {
+                Token::LBrace => open_braces.push(start),
+                Token::RBrace => {
+                    if let Some(open) = open_braces.pop() {
+                        matching_braces.insert(open, (start, end));
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        Self {
+            input,
+            output: String::new(),
+
+            tokens,
+            comments,
+            next_comment: 0,
+
+            matching_braces,
+
+            previous_end: 0,
+            at_block_start: true,
+            indent: 0,
+        }
+    }
+
+    fn item_start(&self, item: &TopLevelItem) -> usize {
+        match item {
+            TopLevelItem::Statement(statement) => statement.span.start,
+            TopLevelItem::Function(function) => self.definition_start(function.span.start),
+            TopLevelItem::ExternFunction(function) => self.definition_start(function.span.start),
+        }
+    }
+
+    /// Function spans only cover the name, so walk back over the `fn`, `event`, `generator` and
+    /// `extern` keywords
+    fn definition_start(&self, name_start: usize) -> usize {
+        let name_index = self.token_index_at(name_start);
+
+        self.tokens[..name_index]
+            .iter()
+            .rev()
+            .take_while(|(_, token, _)| {
+                matches!(
+                    token,
+                    Token::KeywordFn
+                        | Token::KeywordEvent
+                        | Token::KeywordGenerator
+                        | Token::KeywordExtern
+                )
+            })
+            .last()
+            .map_or(name_start, |&(start, _, _)| start)
+    }
+
+    fn token_index_at(&self, position: usize) -> usize {
+        self.tokens
+            .partition_point(|&(start, _, _)| start < position)
+    }
+
+    /// Finds the first token of the given kind at or after `position`, returning its span
+    fn next_token(&self, position: usize, kind: &Token) -> (usize, usize) {
+        self.tokens[self.token_index_at(position)..]
+            .iter()
+            .find(|(_, token, _)| token == kind)
+            .map(|&(start, _, end)| (start, end))
+            .expect("the script parsed, so the token should exist")
+    }
+
+    fn new_line(&mut self, indent: usize) {
+        for _ in 0..indent {
+            self.output.push_str(INDENT);
+        }
+    }
+
+    /// Adds a blank line before the thing starting at `position` if there was one in the
+    /// original source, or if one is required.
+    fn separator(&mut self, position: usize, force: bool) {
+        let gap = &self.input[self.previous_end.min(position)..position];
+        if !self.at_block_start && (force || gap.matches('\n').count() >= 2) {
+            self.output.push('\n');
+        }
+
+        self.at_block_start = false;
+    }
+
+    /// Writes out any comments which appear before `position`. Comments which were on the same
+    /// line as the previous bit of code stay at the end of that line.
+    ///
+    /// Returns whether any comments were written on their own line.
+    fn comments_before(&mut self, position: usize, indent: usize, force_separator: bool) -> bool {
+        let mut wrote_comments = false;
+
+        while let Some(comment) = self.comments.get(self.next_comment) {
+            if comment.start >= position {
+                break;
+            }
+
+            let (start, end, text) = (comment.start, comment.end, comment.text);
+            self.next_comment += 1;
+
+            let is_trailing = !self.at_block_start
+                && start >= self.previous_end
+                && !self.input[self.previous_end..start].contains('\n');
+
+            if is_trailing || (self.at_block_start && self.is_after_open_brace(start)) {
+                // put it back on the end of the line it came from
+                self.output.pop();
+                writeln!(&mut self.output, " {text}").unwrap();
+            } else {
+                self.separator(start, force_separator && !wrote_comments);
+                wrote_comments = true;
+
+                self.new_line(indent);
+                writeln!(&mut self.output, "{text}").unwrap();
+            }
+
+            self.previous_end = end;
+        }
+
+        wrote_comments
+    }
+
+    /// Writes out any comments which appear before `position` in the middle of a statement, each
+    /// at the end of the line it was on, so they stay next to the code they describe.
+    ///
+    /// Returns whether any comments were written, in which case the output is at the start of a
+    /// new line.
+    fn comments_within_statement(&mut self, position: usize) -> bool {
+        let mut wrote_comments = false;
+
+        while let Some(comment) = self.comments.get(self.next_comment) {
+            if comment.start >= position {
+                break;
+            }
+
+            let text = comment.text;
+            self.next_comment += 1;
+
+            let trimmed_length = self.output.trim_end_matches(' ').len();
+            self.output.truncate(trimmed_length);
+
+            if self.output.ends_with('\n') {
+                // more than one comment in a row, so this one goes on its own line
+                self.new_line(self.indent + 1);
+                writeln!(&mut self.output, "{text}").unwrap();
+            } else {
+                writeln!(&mut self.output, " {text}").unwrap();
+            }
+
+            wrote_comments = true;
+        }
+
+        wrote_comments
+    }
+
+    /// Is this comment on the same line as a `{` which was just written
+    fn is_after_open_brace(&self, comment_start: usize) -> bool {
+        self.output.ends_with("{\n")
+            && comment_start >= self.previous_end
+            && !self.input[self.previous_end..comment_start].contains('\n')
+    }
+
+    fn function(&mut self, function: &Function) {
+        if function.modifiers.is_event_handler.is_some() {
+            self.output.push_str("event ");
+        }
+
+        if function.modifiers.is_generator.is_some() {
+            self.output.push_str("generator ");
+        }
+
+        write!(&mut self.output, "fn {}(", function.name).unwrap();
+        self.arguments(&function.arguments);
+        self.output.push(')');
+        self.return_types(&function.return_types);
+
+        if let Some(generator) = &function.modifiers.is_generator {
+            write!(&mut self.output, " -> {}", generator.yields.t).unwrap();
+        }
+        self.output.push(' ');
+
+        let open_brace = self.next_token(function.span.end, &Token::LBrace);
+        self.block(&function.statements, open_brace, 0);
+        self.output.push('\n');
+    }
+
+    fn extern_function(&mut self, function: &ExternFunctionDefinition) {
+        write!(&mut self.output, "extern fn {}(", function.name).unwrap();
+        self.arguments(&function.arguments);
+        self.output.push(')');
+        self.return_types(&function.return_types);
+        self.output.push_str(";\n");
+
+        let (_, end) = self.next_token(function.span.end, &Token::Semicolon);
+        self.previous_end = end;
+    }
+
+    fn arguments(&mut self, arguments: &[FunctionArgument]) {
+        for (i, argument) in arguments.iter().enumerate() {
+            if i != 0 {
+                self.output.push_str(", ");
+            }
+
+            let MaybeResolved::Unresolved(name) = argument.name else {
+                unreachable!("formatting happens before symbols are resolved");
+            };
+
+            write!(&mut self.output, "{name}: {}", argument.t.t).unwrap();
+        }
+    }
+
+    fn return_types(&mut self, return_types: &FunctionReturn) {
+        match return_types.types.as_slice() {
+            [] => {}
+            [single] => write!(&mut self.output, " -> {}", single.t).unwrap(),
+            types => {
+                self.output.push_str(" -> (");
+                for (i, ty) in types.iter().enumerate() {
+                    if i != 0 {
+                        self.output.push_str(", ");
+                    }
+
+                    write!(&mut self.output, "{}", ty.t).unwrap();
+                }
+                self.output.push(')');
+            }
+        }
+    }
+
+    /// Writes a block starting with the `{` at `open_brace`, and returns the end of the matching `}`.
+    /// Doesn't write a new line after the closing `}`.
+    fn block(
+        &mut self,
+        statements: &[Statement],
+        open_brace: (usize, usize),
+        indent: usize,
+    ) -> usize {
+        let (close_start, close_end) = self.matching_braces[&open_brace.0];
+
+        let has_comments = self
+            .comments
+            .get(self.next_comment)
+            .is_some_and(|comment| comment.start < close_start);
+
+        if statements.is_empty() && !has_comments {
+            self.output.push_str("{}");
+            self.previous_end = close_end;
+            return close_end;
+        }
+
+        self.output.push_str("{\n");
+        self.previous_end = open_brace.1;
+        self.at_block_start = true;
+
+        for statement in statements {
+            self.comments_before(statement.span.start, indent + 1, false);
+            self.separator(statement.span.start, false);
+            self.statement(statement, indent + 1);
+        }
+
+        self.comments_before(close_start, indent + 1, false);
+
+        self.new_line(indent);
+        self.output.push('}');
+
+        self.previous_end = close_end;
+        self.at_block_start = false;
+        close_end
+    }
+
+    fn statement(&mut self, statement: &Statement, indent: usize) {
+        self.new_line(indent);
+        self.indent = indent;
+
+        match &statement.kind {
+            StatementKind::Error | StatementKind::Nop => {
+                unreachable!("scripts with errors aren't formatted")
+            }
+            StatementKind::VariableDeclaration { ident, value } => {
+                write!(&mut self.output, "var {ident} = ").unwrap();
+                self.expression(value);
+                self.output.push(';');
+            }
+            StatementKind::Assignment { ident, value } => {
+                write!(&mut self.output, "{ident} = ").unwrap();
+                self.expression(value);
+                self.output.push(';');
+            }
+            StatementKind::Wait => self.output.push_str("wait;"),
+            StatementKind::Yield { value } => {
+                self.output.push_str("yield ");
+                self.expression(value);
+                self.output.push(';');
+            }
+            StatementKind::ControlGroup { group, control } => {
+                self.output.push_str(match control {
+                    GroupControl::Pause => "pause ",
+                    GroupControl::Resume => "resume ",
+                    GroupControl::TimeScale { .. } => "timescale ",
+                });
+                self.expression(group);
+                if let Some(frames) = control.frames() {
+                    self.output.push_str(", ");
+                    self.expression(frames);
+                }
+                self.output.push(';');
+            }
+            StatementKind::Continue => self.output.push_str("continue;"),
+            StatementKind::Break => self.output.push_str("break;"),
+            StatementKind::Block { block } => {
+                let open_brace = self.next_token(statement.span.start, &Token::LBrace);
+                self.block(block, open_brace, indent);
+            }
+            StatementKind::If {
+                condition,
+                true_block,
+                false_block,
+            } => {
+                self.output.push_str("if ");
+                self.expression(condition);
+                self.output.push(' ');
+
+                let open_brace = self.next_token(condition.span.end, &Token::LBrace);
+                let true_block_end = self.block(true_block, open_brace, indent);
+
+                // there may not have been an else block at all, so check it is actually part of this statement
+                let else_index = self.token_index_at(true_block_end);
+                if matches!(
+                    self.tokens.get(else_index),
+                    Some((_, Token::KeywordElse, _))
+                ) {
+                    self.output.push_str(" else ");
+
+                    let open_brace = self.next_token(true_block_end, &Token::LBrace);
+                    self.block(false_block, open_brace, indent);
+                }
+            }
+            StatementKind::Loop { block } => {
+                self.output.push_str("loop ");
+
+                let open_brace = self.next_token(statement.span.start, &Token::LBrace);
+                self.block(block, open_brace, indent);
+            }
+            StatementKind::Call { name, arguments } => {
+                self.call(name, arguments);
+                self.output.push(';');
+            }
+            StatementKind::Spawn {
+                group,
+                name,
+                arguments,
+            } => {
+                self.output.push_str("spawn ");
+                if let Some(group) = group {
+                    self.expression(group);
+                    self.output.push_str(", ");
+                }
+                self.call(name, arguments);
+                self.output.push(';');
+            }
+            StatementKind::Trigger {
+                name,
+                arguments,
+                field_names: Some(field_names),
+            } => {
+                write!(&mut self.output, "trigger {name} {{").unwrap();
+                for (i, (field_name, argument)) in field_names.iter().zip(arguments).enumerate() {
+                    self.output.push_str(if i == 0 { " " } else { ", " });
+                    write!(&mut self.output, "{}: ", field_name.name).unwrap();
+                    self.expression(argument);
+                }
+                self.output
+                    .push_str(if arguments.is_empty() { "};" } else { " };" });
+            }
+            StatementKind::Trigger {
+                name,
+                arguments,
+                field_names: None,
+            } => {
+                self.output.push_str("trigger ");
+                if arguments.is_empty() {
+                    self.output.push_str(name);
+                } else {
+                    self.call(name, arguments);
+                }
+                self.output.push(';');
+            }
+            StatementKind::Send {
+                target,
+                name,
+                arguments,
+            } => {
+                self.output.push_str("send ");
+                self.expression(target);
+                self.output.push_str(", ");
+                self.call(name, arguments);
+                self.output.push(';');
+            }
+            StatementKind::Return { values } => {
+                self.output.push_str("return");
+                for (i, value) in values.iter().enumerate() {
+                    self.output.push_str(if i == 0 { " " } else { ", " });
+                    self.expression(value);
+                }
+                self.output.push(';');
+            }
+        }
+
+        // anything left is between the last expression and the end of the statement
+        if !self.comments_within_statement(statement.span.end) {
+            self.output.push('\n');
+        }
+        self.previous_end = statement.span.end;
+    }
+
+    fn call(&mut self, name: &str, arguments: &[Expression]) {
+        write!(&mut self.output, "{name}(").unwrap();
+        for (i, argument) in arguments.iter().enumerate() {
+            if i != 0 {
+                self.output.push_str(", ");
+            }
+
+            self.expression(argument);
+        }
+        self.output.push(')');
+    }
+
+    fn expression(&mut self, expression: &Expression) {
+        if self.comments_within_statement(expression.span.start) {
+            self.new_line(self.indent + 1);
+        }
+
+        match &expression.kind {
+            // keep numbers as they were written, so `1.50` doesn't become `1.5` or lose precision
+            ExpressionKind::Integer(_) | ExpressionKind::Fix(_) => self
+                .output
+                .push_str(&self.input[expression.span.start..expression.span.end]),
+            ExpressionKind::Bool(value) => write!(&mut self.output, "{value}").unwrap(),
+            ExpressionKind::Variable(name) => self.output.push_str(name),
+            ExpressionKind::BinaryOperation { lhs, operator, rhs } => {
+                self.operand(lhs, *operator, Side::Left);
+                write!(&mut self.output, " {operator} ").unwrap();
+                self.operand(rhs, *operator, Side::Right);
+            }
+            ExpressionKind::Call { name, arguments } => self.call(name, arguments),
+            ExpressionKind::Error | ExpressionKind::Nop => {
+                unreachable!("scripts with errors aren't formatted")
+            }
+        }
+    }
+
+    /// Writes one side of a binary operation, only adding parentheses where they are needed
+    fn operand(&mut self, operand: &Expression, parent: BinaryOperator, side: Side) {
+        let needs_parentheses = match &operand.kind {
+            ExpressionKind::BinaryOperation { operator, .. } => {
+                let (precedence, parent_precedence) = (precedence(*operator), precedence(parent));
+
+                precedence > parent_precedence
+                    || (precedence == parent_precedence
+                        && (side == Side::Right || !is_left_associative(parent)))
+            }
+            _ => false,
+        };
+
+        if needs_parentheses {
+            self.output.push('(');
+            self.expression(operand);
+            self.output.push(')');
+        } else {
+            self.expression(operand);
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Side {
+    Left,
+    Right,
+}
+
+/// Matches the precedence levels in the grammar, lower binds tighter
+fn precedence(operator: BinaryOperator) -> u8 {
+    use BinaryOperator as B;
+
+    match operator {
+        B::Mul
+        | B::Div
+        | B::Mod
+        | B::RealDiv
+        | B::RealMod
+        | B::FixMul
+        | B::FixDiv
+        | B::ShiftLeft
+        | B::ShiftRight => 2,
+        B::Add | B::Sub => 3,
+        B::EqEq | B::NeEq | B::Gt | B::GtEq | B::Lt | B::LtEq => 4,
+        B::Then => 6,
+    }
+}
+
+fn is_left_associative(operator: BinaryOperator) -> bool {
+    precedence(operator) <= 3
+}
+
+#[cfg(test)]
+mod test {
+    use std::fs;
+
+    use insta::{assert_snapshot, glob};
+
+    use crate::lexer::Lexer;
+
+    use super::*;
+
+    fn parse_and_print(input: &str) -> String {
+        let file_id = FileId::new(0);
+        let mut diagnostics = Diagnostics::new(file_id, "input.tapir", input);
+
+        let ast = grammar::ScriptParser::new()
+            .parse(
+                file_id,
+                CompileSettings::DEFAULT_FIX_PRECISION,
+                &mut diagnostics,
+                Lexer::new(input, file_id),
+            )
+            .unwrap();
+
+        ast.pretty_print()
+    }
+
+    #[test]
+    fn formatter_snapshot_tests() {
+        glob!("snapshot_tests", "formatter/*.tapir", |path| {
+            let input = fs::read_to_string(path).unwrap();
+
+            let formatted = format(path.file_name().unwrap(), &input).unwrap();
+
+            let formatted_again = format(path.file_name().unwrap(), &formatted).unwrap();
+            assert_eq!(
+                formatted, formatted_again,
+                "formatting should be idempotent"
+            );
+
+            assert_eq!(
+                parse_and_print(&input),
+                parse_and_print(&formatted),
+                "formatting shouldn't change the meaning of the script"
+            );
+
+            assert_snapshot!(formatted);
+        });
+    }
+
+    #[test]
+    fn scripts_with_errors_are_not_formatted() {
+        assert!(format("input.tapir", "var x = ;").is_err());
+        assert!(format("input.tapir", "fn foo() { var y = 3 }").is_err());
+    }
+}
@@ -2,7 +2,7 @@ use std::fs;
 
 use insta::{assert_ron_snapshot, assert_snapshot, glob};
 
-use crate::{grammar, lexer::Lexer, reporting::Diagnostics, tokens::FileId};
+use crate::{grammar, lexer::Lexer, reporting::Diagnostics, tokens::FileId, CompileSettings};
 
 #[test]
 fn snapshot_success() {
@@ -16,7 +16,12 @@ fn snapshot_success() {
         let mut diagnostics = Diagnostics::new(file_id, path.file_name().unwrap(), &input);
 
         let ast = parser
-            .parse(FileId::new(0), &mut diagnostics, lexer)
+            .parse(
+                FileId::new(0),
+                CompileSettings::DEFAULT_FIX_PRECISION,
+                &mut diagnostics,
+                lexer,
+            )
             .unwrap();
 
         assert_ron_snapshot!(ast, {
@@ -37,7 +42,12 @@ fn snapshot_failures() {
 
         let mut diagnostics = Diagnostics::new(file_id, path.file_name().unwrap(), &input);
 
-        match parser.parse(file_id, &mut diagnostics, lexer) {
+        match parser.parse(
+            file_id,
+            CompileSettings::DEFAULT_FIX_PRECISION,
+            &mut diagnostics,
+            lexer,
+        ) {
             Ok(_) => {}
             Err(e) => {
                 diagnostics.add_lalrpop(e, file_id);
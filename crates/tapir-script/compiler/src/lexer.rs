@@ -1,4 +1,5 @@
 use logos::{Logos, SpannedIter};
+use serde::Serialize;
 
 use crate::tokens::{FileId, LexicalError, Token};
 
@@ -31,6 +32,85 @@ impl<'input> Iterator for Lexer<'input> {
     }
 }
 
+/// Something found in the source by the [`TriviaLexer`]. Whitespace and comments are
+/// skipped by the regular lexer, but tools like the formatter need to know where they are.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub enum TriviaToken<'input> {
+    Token(Token<'input>),
+    Comment(&'input str),
+    Whitespace(&'input str),
+}
+
+/// A lexer which produces the same tokens as [`Lexer`], but additionally reports the
+/// comments and whitespace between them so that the original source can be reconstructed.
+pub struct TriviaLexer<'input> {
+    input: &'input str,
+    lexer: Lexer<'input>,
+    position: usize,
+    next_token: Option<Spanned<Token<'input>, usize, LexicalError>>,
+}
+
+impl<'input> TriviaLexer<'input> {
+    pub fn new(input: &'input str, file_id: FileId) -> Self {
+        Self {
+            input,
+            lexer: Lexer::new(input, file_id),
+            position: 0,
+            next_token: None,
+        }
+    }
+
+    fn next_trivia(&mut self, trivia_end: usize) -> Option<(usize, TriviaToken<'input>, usize)> {
+        let start = self.position;
+        if start >= trivia_end {
+            return None;
+        }
+
+        let remaining = &self.input[start..trivia_end];
+        let (end, trivia) = if remaining.starts_with('#') {
+            let length = remaining.find('\n').unwrap_or(remaining.len());
+            (start + length, TriviaToken::Comment(&remaining[..length]))
+        } else {
+            let length = remaining.find('#').unwrap_or(remaining.len());
+            (
+                start + length,
+                TriviaToken::Whitespace(&remaining[..length]),
+            )
+        };
+
+        self.position = end;
+        Some((start, trivia, end))
+    }
+}
+
+impl<'input> Iterator for TriviaLexer<'input> {
+    type Item = Spanned<TriviaToken<'input>, usize, LexicalError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.next_token.is_none() {
+            self.next_token = self.lexer.next();
+        }
+
+        let trivia_end = match &self.next_token {
+            Some(Ok((start, _, _))) => *start,
+            Some(Err(e)) => e.span.start,
+            None => self.input.len(),
+        };
+
+        if let Some(trivia) = self.next_trivia(trivia_end) {
+            return Some(Ok(trivia));
+        }
+
+        let token = self.next_token.take()?;
+        self.position = match &token {
+            Ok((_, _, end)) => *end,
+            Err(e) => e.span.end,
+        };
+
+        Some(token.map(|(start, token, end)| (start, TriviaToken::Token(token), end)))
+    }
+}
+
 #[cfg(test)]
 mod test {
     use std::fs;
@@ -52,4 +132,34 @@ mod test {
             assert_ron_snapshot!(output);
         });
     }
+
+    #[test]
+    fn trivia_lexer_reconstructs_the_input() {
+        glob!("snapshot_tests", "lexer/*.tapir", |path| {
+            let input = fs::read_to_string(path).unwrap();
+
+            let reconstructed = TriviaLexer::new(&input, FileId::new(0))
+                .map(|token| {
+                    let (start, _, end) = token.unwrap();
+                    &input[start..end]
+                })
+                .collect::<String>();
+
+            assert_eq!(reconstructed, input);
+        });
+    }
+
+    #[test]
+    fn trivia_lexer_finds_comments() {
+        let input = "wait; # a comment\n# another\nwait;";
+
+        let comments = TriviaLexer::new(input, FileId::new(0))
+            .filter_map(|token| match token.unwrap().1 {
+                TriviaToken::Comment(comment) => Some(comment),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+
+        assert_eq!(comments, ["# a comment", "# another"]);
+    }
 }
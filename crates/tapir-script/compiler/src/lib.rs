@@ -3,21 +3,36 @@ use std::path::Path;
 
 use lalrpop_util::lalrpop_mod;
 
-use reporting::Diagnostics;
+pub use reporting::{CompilerWarningKind, Diagnostics, LintLevel, Severity, TriggerMismatch};
 
 mod ast;
 mod compile;
+mod container;
+mod formatter;
 mod lexer;
 mod reporting;
 mod tokens;
 mod types;
 
-lalrpop_mod!(grammar);
+use reporting::CompilerErrorKind;
+use tokens::{FileId, Span};
+
+lalrpop_mod!(
+    #[allow(clippy::all)]
+    grammar
+);
 
 #[cfg(test)]
 mod grammar_test;
 
-pub use compile::{CompileSettings, Property};
+pub use compile::{
+    analysis::{
+        analyse, Analysis, AnalysisDiagnostic, AnalysisFunction, AnalysisReference, AnalysisSymbol,
+        ReferenceTarget, SymbolKind,
+    },
+    CompileSettings, Property, PropertyAccess,
+};
+pub use formatter::format;
 pub use reporting::{format::DiagnosticCache, Message};
 pub use types::Type;
 
@@ -26,31 +41,165 @@ pub fn compile(
     input: &str,
     compile_settings: CompileSettings,
 ) -> Result<CompileResult, Diagnostics> {
-    let bytecode = compile::compile(filename, input, &compile_settings)?;
+    let (mut bytecode, warnings) = compile::compile(filename, input, &compile_settings)?;
+
+    if compile_settings.enable_optimisations {
+        bytecode.fuse_superinstructions();
+    }
+
+    Ok(compile_result(bytecode, warnings, compile_settings))
+}
 
+/// Compiles exactly like [`compile`], except that instructions are never fused into
+/// superinstructions. Only useful for measuring how much the superinstructions save.
+#[doc(hidden)]
+pub fn compile_without_superinstructions(
+    filename: impl AsRef<Path>,
+    input: &str,
+    compile_settings: CompileSettings,
+) -> Result<CompileResult, Diagnostics> {
+    let (bytecode, warnings) = compile::compile(filename, input, &compile_settings)?;
+
+    Ok(compile_result(bytecode, warnings, compile_settings))
+}
+
+fn compile_result(
+    bytecode: compile::Bytecode,
+    warnings: Diagnostics,
+    compile_settings: CompileSettings,
+) -> CompileResult {
     let compiled = bytecode.compile();
-    Ok(CompileResult {
+    CompileResult {
         bytecode: compiled,
         event_handlers: bytecode.event_handlers,
         triggers: bytecode.triggers,
-    })
+        messages: bytecode.messages,
+        stack_depths: bytecode.stack_depths,
+        functions: bytecode.functions,
+        fix_precision: compile_settings.fix_precision,
+        properties: compile_settings.properties,
+        warnings,
+    }
 }
 
 pub struct CompileResult {
     pub bytecode: Vec<u16>,
     pub event_handlers: Vec<EventHandler>,
     pub triggers: Vec<Trigger>,
+    /// The messages the script sends to other scripts, which are delivered to the event handler
+    /// with the same name
+    pub messages: Vec<SentMessage>,
+    /// The most stack space each function needs, including anything it calls. Use these to
+    /// allocate stacks up front for the toplevel function, event handlers and spawned threads.
+    pub stack_depths: Vec<StackDepth>,
+    /// Where each function starts, for debugging
+    pub functions: Vec<FunctionInfo>,
+    pub fix_precision: u8,
+    /// The properties the script was compiled against
+    pub properties: Vec<Property>,
+    /// Any warnings produced while compiling. These never contain errors, but `Diagnostics::deny`
+    /// can turn them into errors.
+    pub warnings: Diagnostics,
 }
 
 pub struct EventHandler {
     pub name: String,
     pub bytecode_offset: usize,
     pub arguments: Vec<EventHandlerArgument>,
+    /// Event handlers which return something are called synchronously, and must finish without
+    /// waiting
+    pub return_types: Vec<Type>,
+    /// Set for generators, which rust runs one `yield` at a time rather than as a thread
+    pub yields: Option<Type>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct StackDepth {
+    /// Where the function starts in the bytecode
+    pub bytecode_offset: usize,
+    /// How many stack slots the function could need, or `None` if it is recursive and so has no
+    /// upper bound
+    pub max_depth: Option<usize>,
+}
+
+pub struct FunctionInfo {
+    pub name: String,
+    pub bytecode_offset: usize,
 }
 
 pub struct Trigger {
     pub name: String,
     pub arguments: Vec<Type>,
+    /// The names of each argument if the trigger is called like `trigger Hit { damage: 5 };`, in
+    /// which case it becomes a struct variant rather than a tuple variant
+    pub field_names: Option<Vec<String>>,
+    /// Where the trigger is first called, and its arguments (or their names) in that call
+    pub(crate) span: Span,
+    pub(crate) argument_spans: Vec<Span>,
+}
+
+impl Trigger {
+    /// An error pointing at where the script calls this trigger, for when it can't become a
+    /// variant of the rust `trigger_type`. `filename` and `input` must be the script it was
+    /// compiled from.
+    pub fn mismatch_diagnostics(
+        &self,
+        trigger_type: &str,
+        mismatch: TriggerMismatch,
+        filename: impl AsRef<Path>,
+        input: &str,
+    ) -> Diagnostics {
+        let span = match mismatch {
+            TriggerMismatch::UnknownField(index) | TriggerMismatch::FieldType(index) => {
+                self.argument_spans[index]
+            }
+            TriggerMismatch::UnknownVariant
+            | TriggerMismatch::Shape
+            | TriggerMismatch::MissingFields => self.span,
+        };
+
+        let mut diagnostics = Diagnostics::new(FileId::new(0), filename, input);
+        diagnostics.add_message(
+            CompilerErrorKind::TriggerMismatch {
+                trigger_type: trigger_type.to_string(),
+                name: self.name.clone(),
+                field_names: self.field_names.clone(),
+                arguments: self.arguments.clone(),
+                mismatch,
+            }
+            .into_message(span),
+        );
+
+        diagnostics
+    }
+}
+
+pub struct SentMessage {
+    pub name: String,
+    pub arguments: Vec<Type>,
+    /// Where the message is first sent
+    pub(crate) span: Span,
+}
+
+impl SentMessage {
+    /// An error pointing at where the script sends this message, for when the script has no event
+    /// handler for it to be delivered to. `filename` and `input` must be the script it was
+    /// compiled from.
+    pub fn unknown_event_diagnostics(
+        &self,
+        filename: impl AsRef<Path>,
+        input: &str,
+    ) -> Diagnostics {
+        let mut diagnostics = Diagnostics::new(FileId::new(0), filename, input);
+        diagnostics.add_message(
+            CompilerErrorKind::UnknownMessage {
+                name: self.name.clone(),
+            }
+            .into_message(self.span),
+        );
+
+        diagnostics
+    }
 }
 
 pub struct EventHandlerArgument {
@@ -55,11 +55,74 @@ impl Diagnostics {
     pub fn has_any(&self) -> bool {
         !self.messages.is_empty()
     }
+
+    pub fn has_errors(&self) -> bool {
+        self.messages
+            .iter()
+            .any(|message| message.severity == Severity::Error)
+    }
+
+    /// Removes any warnings for the given lint, or all warnings if the lint is `warnings`
+    pub fn allow(&mut self, lint: &str) {
+        self.messages
+            .retain(|message| !message.is_warning_for_lint(lint));
+    }
+
+    /// Turns any warnings for the given lint into errors, or all warnings if the lint is `warnings`
+    pub fn deny(&mut self, lint: &str) {
+        for message in &mut self.messages {
+            if message.is_warning_for_lint(lint) {
+                message.severity = Severity::Error;
+            }
+        }
+    }
+
+    /// Allows or denies lints in the order given, so whichever is last out of the ones which
+    /// cover a warning decides what happens to it, like rust's `#[allow]` and `#[deny]`
+    pub fn set_lint_levels<'a>(&mut self, levels: impl IntoIterator<Item = (LintLevel, &'a str)>) {
+        let levels = levels.into_iter().collect::<Vec<_>>();
+
+        self.messages.retain_mut(|message| {
+            match levels
+                .iter()
+                .rev()
+                .find(|(_, lint)| message.is_warning_for_lint(lint))
+            {
+                Some((LintLevel::Allow, _)) => false,
+                Some((LintLevel::Deny, _)) => {
+                    message.severity = Severity::Error;
+                    true
+                }
+                None => true,
+            }
+        });
+    }
+
+    /// The location, severity and headline of every message, without any of the surrounding source
+    pub fn summaries(&self) -> Vec<(Span, Severity, String)> {
+        self.messages
+            .iter()
+            .map(|message| (message.span, message.severity, message.summary()))
+            .collect()
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LintLevel {
+    Allow,
+    Deny,
 }
 
 #[derive(Clone, Debug, Serialize)]
 pub struct Message {
     pub span: Span,
+    pub severity: Severity,
     pub error: Box<MessageKind>,
 }
 
@@ -68,12 +131,20 @@ impl Message {
         self.span = span;
         self
     }
+
+    fn is_warning_for_lint(&self, lint: &str) -> bool {
+        match &*self.error {
+            MessageKind::CompilerWarning(warning) => lint == "warnings" || warning.lint() == lint,
+            _ => false,
+        }
+    }
 }
 
 impl From<LexicalError> for Message {
     fn from(value: LexicalError) -> Self {
         Self {
             span: value.span,
+            severity: Severity::Error,
             error: Box::new(MessageKind::LexerError(value.kind)),
         }
     }
@@ -85,12 +156,14 @@ pub enum MessageKind {
     ParseError(ParseError),
     LexerError(LexicalErrorKind),
     ComplierError(CompilerErrorKind),
+    CompilerWarning(CompilerWarningKind),
 }
 
 impl MessageKind {
     pub fn with_span(self, file_id: FileId, start: usize, end: usize) -> Message {
         Message {
             span: Span::new(file_id, start, end),
+            severity: Severity::Error,
             error: Box::new(self),
         }
     }
@@ -152,15 +225,21 @@ impl Message {
 
 #[derive(Clone, Debug, Serialize)]
 pub enum CompilerErrorKind {
-    UnknownVariable(String),
+    UnknownVariable {
+        name: String,
+        suggestion: Option<String>,
+    },
     TypeError {
         expected: Type,
         actual: Type,
+        expected_because: Option<TypeOrigin>,
     },
     UnknownType(String),
     BinaryOperatorTypeError {
         lhs_type: Type,
         rhs_type: Type,
+        lhs_span: Span,
+        rhs_span: Span,
     },
     InvalidTypeForBinaryOperator {
         type_: Type,
@@ -186,12 +265,14 @@ pub enum CompilerErrorKind {
     },
     UnknownFunction {
         name: String,
+        suggestion: Option<String>,
     },
     IncorrectNumberOfArguments {
         function_name: String,
         expected: usize,
         actual: usize,
         function_span: Span,
+        argument_spans: Vec<Span>,
     },
     FunctionMustReturnOneValueInThisLocation {
         actual: usize,
@@ -202,11 +283,6 @@ pub enum CompilerErrorKind {
     },
     BreakOrContinueOutsideOfLoop,
     DivideByZero,
-    EventFunctionsShouldNotHaveAReturnType {
-        return_type_span: Span,
-        event_span: Span,
-        function_name: String,
-    },
     CannotCallEventHandler {
         function_span: Span,
         function_name: String,
@@ -217,13 +293,160 @@ pub enum CompilerErrorKind {
         first_definition_args: Vec<Type>,
         second_definition_args: Vec<Type>,
     },
+    TriggerIncorrectFields {
+        name: String,
+        first_definition_span: Span,
+        first_definition_fields: Option<Vec<String>>,
+        second_definition_fields: Option<Vec<String>>,
+    },
+    DuplicateTriggerField {
+        name: String,
+        first_span: Span,
+    },
+    /// Found by the derive macro rather than the compiler, since only rust knows what the
+    /// variants of the trigger type look like
+    TriggerMismatch {
+        trigger_type: String,
+        name: String,
+        field_names: Option<Vec<String>>,
+        arguments: Vec<Type>,
+        mismatch: TriggerMismatch,
+    },
+    InvalidTypeForSendTarget {
+        got: Type,
+    },
+    MessageIncorrectArgs {
+        name: String,
+        first_definition_span: Span,
+        first_definition_args: Vec<Type>,
+        second_definition_args: Vec<Type>,
+    },
+    /// The script handles the message itself, but the event handler takes different arguments
+    MessageEventHandlerMismatch {
+        name: String,
+        event_handler_span: Span,
+        event_handler_args: Vec<Type>,
+        message_args: Vec<Type>,
+    },
+    /// Found by the derive macro rather than the compiler, since scripts compiled at runtime can
+    /// send messages to event handlers which only other scripts for the same struct define
+    UnknownMessage {
+        name: String,
+    },
+    CannotCallGenerator {
+        function_span: Span,
+        function_name: String,
+    },
+    CannotSendToGenerator {
+        name: String,
+        generator_span: Span,
+    },
+    YieldOutsideGenerator,
+    IncorrectYieldType {
+        expected: Type,
+        actual: Type,
+        yield_type_span: Span,
+    },
+    /// Generators only run when rust asks for their next value, so there's nothing to wait for
+    WaitInGenerator {
+        generator_span: Span,
+    },
+    InvalidTypeForGroup {
+        got: Type,
+    },
+    InvalidTypeForTimeScale {
+        got: Type,
+    },
+    AssignmentToReadOnlyProperty {
+        name: String,
+    },
+    ReadFromWriteOnlyProperty {
+        name: String,
+    },
+}
+
+/// Why a trigger can't become a variant of the rust `trigger_type`
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+pub enum TriggerMismatch {
+    /// There is no variant with the same name as the trigger
+    UnknownVariant,
+    /// The variant is a unit, tuple or struct variant when the trigger is called differently, or
+    /// it has a different number of fields to the trigger's arguments
+    Shape,
+    /// The trigger doesn't set every field of the struct variant
+    MissingFields,
+    /// The struct variant has no field with the name of this argument
+    UnknownField(usize),
+    /// The field for this argument has a different type in rust
+    FieldType(usize),
+}
+
+/// Why something was expected to have a given type
+#[derive(Clone, Debug, Serialize)]
+pub enum TypeOrigin {
+    /// The variable or argument was given its type here
+    Declaration(Span),
+    /// Properties get their type from the rust struct
+    Property(String),
 }
 
 impl CompilerErrorKind {
     pub fn into_message(self, span: Span) -> Message {
         Message {
             span,
+            severity: Severity::Error,
             error: Box::new(MessageKind::ComplierError(self)),
         }
     }
 }
+
+#[derive(Clone, Debug, Serialize)]
+pub enum CompilerWarningKind {
+    UnusedVariable {
+        name: String,
+    },
+    UnusedFunction {
+        name: String,
+    },
+    EmptyEventHandler {
+        name: String,
+    },
+    UnreachableCode {
+        diverging_statement: Span,
+    },
+    ShadowedVariable {
+        name: String,
+        /// `None` if this shadows a property
+        original_declaration: Option<Span>,
+    },
+}
+
+impl CompilerWarningKind {
+    /// Every lint name which can be passed to `Diagnostics::allow` or `Diagnostics::deny`
+    pub const LINTS: &'static [&'static str] = &[
+        "warnings",
+        "unused_variable",
+        "unused_function",
+        "empty_event_handler",
+        "unreachable_code",
+        "shadowing",
+    ];
+
+    pub fn lint(&self) -> &'static str {
+        match self {
+            CompilerWarningKind::UnusedVariable { .. } => "unused_variable",
+            CompilerWarningKind::UnusedFunction { .. } => "unused_function",
+            CompilerWarningKind::EmptyEventHandler { .. } => "empty_event_handler",
+            CompilerWarningKind::UnreachableCode { .. } => "unreachable_code",
+            CompilerWarningKind::ShadowedVariable { .. } => "shadowing",
+        }
+    }
+
+    pub fn into_message(self, span: Span) -> Message {
+        Message {
+            span,
+            severity: Severity::Warning,
+            error: Box::new(MessageKind::CompilerWarning(self)),
+        }
+    }
+}
@@ -8,9 +8,15 @@ use std::{
 
 use ariadne::{Label, Source};
 
-use crate::tokens::{FileId, LexicalErrorKind, Span};
+use crate::{
+    tokens::{FileId, LexicalErrorKind, Span},
+    types::Type,
+};
 
-use super::{CompilerErrorKind, Message, MessageKind, ParseError};
+use super::{
+    CompilerErrorKind, CompilerWarningKind, Message, MessageKind, ParseError, Severity,
+    TriggerMismatch, TypeOrigin,
+};
 
 impl Message {
     pub fn write_diagnostic<W: Write>(
@@ -27,13 +33,33 @@ impl Message {
             MessageKind::ComplierError(compiler_error_kind) => {
                 compiler_error_report(compiler_error_kind, self.span)
             }
+            MessageKind::CompilerWarning(compiler_warning_kind) => {
+                compiler_warning_report(compiler_warning_kind, self.span, self.severity)
+            }
         };
 
         report
+            .with_message(self.summary())
             .with_config(ariadne::Config::default().with_color(include_colour))
             .finish()
             .write_for_stdout(code, w)
     }
+
+    /// Just the headline of the diagnostic, for places like editors which show the location separately
+    pub fn summary(&self) -> String {
+        match &*self.error {
+            MessageKind::ParseError(parse_error) => parse_error_summary(parse_error),
+            MessageKind::LexerError(lexical_error_kind) => {
+                lexical_error_summary(lexical_error_kind)
+            }
+            MessageKind::ComplierError(compiler_error_kind) => {
+                compiler_error_summary(compiler_error_kind)
+            }
+            MessageKind::CompilerWarning(compiler_warning_kind) => {
+                compiler_warning_summary(compiler_warning_kind)
+            }
+        }
+    }
 }
 
 #[derive(Clone)]
@@ -105,29 +131,32 @@ fn build_error_report(span: Span) -> ariadne::ReportBuilder<'static, Span> {
     ariadne::Report::build(ariadne::ReportKind::Error, span.file_id, 0)
 }
 
+/// Warnings which have been denied are reported as errors
+fn build_warning_report(span: Span, severity: Severity) -> ariadne::ReportBuilder<'static, Span> {
+    let kind = match severity {
+        Severity::Error => ariadne::ReportKind::Error,
+        Severity::Warning => ariadne::ReportKind::Warning,
+    };
+
+    ariadne::Report::build(kind, span.file_id, 0)
+}
+
 fn parse_error_report(parse_error: &ParseError, span: Span) -> ariadne::ReportBuilder<'_, Span> {
     match parse_error {
         ParseError::UnrecognizedEof { expected } => build_error_report(span)
             .with_label(Label::new(span).with_message("End of file not expected here"))
-            .with_message("Unexpected end of file")
             .with_note(format!("Expected one of tokens {}", expected.join(", "))),
-        ParseError::UnrecognizedToken { token, expected } => build_error_report(span)
-            .with_label(Label::new(span).with_message("Unexpected token"))
-            .with_message(format!(
-                "Unexpected token {token}, expected one of {}",
-                expected.join(", ")
-            )),
-        ParseError::ExtraToken { token } => build_error_report(span)
-            .with_label(Label::new(span).with_message("Extra token"))
-            .with_message(format!("Unexpected extra token {token}")),
-        ParseError::UnknownType { token } => build_error_report(span)
-            .with_label(Label::new(span).with_message("Unknown type"))
-            .with_message(format!(
-                "'{token}' is not a valid type, must be one of fix, bool or int"
-            )),
-        ParseError::ExternFunctionWithBlock { name } => build_error_report(span)
-            .with_label(Label::new(span).with_message("extern function cannot have body"))
-            .with_message(format!("extern function '{name}' cannot have a body")),
+        ParseError::UnrecognizedToken { .. } => {
+            build_error_report(span).with_label(Label::new(span).with_message("Unexpected token"))
+        }
+        ParseError::ExtraToken { .. } => {
+            build_error_report(span).with_label(Label::new(span).with_message("Extra token"))
+        }
+        ParseError::UnknownType { .. } => {
+            build_error_report(span).with_label(Label::new(span).with_message("Unknown type"))
+        }
+        ParseError::ExternFunctionWithBlock { .. } => build_error_report(span)
+            .with_label(Label::new(span).with_message("extern function cannot have body")),
     }
 }
 
@@ -138,7 +167,6 @@ fn lexical_error_report(
     match lexical_error_kind {
         LexicalErrorKind::InvalidNumber(parse_int_error) => build_error_report(span)
             .with_label(Label::new(span).with_message("Invalid integer"))
-            .with_message(format!("{parse_int_error}"))
             .with_note(match parse_int_error.kind() {
                 std::num::IntErrorKind::PosOverflow => {
                     format!("Larger than maximum positive number which is {}", i32::MAX)
@@ -163,78 +191,345 @@ fn compiler_error_report(
     span: Span,
 ) -> ariadne::ReportBuilder<'_, Span> {
     match compiler_error_kind {
-        CompilerErrorKind::UnknownVariable(var) => build_error_report(span)
-            .with_label(Label::new(span).with_message("Unknown variable"))
-            .with_message(format!("Unknown variable '{var}'")),
-        CompilerErrorKind::TypeError { expected, actual } => build_error_report(span)
-            .with_label(Label::new(span).with_message("Incorrect type"))
-            .with_message(format!(
-                "Incorrect type, expected {expected} but got {actual}",
-            )),
-        CompilerErrorKind::UnknownType(var) => build_error_report(span)
-            .with_label(Label::new(span).with_message("Unknown type for variable"))
-            .with_message(format!("Unknown type for variable '{var}'")),
-        CompilerErrorKind::BinaryOperatorTypeError { lhs_type, rhs_type } => {
+        CompilerErrorKind::UnknownVariable { suggestion, .. } => with_suggestion(
+            build_error_report(span)
+                .with_label(Label::new(span).with_message("Unknown variable")),
+            suggestion,
+        ),
+        CompilerErrorKind::TypeError { expected, actual, expected_because } => {
+            let report = build_error_report(span)
+                .with_label(Label::new(span).with_message(format!("This has type {actual}")));
+
+            match expected_because {
+                Some(TypeOrigin::Declaration(declaration)) => report.with_label(
+                    Label::new(*declaration).with_message(format!("Expected {expected} because of this")),
+                ),
+                Some(TypeOrigin::Property(name)) => report.with_note(format!(
+                    "'{name}' is a property which has type {expected}"
+                )),
+                None => report,
+            }
+        }
+        CompilerErrorKind::UnknownType(_) => build_error_report(span)
+            .with_label(Label::new(span).with_message("Unknown type for variable")),
+        CompilerErrorKind::BinaryOperatorTypeError { lhs_type, rhs_type, lhs_span, rhs_span } => {
             build_error_report(span)
-                .with_label(Label::new(span).with_message("Mismatching types on binary operator"))
-                .with_message(format!(
-                    "Left hand side has type {lhs_type} but right hand side has type {rhs_type}"
-                ))
-        }
-        CompilerErrorKind::InvalidTypeForBinaryOperator { type_ } => build_error_report(span)
-            .with_label(Label::new(span).with_message("Binary operator cannot handle this type"))
-            .with_message(format!("Binary operator cannot items of type {type_}")),
+                .with_label(Label::new(*lhs_span).with_message(format!("This has type {lhs_type}")))
+                .with_label(Label::new(*rhs_span).with_message(format!("This has type {rhs_type}")))
+        }
+        CompilerErrorKind::InvalidTypeForBinaryOperator { .. } => build_error_report(span)
+            .with_label(Label::new(span).with_message("Binary operator cannot handle this type")),
         CompilerErrorKind::InvalidTypeForIfCondition { got } => build_error_report(span)
-            .with_label(Label::new(span).with_message(format!("This has type {got}")))
-            .with_message(format!(
-                "Condition in if statement must be a bool, but got a {got}"
-            )),
+            .with_label(Label::new(span).with_message(format!("This has type {got}"))),
         CompilerErrorKind::IncorrectNumberOfReturnTypes { expected, actual, function_return_location } => build_error_report(span)
             .with_label(Label::new(span).with_message(format!("This has {actual} return values")))
             .with_label(Label::new(*function_return_location).with_message(format!("Function returns {expected} values")))
-            .with_message(format!("Function should be returning {expected} return values, but you are actually returning {actual}."))
             .with_note("Functions must return a fixed number of values"),
         CompilerErrorKind::MismatchingReturnTypes { expected, actual, expected_location, actual_location } => build_error_report(span)
             .with_label(Label::new(*actual_location).with_message(format!("This has type {actual}")))
-            .with_label(Label::new(*expected_location).with_message(format!("This has type {expected}")))
-            .with_message(format!("Function is declared to return type {expected} but got {actual}")),
-        CompilerErrorKind::FunctionAlreadyDeclared { function_name, old_function_declaration, new_function_declaration } => build_error_report(span)
+            .with_label(Label::new(*expected_location).with_message(format!("This has type {expected}"))),
+        CompilerErrorKind::FunctionAlreadyDeclared { old_function_declaration, new_function_declaration, .. } => build_error_report(span)
             .with_label(Label::new(*old_function_declaration).with_message("Originally declared here"))
-            .with_label(Label::new(*new_function_declaration).with_message("Also declared here"))
-            .with_message(format!("Function with name '{function_name}' already exists")),
-        CompilerErrorKind::UnknownFunction { name } => build_error_report(span)
-            .with_label(Label::new(span).with_message("Unknown function"))
-            .with_message(format!("No such function {name}")),
-        CompilerErrorKind::IncorrectNumberOfArguments { expected, actual, function_span, function_name } => build_error_report(span)
+            .with_label(Label::new(*new_function_declaration).with_message("Also declared here")),
+        CompilerErrorKind::UnknownFunction { suggestion, .. } => with_suggestion(
+            build_error_report(span)
+                .with_label(Label::new(span).with_message("Unknown function")),
+            suggestion,
+        ),
+        CompilerErrorKind::IncorrectNumberOfArguments { expected, actual, function_span, argument_spans, .. } => build_error_report(span)
             .with_label(Label::new(span).with_message(format!("Got {actual} arguments")))
             .with_label(Label::new(*function_span).with_message(format!("Expected {expected} arguments")))
-            .with_message(format!("Incorrect number of argumets for function {function_name}, expected {expected} arguments but got {actual}.")),
-        CompilerErrorKind::FunctionMustReturnOneValueInThisLocation { actual } => build_error_report(span)
-            .with_label(Label::new(span).with_message("Function must return 1 value here"))
-            .with_message(format!("Function call must return exactly 1 value here, but got {actual}")),
-        CompilerErrorKind::FunctionDoesNotHaveReturn { name, return_location } => build_error_report(span)
-            .with_label(Label::new(*return_location).with_message("Function returns results"))
-            .with_message(format!("Function {name} should return results, but not all branches return.")),
+            .with_labels(argument_spans.iter().enumerate().map(|(i, argument_span)| {
+                Label::new(*argument_span).with_message(format!("Argument {}", i + 1))
+            })),
+        CompilerErrorKind::FunctionMustReturnOneValueInThisLocation { .. } => build_error_report(span)
+            .with_label(Label::new(span).with_message("Function must return 1 value here")),
+        CompilerErrorKind::FunctionDoesNotHaveReturn { return_location, .. } => build_error_report(span)
+            .with_label(Label::new(*return_location).with_message("Function returns results")),
         CompilerErrorKind::BreakOrContinueOutsideOfLoop => build_error_report(span)
-            .with_label(Label::new(span).with_message("This statement"))
-            .with_message("`break` or `continue` must be within a loop"),
+            .with_label(Label::new(span).with_message("This statement")),
         CompilerErrorKind::DivideByZero => build_error_report(span)
-            .with_label(Label::new(span).with_message("This reduces to 0"))
-            .with_message("Divide by zero not allowed"),
-        CompilerErrorKind::EventFunctionsShouldNotHaveAReturnType { return_type_span, function_name, event_span } => build_error_report(span)
-            .with_label(Label::new(*return_type_span).with_message("Expected no return type"))
-            .with_label(Label::new(*event_span).with_message(format!("'{function_name}' has been declared as an event handler")))
-            .with_message("Event handlers should not have a return type")
-            .with_help("Either remove the return type, or change this to be a regular function"),
+            .with_label(Label::new(span).with_message("This reduces to 0")),
         CompilerErrorKind::CannotCallEventHandler { function_span, function_name } => build_error_report(span)
             .with_label(Label::new(span).with_message("This call here"))
             .with_label(Label::new(*function_span).with_message("This event handler"))
-            .with_message("Cannot call event handlers")
             .with_note(format!("'{function_name}' is an event handler. It must be called in rust via the generated 'on_{function_name}' method")),
-        CompilerErrorKind::TriggerIncorrectArgs { name, first_definition_span, first_definition_args, second_definition_args } => build_error_report(span)
+        CompilerErrorKind::TriggerIncorrectArgs { first_definition_span, first_definition_args, second_definition_args, .. } => build_error_report(span)
             .with_label(Label::new(*first_definition_span).with_message(format!("This is called with types {}", first_definition_args.iter().map(|a| a.to_string()).collect::<Vec<_>>().join(", "))))
             .with_label(Label::new(span).with_message(format!("This is called with types {}", second_definition_args.iter().map(|a| a.to_string()).collect::<Vec<_>>().join(", "))))
-            .with_message(format!("Trigger '{name}' has been called with inconsistent arguments"))
             .with_help("`trigger` calls must be made with the same argument types"),
+        CompilerErrorKind::TriggerIncorrectFields { first_definition_span, first_definition_fields, second_definition_fields, .. } => build_error_report(span)
+            .with_label(Label::new(*first_definition_span).with_message(describe_trigger_fields(first_definition_fields)))
+            .with_label(Label::new(span).with_message(describe_trigger_fields(second_definition_fields)))
+            .with_help("`trigger` calls must name the same fields in the same order"),
+        CompilerErrorKind::DuplicateTriggerField { first_span, .. } => build_error_report(span)
+            .with_label(Label::new(*first_span).with_message("First set here"))
+            .with_label(Label::new(span).with_message("Set again here")),
+        CompilerErrorKind::TriggerMismatch { trigger_type, name, field_names, arguments, mismatch } => trigger_mismatch_report(trigger_type, name, field_names, arguments, *mismatch, span),
+        CompilerErrorKind::InvalidTypeForSendTarget { got } => build_error_report(span)
+            .with_label(Label::new(span).with_message(format!("This has type {got}"))),
+        CompilerErrorKind::MessageIncorrectArgs { first_definition_span, first_definition_args, second_definition_args, .. } => build_error_report(span)
+            .with_label(Label::new(*first_definition_span).with_message(format!("This is sent with types {}", first_definition_args.iter().map(|a| a.to_string()).collect::<Vec<_>>().join(", "))))
+            .with_label(Label::new(span).with_message(format!("This is sent with types {}", second_definition_args.iter().map(|a| a.to_string()).collect::<Vec<_>>().join(", "))))
+            .with_help("`send` calls must be made with the same argument types"),
+        CompilerErrorKind::MessageEventHandlerMismatch { event_handler_span, event_handler_args, message_args, .. } => build_error_report(span)
+            .with_label(Label::new(*event_handler_span).with_message(format!("This takes types {}", event_handler_args.iter().map(|a| a.to_string()).collect::<Vec<_>>().join(", "))))
+            .with_label(Label::new(span).with_message(format!("This is sent with types {}", message_args.iter().map(|a| a.to_string()).collect::<Vec<_>>().join(", "))))
+            .with_note("Messages are delivered to the event handler with the same name"),
+        CompilerErrorKind::UnknownMessage { .. } => build_error_report(span)
+            .with_label(Label::new(span).with_message("Sent here"))
+            .with_note("Messages are delivered to the event handler with the same name, so the script must define one"),
+        CompilerErrorKind::CannotCallGenerator { function_span, function_name } => build_error_report(span)
+            .with_label(Label::new(span).with_message("This call here"))
+            .with_label(Label::new(*function_span).with_message("This generator"))
+            .with_note(format!("'{function_name}' is a generator. Its values must be read in rust via the generated 'iter_{function_name}' method")),
+        CompilerErrorKind::CannotSendToGenerator { generator_span, .. } => build_error_report(span)
+            .with_label(Label::new(span).with_message("Sent here"))
+            .with_label(Label::new(*generator_span).with_message("This generator"))
+            .with_note("Messages are delivered to event handlers, but generators can only be run from rust"),
+        CompilerErrorKind::YieldOutsideGenerator => build_error_report(span)
+            .with_label(Label::new(span).with_message("This yield"))
+            .with_help("Declare the function with `generator fn` and the type of the values it yields"),
+        CompilerErrorKind::IncorrectYieldType { expected, actual, yield_type_span } => build_error_report(span)
+            .with_label(Label::new(span).with_message(format!("This yields a {actual}")))
+            .with_label(Label::new(*yield_type_span).with_message(format!("The generator yields {expected}"))),
+        CompilerErrorKind::WaitInGenerator { generator_span } => build_error_report(span)
+            .with_label(Label::new(span).with_message("This wait"))
+            .with_label(Label::new(*generator_span).with_message("In this generator"))
+            .with_note("Generators run straight through to their next `yield` whenever rust asks for a value"),
+        CompilerErrorKind::InvalidTypeForGroup { got } => build_error_report(span)
+            .with_label(Label::new(span).with_message(format!("This has type {got}"))),
+        CompilerErrorKind::InvalidTypeForTimeScale { got } => build_error_report(span)
+            .with_label(Label::new(span).with_message(format!("This has type {got}"))),
+        CompilerErrorKind::AssignmentToReadOnlyProperty { name } => build_error_report(span)
+            .with_label(Label::new(span).with_message("Assigned here"))
+            .with_note(format!("'{name}' is marked as readonly on the rust struct, so can only be changed from rust")),
+        CompilerErrorKind::ReadFromWriteOnlyProperty { name } => build_error_report(span)
+            .with_label(Label::new(span).with_message("Read here"))
+            .with_note(format!("'{name}' is marked as writeonly on the rust struct, so can only be assigned to")),
+    }
+}
+
+fn describe_trigger_fields(field_names: &Option<Vec<String>>) -> String {
+    match field_names {
+        Some(field_names) if field_names.is_empty() => "This is called with no fields".to_string(),
+        Some(field_names) => format!("This is called with fields {}", field_names.join(", ")),
+        None => "This is called with unnamed arguments".to_string(),
+    }
+}
+
+fn trigger_mismatch_report<'a>(
+    trigger_type: &str,
+    name: &str,
+    field_names: &Option<Vec<String>>,
+    arguments: &[Type],
+    mismatch: TriggerMismatch,
+    span: Span,
+) -> ariadne::ReportBuilder<'a, Span> {
+    let variant = format!("{trigger_type}::{name}");
+    match mismatch {
+        TriggerMismatch::UnknownVariant => build_error_report(span)
+            .with_label(Label::new(span).with_message("Triggered here"))
+            .with_note(format!(
+                "Every trigger must be a variant of the trigger_type, '{trigger_type}'"
+            )),
+        TriggerMismatch::Shape => {
+            let expected = match field_names {
+                Some(field_names) => format!(
+                    "a struct variant with fields {}",
+                    field_names
+                        .iter()
+                        .zip(arguments)
+                        .map(|(field_name, ty)| format!("{field_name}: {ty}"))
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                ),
+                None if arguments.is_empty() => "a unit variant".to_string(),
+                None => format!(
+                    "a tuple variant with fields of types {}",
+                    arguments
+                        .iter()
+                        .map(|ty| ty.to_string())
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                ),
+            };
+
+            build_error_report(span)
+                .with_label(Label::new(span).with_message("Triggered here"))
+                .with_help(format!("This needs '{variant}' to be {expected}"))
+        }
+        TriggerMismatch::MissingFields => {
+            build_error_report(span).with_label(Label::new(span).with_message("Triggered here"))
+        }
+        TriggerMismatch::UnknownField(_) => {
+            build_error_report(span).with_label(Label::new(span).with_message("Unknown field"))
+        }
+        TriggerMismatch::FieldType(index) => build_error_report(span)
+            .with_label(Label::new(span).with_message(format!("This is a {}", arguments[index])))
+            .with_note(format!(
+                "The field's type must implement TapirProperty with a SCRIPT_TYPE of {}",
+                arguments[index]
+            )),
+    }
+}
+
+fn with_suggestion<'a>(
+    report: ariadne::ReportBuilder<'a, Span>,
+    suggestion: &Option<String>,
+) -> ariadne::ReportBuilder<'a, Span> {
+    match suggestion {
+        Some(suggestion) => report.with_help(format!("Did you mean '{suggestion}'?")),
+        None => report,
+    }
+}
+
+fn compiler_warning_report(
+    compiler_warning_kind: &CompilerWarningKind,
+    span: Span,
+    severity: Severity,
+) -> ariadne::ReportBuilder<'_, Span> {
+    match compiler_warning_kind {
+        CompilerWarningKind::UnusedVariable { name } => build_warning_report(span, severity)
+            .with_label(Label::new(span).with_message("This is never read"))
+            .with_help(format!(
+                "If this is intentional, prefix it with an underscore: '_{name}'"
+            )),
+        CompilerWarningKind::UnusedFunction { .. } => build_warning_report(span, severity)
+            .with_label(Label::new(span).with_message("This function is never called")),
+        CompilerWarningKind::EmptyEventHandler { name } => build_warning_report(span, severity)
+            .with_label(Label::new(span).with_message("This event handler is empty"))
+            .with_note(format!("Calling 'on_{name}' from rust will have no effect")),
+        CompilerWarningKind::UnreachableCode {
+            diverging_statement,
+        } => build_warning_report(span, severity)
+            .with_label(Label::new(span).with_message("This code will never run"))
+            .with_label(
+                Label::new(*diverging_statement)
+                    .with_message("Because control flow never gets past this statement"),
+            ),
+        CompilerWarningKind::ShadowedVariable {
+            name,
+            original_declaration,
+        } => {
+            let report = build_warning_report(span, severity)
+                .with_label(Label::new(span).with_message("New declaration here"));
+
+            match original_declaration {
+                Some(original_declaration) => report.with_label(
+                    Label::new(*original_declaration).with_message("Originally declared here"),
+                ),
+                None => report.with_note(format!(
+                    "'{name}' is a property, which can no longer be accessed in this scope"
+                )),
+            }
+        }
+    }
+}
+
+fn parse_error_summary(parse_error: &ParseError) -> String {
+    match parse_error {
+        ParseError::UnrecognizedEof { .. } => "Unexpected end of file".to_string(),
+        ParseError::UnrecognizedToken { token, expected } => format!(
+            "Unexpected token {token}, expected one of {}",
+            expected.join(", ")
+        ),
+        ParseError::ExtraToken { token } => format!("Unexpected extra token {token}"),
+        ParseError::UnknownType { token } => {
+            format!("'{token}' is not a valid type, must be one of fix, bool or int")
+        }
+        ParseError::ExternFunctionWithBlock { name } => {
+            format!("extern function '{name}' cannot have a body")
+        }
+    }
+}
+
+fn lexical_error_summary(lexical_error_kind: &LexicalErrorKind) -> String {
+    match lexical_error_kind {
+        LexicalErrorKind::InvalidNumber(parse_int_error) => format!("{parse_int_error}"),
+        LexicalErrorKind::InvalidToken => "Invalid token".to_string(),
+        LexicalErrorKind::InvalidFix => "Invalid fixnum".to_string(),
+    }
+}
+
+fn compiler_error_summary(compiler_error_kind: &CompilerErrorKind) -> String {
+    match compiler_error_kind {
+        CompilerErrorKind::UnknownVariable { name, .. } => format!("Unknown variable '{name}'"),
+        CompilerErrorKind::TypeError { expected, actual, .. } => format!("Incorrect type, expected {expected} but got {actual}"),
+        CompilerErrorKind::UnknownType(var) => format!("Unknown type for variable '{var}'"),
+        CompilerErrorKind::BinaryOperatorTypeError { lhs_type, rhs_type, .. } => format!("Left hand side has type {lhs_type} but right hand side has type {rhs_type}"),
+        CompilerErrorKind::InvalidTypeForBinaryOperator { type_ } => format!("Binary operator cannot items of type {type_}"),
+        CompilerErrorKind::InvalidTypeForIfCondition { got } => format!("Condition in if statement must be a bool, but got a {got}"),
+        CompilerErrorKind::IncorrectNumberOfReturnTypes { expected, actual, .. } => format!("Function should be returning {expected} return values, but you are actually returning {actual}."),
+        CompilerErrorKind::MismatchingReturnTypes { expected, actual, .. } => format!("Function is declared to return type {expected} but got {actual}"),
+        CompilerErrorKind::FunctionAlreadyDeclared { function_name, .. } => format!("Function with name '{function_name}' already exists"),
+        CompilerErrorKind::UnknownFunction { name, .. } => format!("No such function {name}"),
+        CompilerErrorKind::IncorrectNumberOfArguments { expected, actual, function_name, .. } => format!("Incorrect number of argumets for function {function_name}, expected {expected} arguments but got {actual}."),
+        CompilerErrorKind::FunctionMustReturnOneValueInThisLocation { actual } => format!("Function call must return exactly 1 value here, but got {actual}"),
+        CompilerErrorKind::FunctionDoesNotHaveReturn { name, .. } => format!("Function {name} should return results, but not all branches return."),
+        CompilerErrorKind::BreakOrContinueOutsideOfLoop => "`break` or `continue` must be within a loop".to_string(),
+        CompilerErrorKind::DivideByZero => "Divide by zero not allowed".to_string(),
+        CompilerErrorKind::CannotCallEventHandler { .. } => "Cannot call event handlers".to_string(),
+        CompilerErrorKind::TriggerIncorrectArgs { name, .. } => format!("Trigger '{name}' has been called with inconsistent arguments"),
+        CompilerErrorKind::TriggerIncorrectFields { name, .. } => format!("Trigger '{name}' has been called with inconsistent fields"),
+        CompilerErrorKind::DuplicateTriggerField { name, .. } => format!("Field '{name}' is set more than once"),
+        CompilerErrorKind::TriggerMismatch { trigger_type, name, field_names, arguments, mismatch } => trigger_mismatch_summary(trigger_type, name, field_names, arguments, *mismatch),
+        CompilerErrorKind::InvalidTypeForSendTarget { got } => format!("The target of a send must be an int, but got a {got}"),
+        CompilerErrorKind::MessageIncorrectArgs { name, .. } => format!("Message '{name}' has been sent with inconsistent arguments"),
+        CompilerErrorKind::MessageEventHandlerMismatch { name, .. } => format!("Message '{name}' is sent with different arguments to its event handler"),
+        CompilerErrorKind::UnknownMessage { name } => format!("Message '{name}' is not an event handler of this script"),
+        CompilerErrorKind::CannotCallGenerator { .. } => "Cannot call generators".to_string(),
+        CompilerErrorKind::CannotSendToGenerator { name, .. } => format!("Cannot send message '{name}' to a generator"),
+        CompilerErrorKind::YieldOutsideGenerator => "`yield` can only be used in a generator".to_string(),
+        CompilerErrorKind::IncorrectYieldType { expected, actual, .. } => format!("Expected to yield a {expected}, but got a {actual}"),
+        CompilerErrorKind::WaitInGenerator { .. } => "Cannot wait in a generator".to_string(),
+        CompilerErrorKind::InvalidTypeForGroup { got } => format!("Thread groups must be ints, but got a {got}"),
+        CompilerErrorKind::InvalidTypeForTimeScale { got } => format!("A group's time scale must be an int number of frames, but got a {got}"),
+        CompilerErrorKind::AssignmentToReadOnlyProperty { name } => format!("Cannot assign to read only property '{name}'"),
+        CompilerErrorKind::ReadFromWriteOnlyProperty { name } => format!("Cannot read write only property '{name}'"),
+    }
+}
+
+fn trigger_mismatch_summary(
+    trigger_type: &str,
+    name: &str,
+    field_names: &Option<Vec<String>>,
+    arguments: &[Type],
+    mismatch: TriggerMismatch,
+) -> String {
+    let variant = format!("{trigger_type}::{name}");
+    let field_description = |index: usize| match field_names {
+        Some(field_names) => format!("'{}'", field_names[index]),
+        None => index.to_string(),
+    };
+
+    match mismatch {
+        TriggerMismatch::UnknownVariant => format!("'{trigger_type}' has no variant '{name}'"),
+        TriggerMismatch::Shape => format!("'{variant}' doesn't match how it is triggered"),
+        TriggerMismatch::MissingFields => {
+            format!("Trigger '{name}' doesn't set every field of '{variant}'")
+        }
+        TriggerMismatch::UnknownField(index) => {
+            format!("'{variant}' has no field {}", field_description(index))
+        }
+        TriggerMismatch::FieldType(index) => format!(
+            "Field {} of '{variant}' isn't a {}",
+            field_description(index),
+            arguments[index]
+        ),
+    }
+}
+
+fn compiler_warning_summary(compiler_warning_kind: &CompilerWarningKind) -> String {
+    match compiler_warning_kind {
+        CompilerWarningKind::UnusedVariable { name } => format!("Unused variable '{name}'"),
+        CompilerWarningKind::UnusedFunction { name } => format!("Unused function '{name}'"),
+        CompilerWarningKind::EmptyEventHandler { name } => {
+            format!("Event handler '{name}' does nothing")
+        }
+        CompilerWarningKind::UnreachableCode { .. } => "Unreachable code".to_string(),
+        CompilerWarningKind::ShadowedVariable { name, .. } => {
+            format!("Declaration of '{name}' shadows an existing variable")
+        }
     }
 }
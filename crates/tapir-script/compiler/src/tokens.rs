@@ -104,6 +104,18 @@ pub enum Token<'input> {
     KeywordThen,
     #[token("trigger")]
     KeywordTrigger,
+    #[token("send")]
+    KeywordSend,
+    #[token("generator")]
+    KeywordGenerator,
+    #[token("yield")]
+    KeywordYield,
+    #[token("pause")]
+    KeywordPause,
+    #[token("resume")]
+    KeywordResume,
+    #[token("timescale")]
+    KeywordTimescale,
 
     #[token("true")]
     True,
@@ -0,0 +1,119 @@
+#![deny(clippy::all)]
+
+//! Formats tapir scripts in place.
+//!
+//! Usage: `tapir-script-fmt [--check] [files...]`
+//!
+//! With `--check`, files are left untouched and the process exits with a failure status if
+//! any of them aren't formatted, which is useful for enforcing style in CI. With no files,
+//! the script is read from stdin and the formatted version written to stdout.
+
+use std::{
+    env, fs,
+    io::{self, IsTerminal, Read},
+    process::ExitCode,
+};
+
+const USAGE: &str = "Usage: tapir-script-fmt [--check] [files...]";
+
+fn main() -> ExitCode {
+    let mut check = false;
+    let mut files = vec![];
+
+    for arg in env::args().skip(1) {
+        match arg.as_str() {
+            "--check" => check = true,
+            "-h" | "--help" => {
+                println!("{USAGE}");
+                return ExitCode::SUCCESS;
+            }
+            flag if flag.starts_with('-') => {
+                eprintln!("Unknown argument {flag}\n{USAGE}");
+                return ExitCode::FAILURE;
+            }
+            _ => files.push(arg),
+        }
+    }
+
+    if files.is_empty() {
+        return format_stdin(check);
+    }
+
+    let mut success = true;
+    for file in &files {
+        success &= format_file(file, check);
+    }
+
+    if success {
+        ExitCode::SUCCESS
+    } else {
+        ExitCode::FAILURE
+    }
+}
+
+fn format_stdin(check: bool) -> ExitCode {
+    let mut input = String::new();
+    if let Err(e) = io::stdin().read_to_string(&mut input) {
+        eprintln!("Failed to read stdin: {e}");
+        return ExitCode::FAILURE;
+    }
+
+    match compiler::format("<stdin>", &input) {
+        Ok(formatted) if check => {
+            if formatted == input {
+                ExitCode::SUCCESS
+            } else {
+                eprintln!("<stdin> is not formatted");
+                ExitCode::FAILURE
+            }
+        }
+        Ok(formatted) => {
+            print!("{formatted}");
+            ExitCode::SUCCESS
+        }
+        Err(diagnostics) => {
+            print_diagnostics(diagnostics);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+/// Returns false if the file couldn't be formatted, or if it isn't formatted in check mode
+fn format_file(file: &str, check: bool) -> bool {
+    let input = match fs::read_to_string(file) {
+        Ok(input) => input,
+        Err(e) => {
+            eprintln!("Failed to read {file}: {e}");
+            return false;
+        }
+    };
+
+    let formatted = match compiler::format(file, &input) {
+        Ok(formatted) => formatted,
+        Err(diagnostics) => {
+            print_diagnostics(diagnostics);
+            return false;
+        }
+    };
+
+    if formatted == input {
+        return true;
+    }
+
+    if check {
+        eprintln!("{file} is not formatted");
+        return false;
+    }
+
+    if let Err(e) = fs::write(file, formatted) {
+        eprintln!("Failed to write {file}: {e}");
+        return false;
+    }
+
+    true
+}
+
+/// Only colours the output on a terminal, so the escape codes don't end up in CI logs
+fn print_diagnostics(mut diagnostics: compiler::Diagnostics) {
+    eprintln!("{}", diagnostics.pretty_string(io::stderr().is_terminal()));
+}
@@ -0,0 +1,85 @@
+use lsp_types::Position;
+
+/// Converts between byte offsets, which the compiler uses, and line / UTF-16 column
+/// positions which is what the language server protocol uses.
+pub struct LineIndex {
+    line_starts: Vec<usize>,
+}
+
+impl LineIndex {
+    pub fn new(text: &str) -> Self {
+        let line_starts = std::iter::once(0)
+            .chain(text.match_indices('\n').map(|(i, _)| i + 1))
+            .collect();
+
+        Self { line_starts }
+    }
+
+    pub fn position(&self, text: &str, offset: usize) -> Position {
+        let offset = offset.min(text.len());
+        let line = self
+            .line_starts
+            .partition_point(|&line_start| line_start <= offset)
+            - 1;
+
+        let line_start = self.line_starts[line];
+        let character = text[line_start..offset].encode_utf16().count();
+
+        Position::new(line as u32, character as u32)
+    }
+
+    pub fn offset(&self, text: &str, position: Position) -> usize {
+        let Some(&line_start) = self.line_starts.get(position.line as usize) else {
+            return text.len();
+        };
+
+        let mut utf16_count = 0;
+        for (i, c) in text[line_start..].char_indices() {
+            if utf16_count >= position.character as usize || c == '\n' {
+                return line_start + i;
+            }
+
+            utf16_count += c.len_utf16();
+        }
+
+        text.len()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn round_trips_ascii() {
+        let text = "var x = 5;\nx = x + 1;\n";
+        let index = LineIndex::new(text);
+
+        assert_eq!(index.position(text, 0), Position::new(0, 0));
+        assert_eq!(index.position(text, 15), Position::new(1, 4));
+        assert_eq!(index.offset(text, Position::new(1, 4)), 15);
+        assert_eq!(index.position(text, text.len()), Position::new(2, 0));
+    }
+
+    #[test]
+    fn counts_utf16_columns() {
+        let text = "# héllo 🦫\nwait;";
+        let index = LineIndex::new(text);
+
+        let wait_offset = text.find("wait").unwrap();
+        assert_eq!(index.position(text, wait_offset), Position::new(1, 0));
+
+        let end_of_comment = text.find('\n').unwrap();
+        assert_eq!(index.position(text, end_of_comment), Position::new(0, 10));
+        assert_eq!(index.offset(text, Position::new(0, 10)), end_of_comment);
+    }
+
+    #[test]
+    fn clamps_positions_past_the_end_of_a_line() {
+        let text = "wait;\nwait;";
+        let index = LineIndex::new(text);
+
+        assert_eq!(index.offset(text, Position::new(0, 100)), 5);
+        assert_eq!(index.offset(text, Position::new(10, 0)), text.len());
+    }
+}
@@ -0,0 +1,394 @@
+#![deny(clippy::all)]
+
+use std::{collections::HashMap, error::Error};
+
+use compiler::{Analysis, CompileSettings, Property, ReferenceTarget, Severity, SymbolKind};
+use line_index::LineIndex;
+use lsp_server::{Connection, ErrorCode, Message, Notification, Request, Response};
+use lsp_types::{
+    notification::{
+        DidChangeTextDocument, DidCloseTextDocument, DidOpenTextDocument, DidSaveTextDocument,
+        Notification as _, PublishDiagnostics,
+    },
+    request::{Completion, GotoDefinition, HoverRequest, Request as _},
+    CompletionItem, CompletionItemKind, CompletionOptions, CompletionParams, CompletionResponse,
+    Diagnostic, DiagnosticSeverity, GotoDefinitionParams, GotoDefinitionResponse, Hover,
+    HoverContents, HoverParams, HoverProviderCapability, Location, MarkupContent, MarkupKind,
+    OneOf, Position, PublishDiagnosticsParams, Range, ServerCapabilities,
+    TextDocumentSyncCapability, TextDocumentSyncKind, Url,
+};
+use serde::{de::DeserializeOwned, Serialize};
+
+mod line_index;
+mod properties;
+
+const KEYWORDS: &[&str] = &[
+    "wait",
+    "var",
+    "if",
+    "else",
+    "fn",
+    "extern",
+    "int",
+    "fix",
+    "bool",
+    "return",
+    "spawn",
+    "loop",
+    "break",
+    "continue",
+    "event",
+    "then",
+    "trigger",
+    "send",
+    "generator",
+    "yield",
+    "pause",
+    "resume",
+    "timescale",
+    "true",
+    "false",
+];
+
+fn main() -> Result<(), Box<dyn Error + Sync + Send>> {
+    let (connection, io_threads) = Connection::stdio();
+
+    let server_capabilities = serde_json::to_value(ServerCapabilities {
+        text_document_sync: Some(TextDocumentSyncCapability::Kind(TextDocumentSyncKind::FULL)),
+        hover_provider: Some(HoverProviderCapability::Simple(true)),
+        definition_provider: Some(OneOf::Left(true)),
+        completion_provider: Some(CompletionOptions::default()),
+        ..Default::default()
+    })?;
+
+    connection.initialize(server_capabilities)?;
+
+    Server::default().run(connection)?;
+
+    io_threads.join()?;
+    Ok(())
+}
+
+struct Document {
+    text: String,
+    line_index: LineIndex,
+    properties: Vec<Property>,
+    analysis: Analysis,
+}
+
+impl Document {
+    fn new(uri: &Url, text: String) -> Self {
+        let properties = uri
+            .to_file_path()
+            .map(|path| properties::properties_for_script(&path))
+            .unwrap_or_default();
+
+        let mut document = Self {
+            text: String::new(),
+            line_index: LineIndex::new(""),
+            properties,
+            analysis: Analysis::default(),
+        };
+
+        document.update(uri, text);
+        document
+    }
+
+    fn update(&mut self, uri: &Url, text: String) {
+        self.analysis = compiler::analyse(
+            uri.path(),
+            &text,
+            &CompileSettings {
+                properties: self.properties.clone(),
+                enable_optimisations: false,
+                fix_precision: CompileSettings::DEFAULT_FIX_PRECISION,
+            },
+        );
+
+        self.line_index = LineIndex::new(&text);
+        self.text = text;
+    }
+
+    fn range(&self, range: &std::ops::Range<usize>) -> Range {
+        Range::new(
+            self.line_index.position(&self.text, range.start),
+            self.line_index.position(&self.text, range.end),
+        )
+    }
+
+    fn offset(&self, position: Position) -> usize {
+        self.line_index.offset(&self.text, position)
+    }
+}
+
+#[derive(Default)]
+struct Server {
+    documents: HashMap<Url, Document>,
+}
+
+impl Server {
+    fn run(&mut self, connection: Connection) -> Result<(), Box<dyn Error + Sync + Send>> {
+        for message in &connection.receiver {
+            match message {
+                Message::Request(request) => {
+                    if connection.handle_shutdown(&request)? {
+                        return Ok(());
+                    }
+
+                    connection
+                        .sender
+                        .send(Message::Response(self.handle_request(request)))?;
+                }
+                Message::Notification(notification) => {
+                    if let Some(uri) = self.handle_notification(notification) {
+                        connection
+                            .sender
+                            .send(Message::Notification(self.diagnostics(uri)))?;
+                    }
+                }
+                Message::Response(_) => {}
+            }
+        }
+
+        Ok(())
+    }
+
+    fn handle_request(&self, request: Request) -> Response {
+        match request.method.as_str() {
+            HoverRequest::METHOD => respond(request, |params| self.hover(params)),
+            GotoDefinition::METHOD => respond(request, |params| self.definition(params)),
+            Completion::METHOD => respond(request, |params| self.completion(params)),
+            _ => Response::new_err(
+                request.id,
+                ErrorCode::MethodNotFound as i32,
+                format!("Unsupported request {}", request.method),
+            ),
+        }
+    }
+
+    /// Returns the document which needs its diagnostics republishing, if any
+    fn handle_notification(&mut self, notification: Notification) -> Option<Url> {
+        match notification.method.as_str() {
+            DidOpenTextDocument::METHOD => {
+                let params = extract::<DidOpenTextDocument>(notification)?;
+                let uri = params.text_document.uri;
+
+                self.documents
+                    .insert(uri.clone(), Document::new(&uri, params.text_document.text));
+                Some(uri)
+            }
+            DidChangeTextDocument::METHOD => {
+                let params = extract::<DidChangeTextDocument>(notification)?;
+                let uri = params.text_document.uri;
+
+                // we only ask for full document syncs, so the last change has all the text
+                let text = params.content_changes.into_iter().last()?.text;
+                self.documents.get_mut(&uri)?.update(&uri, text);
+                Some(uri)
+            }
+            DidSaveTextDocument::METHOD => {
+                // re-read the properties in case they have changed
+                let params = extract::<DidSaveTextDocument>(notification)?;
+                let uri = params.text_document.uri;
+
+                let text = self.documents.remove(&uri)?.text;
+                self.documents
+                    .insert(uri.clone(), Document::new(&uri, text));
+                Some(uri)
+            }
+            DidCloseTextDocument::METHOD => {
+                let params = extract::<DidCloseTextDocument>(notification)?;
+                self.documents.remove(&params.text_document.uri);
+                Some(params.text_document.uri)
+            }
+            _ => None,
+        }
+    }
+
+    fn diagnostics(&self, uri: Url) -> Notification {
+        let diagnostics = self
+            .documents
+            .get(&uri)
+            .map(|document| {
+                document
+                    .analysis
+                    .diagnostics
+                    .iter()
+                    .map(|diagnostic| Diagnostic {
+                        range: document.range(&diagnostic.range),
+                        severity: Some(match diagnostic.severity {
+                            Severity::Error => DiagnosticSeverity::ERROR,
+                            Severity::Warning => DiagnosticSeverity::WARNING,
+                        }),
+                        source: Some("tapir".to_string()),
+                        message: diagnostic.message.clone(),
+                        ..Default::default()
+                    })
+                    .collect()
+            })
+            .unwrap_or_default();
+
+        Notification::new(
+            PublishDiagnostics::METHOD.to_string(),
+            PublishDiagnosticsParams {
+                uri,
+                diagnostics,
+                version: None,
+            },
+        )
+    }
+
+    fn hover(&self, params: HoverParams) -> Option<Hover> {
+        let position = params.text_document_position_params;
+        let document = self.documents.get(&position.text_document.uri)?;
+        let analysis = &document.analysis;
+
+        let reference = analysis.reference_at(document.offset(position.position))?;
+
+        let description = match reference.target {
+            ReferenceTarget::Symbol(index) => {
+                let symbol = &analysis.symbols[index];
+                let kind = match symbol.kind {
+                    SymbolKind::Property => "property",
+                    SymbolKind::Argument => "argument",
+                    SymbolKind::Variable => "var",
+                };
+
+                format!("{kind} {}: {}", symbol.name, symbol.ty)
+            }
+            ReferenceTarget::Function(index) => function_signature(&analysis.functions[index]),
+        };
+
+        Some(Hover {
+            contents: HoverContents::Markup(MarkupContent {
+                kind: MarkupKind::Markdown,
+                value: format!("```tapir\n{description}\n```"),
+            }),
+            range: Some(document.range(&reference.range)),
+        })
+    }
+
+    fn definition(&self, params: GotoDefinitionParams) -> Option<GotoDefinitionResponse> {
+        let position = params.text_document_position_params;
+        let uri = position.text_document.uri;
+        let document = self.documents.get(&uri)?;
+        let analysis = &document.analysis;
+
+        let reference = analysis.reference_at(document.offset(position.position))?;
+
+        let declaration = match reference.target {
+            ReferenceTarget::Symbol(index) => analysis.symbols[index].declaration.as_ref()?,
+            ReferenceTarget::Function(index) => &analysis.functions[index].declaration,
+        };
+
+        Some(GotoDefinitionResponse::Scalar(Location::new(
+            uri,
+            document.range(declaration),
+        )))
+    }
+
+    fn completion(&self, params: CompletionParams) -> Option<CompletionResponse> {
+        let position = params.text_document_position;
+        let document = self.documents.get(&position.text_document.uri)?;
+        let analysis = &document.analysis;
+        let offset = document.offset(position.position);
+
+        let keywords = KEYWORDS.iter().map(|keyword| CompletionItem {
+            label: keyword.to_string(),
+            kind: Some(CompletionItemKind::KEYWORD),
+            ..Default::default()
+        });
+
+        // only suggest variables which have been declared before the cursor
+        let symbols = analysis
+            .symbols
+            .iter()
+            .filter(|symbol| {
+                symbol
+                    .declaration
+                    .as_ref()
+                    .is_none_or(|declaration| declaration.end <= offset)
+            })
+            .map(|symbol| CompletionItem {
+                label: symbol.name.clone(),
+                kind: Some(match symbol.kind {
+                    SymbolKind::Property => CompletionItemKind::FIELD,
+                    SymbolKind::Argument | SymbolKind::Variable => CompletionItemKind::VARIABLE,
+                }),
+                detail: Some(symbol.ty.to_string()),
+                ..Default::default()
+            });
+
+        // event handlers and generators can't be called from script
+        let functions = analysis
+            .functions
+            .iter()
+            .filter(|function| !function.is_event_handler && function.yields.is_none())
+            .map(|function| CompletionItem {
+                label: function.name.clone(),
+                kind: Some(CompletionItemKind::FUNCTION),
+                detail: Some(function_signature(function)),
+                ..Default::default()
+            });
+
+        Some(CompletionResponse::Array(
+            keywords.chain(symbols).chain(functions).collect(),
+        ))
+    }
+}
+
+fn function_signature(function: &compiler::AnalysisFunction) -> String {
+    let arguments = function
+        .arguments
+        .iter()
+        .map(|(name, ty)| format!("{name}: {ty}"))
+        .collect::<Vec<_>>()
+        .join(", ");
+
+    let event = if function.is_event_handler {
+        "event "
+    } else if function.yields.is_some() {
+        "generator "
+    } else {
+        ""
+    };
+
+    let return_types = match function.return_types.as_slice() {
+        // generators don't return anything, but are declared with the type they yield
+        [] => function
+            .yields
+            .map(|ty| format!(" -> {ty}"))
+            .unwrap_or_default(),
+        [ty] => format!(" -> {ty}"),
+        types => format!(
+            " -> ({})",
+            types
+                .iter()
+                .map(|ty| ty.to_string())
+                .collect::<Vec<_>>()
+                .join(", ")
+        ),
+    };
+
+    format!("{event}fn {}({arguments}){return_types}", function.name)
+}
+
+fn respond<P: DeserializeOwned, R: Serialize>(
+    request: Request,
+    handler: impl FnOnce(P) -> R,
+) -> Response {
+    match serde_json::from_value(request.params) {
+        Ok(params) => Response::new_ok(request.id, handler(params)),
+        Err(e) => Response::new_err(
+            request.id,
+            ErrorCode::InvalidParams as i32,
+            format!("Invalid parameters: {e}"),
+        ),
+    }
+}
+
+fn extract<N: lsp_types::notification::Notification>(
+    notification: Notification,
+) -> Option<N::Params> {
+    notification.extract(N::METHOD).ok()
+}
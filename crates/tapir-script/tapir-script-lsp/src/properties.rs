@@ -0,0 +1,196 @@
+//! Works out which properties are available to a script. Properties are declared on the rust
+//! struct which derives `TapirScript`, so we either read them from a sidecar file next to the
+//! script, or find the struct by scanning the rust source of the crate the script lives in.
+
+use std::{
+    fs,
+    panic::{self, AssertUnwindSafe},
+    path::{Path, PathBuf},
+};
+
+use compiler::{Property, PropertyAccess, Type};
+
+/// The extension appended to the script's filename to find the sidecar file, so
+/// `enemy.tapir` has its properties declared in `enemy.tapir.props`.
+const SIDECAR_EXTENSION: &str = "props";
+
+pub fn properties_for_script(script: &Path) -> Vec<Property> {
+    let mut sidecar = script.as_os_str().to_owned();
+    sidecar.push(".");
+    sidecar.push(SIDECAR_EXTENSION);
+
+    if let Ok(content) = fs::read_to_string(PathBuf::from(sidecar)) {
+        return parse_sidecar(&content);
+    }
+
+    properties_from_rust_source(script).unwrap_or_default()
+}
+
+/// The sidecar file has one property per line in the same format as function arguments,
+/// so `health: int`, optionally followed by `readonly` or `writeonly`. Lines starting with `#`
+/// are comments.
+fn parse_sidecar(content: &str) -> Vec<Property> {
+    content
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .filter_map(|line| {
+            let (name, rest) = line.split_once(':')?;
+            let mut rest = rest.split_whitespace();
+
+            let ty = match rest.next()? {
+                "int" => Type::Int,
+                "fix" => Type::Fix,
+                "bool" => Type::Bool,
+                _ => return None,
+            };
+
+            let access = match rest.next() {
+                None => PropertyAccess::ReadWrite,
+                Some("readonly") => PropertyAccess::ReadOnly,
+                Some("writeonly") => PropertyAccess::WriteOnly,
+                Some(_) => return None,
+            };
+
+            Some((name.trim().to_string(), ty, access))
+        })
+        .enumerate()
+        .map(|(index, (name, ty, access))| Property {
+            ty,
+            index,
+            name,
+            access,
+        })
+        .collect()
+}
+
+fn properties_from_rust_source(script: &Path) -> Option<Vec<Property>> {
+    let script = script.canonicalize().ok()?;
+    let crate_root = script
+        .ancestors()
+        .find(|dir| dir.join("Cargo.toml").is_file())?;
+
+    let mut rust_files = vec![];
+    find_rust_files(crate_root, &mut rust_files);
+
+    rust_files.iter().find_map(|rust_file| {
+        let content = fs::read_to_string(rust_file).ok()?;
+        let file = syn::parse_file(&content).ok()?;
+
+        find_in_items(&file.items, crate_root, &script)
+    })
+}
+
+fn find_rust_files(dir: &Path, rust_files: &mut Vec<PathBuf>) {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return;
+    };
+
+    for entry in entries.flatten() {
+        let path = entry.path();
+        let file_name = entry.file_name();
+        let file_name = file_name.to_string_lossy();
+
+        if path.is_dir() {
+            if file_name != "target" && !file_name.starts_with('.') {
+                find_rust_files(&path, rust_files);
+            }
+        } else if file_name.ends_with(".rs") {
+            rust_files.push(path);
+        }
+    }
+}
+
+fn find_in_items(items: &[syn::Item], crate_root: &Path, script: &Path) -> Option<Vec<Property>> {
+    items.iter().find_map(|item| match item {
+        syn::Item::Struct(item_struct) => {
+            let derive_input = syn::DeriveInput::from(item_struct.clone());
+
+            // the derive macro reports invalid attributes by panicking, which shouldn't bring down the server
+            let (script_name, properties) = panic::catch_unwind(AssertUnwindSafe(|| {
+                tapir_script_macros_core::script_properties(&derive_input)
+            }))
+            .ok()??;
+
+            let declared_script = crate_root.join(script_name).canonicalize().ok()?;
+            (declared_script == script).then_some(properties)
+        }
+        syn::Item::Mod(syn::ItemMod {
+            content: Some((_, items)),
+            ..
+        }) => find_in_items(items, crate_root, script),
+        _ => None,
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn sidecar_properties() {
+        let properties = parse_sidecar(
+            "# the player's state\nhealth: int\n\n  speed :fix\non_ground: bool readonly\nbroken\nname: string\nframe: int sometimes\n",
+        );
+
+        let properties = properties
+            .iter()
+            .map(|property| {
+                (
+                    property.name.as_str(),
+                    property.ty,
+                    property.index,
+                    property.access,
+                )
+            })
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            properties,
+            [
+                ("health", Type::Int, 0, PropertyAccess::ReadWrite),
+                ("speed", Type::Fix, 1, PropertyAccess::ReadWrite),
+                ("on_ground", Type::Bool, 2, PropertyAccess::ReadOnly)
+            ]
+        );
+    }
+
+    #[test]
+    fn properties_from_derive() {
+        let script = Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("../tapir-script/tests/booleans.tapir")
+            .canonicalize()
+            .unwrap();
+
+        let file = syn::parse_file(
+            r#"
+            mod inner {
+                #[derive(TapirScript)]
+                #[tapir("tests/booleans.tapir")]
+                struct ManyProperties {
+                    #[tapir(int)]
+                    input1: i32,
+                    #[tapir(skip)]
+                    ignored: i32,
+                    #[tapir(bool)]
+                    output: bool,
+                }
+            }
+            "#,
+        )
+        .unwrap();
+
+        let crate_root = script.parent().unwrap().parent().unwrap();
+        let properties = find_in_items(&file.items, crate_root, &script).unwrap();
+
+        let properties = properties
+            .iter()
+            .map(|property| (property.name.as_str(), property.ty, property.index))
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            properties,
+            [("input1", Type::Int, 0), ("output", Type::Bool, 2)]
+        );
+    }
+}
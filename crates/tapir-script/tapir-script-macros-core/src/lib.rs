@@ -4,28 +4,29 @@ use std::{
     path::{Path, PathBuf},
 };
 
-use compiler::{CompileSettings, Property, Type};
+use compiler::{
+    CompileSettings, CompilerWarningKind, LintLevel, Property, PropertyAccess, TriggerMismatch,
+    Type,
+};
 use proc_macro2::TokenStream;
-use quote::{format_ident, quote, ToTokens};
-use syn::{parse2, DeriveInput, Ident, LitStr, Token};
+use quote::{format_ident, quote, quote_spanned, ToTokens};
+use syn::{parse::Parse, parse2, spanned::Spanned, DeriveInput, Ident, LitInt, LitStr, Token};
 
 pub fn tapir_script_derive(struct_def: TokenStream) -> TokenStream {
     let ast: DeriveInput = parse2(struct_def).unwrap();
 
-    let syn::Data::Struct(data) = &ast.data else {
+    let syn::Data::Struct(_) = &ast.data else {
         panic!("Can only be defined on structs");
     };
 
-    let (reduced_filename, trigger_type) = get_script_path(&ast);
+    let (reduced_filename, top_level_args) = get_script_path(&ast);
+    let trigger_type = top_level_args.trigger_type;
+    let fix_precision = top_level_args.fix_precision;
 
     let file_content = fs::read_to_string(&reduced_filename)
         .unwrap_or_else(|e| panic!("Failed to read file {}: {e}", reduced_filename.display()));
 
-    let properties = if let syn::Fields::Named(named) = &data.fields {
-        extract_properties(named)
-    } else {
-        vec![]
-    };
+    let properties = extract_properties(&ast, fix_precision);
 
     let compiled_content = match compiler::compile(
         &reduced_filename,
@@ -36,6 +37,7 @@ pub fn tapir_script_derive(struct_def: TokenStream) -> TokenStream {
                 .map(|property| property.property.clone())
                 .collect(),
             enable_optimisations: true,
+            fix_precision,
         },
     ) {
         Ok(content) => content,
@@ -45,14 +47,97 @@ pub fn tapir_script_derive(struct_def: TokenStream) -> TokenStream {
         }
     };
 
+    let mut warnings = compiled_content.warnings;
+    let lints = top_level_args
+        .lints
+        .iter()
+        .map(|(level, lint)| (*level, lint.to_string()))
+        .collect::<Vec<_>>();
+    warnings.set_lint_levels(lints.iter().map(|(level, lint)| (*level, lint.as_str())));
+
+    if warnings.has_errors() {
+        eprintln!("{}", warnings.pretty_string(true));
+        panic!("Compile error");
+    }
+
+    let warnings = script_warnings(&mut warnings, top_level_args.script_name.span());
+
     if !compiled_content.triggers.is_empty() && trigger_type.is_none() {
         panic!("Tapir code is calling triggers, but no trigger_type defined");
     }
 
+    // every script in a world is the same struct, so messages go to this script's own events
+    let messages = compiled_content
+        .messages
+        .iter()
+        .map(|message| {
+            compiled_content
+                .event_handlers
+                .iter()
+                .position(|event_handler| event_handler.name == message.name)
+                .unwrap_or_else(|| {
+                    let mut diagnostics =
+                        message.unknown_event_diagnostics(&reduced_filename, &file_content);
+                    eprintln!("{}", diagnostics.pretty_string(true));
+                    panic!("Compile error");
+                })
+        })
+        .collect::<Vec<_>>();
+
+    let trigger_type_name = trigger_type
+        .as_ref()
+        .map(|t| {
+            t.segments
+                .iter()
+                .map(|segment| segment.ident.to_string())
+                .collect::<Vec<_>>()
+                .join("::")
+        })
+        .unwrap_or_default();
+
     let trigger_type = trigger_type
         .map(|t| t.into_token_stream())
         .unwrap_or(quote! { () });
 
+    if top_level_args.groups.is_some() && top_level_args.threads.is_none() {
+        panic!("groups can only be set along with threads and stack_size");
+    }
+
+    let storage = match (&top_level_args.threads, &top_level_args.stack_size) {
+        (None, None) => quote! { ::tapir_script::Growable },
+        (Some(threads), Some(stack_size)) => {
+            let stack_size_value: usize = stack_size
+                .base10_parse()
+                .unwrap_or_else(|e| panic!("Invalid stack_size: {e}"));
+
+            // recursive functions have no limit, so no fixed stack could be big enough for them
+            let max_depth = compiled_content
+                .stack_depths
+                .iter()
+                .map(|stack_depth| stack_depth.max_depth)
+                .collect::<Option<Vec<_>>>()
+                .unwrap_or_else(|| {
+                    panic!("stack_size is {stack_size_value}, but the script has recursive functions which could need any amount of stack. Remove threads and stack_size to let the stack grow as needed")
+                });
+
+            if let Some(max_depth) = max_depth
+                .into_iter()
+                .max()
+                .filter(|&max_depth| max_depth > stack_size_value)
+            {
+                panic!("stack_size is {stack_size_value}, but the script could need a stack of {max_depth}");
+            }
+
+            match &top_level_args.groups {
+                Some(groups) => quote! { ::tapir_script::Fixed<#threads, #stack_size, #groups> },
+                None => quote! { ::tapir_script::Fixed<#threads, #stack_size> },
+            }
+        }
+        _ => panic!("Must specify both threads and stack_size, or neither"),
+    };
+
+    let fix_type = fix_type(fix_precision);
+
     let triggers = compiled_content
         .triggers
         .iter()
@@ -70,7 +155,7 @@ pub fn tapir_script_derive(struct_def: TokenStream) -> TokenStream {
                     let pop = quote!(stack.pop().expect("Stack underflow"));
                     let value = match *ty {
                         Type::Int => quote! { #pop },
-                        Type::Fix => quote! { ::tapir_script::Fix::from_raw(#pop) },
+                        Type::Fix => quote! { #fix_type::from_raw(#pop) },
                         Type::Bool => quote! { #pop != 0 },
                         _ => panic!("Unknown type {ty}"),
                     };
@@ -81,11 +166,14 @@ pub fn tapir_script_derive(struct_def: TokenStream) -> TokenStream {
 
             let trigger_index = trigger_index as u8;
 
-            let args = if args.is_empty() {
-                quote! {}
-            } else {
-                args.reverse();
-                quote! { (#(#args,)*) }
+            args.reverse();
+            let args = match &trigger.field_names {
+                Some(field_names) => {
+                    let field_names = field_names.iter().map(|name| format_ident!("{name}"));
+                    quote! { { #(#field_names: #args,)* } }
+                }
+                None if args.is_empty() => quote! {},
+                None => quote! { (#(#args,)*) },
             };
 
             quote! {
@@ -97,7 +185,17 @@ pub fn tapir_script_derive(struct_def: TokenStream) -> TokenStream {
             }
         });
 
+    let trigger_checks = compiled_content.triggers.iter().map(|trigger| {
+        trigger_check(
+            trigger,
+            &trigger_type_name,
+            &reduced_filename,
+            &file_content,
+        )
+    });
+
     let setters = properties.iter().map(|property| &property.setter);
+    let type_checks = properties.iter().map(|property| &property.type_check);
     let getters = properties.iter().map(|property| &property.getter);
 
     let struct_name = ast.ident;
@@ -108,24 +206,99 @@ pub fn tapir_script_derive(struct_def: TokenStream) -> TokenStream {
     let reduced_filename = reduced_filename.to_string_lossy();
 
     let bytecode = &compiled_content.bytecode;
+    let stack_depths = compiled_content
+        .stack_depths
+        .iter()
+        .filter_map(|stack_depth| {
+            let bytecode_offset = stack_depth.bytecode_offset;
+            // recursive functions have no limit, so their stacks just grow as needed
+            let max_depth = stack_depth.max_depth?;
+
+            Some(quote! {
+                ::tapir_script::StackDepth { bytecode_offset: #bytecode_offset, max_depth: #max_depth }
+            })
+        });
     let event_handlers = compiled_content.event_handlers;
+    let event_handler_offsets = event_handlers
+        .iter()
+        .map(|event_handler| event_handler.bytecode_offset)
+        .collect::<Vec<_>>();
+
+    let property_schemas = properties.iter().map(|property| {
+        let Property {
+            ty,
+            index,
+            name,
+            access,
+        } = &property.property;
+
+        let index = *index as u8;
+        let ty = script_property_type(*ty);
+        let access = match access {
+            PropertyAccess::ReadWrite => quote!(::tapir_script::PropertyAccess::ReadWrite),
+            PropertyAccess::ReadOnly => quote!(::tapir_script::PropertyAccess::ReadOnly),
+            PropertyAccess::WriteOnly => quote!(::tapir_script::PropertyAccess::WriteOnly),
+        };
+
+        quote! {
+            ::tapir_script::PropertySchema { name: #name, index: #index, ty: #ty, access: #access }
+        }
+    });
+    let event_schemas = event_handlers
+        .iter()
+        .map(|event_handler| {
+            signature_schema(
+                &event_handler.name,
+                event_handler.arguments.iter().map(|argument| argument.ty),
+                event_handler.return_types.iter().copied(),
+                event_handler.yields,
+                None,
+            )
+        })
+        .collect::<Vec<_>>();
+    let trigger_schemas = compiled_content.triggers.iter().map(|trigger| {
+        signature_schema(
+            &trigger.name,
+            trigger.arguments.iter().copied(),
+            std::iter::empty(),
+            None,
+            trigger.field_names.as_deref(),
+        )
+    });
 
     let (event_handler_trait_fns, event_handler_trait_impls) =
-        generate_event_handlers(event_handlers);
+        generate_event_handlers(event_handlers, fix_precision);
 
     let event_handler_trait_name = format_ident!("{}Events", struct_name);
 
     quote! {
+        #warnings
+
         #[automatically_derived]
         unsafe impl #impl_generics ::tapir_script::TapirScript for #struct_name #ty_generics #where_clause {
             fn script(self) -> ::tapir_script::Script<Self> {
+                const { #(#type_checks;)* }
+
                 static BYTECODE: &[u16] = &[#(#bytecode),*];
+                static STACK_DEPTHS: &[::tapir_script::StackDepth] = &[#(#stack_depths),*];
+                static EVENT_HANDLERS: &[Option<usize>] = &[#(Some(#event_handler_offsets)),*];
+                static MESSAGES: &[usize] = &[#(#messages),*];
 
-                ::tapir_script::Script::new(self, BYTECODE)
+                let program =
+                    ::tapir_script::Program::new(BYTECODE, STACK_DEPTHS, EVENT_HANDLERS, MESSAGES);
+
+                // SAFETY: the bytecode was compiled against this struct's properties, triggers and
+                // events, and bytecode straight from the compiler always verifies
+                ::tapir_script::Script::new(self, unsafe {
+                    ::tapir_script::VerifiedProgram::new_unchecked(program)
+                })
             }
 
             type EventType = #trigger_type;
-            fn create_event(&self, index: u8, stack: &mut Vec<i32>) -> Self::EventType {
+            type Storage = #storage;
+            const FIX_PRECISION: u8 = #fix_precision;
+
+            fn create_event(&self, index: u8, stack: &mut dyn ::tapir_script::Storage<i32>) -> Self::EventType {
                 match index {
                     #(#triggers,)*
                     _ => unreachable!("Invalid index {index}"),
@@ -147,6 +320,16 @@ pub fn tapir_script_derive(struct_def: TokenStream) -> TokenStream {
             }
         }
 
+        #[automatically_derived]
+        impl #impl_generics ::tapir_script::TapirSchema for #struct_name #ty_generics #where_clause {
+            const SCHEMA: ::tapir_script::Schema = ::tapir_script::Schema {
+                script_path: #reduced_filename,
+                properties: &[#(#property_schemas),*],
+                events: &[#(#event_schemas),*],
+                triggers: &[#(#trigger_schemas),*],
+            };
+        }
+
         #visibility trait #event_handler_trait_name {
             #(#event_handler_trait_fns;)*
         }
@@ -155,26 +338,237 @@ pub fn tapir_script_derive(struct_def: TokenStream) -> TokenStream {
             #(#event_handler_trait_impls)*
         }
 
+        const _: () = {
+            let variants = <#trigger_type as ::tapir_script::TapirTrigger>::VARIANTS;
+            #(#trigger_checks)*
+        };
+
         const _: &[u8] = include_bytes!(#reduced_filename);
     }
 }
 
+pub fn tapir_trigger_derive(enum_def: TokenStream) -> TokenStream {
+    let ast: DeriveInput = parse2(enum_def).unwrap();
+
+    let syn::Data::Enum(data) = &ast.data else {
+        panic!("Can only be defined on enums");
+    };
+
+    let variants = data
+        .variants
+        .iter()
+        .filter(|variant| !is_skipped(&variant.attrs))
+        .map(|variant| {
+            let name = variant.ident.to_string();
+
+            let script_type = |ty: &syn::Type| {
+                quote_spanned! {ty.span()=>
+                    <#ty as ::tapir_script::TapirProperty>::SCRIPT_TYPE
+                }
+            };
+
+            let fields = match &variant.fields {
+                syn::Fields::Unit => quote!(::tapir_script::TriggerFields::Unit),
+                syn::Fields::Unnamed(fields) => {
+                    let types = fields.unnamed.iter().map(|field| script_type(&field.ty));
+                    quote!(::tapir_script::TriggerFields::Unnamed(&[#(#types),*]))
+                }
+                syn::Fields::Named(fields) => {
+                    let fields = fields.named.iter().map(|field| {
+                        let name = field.ident.as_ref().unwrap().to_string();
+                        let ty = script_type(&field.ty);
+                        quote!((#name, #ty))
+                    });
+                    quote!(::tapir_script::TriggerFields::Named(&[#(#fields),*]))
+                }
+            };
+
+            quote! {
+                ::tapir_script::TriggerVariant { name: #name, fields: #fields }
+            }
+        });
+
+    let enum_name = &ast.ident;
+    let (impl_generics, ty_generics, where_clause) = ast.generics.split_for_impl();
+
+    quote! {
+        #[automatically_derived]
+        impl #impl_generics ::tapir_script::TapirTrigger for #enum_name #ty_generics #where_clause {
+            const VARIANTS: &'static [::tapir_script::TriggerVariant] = &[#(#variants),*];
+        }
+    }
+}
+
+/// Variants marked with `#[tapir(skip)]` can't be triggered by scripts, so can have fields which
+/// aren't properties
+fn is_skipped(attrs: &[syn::Attribute]) -> bool {
+    attrs
+        .iter()
+        .filter(|attr| attr.path().is_ident("tapir"))
+        .any(|attr| {
+            let ident: Ident = attr
+                .parse_args()
+                .unwrap_or_else(|e| panic!("Invalid tapir attribute on variant: {e}"));
+
+            if ident != "skip" {
+                panic!("Expected 'skip' in the tapir attribute on a variant, found '{ident}'");
+            }
+
+            true
+        })
+}
+
+/// The script path (relative to the crate root) and the properties declared by a struct which
+/// derives `TapirScript`, without compiling the script. Returns `None` if the struct has no
+/// top level `#[tapir(...)]` attribute.
+///
+/// This panics in the same cases as the derive macro if the property attributes are invalid.
+pub fn script_properties(ast: &DeriveInput) -> Option<(String, Vec<Property>)> {
+    let top_level_args = ast
+        .attrs
+        .iter()
+        .find(|attr| attr.meta.path().is_ident("tapir"))?
+        .parse_args::<TopLevelTapirArgs>()
+        .ok()?;
+
+    let properties = extract_properties(ast, top_level_args.fix_precision)
+        .into_iter()
+        .map(|property| property.property)
+        .collect();
+
+    Some((top_level_args.script_name.value(), properties))
+}
+
+/// The Rust type for a fix with `fix_precision` fractional bits
+fn rust_type(ty: Type, fix_precision: u8) -> TokenStream {
+    match ty {
+        Type::Int => quote!(i32),
+        Type::Fix => fix_type(fix_precision),
+        Type::Bool => quote!(bool),
+        Type::Error => panic!("Should not have errors here"),
+    }
+}
+
+/// Converts `value`, an `i32` from the script, to the Rust type for `ty`
+fn from_script_value(ty: Type, value: TokenStream, fix_precision: u8) -> TokenStream {
+    match ty {
+        Type::Int => value,
+        Type::Fix => {
+            let fix = fix_type(fix_precision);
+            quote!(#fix::from_raw(#value))
+        }
+        Type::Bool => quote!(#value != 0),
+        Type::Error => panic!("Should not have errors here"),
+    }
+}
+
+fn fix_type(fix_precision: u8) -> TokenStream {
+    let fix_precision = fix_precision as usize;
+    quote! { ::tapir_script::Num::<i32, #fix_precision> }
+}
+
+/// The name, argument types and return types of an event handler or trigger, for the
+/// `TapirSchema`
+fn signature_schema(
+    name: &str,
+    arguments: impl Iterator<Item = Type>,
+    returns: impl Iterator<Item = Type>,
+    yields: Option<Type>,
+    field_names: Option<&[String]>,
+) -> TokenStream {
+    let arguments = arguments.map(script_property_type);
+    let returns = returns.map(script_property_type);
+    let yields = match yields {
+        Some(ty) => {
+            let ty = script_property_type(ty);
+            quote!(Some(#ty))
+        }
+        None => quote!(None),
+    };
+    let field_names = match field_names {
+        Some(field_names) => quote!(Some(&[#(#field_names),*])),
+        None => quote!(None),
+    };
+
+    quote! {
+        ::tapir_script::Signature {
+            name: #name,
+            arguments: &[#(#arguments),*],
+            returns: &[#(#returns),*],
+            yields: #yields,
+            field_names: #field_names,
+        }
+    }
+}
+
+/// Checks at compile time that the trigger matches a variant of the trigger type. The derive
+/// macro can't see the enum, so this is done in a const using its `TapirTrigger` implementation,
+/// but every error message is rendered here so that it can point at the script.
+fn trigger_check(
+    trigger: &compiler::Trigger,
+    trigger_type_name: &str,
+    filename: &Path,
+    file_content: &str,
+) -> TokenStream {
+    let message = |mismatch| {
+        trigger
+            .mismatch_diagnostics(trigger_type_name, mismatch, filename, file_content)
+            .pretty_string(false)
+    };
+
+    let unknown_variant = message(TriggerMismatch::UnknownVariant);
+    let shape = message(TriggerMismatch::Shape);
+    let missing_fields = message(TriggerMismatch::MissingFields);
+
+    let argument_indices = 0..trigger.arguments.len();
+    let unknown_fields = if trigger.field_names.is_some() {
+        argument_indices
+            .clone()
+            .map(|index| message(TriggerMismatch::UnknownField(index)))
+            .collect()
+    } else {
+        vec![]
+    };
+    let field_types = argument_indices.map(|index| message(TriggerMismatch::FieldType(index)));
+
+    let name = &trigger.name;
+    let field_names = match &trigger.field_names {
+        Some(field_names) => quote!(Some(&[#(#field_names),*] as &[&str])),
+        None => quote!(None),
+    };
+    let arguments = trigger.arguments.iter().map(|&ty| script_property_type(ty));
+
+    quote! {
+        match ::tapir_script::__private_check_trigger(variants, #name, #field_names, &[#(#arguments),*]) {
+            None => {}
+            Some(::tapir_script::TriggerMismatch::UnknownVariant) => panic!("{}", #unknown_variant),
+            Some(::tapir_script::TriggerMismatch::Shape) => panic!("{}", #shape),
+            Some(::tapir_script::TriggerMismatch::MissingFields) => panic!("{}", #missing_fields),
+            Some(::tapir_script::TriggerMismatch::UnknownField(index)) => {
+                let messages: &[&str] = &[#(#unknown_fields),*];
+                panic!("{}", messages[index])
+            }
+            Some(::tapir_script::TriggerMismatch::FieldType(index)) => {
+                let messages: &[&str] = &[#(#field_types),*];
+                panic!("{}", messages[index])
+            }
+        }
+    }
+}
+
 fn generate_event_handlers(
     event_handlers: Vec<compiler::EventHandler>,
+    fix_precision: u8,
 ) -> (Vec<TokenStream>, Vec<TokenStream>) {
     event_handlers
         .iter()
-        .map(|event_handler| {
+        .enumerate()
+        .map(|(event_index, event_handler)| {
             let arg_definitions = event_handler
                 .arguments
                 .iter()
                 .map(|arg| {
-                    let kind = match arg.ty {
-                        Type::Int => quote!(i32),
-                        Type::Fix => quote!(::tapir_script::Fix),
-                        Type::Bool => quote!(bool),
-                        Type::Error => panic!("Should not have errors here"),
-                    };
+                    let kind = rust_type(arg.ty, fix_precision);
                     let name = format_ident!("{}", arg.name);
 
                     quote!(#name: #kind)
@@ -183,25 +577,88 @@ fn generate_event_handlers(
 
             let event_name = format_ident!("on_{}", event_handler.name);
 
-            let initial_stack_vector = event_handler.arguments.iter().map(|arg| {
+            let initial_stack = event_handler.arguments.iter().map(|arg| {
                 let arg_name = format_ident!("{}", arg.name);
                 quote! {
-                    initial_stack.push(::tapir_script::TapirProperty::to_i32(&#arg_name))
+                    ::tapir_script::TapirProperty::to_i32(&#arg_name)
                 }
             });
 
-            let initial_stack_vector_len = event_handler.arguments.len() + 1; // the mandatory return value
+            if let Some(yields) = event_handler.yields {
+                let generator_name = format_ident!("iter_{}", event_handler.name);
+                let item_type = rust_type(yields, fix_precision);
+                let item = from_script_value(yields, quote!(value), fix_precision);
+
+                let signature = quote! {
+                    fn #generator_name(&mut self, #(#arg_definitions,)*)
+                        -> impl Iterator<Item = #item_type> + '_
+                };
+
+                return (
+                    signature.clone(),
+                    quote! {
+                        #signature {
+                            let initial_stack = [#(#initial_stack),*];
+
+                            unsafe { self.__private_generate(#event_index, &initial_stack) }
+                                .map(|value| #item)
+                        }
+                    },
+                );
+            }
+
+            if event_handler.return_types.is_empty() {
+                return (
+                    quote!(fn #event_name(&mut self, #(#arg_definitions,)*)),
+                    quote! {
+                        fn #event_name(&mut self, #(#arg_definitions,)*) {
+                            let initial_stack = [#(#initial_stack),*];
 
-            let initial_pc = event_handler.bytecode_offset;
+                            unsafe { self.__private_trigger_event(#event_index, &initial_stack); }
+                        }
+                    },
+                );
+            }
+
+            // event handlers which return something are run straight away, so the caller gets the
+            // answer this frame
+            let return_count = event_handler.return_types.len();
+            let return_names = (0..return_count)
+                .map(|i| format_ident!("ret{i}"))
+                .collect::<Vec<_>>();
+            let return_types = event_handler
+                .return_types
+                .iter()
+                .map(|&ty| rust_type(ty, fix_precision));
+            let return_values = event_handler
+                .return_types
+                .iter()
+                .zip(&return_names)
+                .map(|(&ty, name)| from_script_value(ty, quote!(#name), fix_precision))
+                .collect::<Vec<_>>();
+
+            let (return_type, return_value) = if return_count == 1 {
+                (quote!(#(#return_types)*), quote!(#(#return_values)*))
+            } else {
+                (quote!((#(#return_types),*)), quote!((#(#return_values),*)))
+            };
+
+            let signature = quote! {
+                fn #event_name(&mut self, #(#arg_definitions,)*)
+                    -> Result<#return_type, ::tapir_script::CallError>
+            };
 
             (
-                quote!(fn #event_name(&mut self, #(#arg_definitions,)*)),
+                signature.clone(),
                 quote! {
-                    fn #event_name(&mut self, #(#arg_definitions,)*) {
-                        let mut initial_stack = ::tapir_script::Vec::with_capacity(#initial_stack_vector_len);
-                        #(#initial_stack_vector;)*
+                    #signature {
+                        let initial_stack = [#(#initial_stack),*];
 
-                        unsafe { self.__private_trigger_event(initial_stack, #initial_pc); }
+                        let [#(#return_names),*] = unsafe {
+                            self.__private_call_event::<#return_count>(#event_index, &initial_stack)
+                        }?;
+
+                        Ok(#return_value)
                     }
                 },
             )
@@ -209,7 +666,7 @@ fn generate_event_handlers(
         .unzip()
 }
 
-fn get_script_path(ast: &DeriveInput) -> (PathBuf, Option<syn::Path>) {
+fn get_script_path(ast: &DeriveInput) -> (PathBuf, TopLevelTapirArgs) {
     let Some(top_level_tapir_attribute) = ast
         .attrs
         .iter()
@@ -221,7 +678,7 @@ fn get_script_path(ast: &DeriveInput) -> (PathBuf, Option<syn::Path>) {
     };
 
     let top_level_args = top_level_tapir_attribute.parse_args::<TopLevelTapirArgs>()
-        .expect(r#"tapir must take exactly 1 argument which is a path to the script, so be of the format #[tapir("path/to/my/script.tapir")]"#);
+        .unwrap_or_else(|e| panic!(r#"Invalid tapir attribute, should be of the format #[tapir("path/to/my/script.tapir")]: {e}"#));
 
     let filename = top_level_args.script_name.value();
 
@@ -240,94 +697,556 @@ fn get_script_path(ast: &DeriveInput) -> (PathBuf, Option<syn::Path>) {
         filename
     };
 
-    (reduced_filename, top_level_args.trigger_type)
+    (reduced_filename, top_level_args)
+}
+
+/// Proc macros can't emit warnings on stable, so this uses a deprecated constant to get rustc to
+/// report them as a warning pointing at the script's path
+fn script_warnings(warnings: &mut compiler::Diagnostics, span: proc_macro2::Span) -> TokenStream {
+    if !warnings.has_any() {
+        return quote! {};
+    }
+
+    let note = format!("\n{}", warnings.pretty_string(false));
+
+    quote_spanned! {span=>
+        const _: () = {
+            #[deprecated(note = #note)]
+            const TAPIR_SCRIPT_WARNINGS: () = ();
+            TAPIR_SCRIPT_WARNINGS
+        };
+    }
 }
 
+/// The arguments to the `#[tapir(...)]` attribute on the struct, which look like
+/// `#[tapir("script.tapir", trigger_type = Event, allow(unused_variable), deny(warnings))]`.
+/// Adding `threads = 4, stack_size = 64` makes the script run without allocating, optionally with
+/// `groups = 8` to change how many groups it can pause or slow down at once, and
+/// `fix_precision = 12` makes `fix` values match `Num<i32, 12>` rather than `Num<i32, 8>`.
 struct TopLevelTapirArgs {
     script_name: LitStr,
     trigger_type: Option<syn::Path>,
+    fix_precision: u8,
+    threads: Option<LitInt>,
+    stack_size: Option<LitInt>,
+    groups: Option<LitInt>,
+    /// In the order they were written, since the last one which covers a warning wins
+    lints: Vec<(LintLevel, Ident)>,
 }
 
 impl syn::parse::Parse for TopLevelTapirArgs {
     fn parse(input: syn::parse::ParseStream) -> syn::Result<Self> {
         let script_name = input.parse()?;
         let mut trigger_type = None;
+        let mut fix_precision = CompileSettings::DEFAULT_FIX_PRECISION;
+        let mut threads = None;
+        let mut stack_size = None;
+        let mut groups = None;
+        let mut lints = vec![];
 
-        if input.peek(Token![,]) {
+        while !input.is_empty() {
             let _: Token![,] = input.parse()?;
+            if input.is_empty() {
+                break; // trailing comma
+            }
+
             let ident: Ident = input.parse()?;
-            let _: Token![=] = input.parse()?;
 
             match ident.to_string().as_str() {
-                "trigger_type" => trigger_type = Some(input.parse()?),
-                _ => return Err(input.error("Expected 'trigger_type'")),
+                "trigger_type" => {
+                    let _: Token![=] = input.parse()?;
+                    trigger_type = Some(input.parse()?);
+                }
+                "fix_precision" => {
+                    let _: Token![=] = input.parse()?;
+                    let precision: LitInt = input.parse()?;
+                    fix_precision = precision.base10_parse()?;
+
+                    if !(1..=CompileSettings::MAX_FIX_PRECISION).contains(&fix_precision) {
+                        return Err(syn::Error::new(
+                            precision.span(),
+                            format!(
+                                "fix_precision must be between 1 and {}",
+                                CompileSettings::MAX_FIX_PRECISION
+                            ),
+                        ));
+                    }
+                }
+                "threads" => {
+                    let _: Token![=] = input.parse()?;
+                    threads = Some(input.parse()?);
+                }
+                "stack_size" => {
+                    let _: Token![=] = input.parse()?;
+                    stack_size = Some(input.parse()?);
+                }
+                "groups" => {
+                    let _: Token![=] = input.parse()?;
+                    groups = Some(input.parse()?);
+                }
+                "allow" => lints.extend(
+                    parse_lints(input)?
+                        .into_iter()
+                        .map(|lint| (LintLevel::Allow, lint)),
+                ),
+                "deny" => lints.extend(
+                    parse_lints(input)?
+                        .into_iter()
+                        .map(|lint| (LintLevel::Deny, lint)),
+                ),
+                _ => {
+                    return Err(syn::Error::new(
+                        ident.span(),
+                        "Expected 'trigger_type', 'fix_precision', 'threads', 'stack_size', 'groups', 'allow' or 'deny'",
+                    ))
+                }
             }
-        } else if !input.is_empty() {
-            return Err(input.error("Expected 'trigger_type =' or nothing"));
         }
 
         Ok(Self {
             script_name,
             trigger_type,
+            fix_precision,
+            threads,
+            stack_size,
+            groups,
+            lints,
         })
     }
 }
 
+fn parse_lints(input: syn::parse::ParseStream) -> syn::Result<Vec<Ident>> {
+    let content;
+    syn::parenthesized!(content in input);
+
+    let lints = content.parse_terminated(Ident::parse, Token![,])?;
+
+    for lint in &lints {
+        if !CompilerWarningKind::LINTS.contains(&lint.to_string().as_str()) {
+            return Err(syn::Error::new(
+                lint.span(),
+                format!(
+                    "Unknown lint '{lint}', expected one of {}",
+                    CompilerWarningKind::LINTS.join(", ")
+                ),
+            ));
+        }
+    }
+
+    Ok(lints.into_iter().collect())
+}
+
 struct DeriveProperty {
     property: Property,
     getter: TokenStream,
     setter: TokenStream,
+    /// Checks at compile time that the type given matches the `TapirProperty::SCRIPT_TYPE` of
+    /// whatever the property is bound to, and that its `FIX_PRECISION` matches the script's
+    type_check: TokenStream,
 }
 
-fn extract_properties(named: &syn::FieldsNamed) -> Vec<DeriveProperty> {
-    named
-        .named
-        .iter()
-        .enumerate()
-        .filter_map(|(i, field)| {
-            let field_ident = field.ident.as_ref().unwrap();
-            let prop_name = field_ident.to_string();
+struct PropertyDeclaration {
+    index: usize,
+    name: String,
+    ty: Type,
+    access: PropertyAccess,
+    binding: PropertyBinding,
+    /// Where to report a type which doesn't match
+    span: proc_macro2::Span,
+}
+
+/// Where the value of a property lives on the struct
+enum PropertyBinding {
+    /// `self.a.b.c`, which can include tuple indices like `self.0.x`
+    Field(Vec<syn::Member>),
+    /// `self.get()` and `self.set(value)`. Read only properties don't need a setter.
+    Methods { get: Ident, set: Option<Ident> },
+}
+
+impl PropertyBinding {
+    fn getter(&self) -> TokenStream {
+        match self {
+            Self::Field(path) => quote! {
+                ::tapir_script::TapirProperty::to_i32(&self #(.#path)*)
+            },
+            Self::Methods { get, .. } => quote! {
+                ::tapir_script::TapirProperty::to_i32(&self.#get())
+            },
+        }
+    }
 
-            let ty = field
+    /// The field's type isn't known here, so it is inferred by coercing a closure which borrows
+    /// the field, or the getter, to a function pointer
+    fn type_check(&self, ty: Type, fix_precision: u8, span: proc_macro2::Span) -> TokenStream {
+        let property_type = script_property_type(ty);
+
+        match self {
+            Self::Field(path) => quote_spanned! {span=>
+                ::tapir_script::__private_check_field_property::<Self, _>(
+                    |properties: &Self| &properties #(.#path)*,
+                    #property_type,
+                    #fix_precision,
+                )
+            },
+            Self::Methods { get, .. } => quote_spanned! {span=>
+                ::tapir_script::__private_check_getter_property::<Self, _>(
+                    Self::#get,
+                    #property_type,
+                    #fix_precision,
+                )
+            },
+        }
+    }
+
+    fn setter(&self) -> TokenStream {
+        match self {
+            Self::Field(path) => quote! {
+                ::tapir_script::TapirProperty::set_from_i32(&mut self #(.#path)*, value);
+            },
+            Self::Methods {
+                get,
+                set: Some(set),
+            } => quote! {
+                let mut property = self.#get();
+                ::tapir_script::TapirProperty::set_from_i32(&mut property, value);
+                self.#set(property);
+            },
+            // the compiler won't let the script assign to it
+            Self::Methods { set: None, .. } => quote! {
+                unreachable!("Assigned to a read only property")
+            },
+        }
+    }
+}
+
+/// Every named field is a property unless marked `#[tapir(skip)]`. The script type is inferred for
+/// the primitive integers and `bool`, but other types implementing `TapirProperty`, including
+/// `Fix`, need it given with `#[tapir(int)]` and friends. Properties can be renamed with `#[tapir(name = health)]`, and
+/// tuple struct fields must be given a name. Adding `readonly` or `writeonly` stops the script
+/// assigning to or reading from the property.
+///
+/// Properties can also be declared on the struct itself, either bound to a nested field with
+/// `#[tapir_property(x: fix = transform.pos.x)]` or to a pair of methods with
+/// `#[tapir_property(health: int, get = health, set = set_health)]`. Read only properties bound
+/// to methods don't need a setter.
+fn extract_properties(ast: &DeriveInput, fix_precision: u8) -> Vec<DeriveProperty> {
+    let mut bindings = vec![];
+    // fields are indexed by their position, and properties on the struct come after them
+    let mut next_index = 0;
+
+    if let syn::Data::Struct(data) = &ast.data {
+        next_index = data.fields.len();
+
+        for (field_index, field) in data.fields.iter().enumerate() {
+            let field_name = field
+                .ident
+                .as_ref()
+                .map_or_else(|| field_index.to_string(), |ident| ident.to_string());
+
+            let attr = field
                 .attrs
                 .iter()
-                .find(|attr| attr.meta.path().is_ident("tapir"))
-                .map(|attr| {
-                    attr.parse_args::<syn::Ident>()
-                        .unwrap_or_else(|_| {
-                            panic!("tapir attribute on property {prop_name} is invalid",)
-                        })
-                        .to_string()
-                });
+                .find(|attr| attr.meta.path().is_ident("tapir"));
 
-            let ty = match ty.as_deref() {
-                None => {
-                    panic!("Must specify the type for every property, missing on {prop_name}")
-                }
-                Some("int") => Type::Int,
-                Some("bool") => Type::Bool,
-                Some("fix") => Type::Fix,
-                Some("skip") => {
-                    return None;
-                }
-                Some(unknown) => panic!("Unknown type {unknown} on property {prop_name}"),
+            // tuple struct fields are only properties if asked for, since they need a name
+            if attr.is_none() && field.ident.is_none() {
+                continue;
+            }
+
+            let args = attr.map_or_else(
+                || Ok(FieldPropertyArgs::default()),
+                |attr| attr.parse_args::<FieldPropertyArgs>(),
+            );
+            let args = args.unwrap_or_else(|e| {
+                panic!("tapir attribute on property {field_name} is invalid: {e}")
+            });
+
+            let ty = match &args.ty {
+                Some(ty) => match property_type(ty, &field_name) {
+                    Some(ty) => ty,
+                    None => continue, // skipped
+                },
+                None => infer_property_type(&field.ty).unwrap_or_else(|| {
+                    panic!(
+                        "Must specify the type for every property which isn't a primitive integer or bool, missing on {field_name}. \
+                        Use #[tapir(int)], #[tapir(fix)] or #[tapir(bool)] to match its TapirProperty implementation, or #[tapir(skip)]"
+                    )
+                }),
+            };
+
+            let name = match (args.name, &field.ident) {
+                (Some(name), _) => name.to_string(),
+                (None, Some(ident)) => ident.to_string(),
+                (None, None) => panic!(
+                    "Tuple struct field {field_name} needs a name, like #[tapir(name = my_property)]"
+                ),
+            };
+
+            let member = match &field.ident {
+                Some(ident) => syn::Member::Named(ident.clone()),
+                None => syn::Member::Unnamed(syn::Index::from(field_index)),
             };
 
-            let i_u8 = i as u8;
+            bindings.push(PropertyDeclaration {
+                index: field_index,
+                name,
+                ty,
+                access: args.access,
+                binding: PropertyBinding::Field(vec![member]),
+                span: field.ty.span(),
+            });
+        }
+    }
+
+    for attr in ast
+        .attrs
+        .iter()
+        .filter(|attr| attr.meta.path().is_ident("tapir_property"))
+    {
+        let args = attr
+            .parse_args::<StructPropertyArgs>()
+            .unwrap_or_else(|e| panic!("tapir_property attribute is invalid: {e}"));
+
+        let name = args.name.to_string();
+        let ty = property_type(&args.ty, &name).unwrap_or_else(|| {
+            panic!("Property {name} declared with tapir_property can't be skipped")
+        });
+
+        bindings.push(PropertyDeclaration {
+            index: next_index,
+            name,
+            ty,
+            access: args.access,
+            binding: args.binding,
+            span: args.ty.span(),
+        });
+        next_index += 1;
+    }
+
+    bindings
+        .into_iter()
+        .map(|declaration| {
+            let PropertyDeclaration {
+                index,
+                name,
+                ty,
+                access,
+                binding,
+                span,
+            } = declaration;
+
+            let index_u8 = u8::try_from(index).expect("Too many properties");
+            let getter = binding.getter();
+            let setter = binding.setter();
+            let type_check = binding.type_check(ty, fix_precision, span);
 
-            Some(DeriveProperty {
+            DeriveProperty {
                 property: Property {
                     ty,
-                    index: i,
-                    name: prop_name,
+                    index,
+                    name,
+                    access,
                 },
                 setter: quote! {
-                    #i_u8 => { ::tapir_script::TapirProperty::set_from_i32(&mut self.#field_ident, value); }
+                    #index_u8 => { #setter }
                 },
                 getter: quote! {
-                    #i_u8 => ::tapir_script::TapirProperty::to_i32(&self.#field_ident)
+                    #index_u8 => #getter
                 },
-            })
+                type_check,
+            }
         })
         .collect()
 }
+
+/// Returns `None` for properties marked as `skip`
+fn property_type(ty: &Ident, property_name: &str) -> Option<Type> {
+    match ty.to_string().as_str() {
+        "int" => Some(Type::Int),
+        "bool" => Some(Type::Bool),
+        "fix" => Some(Type::Fix),
+        "skip" => None,
+        unknown => panic!("Unknown type {unknown} on property {property_name}"),
+    }
+}
+
+/// The script type of a field whose type is written as one of the primitives with a built in
+/// `TapirProperty` implementation. Anything else has to say what type it is, since a path or alias
+/// could name any type and the trait can't be looked at from here.
+fn infer_property_type(ty: &syn::Type) -> Option<Type> {
+    let syn::Type::Path(syn::TypePath { qself: None, path }) = ty else {
+        return None;
+    };
+
+    match path.get_ident()?.to_string().as_str() {
+        "i32" | "i16" | "i8" | "u16" | "u8" => Some(Type::Int),
+        "bool" => Some(Type::Bool),
+        _ => None,
+    }
+}
+
+fn script_property_type(ty: Type) -> TokenStream {
+    match ty {
+        Type::Int => quote!(::tapir_script::PropertyType::Int),
+        Type::Fix => quote!(::tapir_script::PropertyType::Fix),
+        Type::Bool => quote!(::tapir_script::PropertyType::Bool),
+        Type::Error => panic!("Should not have errors here"),
+    }
+}
+
+/// The arguments to `#[tapir(...)]` on a field, like `#[tapir(int)]`, `#[tapir(name = speed)]` or
+/// `#[tapir(fix, name = speed)]`
+#[derive(Default)]
+struct FieldPropertyArgs {
+    /// `None` if the type should be inferred from the field's Rust type
+    ty: Option<Ident>,
+    name: Option<Ident>,
+    access: PropertyAccess,
+}
+
+impl syn::parse::Parse for FieldPropertyArgs {
+    fn parse(input: syn::parse::ParseStream) -> syn::Result<Self> {
+        let mut args = Self::default();
+
+        while !input.is_empty() {
+            let ident: Ident = input.parse()?;
+
+            if ident == "name" && input.peek(Token![=]) {
+                let _: Token![=] = input.parse()?;
+                args.name = Some(input.parse()?);
+            } else if let Some(access) = parse_property_access(&ident) {
+                args.access = access;
+            } else if args.ty.is_none() {
+                args.ty = Some(ident);
+            } else {
+                return Err(syn::Error::new(
+                    ident.span(),
+                    "Expected 'name', 'readonly' or 'writeonly'",
+                ));
+            }
+
+            if input.is_empty() {
+                break;
+            }
+
+            let _: Token![,] = input.parse()?;
+        }
+
+        Ok(args)
+    }
+}
+
+/// The arguments to `#[tapir_property(...)]` on the struct, which look like
+/// `#[tapir_property(x: fix = transform.pos.x)]` or
+/// `#[tapir_property(health: int, get = health, set = set_health)]`, optionally followed by
+/// `readonly` or `writeonly`
+struct StructPropertyArgs {
+    name: Ident,
+    ty: Ident,
+    access: PropertyAccess,
+    binding: PropertyBinding,
+}
+
+impl syn::parse::Parse for StructPropertyArgs {
+    fn parse(input: syn::parse::ParseStream) -> syn::Result<Self> {
+        let name: Ident = input.parse()?;
+        let _: Token![:] = input.parse()?;
+        let ty = input.parse()?;
+
+        let mut path = None;
+        if input.peek(Token![=]) {
+            let _: Token![=] = input.parse()?;
+            path = Some(parse_field_path(input)?);
+        }
+
+        let mut get = None;
+        let mut set = None;
+        let mut access = PropertyAccess::ReadWrite;
+
+        while !input.is_empty() {
+            let _: Token![,] = input.parse()?;
+            if input.is_empty() {
+                break; // trailing comma
+            }
+
+            let ident: Ident = input.parse()?;
+            if let Some(property_access) = parse_property_access(&ident) {
+                access = property_access;
+                continue;
+            }
+
+            match ident.to_string().as_str() {
+                "get" if path.is_none() => {
+                    let _: Token![=] = input.parse()?;
+                    get = Some(input.parse()?);
+                }
+                "set" if path.is_none() => {
+                    let _: Token![=] = input.parse()?;
+                    set = Some(input.parse()?);
+                }
+                _ => {
+                    return Err(syn::Error::new(
+                        ident.span(),
+                        "Expected 'get', 'set', 'readonly' or 'writeonly'",
+                    ))
+                }
+            }
+        }
+
+        let binding = match (path, get, set) {
+            (Some(path), ..) => PropertyBinding::Field(path),
+            (None, Some(get), set) if set.is_some() || access == PropertyAccess::ReadOnly => {
+                PropertyBinding::Methods { get, set }
+            }
+            _ => {
+                return Err(syn::Error::new(
+                    name.span(),
+                    "Expected either '= path.to.field' or both 'get = ...' and 'set = ...'",
+                ))
+            }
+        };
+
+        Ok(Self {
+            name,
+            ty,
+            access,
+            binding,
+        })
+    }
+}
+
+fn parse_property_access(ident: &Ident) -> Option<PropertyAccess> {
+    match ident.to_string().as_str() {
+        "readonly" => Some(PropertyAccess::ReadOnly),
+        "writeonly" => Some(PropertyAccess::WriteOnly),
+        _ => None,
+    }
+}
+
+/// Parses `a.b.c`, where any part can be a tuple index like `a.0.b`
+fn parse_field_path(input: syn::parse::ParseStream) -> syn::Result<Vec<syn::Member>> {
+    let mut path = vec![];
+
+    loop {
+        // `0.1` is lexed as a single float literal rather than two indices
+        if input.peek(syn::LitFloat) {
+            let float: syn::LitFloat = input.parse()?;
+            for index in float.base10_digits().split('.') {
+                path.push(syn::Member::Unnamed(syn::Index {
+                    index: index
+                        .parse()
+                        .map_err(|_| syn::Error::new(float.span(), "Invalid tuple index"))?,
+                    span: float.span(),
+                }));
+            }
+        } else {
+            path.push(input.parse()?);
+        }
+
+        if !input.peek(Token![.]) {
+            return Ok(path);
+        }
+
+        let _: Token![.] = input.parse()?;
+    }
+}
@@ -0,0 +1,23 @@
+use quote::quote;
+use tapir_script_macros_core::tapir_script_derive;
+
+#[test]
+#[should_panic = "the script has recursive functions which could need any amount of stack"]
+fn fixed_storage_rejects_recursive_scripts() {
+    tapir_script_derive(quote! {
+        #[tapir("tests/recursive.tapir", trigger_type = Event, threads = 1, stack_size = 64)]
+        struct Recursive;
+    });
+}
+
+#[test]
+#[should_panic = "Must specify the type for every property which isn't a primitive integer or bool, missing on health"]
+fn paths_to_other_types_are_not_inferred() {
+    // this could be an alias for anything, even though it ends in i32
+    tapir_script_derive(quote! {
+        #[tapir("tests/empty.tapir")]
+        struct Enemy {
+            health: units::i32,
+        }
+    });
+}
@@ -1,7 +1,12 @@
 #![deny(clippy::all)]
 use proc_macro::TokenStream;
 
-#[proc_macro_derive(TapirScript, attributes(tapir))]
+#[proc_macro_derive(TapirScript, attributes(tapir, tapir_property))]
 pub fn tapir_script(struct_def: TokenStream) -> TokenStream {
     tapir_script_macros_core::tapir_script_derive(struct_def.into()).into()
 }
+
+#[proc_macro_derive(TapirTrigger, attributes(tapir))]
+pub fn tapir_trigger(enum_def: TokenStream) -> TokenStream {
+    tapir_script_macros_core::tapir_trigger_derive(enum_def.into()).into()
+}
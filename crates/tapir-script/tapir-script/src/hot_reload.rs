@@ -0,0 +1,140 @@
+//! Reloading scripts while the game is running whenever their source changes, so they can be
+//! tweaked without rebuilding. This is intended for development on desktop builds.
+//!
+//! Reloading restarts the script from the top, so the top level of the script runs again, but
+//! the properties keep the values they had before the reload rather than being reset by
+//! assignments like `health = 10;`. See [`Script::reload`] for exactly what happens.
+//!
+//! ```no_run
+//! # use tapir_script::{hot_reload::ScriptWatcher, TapirScript};
+//! # #[derive(TapirScript)]
+//! # #[tapir("tests/empty.tapir")]
+//! # struct Player;
+//! let mut player = Player.script();
+//! let mut watcher = ScriptWatcher::<Player>::new();
+//!
+//! loop {
+//!     if let Err(e) = watcher.reload_if_changed([&mut player]) {
+//!         eprintln!("{e}");
+//!     }
+//!
+//!     player.run();
+//! }
+//! ```
+
+extern crate std;
+
+use core::{fmt, marker::PhantomData};
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+use crate::{
+    runtime::{self, CompiledScript, LoadError},
+    Script, TapirSchema,
+};
+
+/// Watches a script's source for changes. This polls the file's modification time rather than
+/// using any operating system notifications, so call it once a frame or so.
+pub struct ScriptWatcher<T> {
+    path: PathBuf,
+    modified: Option<SystemTime>,
+    _schema: PhantomData<fn() -> T>,
+}
+
+impl<T: TapirSchema> ScriptWatcher<T> {
+    /// Watches the script that `T` was built with
+    pub fn new() -> Self {
+        Self::with_path(T::SCHEMA.script_path)
+    }
+
+    /// Watches a different script, which must still match `T`'s [`Schema`](crate::Schema)
+    pub fn with_path(path: impl Into<PathBuf>) -> Self {
+        let path = path.into();
+        let modified = modified_time(&path).ok();
+
+        Self {
+            path,
+            modified,
+            _schema: PhantomData,
+        }
+    }
+
+    /// Recompiles the script if it has changed since it was last checked. Broken scripts are only
+    /// reported once, and then not again until they change.
+    ///
+    /// Many editors save by writing a new file and renaming it over the old one, so a missing file
+    /// counts as no change until it comes back.
+    pub fn poll(&mut self) -> Option<Result<CompiledScript<T>, ReloadError>> {
+        let modified = match modified_time(&self.path) {
+            Ok(modified) => modified,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return None,
+            Err(e) => return Some(Err(ReloadError::Io(e))),
+        };
+
+        if self.modified == Some(modified) {
+            return None;
+        }
+
+        let source = match fs::read_to_string(&self.path) {
+            Ok(source) => source,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return None,
+            Err(e) => return Some(Err(ReloadError::Io(e))),
+        };
+
+        self.modified = Some(modified);
+
+        Some(runtime::compile(&self.path, &source).map_err(ReloadError::Load))
+    }
+
+    /// Reloads every script in `scripts` if the source has changed. Returns whether anything was
+    /// reloaded. Each script starts again from the top, as described in [`Script::reload`].
+    pub fn reload_if_changed<'a>(
+        &mut self,
+        scripts: impl IntoIterator<Item = &'a mut Script<T>>,
+    ) -> Result<bool, ReloadError>
+    where
+        T: 'a,
+    {
+        let Some(compiled) = self.poll() else {
+            return Ok(false);
+        };
+
+        let compiled = compiled?;
+        for script in scripts {
+            compiled.reload(script);
+        }
+
+        Ok(true)
+    }
+}
+
+impl<T: TapirSchema> Default for ScriptWatcher<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn modified_time(path: &Path) -> io::Result<SystemTime> {
+    fs::metadata(path)?.modified()
+}
+
+#[derive(Debug)]
+pub enum ReloadError {
+    /// The script couldn't be read
+    Io(io::Error),
+    Load(LoadError),
+}
+
+impl fmt::Display for ReloadError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ReloadError::Io(e) => write!(f, "Failed to read script: {e}"),
+            ReloadError::Load(e) => write!(f, "{e}"),
+        }
+    }
+}
+
+impl std::error::Error for ReloadError {}
@@ -3,19 +3,236 @@
 
 extern crate alloc;
 
-pub use tapir_script_macros::TapirScript;
-pub use vm::{Script, TapirScript};
+mod link;
+mod schema;
+mod trigger;
 
-pub type Fix = agb_fixnum::Num<i32, 8>;
+#[cfg(feature = "runtime")]
+pub mod hot_reload;
+pub mod precompiled;
+#[cfg(feature = "runtime")]
+pub mod runtime;
+
+pub use link::LoadError;
+
+pub use schema::{PropertySchema, Schema, Signature, TapirSchema};
+pub use tapir_script_macros::{TapirScript, TapirTrigger};
+#[doc(hidden)]
+pub use trigger::{__private_check_trigger, TriggerMismatch};
+pub use trigger::{TapirTrigger, TriggerFields, TriggerVariant};
+pub use vm::{
+    CallError, Fixed, Generator, GroupError, Growable, Program, Script, ScriptWorld, StackDepth,
+    Storage, TapirScript, VerifiedProgram, VmStorage,
+};
+
+pub use agb_fixnum::Num;
+
+/// The Rust type for a script's `fix` values, unless it sets a different `fix_precision`
+pub type Fix = Num<i32, 8>;
 
 pub use alloc::vec::Vec;
 
+/// The type a property or argument has in the script
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PropertyType {
+    Int,
+    Fix,
+    Bool,
+}
+
+/// Whether the script can read from or assign to a property, set with `#[tapir(readonly)]` or
+/// `#[tapir(writeonly)]`
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum PropertyAccess {
+    #[default]
+    ReadWrite,
+    ReadOnly,
+    WriteOnly,
+}
+
+/// A value which can be used as a property. Implement this for your own types to use them
+/// directly as fields, or from a getter and setter bound with `#[tapir_property(...)]`, for
+/// example a `Health(i32)` newtype which acts as an `int` in the script.
+///
+/// Fields whose type is written as `i32`, `bool` or one of the smaller integer types have their
+/// script type inferred, but fields of any other type, including `Fix`, must say which type they
+/// are with `#[tapir(int)]`, `#[tapir(fix)]` or `#[tapir(bool)]`. This must match `SCRIPT_TYPE`.
+///
+/// `fix` fields must also have the same precision as the script, so this doesn't compile:
+///
+/// ```compile_fail
+/// # use tapir_script::{Num, TapirScript, TapirTrigger};
+/// #[derive(TapirScript)]
+/// #[tapir("tests/fix_precision.tapir", trigger_type = Event, fix_precision = 12)]
+/// struct Physics {
+///     #[tapir(fix)]
+///     position: Num<i32, 8>,
+/// }
+/// # #[derive(TapirTrigger)]
+/// # enum Event {
+/// #     Moved(Num<i32, 12>),
+/// # }
+/// # Physics { position: Num::new(1) }.script();
+/// ```
+///
+/// Properties declared with `#[tapir_property(...)]` are checked in the same way, whether they
+/// are bound to a nested field or to a getter and setter. Neither of these compile, since `pos.x`
+/// is a `fix` and `level` returns an `int`:
+///
+/// ```compile_fail
+/// # use tapir_script::{Fix, TapirScript};
+/// # struct Position {
+/// #     x: Fix,
+/// # }
+/// #[derive(TapirScript)]
+/// #[tapir("tests/empty.tapir")]
+/// #[tapir_property(x: int = pos.x)]
+/// struct Player {
+///     #[tapir(skip)]
+///     pos: Position,
+/// }
+/// # Player { pos: Position { x: Fix::new(1) } }.script();
+/// ```
+///
+/// ```compile_fail
+/// # use tapir_script::TapirScript;
+/// #[derive(TapirScript)]
+/// #[tapir("tests/empty.tapir")]
+/// #[tapir_property(level: bool, get = level, readonly)]
+/// struct Sprite;
+///
+/// impl Sprite {
+///     fn level(&self) -> i32 {
+///         3
+///     }
+/// }
+/// # Sprite.script();
+/// ```
+///
+/// Your own types get both checks too, whichever way they're bound. This doesn't compile because
+/// `Speed` has 4 fractional bits but the script uses the default of 8:
+///
+/// ```compile_fail
+/// # use tapir_script::{Num, PropertyType, TapirProperty, TapirScript};
+/// struct Speed(Num<i32, 4>);
+///
+/// impl TapirProperty for Speed {
+///     const SCRIPT_TYPE: PropertyType = PropertyType::Fix;
+///     const FIX_PRECISION: Option<u8> = Some(4);
+///
+///     fn to_i32(&self) -> i32 {
+///         self.0.to_raw()
+///     }
+///
+///     fn set_from_i32(&mut self, value: i32) {
+///         self.0 = Num::from_raw(value);
+///     }
+/// }
+///
+/// #[derive(TapirScript)]
+/// #[tapir("tests/empty.tapir")]
+/// #[tapir_property(speed: fix, get = speed, readonly)]
+/// struct Car;
+///
+/// impl Car {
+///     fn speed(&self) -> Speed {
+///         Speed(Num::new(1))
+///     }
+/// }
+/// # Car.script();
+/// ```
+///
+/// and neither does declaring a `Health(i32)` newtype as anything other than an `int`:
+///
+/// ```compile_fail
+/// # use tapir_script::{PropertyType, TapirProperty, TapirScript};
+/// # struct Health(i32);
+/// #
+/// # impl TapirProperty for Health {
+/// #     const SCRIPT_TYPE: PropertyType = PropertyType::Int;
+/// #
+/// #     fn to_i32(&self) -> i32 {
+/// #         self.0
+/// #     }
+/// #
+/// #     fn set_from_i32(&mut self, value: i32) {
+/// #         self.0 = value;
+/// #     }
+/// # }
+/// #[derive(TapirScript)]
+/// #[tapir("tests/empty.tapir")]
+/// #[tapir_property(health: fix, get = health, set = set_health)]
+/// struct Boss {
+///     #[tapir(skip)]
+///     health: i32,
+/// }
+///
+/// impl Boss {
+///     fn health(&self) -> Health {
+///         Health(self.health)
+///     }
+///
+///     fn set_health(&mut self, health: Health) {
+///         self.health = health.0;
+///     }
+/// }
+/// # Boss { health: 10 }.script();
+/// ```
 pub trait TapirProperty {
+    const SCRIPT_TYPE: PropertyType;
+    /// The number of fractional bits a `fix` property's raw value has, which must match the
+    /// script's `fix_precision`. Leave this as `None` to skip the check.
+    const FIX_PRECISION: Option<u8> = None;
+
     fn to_i32(&self) -> i32;
     fn set_from_i32(&mut self, value: i32);
 }
 
+/// Used by the derive macro to check that the type given in `#[tapir(...)]` matches the field's
+/// implementation of [`TapirProperty`], and that `fix` fields use the script's `fix_precision`
+#[doc(hidden)]
+pub const fn __private_check_property_type<T: TapirProperty>(
+    expected: PropertyType,
+    fix_precision: u8,
+) {
+    assert!(
+        T::SCRIPT_TYPE as u8 == expected as u8,
+        "Property type doesn't match the SCRIPT_TYPE of its TapirProperty implementation"
+    );
+
+    if let Some(precision) = T::FIX_PRECISION {
+        assert!(
+            precision == fix_precision,
+            "Property has a different FIX_PRECISION to the script's fix_precision"
+        );
+    }
+}
+
+/// The same check for a property bound to a field, possibly nested, where the field's type is
+/// inferred from a closure returning a reference to it
+#[doc(hidden)]
+pub const fn __private_check_field_property<S, T: TapirProperty>(
+    _field: fn(&S) -> &T,
+    expected: PropertyType,
+    fix_precision: u8,
+) {
+    __private_check_property_type::<T>(expected, fix_precision);
+}
+
+/// The same check for a property bound to a getter and setter, where the type is inferred from
+/// what the getter returns
+#[doc(hidden)]
+pub const fn __private_check_getter_property<S, T: TapirProperty>(
+    _get: fn(&S) -> T,
+    expected: PropertyType,
+    fix_precision: u8,
+) {
+    __private_check_property_type::<T>(expected, fix_precision);
+}
+
 impl TapirProperty for i32 {
+    const SCRIPT_TYPE: PropertyType = PropertyType::Int;
+
     fn to_i32(&self) -> i32 {
         *self
     }
@@ -25,7 +242,30 @@ impl TapirProperty for i32 {
     }
 }
 
+macro_rules! impl_small_integer_property {
+    ($($ty:ty),*) => {
+        $(
+            impl TapirProperty for $ty {
+                const SCRIPT_TYPE: PropertyType = PropertyType::Int;
+
+                fn to_i32(&self) -> i32 {
+                    (*self).into()
+                }
+
+                /// Values which don't fit are truncated
+                fn set_from_i32(&mut self, value: i32) {
+                    *self = value as $ty;
+                }
+            }
+        )*
+    };
+}
+
+impl_small_integer_property!(i8, i16, u8, u16);
+
 impl TapirProperty for bool {
+    const SCRIPT_TYPE: PropertyType = PropertyType::Bool;
+
     fn to_i32(&self) -> i32 {
         (*self).into()
     }
@@ -35,13 +275,16 @@ impl TapirProperty for bool {
     }
 }
 
-impl TapirProperty for Fix {
+impl<const N: usize> TapirProperty for Num<i32, N> {
+    const SCRIPT_TYPE: PropertyType = PropertyType::Fix;
+    const FIX_PRECISION: Option<u8> = Some(N as u8);
+
     fn to_i32(&self) -> i32 {
         self.to_raw()
     }
 
     fn set_from_i32(&mut self, value: i32) {
-        *self = Fix::from_raw(value);
+        *self = Num::from_raw(value);
     }
 }
 
@@ -65,4 +308,14 @@ mod test {
         assert!(!test);
         assert_eq!(TapirProperty::to_i32(&test), 0);
     }
+
+    #[test]
+    fn tapir_property_for_u8_truncates() {
+        let mut test = 0u8;
+
+        TapirProperty::set_from_i32(&mut test, 300);
+
+        assert_eq!(test, 44);
+        assert_eq!(TapirProperty::to_i32(&test), 44);
+    }
 }
@@ -0,0 +1,447 @@
+//! Matching up scripts which weren't built by the derive macro with the struct they'll run with,
+//! whether they were compiled at runtime or loaded from a container.
+
+use alloc::{borrow::Cow, string::String, vec, vec::Vec};
+use core::fmt;
+
+#[cfg(feature = "runtime")]
+use compiler::Diagnostics;
+use vm::{
+    container::ContainerError,
+    verify::{Interface, VerifyError},
+};
+
+use crate::{
+    Program, PropertyAccess, PropertyType, Signature, StackDepth, TapirSchema, VerifiedProgram,
+    VmStorage,
+};
+
+#[derive(Clone, Debug)]
+pub enum LoadError {
+    /// The script didn't compile. The diagnostics contain the reasons why.
+    #[cfg(feature = "runtime")]
+    Compile(Diagnostics),
+    /// The precompiled script was invalid, or was built by a different version of tapir-script
+    Container(ContainerError),
+    /// The script's bytecode isn't safe to run
+    Verify(VerifyError),
+    /// The precompiled script uses a property which the struct doesn't have
+    UnknownProperty { name: String },
+    /// The precompiled script was compiled with a property which has a different type or index to
+    /// the struct's
+    PropertyMismatch { name: String },
+    /// The precompiled script was compiled to read from or assign to a property which the struct
+    /// doesn't allow it to
+    PropertyAccessMismatch {
+        name: String,
+        expected: PropertyAccess,
+        actual: PropertyAccess,
+    },
+    /// The precompiled script was compiled with a different `fix_precision` to the struct's
+    FixPrecisionMismatch { expected: u8, actual: u8 },
+    /// The script has an event handler which the rust side doesn't know about, so could never be
+    /// called
+    UnknownEvent { name: String },
+    EventArgumentsMismatch {
+        name: String,
+        expected: Vec<PropertyType>,
+        actual: Vec<PropertyType>,
+    },
+    EventReturnsMismatch {
+        name: String,
+        expected: Vec<PropertyType>,
+        actual: Vec<PropertyType>,
+    },
+    /// Either only one of the struct and the script has this as a generator, or they yield
+    /// different types
+    EventYieldsMismatch {
+        name: String,
+        expected: Option<PropertyType>,
+        actual: Option<PropertyType>,
+    },
+    /// The script calls a trigger which isn't a variant of the trigger type
+    UnknownTrigger { name: String },
+    TriggerArgumentsMismatch {
+        name: String,
+        expected: Vec<PropertyType>,
+        actual: Vec<PropertyType>,
+    },
+    /// The script names the trigger's arguments differently, so they would end up in the wrong
+    /// fields of the variant
+    TriggerFieldsMismatch {
+        name: String,
+        expected: Option<Vec<String>>,
+        actual: Option<Vec<String>>,
+    },
+    /// The script sends a message which isn't an event of this script, so could never be
+    /// delivered
+    UnknownMessage { name: String },
+    MessageArgumentsMismatch {
+        name: String,
+        expected: Vec<PropertyType>,
+        actual: Vec<PropertyType>,
+    },
+    /// The script sends a message to a generator, which can only be run from rust
+    MessageToGenerator { name: String },
+    /// The struct uses fixed storage, and the script could need more stack than it has
+    StackTooSmall { stack_size: usize, needed: usize },
+    /// The struct uses fixed storage, but the script has recursive functions which could need any
+    /// amount of stack
+    UnboundedStack { stack_size: usize },
+}
+
+impl fmt::Display for LoadError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            #[cfg(feature = "runtime")]
+            LoadError::Compile(diagnostics) => {
+                write!(f, "{}", diagnostics.clone().pretty_string(false))
+            }
+            LoadError::Container(e) => write!(f, "{e}"),
+            LoadError::Verify(e) => write!(f, "{e}"),
+            LoadError::UnknownProperty { name } => {
+                write!(f, "Property '{name}' is not a property of this script")
+            }
+            LoadError::PropertyMismatch { name } => write!(
+                f,
+                "Property '{name}' was compiled with a different type or index to this script's"
+            ),
+            LoadError::PropertyAccessMismatch {
+                name,
+                expected,
+                actual,
+            } => write!(
+                f,
+                "Property '{name}' was compiled as {actual:?}, but is {expected:?} in this script"
+            ),
+            LoadError::FixPrecisionMismatch { expected, actual } => write!(
+                f,
+                "Script was compiled with a fix_precision of {actual}, but this script uses {expected}"
+            ),
+            LoadError::UnknownEvent { name } => {
+                write!(f, "Event handler '{name}' is not an event of this script")
+            }
+            LoadError::EventArgumentsMismatch {
+                name,
+                expected,
+                actual,
+            } => write!(
+                f,
+                "Event handler '{name}' should take arguments {expected:?} but takes {actual:?}"
+            ),
+            LoadError::EventReturnsMismatch {
+                name,
+                expected,
+                actual,
+            } => write!(
+                f,
+                "Event handler '{name}' should return {expected:?} but returns {actual:?}"
+            ),
+            LoadError::EventYieldsMismatch {
+                name,
+                expected,
+                actual,
+            } => write!(
+                f,
+                "Event handler '{name}' should yield {expected:?} but yields {actual:?}"
+            ),
+            LoadError::UnknownTrigger { name } => {
+                write!(f, "Trigger '{name}' is not a trigger of this script")
+            }
+            LoadError::TriggerArgumentsMismatch {
+                name,
+                expected,
+                actual,
+            } => write!(
+                f,
+                "Trigger '{name}' should be called with arguments {expected:?} but is called with {actual:?}"
+            ),
+            LoadError::TriggerFieldsMismatch {
+                name,
+                expected,
+                actual,
+            } => write!(
+                f,
+                "Trigger '{name}' should be called with fields {expected:?} but is called with {actual:?}"
+            ),
+            LoadError::UnknownMessage { name } => {
+                write!(f, "Message '{name}' is not an event of this script")
+            }
+            LoadError::MessageArgumentsMismatch {
+                name,
+                expected,
+                actual,
+            } => write!(
+                f,
+                "Message '{name}' should be sent with arguments {expected:?} but is sent with {actual:?}"
+            ),
+            LoadError::MessageToGenerator { name } => {
+                write!(f, "Message '{name}' is sent to a generator")
+            }
+            LoadError::StackTooSmall { stack_size, needed } => write!(
+                f,
+                "stack_size is {stack_size}, but the script could need a stack of {needed}"
+            ),
+            LoadError::UnboundedStack { stack_size } => write!(
+                f,
+                "stack_size is {stack_size}, but the script has recursive functions which could need any amount of stack"
+            ),
+        }
+    }
+}
+
+impl core::error::Error for LoadError {}
+
+impl From<ContainerError> for LoadError {
+    fn from(value: ContainerError) -> Self {
+        LoadError::Container(value)
+    }
+}
+
+impl From<VerifyError> for LoadError {
+    fn from(value: VerifyError) -> Self {
+        LoadError::Verify(value)
+    }
+}
+
+/// How much stack a function from the script could need, which needs checking against the storage
+pub(crate) struct ScriptStackDepth {
+    pub bytecode_offset: usize,
+    /// `None` for recursive functions, which have no limit
+    pub max_depth: Option<usize>,
+}
+
+/// An event handler from the script, which needs finding in the schema
+pub(crate) struct ScriptEventHandler<'a> {
+    pub name: &'a str,
+    pub bytecode_offset: usize,
+    pub arguments: Vec<PropertyType>,
+    pub returns: Vec<PropertyType>,
+    /// `Some` for generators
+    pub yields: Option<PropertyType>,
+}
+
+/// A trigger from the script, which needs finding in the schema
+pub(crate) struct ScriptTrigger<'a> {
+    pub name: &'a str,
+    pub arguments: Vec<PropertyType>,
+    pub field_names: Option<Vec<&'a str>>,
+}
+
+/// A message the script sends, which needs finding in the schema's events
+pub(crate) struct ScriptMessage<'a> {
+    pub name: &'a str,
+    pub arguments: Vec<PropertyType>,
+}
+
+/// Builds a program for `T` out of a script's bytecode, checking that everything the script uses
+/// is something `T` has and that the bytecode is safe to run
+pub(crate) fn link<'a, T: TapirSchema>(
+    bytecode: Vec<u16>,
+    script_stack_depths: impl IntoIterator<Item = ScriptStackDepth>,
+    script_event_handlers: impl IntoIterator<Item = ScriptEventHandler<'a>>,
+    script_triggers: impl IntoIterator<Item = ScriptTrigger<'a>>,
+    script_messages: impl IntoIterator<Item = ScriptMessage<'a>>,
+) -> Result<VerifiedProgram<'static>, LoadError> {
+    let schema = T::SCHEMA;
+
+    let mut event_handlers = vec![None; schema.events.len()];
+    for event_handler in script_event_handlers {
+        let index =
+            match find_signature(schema.events, event_handler.name, &event_handler.arguments) {
+                Ok(index) => index,
+                Err(SignatureError::Unknown) => {
+                    return Err(LoadError::UnknownEvent {
+                        name: event_handler.name.into(),
+                    })
+                }
+                Err(SignatureError::Mismatch(expected)) => {
+                    return Err(LoadError::EventArgumentsMismatch {
+                        name: event_handler.name.into(),
+                        expected,
+                        actual: event_handler.arguments,
+                    })
+                }
+            };
+
+        let expected_returns = schema.events[index].returns;
+        if expected_returns != event_handler.returns {
+            return Err(LoadError::EventReturnsMismatch {
+                name: event_handler.name.into(),
+                expected: expected_returns.to_vec(),
+                actual: event_handler.returns,
+            });
+        }
+
+        let expected_yields = schema.events[index].yields;
+        if expected_yields != event_handler.yields {
+            return Err(LoadError::EventYieldsMismatch {
+                name: event_handler.name.into(),
+                expected: expected_yields,
+                actual: event_handler.yields,
+            });
+        }
+
+        event_handlers[index] = Some(event_handler.bytecode_offset);
+    }
+
+    let triggers = script_triggers
+        .into_iter()
+        .map(
+            |trigger| match find_signature(schema.triggers, trigger.name, &trigger.arguments) {
+                Ok(index) => {
+                    let expected = schema.triggers[index].field_names;
+                    if expected != trigger.field_names.as_deref() {
+                        return Err(LoadError::TriggerFieldsMismatch {
+                            name: trigger.name.into(),
+                            expected: expected.map(owned_field_names),
+                            actual: trigger.field_names.as_deref().map(owned_field_names),
+                        });
+                    }
+
+                    Ok(index as u8)
+                }
+                Err(SignatureError::Unknown) => Err(LoadError::UnknownTrigger {
+                    name: trigger.name.into(),
+                }),
+                Err(SignatureError::Mismatch(expected)) => {
+                    Err(LoadError::TriggerArgumentsMismatch {
+                        name: trigger.name.into(),
+                        expected,
+                        actual: trigger.arguments,
+                    })
+                }
+            },
+        )
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let messages = script_messages
+        .into_iter()
+        .map(
+            |message| match find_signature(schema.events, message.name, &message.arguments) {
+                Ok(index) if schema.events[index].yields.is_some() => {
+                    Err(LoadError::MessageToGenerator {
+                        name: message.name.into(),
+                    })
+                }
+                Ok(index) => Ok(index),
+                Err(SignatureError::Unknown) => Err(LoadError::UnknownMessage {
+                    name: message.name.into(),
+                }),
+                Err(SignatureError::Mismatch(expected)) => {
+                    Err(LoadError::MessageArgumentsMismatch {
+                        name: message.name.into(),
+                        expected,
+                        actual: message.arguments,
+                    })
+                }
+            },
+        )
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let script_stack_depths = script_stack_depths.into_iter().collect::<Vec<_>>();
+
+    if let Some(stack_size) = <T::Storage as VmStorage>::STACK_SIZE {
+        let needed = script_stack_depths
+            .iter()
+            .map(|stack_depth| stack_depth.max_depth)
+            .collect::<Option<Vec<_>>>()
+            .ok_or(LoadError::UnboundedStack { stack_size })?;
+
+        if let Some(needed) = needed
+            .into_iter()
+            .max()
+            .filter(|&needed| needed > stack_size)
+        {
+            return Err(LoadError::StackTooSmall { stack_size, needed });
+        }
+    }
+
+    // recursive functions have no limit, so their stacks just grow as needed
+    let stack_depths = script_stack_depths
+        .into_iter()
+        .filter_map(|stack_depth| {
+            Some(StackDepth {
+                bytecode_offset: stack_depth.bytecode_offset,
+                max_depth: stack_depth.max_depth?,
+            })
+        })
+        .collect::<Vec<_>>();
+
+    let program = Program {
+        bytecode: Cow::Owned(bytecode),
+        stack_depths: Cow::Owned(stack_depths),
+        event_handlers: Cow::Owned(event_handlers),
+        triggers: Some(Cow::Owned(triggers)),
+        messages: Cow::Owned(messages),
+    };
+
+    let readable_properties = schema
+        .properties
+        .iter()
+        .filter(|property| property.access != PropertyAccess::WriteOnly)
+        .map(|property| property.index)
+        .collect::<Vec<_>>();
+    let writable_properties = schema
+        .properties
+        .iter()
+        .filter(|property| property.access != PropertyAccess::ReadOnly)
+        .map(|property| property.index)
+        .collect::<Vec<_>>();
+    let trigger_arguments = schema
+        .triggers
+        .iter()
+        .map(|trigger| trigger.arguments.len())
+        .collect::<Vec<_>>();
+    let event_arguments = schema
+        .events
+        .iter()
+        .map(|event| event.arguments.len())
+        .collect::<Vec<_>>();
+    let event_returns = schema
+        .events
+        .iter()
+        .map(|event| event.returns.len())
+        .collect::<Vec<_>>();
+
+    let program = VerifiedProgram::verify(
+        program,
+        &Interface {
+            readable_properties: &readable_properties,
+            writable_properties: &writable_properties,
+            trigger_arguments: &trigger_arguments,
+            event_arguments: &event_arguments,
+            event_returns: &event_returns,
+        },
+    )?;
+
+    Ok(program)
+}
+
+fn owned_field_names(field_names: &[&str]) -> Vec<String> {
+    field_names.iter().map(|&name| name.into()).collect()
+}
+
+enum SignatureError {
+    Unknown,
+    /// Contains the expected argument types
+    Mismatch(Vec<PropertyType>),
+}
+
+fn find_signature(
+    signatures: &[Signature],
+    name: &str,
+    arguments: &[PropertyType],
+) -> Result<usize, SignatureError> {
+    let index = signatures
+        .iter()
+        .position(|signature| signature.name == name)
+        .ok_or(SignatureError::Unknown)?;
+
+    let expected = signatures[index].arguments;
+    if expected != arguments {
+        return Err(SignatureError::Mismatch(expected.to_vec()));
+    }
+
+    Ok(index)
+}
@@ -0,0 +1,117 @@
+//! Loading scripts which were compiled ahead of time with `CompileResult::to_container`, so they
+//! can be shipped separately from the game, for example as mods or downloadable content, without
+//! needing the compiler at runtime.
+//!
+//! Like scripts compiled at runtime, these are checked against the [`Schema`](crate::Schema) of
+//! the struct they'll run with. They must also have been compiled against the same properties and
+//! `fix_precision`, since those are baked into the bytecode.
+
+use alloc::vec::Vec;
+
+use vm::container::{self, ValueType};
+
+pub use vm::{container::ContainerError, verify::VerifyError};
+
+use crate::{
+    link::{link, ScriptEventHandler, ScriptMessage, ScriptStackDepth, ScriptTrigger},
+    LoadError, PropertyAccess, PropertyType, TapirSchema, VerifiedProgram,
+};
+
+/// Loads a precompiled script so it can be run with a `T` as its properties, using
+/// [`Script::new`](crate::Script::new) or [`Script::reload`](crate::Script::reload)
+pub fn load<T: TapirSchema>(bytes: &[u8]) -> Result<VerifiedProgram<'static>, LoadError> {
+    let container = container::load(bytes)?;
+
+    if container.fix_precision != T::FIX_PRECISION {
+        return Err(LoadError::FixPrecisionMismatch {
+            expected: T::FIX_PRECISION,
+            actual: container.fix_precision,
+        });
+    }
+
+    let schema = T::SCHEMA;
+    for property in &container.properties {
+        let Some(expected) = schema
+            .properties
+            .iter()
+            .find(|expected| expected.name == property.name)
+        else {
+            return Err(LoadError::UnknownProperty {
+                name: property.name.into(),
+            });
+        };
+
+        if expected.index != property.index || expected.ty != property_type(property.ty) {
+            return Err(LoadError::PropertyMismatch {
+                name: property.name.into(),
+            });
+        }
+
+        let access = property_access(property.access);
+        if expected.access != PropertyAccess::ReadWrite && expected.access != access {
+            return Err(LoadError::PropertyAccessMismatch {
+                name: property.name.into(),
+                expected: expected.access,
+                actual: access,
+            });
+        }
+    }
+
+    let stack_depths = container
+        .stack_depths
+        .iter()
+        .map(|stack_depth| ScriptStackDepth {
+            bytecode_offset: stack_depth.bytecode_offset,
+            max_depth: stack_depth.max_depth,
+        });
+
+    let event_handlers = container
+        .event_handlers
+        .iter()
+        .map(|event_handler| ScriptEventHandler {
+            name: event_handler.name,
+            bytecode_offset: event_handler.bytecode_offset,
+            arguments: property_types(&event_handler.arguments),
+            returns: property_types(&event_handler.returns),
+            yields: event_handler.yields.map(property_type),
+        });
+
+    let triggers = container.triggers.iter().map(|trigger| ScriptTrigger {
+        name: trigger.name,
+        arguments: property_types(&trigger.arguments),
+        field_names: trigger.field_names.clone(),
+    });
+
+    let messages = container.messages.iter().map(|message| ScriptMessage {
+        name: message.name,
+        arguments: property_types(&message.arguments),
+    });
+
+    link::<T>(
+        container.bytecode,
+        stack_depths,
+        event_handlers,
+        triggers,
+        messages,
+    )
+}
+
+fn property_type(ty: ValueType) -> PropertyType {
+    match ty {
+        ValueType::Int => PropertyType::Int,
+        ValueType::Fix => PropertyType::Fix,
+        ValueType::Bool => PropertyType::Bool,
+    }
+}
+
+fn property_access(access: container::PropertyAccess) -> PropertyAccess {
+    match access {
+        container::PropertyAccess::ReadWrite => PropertyAccess::ReadWrite,
+        container::PropertyAccess::ReadOnly => PropertyAccess::ReadOnly,
+        container::PropertyAccess::WriteOnly => PropertyAccess::WriteOnly,
+    }
+}
+
+fn property_types(types: &[ValueType]) -> Vec<PropertyType> {
+    types.iter().map(|&ty| property_type(ty)).collect()
+}
@@ -0,0 +1,161 @@
+//! Compiling scripts while the game is running rather than with the derive macro, for modding or
+//! for trying out changes without rebuilding.
+//!
+//! Scripts are compiled against the [`Schema`] of a struct which derives `TapirScript`, so they
+//! can use the same properties and triggers and handle the same events as the script the struct
+//! was built with. Events, triggers and messages are matched up by name, and must have the same
+//! argument types.
+
+extern crate std;
+
+use alloc::{borrow::ToOwned, string::String, vec::Vec};
+use core::marker::PhantomData;
+use std::path::Path;
+
+use compiler::{CompileSettings, Diagnostics, Type};
+
+use crate::{
+    link::{link, ScriptEventHandler, ScriptMessage, ScriptStackDepth, ScriptTrigger},
+    PropertyAccess, PropertyType, Schema, Script, TapirSchema, VerifiedProgram,
+};
+
+pub use crate::LoadError;
+
+/// A script which has been compiled at runtime, ready to be attached to a `T`
+pub struct CompiledScript<T> {
+    program: VerifiedProgram<'static>,
+    warnings: Diagnostics,
+    _schema: PhantomData<fn() -> T>,
+}
+
+impl<T: TapirSchema> CompiledScript<T> {
+    /// Any warnings from compiling the script. These never contain errors.
+    pub fn warnings(&mut self) -> &mut Diagnostics {
+        &mut self.warnings
+    }
+
+    pub fn script(self, properties: T) -> Script<T> {
+        Script::new(properties, self.program)
+    }
+
+    /// Swaps this into a script which is already running. See [`Script::reload`].
+    pub fn reload(&self, script: &mut Script<T>) {
+        script.reload(self.program.clone());
+    }
+
+    pub fn into_program(self) -> VerifiedProgram<'static> {
+        self.program
+    }
+}
+
+/// Compiles `source` so it can be run with a `T` as its properties
+pub fn compile<T: TapirSchema>(
+    filename: impl AsRef<Path>,
+    source: &str,
+) -> Result<CompiledScript<T>, LoadError> {
+    let schema = T::SCHEMA;
+
+    let compiled = compiler::compile(
+        filename,
+        source,
+        CompileSettings {
+            properties: compiler_properties(&schema),
+            enable_optimisations: true,
+            fix_precision: T::FIX_PRECISION,
+        },
+    )
+    .map_err(LoadError::Compile)?;
+
+    let stack_depths = compiled
+        .stack_depths
+        .iter()
+        .map(|stack_depth| ScriptStackDepth {
+            bytecode_offset: stack_depth.bytecode_offset,
+            max_depth: stack_depth.max_depth,
+        });
+
+    let event_handlers = compiled
+        .event_handlers
+        .iter()
+        .map(|event_handler| ScriptEventHandler {
+            name: &event_handler.name,
+            bytecode_offset: event_handler.bytecode_offset,
+            arguments: event_handler
+                .arguments
+                .iter()
+                .map(|argument| property_type(argument.ty))
+                .collect(),
+            returns: event_handler
+                .return_types
+                .iter()
+                .map(|&ty| property_type(ty))
+                .collect(),
+            yields: event_handler.yields.map(property_type),
+        });
+
+    let triggers = compiled.triggers.iter().map(|trigger| ScriptTrigger {
+        name: &trigger.name,
+        arguments: trigger
+            .arguments
+            .iter()
+            .map(|&ty| property_type(ty))
+            .collect(),
+        field_names: trigger
+            .field_names
+            .as_ref()
+            .map(|field_names| field_names.iter().map(String::as_str).collect()),
+    });
+
+    let messages = compiled.messages.iter().map(|message| ScriptMessage {
+        name: &message.name,
+        arguments: message
+            .arguments
+            .iter()
+            .map(|&ty| property_type(ty))
+            .collect(),
+    });
+
+    let program = link::<T>(
+        compiled.bytecode,
+        stack_depths,
+        event_handlers,
+        triggers,
+        messages,
+    )?;
+
+    Ok(CompiledScript {
+        program,
+        warnings: compiled.warnings,
+        _schema: PhantomData,
+    })
+}
+
+fn compiler_properties(schema: &Schema) -> Vec<compiler::Property> {
+    schema
+        .properties
+        .iter()
+        .map(|property| compiler::Property {
+            ty: match property.ty {
+                PropertyType::Int => Type::Int,
+                PropertyType::Fix => Type::Fix,
+                PropertyType::Bool => Type::Bool,
+            },
+            index: property.index as usize,
+            name: property.name.to_owned(),
+            access: match property.access {
+                PropertyAccess::ReadWrite => compiler::PropertyAccess::ReadWrite,
+                PropertyAccess::ReadOnly => compiler::PropertyAccess::ReadOnly,
+                PropertyAccess::WriteOnly => compiler::PropertyAccess::WriteOnly,
+            },
+        })
+        .collect()
+}
+
+fn property_type(ty: Type) -> PropertyType {
+    match ty {
+        Type::Int => PropertyType::Int,
+        Type::Fix => PropertyType::Fix,
+        Type::Bool => PropertyType::Bool,
+        Type::Error => unreachable!("Should not have errors after compiling"),
+    }
+}
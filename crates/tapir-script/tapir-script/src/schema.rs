@@ -0,0 +1,42 @@
+use crate::{PropertyAccess, PropertyType, TapirScript};
+
+/// Everything a script can use from the rust side. The derive macro implements this alongside
+/// [`TapirScript`], based on the struct and the script it was given, so that other scripts can
+/// be checked against it when they're loaded at runtime.
+pub trait TapirSchema: TapirScript {
+    const SCHEMA: Schema;
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct Schema {
+    /// Where the script was when the struct was built, so it can be watched for changes
+    pub script_path: &'static str,
+    pub properties: &'static [PropertySchema],
+    /// In the same order as the generated `on_` and `iter_` methods refer to them
+    pub events: &'static [Signature],
+    /// In the same order as [`TapirScript::create_event`] expects them
+    pub triggers: &'static [Signature],
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct PropertySchema {
+    pub name: &'static str,
+    /// The index passed to [`TapirScript::get_prop`] and [`TapirScript::set_prop`]
+    pub index: u8,
+    pub ty: PropertyType,
+    pub access: PropertyAccess,
+}
+
+/// The name, argument types and return types of an event handler, generator or trigger
+#[derive(Clone, Copy, Debug)]
+pub struct Signature {
+    pub name: &'static str,
+    pub arguments: &'static [PropertyType],
+    /// Always empty for triggers and generators
+    pub returns: &'static [PropertyType],
+    /// The type of the values a generator yields. Always `None` for event handlers and triggers.
+    pub yields: Option<PropertyType>,
+    /// The name of each argument for triggers called like `trigger Hit { damage: 5 };`. Always
+    /// `None` for event handlers.
+    pub field_names: Option<&'static [&'static str]>,
+}
@@ -0,0 +1,137 @@
+use crate::PropertyType;
+
+/// A type which scripts can trigger as their [`TapirScript::EventType`](crate::TapirScript), set
+/// with `trigger_type` in the `#[tapir(...)]` attribute. Derive this on an enum with
+/// `#[derive(TapirTrigger)]`.
+///
+/// `trigger Hit(5);` becomes the tuple variant `Hit(i32)`, `trigger Hit { damage: 5 };` becomes
+/// the struct variant `Hit { damage: i32 }` and `trigger Hit;` becomes the unit variant `Hit`.
+/// The derive macro for [`TapirScript`](crate::TapirScript) uses [`VARIANTS`](Self::VARIANTS) to
+/// check every trigger in the script against these, and reports any which don't match with an
+/// error pointing at the script.
+#[diagnostic::on_unimplemented(
+    message = "`{Self}` can't be used as a trigger_type",
+    note = "add `#[derive(TapirTrigger)]` to `{Self}`"
+)]
+pub trait TapirTrigger {
+    const VARIANTS: &'static [TriggerVariant];
+}
+
+impl TapirTrigger for () {
+    const VARIANTS: &'static [TriggerVariant] = &[];
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct TriggerVariant {
+    pub name: &'static str,
+    pub fields: TriggerFields,
+}
+
+/// The fields of a variant, with the [`TapirProperty::SCRIPT_TYPE`](crate::TapirProperty) of
+/// each one
+#[derive(Clone, Copy, Debug)]
+pub enum TriggerFields {
+    Unit,
+    Unnamed(&'static [PropertyType]),
+    Named(&'static [(&'static str, PropertyType)]),
+}
+
+/// Why a trigger in the script doesn't match the variant it should become. The derive macro has
+/// an error message for each of these.
+#[doc(hidden)]
+pub enum TriggerMismatch {
+    UnknownVariant,
+    Shape,
+    MissingFields,
+    UnknownField(usize),
+    FieldType(usize),
+}
+
+/// Used by the derive macro to check a trigger called with `field_names` (if it names its
+/// arguments) and `arguments` against the variants of the trigger type
+#[doc(hidden)]
+pub const fn __private_check_trigger(
+    variants: &[TriggerVariant],
+    name: &str,
+    field_names: Option<&[&str]>,
+    arguments: &[PropertyType],
+) -> Option<TriggerMismatch> {
+    let mut variant_index = 0;
+    let variant = loop {
+        if variant_index == variants.len() {
+            return Some(TriggerMismatch::UnknownVariant);
+        }
+
+        if str_eq(variants[variant_index].name, name) {
+            break &variants[variant_index];
+        }
+
+        variant_index += 1;
+    };
+
+    match (variant.fields, field_names) {
+        (TriggerFields::Unit, None) if arguments.is_empty() => None,
+        (TriggerFields::Unnamed(types), None) if types.len() == arguments.len() => {
+            let mut i = 0;
+            while i < arguments.len() {
+                if types[i] as u8 != arguments[i] as u8 {
+                    return Some(TriggerMismatch::FieldType(i));
+                }
+
+                i += 1;
+            }
+
+            None
+        }
+        (TriggerFields::Named(fields), Some(field_names)) => {
+            let mut i = 0;
+            while i < field_names.len() {
+                let mut field_index = 0;
+                loop {
+                    if field_index == fields.len() {
+                        return Some(TriggerMismatch::UnknownField(i));
+                    }
+
+                    let (field_name, ty) = fields[field_index];
+                    if str_eq(field_name, field_names[i]) {
+                        if ty as u8 != arguments[i] as u8 {
+                            return Some(TriggerMismatch::FieldType(i));
+                        }
+
+                        break;
+                    }
+
+                    field_index += 1;
+                }
+
+                i += 1;
+            }
+
+            // the compiler rejects duplicate fields, so every field is set if the counts match
+            if fields.len() != field_names.len() {
+                return Some(TriggerMismatch::MissingFields);
+            }
+
+            None
+        }
+        _ => Some(TriggerMismatch::Shape),
+    }
+}
+
+const fn str_eq(a: &str, b: &str) -> bool {
+    let (a, b) = (a.as_bytes(), b.as_bytes());
+    if a.len() != b.len() {
+        return false;
+    }
+
+    let mut i = 0;
+    while i < a.len() {
+        if a[i] != b[i] {
+            return false;
+        }
+
+        i += 1;
+    }
+
+    true
+}
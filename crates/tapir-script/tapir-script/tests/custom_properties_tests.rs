@@ -0,0 +1,79 @@
+use tapir_script::{PropertyType, TapirProperty, TapirScript};
+
+#[derive(Debug, PartialEq, Eq)]
+struct Health(i32);
+
+impl TapirProperty for Health {
+    const SCRIPT_TYPE: PropertyType = PropertyType::Int;
+
+    fn to_i32(&self) -> i32 {
+        self.0
+    }
+
+    fn set_from_i32(&mut self, value: i32) {
+        self.0 = value.clamp(0, 100);
+    }
+}
+
+#[derive(TapirScript)]
+#[tapir("tests/custom_properties.tapir")]
+struct Enemy {
+    #[tapir(int)]
+    health: Health,
+    sprite: u8,
+    #[tapir(name = alive)]
+    is_alive: bool,
+    score: i32,
+}
+
+#[test]
+fn custom_property_types_use_their_implementation() {
+    let mut script = Enemy {
+        health: Health(10),
+        sprite: 255,
+        is_alive: true,
+        score: 4,
+    }
+    .script();
+
+    script.run();
+
+    assert_eq!(script.properties.health, Health(0));
+    assert_eq!(script.properties.sprite, 0);
+    assert_eq!(script.properties.score, 5);
+}
+
+#[derive(TapirScript)]
+#[tapir("tests/custom_property_accessors.tapir")]
+#[tapir_property(health: int, get = health, set = set_health)]
+struct Boss {
+    #[tapir(skip)]
+    health: Health,
+    #[tapir(skip)]
+    times_hit: i32,
+}
+
+impl Boss {
+    fn health(&self) -> Health {
+        Health(self.health.0)
+    }
+
+    fn set_health(&mut self, health: Health) {
+        self.health = health;
+        self.times_hit += 1;
+    }
+}
+
+#[test]
+fn custom_property_types_can_be_bound_to_methods() {
+    let mut script = Boss {
+        health: Health(50),
+        times_hit: 0,
+    }
+    .script();
+
+    script.run();
+
+    assert_eq!(script.properties.health, Health(0));
+    assert_eq!(script.properties.times_hit, 2);
+}
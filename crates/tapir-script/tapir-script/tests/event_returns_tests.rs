@@ -0,0 +1,61 @@
+use tapir_script::{CallError, Fix, TapirScript, TapirTrigger};
+
+#[derive(TapirScript)]
+#[tapir("tests/event_returns.tapir", trigger_type = Event)]
+struct Enemy {
+    defence: i32,
+}
+
+#[derive(Debug, PartialEq, Eq, TapirTrigger)]
+enum Event {
+    Rolled(i32),
+}
+
+#[test]
+fn event_handlers_return_straight_away() {
+    let mut script = Enemy { defence: 5 }.script();
+
+    assert_eq!(script.on_should_hit(7), Ok(true));
+    assert_eq!(script.on_should_hit(2), Ok(false));
+}
+
+#[test]
+fn event_handlers_can_return_many_values() {
+    let mut script = Enemy { defence: 5 }.script();
+
+    assert_eq!(script.on_knockback(3), Ok((6, Fix::new(1) / 2)));
+}
+
+#[test]
+fn triggers_are_passed_on_by_the_next_run() {
+    let mut script = Enemy { defence: 5 }.script();
+
+    script.on_should_hit(7).unwrap();
+    script.on_should_hit(1).unwrap();
+
+    assert!(script.will_calling_run_do_anything());
+    assert_eq!(script.run(), &[Event::Rolled(7), Event::Rolled(1)]);
+    assert!(!script.will_calling_run_do_anything());
+}
+
+#[test]
+fn waiting_is_an_error() {
+    let mut script = Enemy { defence: 5 }.script();
+
+    assert_eq!(script.on_slow_answer(), Err(CallError::Waited));
+    // it got as far as the wait, and won't carry on from there
+    assert_eq!(script.properties.defence, 6);
+    script.run();
+    assert_eq!(script.properties.defence, 6);
+}
+
+#[test]
+fn spawned_threads_run_with_the_others() {
+    let mut script = Enemy { defence: 5 }.script();
+
+    assert_eq!(script.on_start_regenerating(), Ok(5));
+    assert_eq!(script.properties.defence, 5);
+
+    script.run();
+    assert_eq!(script.properties.defence, 15);
+}
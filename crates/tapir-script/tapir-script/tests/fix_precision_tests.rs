@@ -0,0 +1,31 @@
+use agb_fixnum::{num, Num};
+use tapir_script::{TapirScript, TapirTrigger};
+
+#[derive(TapirScript)]
+#[tapir("tests/fix_precision.tapir", trigger_type = Event, fix_precision = 12)]
+struct Physics {
+    #[tapir(fix)]
+    position: Num<i32, 12>,
+}
+
+#[derive(PartialEq, Eq, Debug, TapirTrigger)]
+enum Event {
+    Moved(Num<i32, 12>),
+}
+
+#[test]
+fn fix_values_keep_their_precision() {
+    let mut script = Physics {
+        position: num!(1.5),
+    }
+    .script();
+
+    assert_eq!(script.run(), &[]);
+    assert_eq!(script.properties.position, num!(1.625));
+
+    script.on_scale(num!(0.3));
+
+    let expected: Num<i32, 12> = num!(1.625) * num!(0.3);
+    assert_eq!(script.run(), &[Event::Moved(expected + num!(0.001))]);
+    assert_eq!(script.properties.position, expected);
+}
@@ -0,0 +1,133 @@
+use tapir_script::{precompiled, runtime, GroupError, LoadError, TapirScript, TapirTrigger};
+
+#[derive(TapirScript)]
+#[tapir(
+    "tests/fixed_storage.tapir",
+    trigger_type = Event,
+    threads = 3,
+    stack_size = 8
+)]
+struct FixedStorage;
+
+#[derive(TapirScript)]
+#[tapir("tests/fixed_storage_groups.tapir", threads = 1, stack_size = 8)]
+struct FixedGroups;
+
+#[derive(TapirScript)]
+#[tapir(
+    "tests/fixed_storage_groups.tapir",
+    threads = 1,
+    stack_size = 8,
+    groups = 2
+)]
+struct TooFewGroups;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, TapirTrigger)]
+enum Event {
+    Counted(i32),
+}
+
+#[test]
+fn fixed_storage_passes_events_to_callback() {
+    let mut script = FixedStorage.script();
+    script.on_start_counting(3);
+
+    let mut events = [None; 5];
+    let mut event_count = 0;
+
+    while script.will_calling_run_do_anything() {
+        script.run_with_callback(|event| {
+            events[event_count] = Some(event);
+            event_count += 1;
+        });
+    }
+
+    assert_eq!(
+        events,
+        [
+            Some(Event::Counted(0)),
+            Some(Event::Counted(0)),
+            Some(Event::Counted(1)),
+            Some(Event::Counted(1)),
+            Some(Event::Counted(2)),
+        ]
+    );
+}
+
+const RECURSIVE: &str = "
+    fn depth(n: int) -> int {
+        if n == 0 {
+            return 0;
+        }
+
+        return depth(n - 1) + 1;
+    }
+
+    trigger Counted(depth(3));
+";
+
+#[test]
+fn fixed_storage_rejects_recursive_runtime_scripts() {
+    let result = runtime::compile::<FixedStorage>("recursive.tapir", RECURSIVE);
+
+    assert!(matches!(
+        result,
+        Err(LoadError::UnboundedStack { stack_size: 8 })
+    ));
+}
+
+#[test]
+fn fixed_storage_rejects_recursive_precompiled_scripts() {
+    let bytes = compiler::compile(
+        "recursive.tapir",
+        RECURSIVE,
+        compiler::CompileSettings {
+            properties: vec![],
+            enable_optimisations: true,
+            fix_precision: compiler::CompileSettings::DEFAULT_FIX_PRECISION,
+        },
+    )
+    .unwrap()
+    .to_container(false);
+
+    assert!(matches!(
+        precompiled::load::<FixedStorage>(&bytes),
+        Err(LoadError::UnboundedStack { stack_size: 8 })
+    ));
+}
+
+#[test]
+fn fixed_storage_has_room_for_more_groups_than_threads() {
+    let mut script = FixedGroups.script();
+    script.run();
+
+    assert!((1..=3).all(|group| script.is_group_paused(group)));
+    assert_eq!(script.take_group_error(), None);
+}
+
+#[test]
+fn fixed_storage_reports_pausing_too_many_groups() {
+    let mut script = TooFewGroups.script();
+    script.run();
+
+    assert!(script.is_group_paused(1));
+    assert!(script.is_group_paused(2));
+    assert!(!script.is_group_paused(3));
+    assert_eq!(script.take_group_error(), Some(GroupError::TooManyGroups));
+    assert_eq!(script.take_group_error(), None);
+
+    assert_eq!(script.pause_group(4), Err(GroupError::TooManyGroups));
+    assert_eq!(
+        script.set_group_time_scale(4, 2),
+        Err(GroupError::TooManyGroups)
+    );
+
+    // resuming and going back to every frame don't need any room
+    script.resume_group(4);
+    assert_eq!(script.set_group_time_scale(4, 1), Ok(()));
+
+    // resumed groups make room again from the next run
+    script.resume_group(1);
+    script.run();
+    assert_eq!(script.pause_group(4), Ok(()));
+}
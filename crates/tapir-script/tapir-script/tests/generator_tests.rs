@@ -0,0 +1,84 @@
+use tapir_script::{Fix, TapirScript, TapirTrigger};
+
+#[derive(TapirScript)]
+#[tapir("tests/generator.tapir", trigger_type = Event)]
+struct Guide {
+    #[tapir(fix)]
+    start: Fix,
+    visited: i32,
+}
+
+#[derive(Debug, PartialEq, Eq, TapirTrigger)]
+enum Event {
+    Reached(i32),
+}
+
+fn guide() -> tapir_script::Script<Guide> {
+    Guide {
+        start: Fix::new(1),
+        visited: 0,
+    }
+    .script()
+}
+
+#[test]
+fn generators_yield_every_value() {
+    let mut script = guide();
+
+    assert_eq!(script.iter_dialogue(3).collect::<Vec<_>>(), [1, 3, 4]);
+    assert_eq!(script.iter_dialogue(8).collect::<Vec<_>>(), [1, 2]);
+}
+
+#[test]
+fn generators_only_run_as_far_as_the_values_asked_for() {
+    let mut script = guide();
+
+    let waypoints = script.iter_waypoints(10).take(2).collect::<Vec<_>>();
+    assert_eq!(waypoints, [Fix::new(1), Fix::new(1) + Fix::new(1) / 2]);
+    assert_eq!(script.properties.visited, 2);
+
+    assert_eq!(script.iter_waypoints(3).count(), 3);
+    assert_eq!(script.properties.visited, 5);
+}
+
+#[test]
+fn triggers_are_passed_on_by_the_next_run() {
+    let mut script = guide();
+
+    assert_eq!(script.iter_waypoints(2).count(), 2);
+    assert_eq!(script.run(), [Event::Reached(0), Event::Reached(1)]);
+}
+
+#[test]
+fn spawned_threads_run_with_the_others() {
+    let mut script = guide();
+    script.properties.visited = 3;
+
+    let mut checks = script.iter_checks();
+    assert_eq!(checks.next(), Some(true));
+    assert_eq!(checks.next(), Some(true));
+    drop(checks);
+
+    script.run();
+    assert_eq!(script.properties.visited, 0);
+}
+
+#[test]
+fn waiting_finishes_the_generator() {
+    let mut script = guide();
+
+    let mut checks = script.iter_checks();
+    assert_eq!(checks.next(), Some(false));
+    assert_eq!(checks.next(), Some(true));
+    assert_eq!(checks.next(), None);
+    assert_eq!(checks.next(), None);
+}
+
+#[test]
+fn generators_can_be_run_again() {
+    let mut script = guide();
+
+    let first = script.iter_dialogue(0).collect::<Vec<_>>();
+    let second = script.iter_dialogue(0).collect::<Vec<_>>();
+    assert_eq!(first, second);
+}
@@ -0,0 +1,156 @@
+use tapir_script::TapirScript;
+
+#[derive(TapirScript)]
+#[tapir("tests/groups.tapir")]
+struct Game {
+    gameplay_ticks: i32,
+    enemy_ticks: i32,
+    menu_ticks: i32,
+}
+
+fn game() -> tapir_script::Script<Game> {
+    let mut script = Game {
+        gameplay_ticks: 0,
+        enemy_ticks: 0,
+        menu_ticks: 0,
+    }
+    .script();
+
+    script.run();
+    script
+}
+
+fn ticks(script: &tapir_script::Script<Game>) -> (i32, i32, i32) {
+    let properties = &script.properties;
+    (
+        properties.gameplay_ticks,
+        properties.enemy_ticks,
+        properties.menu_ticks,
+    )
+}
+
+#[test]
+fn every_group_runs_by_default() {
+    let mut script = game();
+    assert_eq!(ticks(&script), (1, 1, 1));
+
+    script.run();
+    assert_eq!(ticks(&script), (2, 2, 2));
+}
+
+#[test]
+fn paused_groups_dont_run_until_resumed() {
+    let mut script = game();
+
+    script.pause_group(1).unwrap();
+    assert!(script.is_group_paused(1));
+    assert!(!script.is_group_paused(2));
+
+    script.run();
+    script.run();
+    assert_eq!(ticks(&script), (1, 1, 3));
+
+    script.resume_group(1);
+    assert!(!script.is_group_paused(1));
+
+    script.run();
+    assert_eq!(ticks(&script), (2, 2, 4));
+}
+
+#[test]
+fn time_scaled_groups_run_once_every_few_frames() {
+    let mut script = game();
+
+    script.set_group_time_scale(1, 3).unwrap();
+    assert_eq!(script.group_time_scale(1), 3);
+    assert_eq!(script.group_time_scale(2), 1);
+
+    for _ in 0..6 {
+        script.run();
+    }
+    assert_eq!(ticks(&script), (3, 3, 7));
+
+    script.set_group_time_scale(1, 1).unwrap();
+    script.run();
+    assert_eq!(ticks(&script), (4, 4, 8));
+}
+
+#[test]
+fn paused_groups_keep_their_time_scale() {
+    let mut script = game();
+
+    script.set_group_time_scale(1, 2).unwrap();
+    script.pause_group(1).unwrap();
+    script.run();
+    script.run();
+    assert_eq!(ticks(&script), (1, 1, 3));
+
+    script.resume_group(1);
+    assert_eq!(script.group_time_scale(1), 2);
+    for _ in 0..4 {
+        script.run();
+    }
+    assert_eq!(ticks(&script), (3, 3, 7));
+}
+
+#[test]
+fn scripts_can_pause_and_resume_groups() {
+    let mut script = game();
+
+    script.on_cutscene(2);
+    // the pause takes effect from the next run
+    script.run();
+    assert_eq!(ticks(&script), (2, 2, 2));
+
+    script.run();
+    script.run();
+    assert_eq!(ticks(&script), (2, 2, 4));
+
+    script.run();
+    assert_eq!(ticks(&script), (3, 3, 5));
+}
+
+#[test]
+fn scripts_can_time_scale_groups() {
+    let mut script = game();
+
+    script.on_slow_motion(2);
+    script.run();
+    assert_eq!(script.group_time_scale(1), 2);
+
+    for _ in 0..4 {
+        script.run();
+    }
+    assert_eq!(ticks(&script), (4, 4, 6));
+}
+
+#[test]
+fn paused_threads_dont_need_running() {
+    let mut script = game();
+
+    script.pause_group(1).unwrap();
+    assert!(script.will_calling_run_do_anything());
+
+    script.pause_group(2).unwrap();
+    assert!(!script.will_calling_run_do_anything());
+}
+
+#[test]
+fn groups_stay_paused_across_reloads() {
+    let mut script = game();
+    script.pause_group(1).unwrap();
+
+    let source = "
+        spawn 1, gameplay();
+        spawn 2, menu();
+        fn gameplay() { loop { gameplay_ticks = gameplay_ticks + 10; wait; } }
+        fn menu() { loop { menu_ticks = menu_ticks + 10; wait; } }
+    ";
+    tapir_script::runtime::compile::<Game>("reloaded.tapir", source)
+        .unwrap()
+        .reload(&mut script);
+
+    script.run();
+    assert!(script.is_group_paused(1));
+    assert_eq!(ticks(&script), (1, 1, 11));
+}
@@ -0,0 +1,187 @@
+use std::{
+    fs::{self, File},
+    path::PathBuf,
+    time::{Duration, SystemTime},
+};
+
+use tapir_script::{
+    hot_reload::{ReloadError, ScriptWatcher},
+    runtime::LoadError,
+    TapirSchema, TapirScript,
+};
+
+#[derive(TapirScript)]
+#[tapir("tests/hot_reload.tapir")]
+struct Counter {
+    counter: i32,
+    started: i32,
+}
+
+/// A copy of the script which the test can change without affecting the others
+fn scratch_script(name: &str) -> PathBuf {
+    let path = std::env::temp_dir().join(format!("tapir_hot_reload_{name}.tapir"));
+    fs::copy(Counter::SCHEMA.script_path, &path).unwrap();
+    path
+}
+
+fn change_script(path: &PathBuf, source: &str) {
+    fs::write(path, source).unwrap();
+
+    // some filesystems only store the modification time to the nearest second
+    let modified = SystemTime::now() + Duration::from_secs(5);
+    File::options()
+        .write(true)
+        .open(path)
+        .unwrap()
+        .set_modified(modified)
+        .unwrap();
+}
+
+#[test]
+fn watches_the_script_the_struct_was_built_with() {
+    let mut watcher = ScriptWatcher::<Counter>::new();
+
+    assert!(watcher.poll().is_none());
+}
+
+#[test]
+fn reloading_keeps_properties_and_restarts_the_script() {
+    let path = scratch_script("reload");
+    let mut watcher = ScriptWatcher::<Counter>::with_path(&path);
+
+    let mut script = Counter {
+        counter: 0,
+        started: 0,
+    }
+    .script();
+
+    script.run();
+    script.on_tick();
+    script.run();
+    assert_eq!(
+        (script.properties.counter, script.properties.started),
+        (1, 1)
+    );
+
+    assert!(!watcher.reload_if_changed([&mut script]).unwrap());
+
+    change_script(
+        &path,
+        "event fn tick() { counter = counter + 10; }\nstarted = started + 1;",
+    );
+    assert!(watcher.reload_if_changed([&mut script]).unwrap());
+
+    script.run();
+    script.on_tick();
+    script.run();
+    // the top level's `started = started + 1;` ran before the reload, and isn't run again
+    assert_eq!(
+        (script.properties.counter, script.properties.started),
+        (11, 1)
+    );
+
+    // nothing has changed since the last reload
+    assert!(!watcher.reload_if_changed([&mut script]).unwrap());
+}
+
+#[test]
+fn properties_assigned_at_the_top_level_keep_their_values() {
+    let path = scratch_script("keep_properties");
+    let mut watcher = ScriptWatcher::<Counter>::with_path(&path);
+
+    let mut script = Counter {
+        counter: 0,
+        started: 0,
+    }
+    .script();
+
+    script.run();
+    script.properties.counter = 42;
+
+    change_script(
+        &path,
+        "
+        counter = 5;
+        spawn count_frames();
+        wait;
+        counter = counter + 1;
+
+        fn count_frames() {
+            loop {
+                started = started + 1;
+                wait;
+            }
+        }
+        ",
+    );
+    assert!(watcher.reload_if_changed([&mut script]).unwrap());
+
+    // the top level's threads still start, and only its first assignment is skipped
+    script.run();
+    assert_eq!(
+        (script.properties.counter, script.properties.started),
+        (42, 2)
+    );
+
+    script.run();
+    assert_eq!(
+        (script.properties.counter, script.properties.started),
+        (43, 3)
+    );
+}
+
+#[test]
+fn broken_scripts_are_reported_once() {
+    let path = scratch_script("broken");
+    let mut watcher = ScriptWatcher::<Counter>::with_path(&path);
+
+    change_script(&path, "counter = ;");
+
+    assert!(matches!(
+        watcher.poll(),
+        Some(Err(ReloadError::Load(LoadError::Compile(_))))
+    ));
+    assert!(watcher.poll().is_none());
+}
+
+#[test]
+fn scripts_with_unknown_variables_leave_the_old_script_running() {
+    let path = scratch_script("unknown_variable");
+    let mut watcher = ScriptWatcher::<Counter>::with_path(&path);
+
+    let mut script = Counter {
+        counter: 0,
+        started: 0,
+    }
+    .script();
+
+    script.run();
+
+    change_script(&path, "event fn tick() { counter = countr + 10; }");
+    assert!(matches!(
+        watcher.poll(),
+        Some(Err(ReloadError::Load(LoadError::Compile(_))))
+    ));
+
+    script.on_tick();
+    script.run();
+    assert_eq!(
+        (script.properties.counter, script.properties.started),
+        (1, 1)
+    );
+}
+
+#[test]
+fn missing_scripts_are_not_changes() {
+    let path = scratch_script("missing");
+    let mut watcher = ScriptWatcher::<Counter>::with_path(&path);
+
+    // like an editor which saves by renaming a new file over the old one
+    fs::remove_file(&path).unwrap();
+    assert!(watcher.poll().is_none());
+    assert!(watcher.poll().is_none());
+
+    change_script(&path, "event fn tick() { counter = counter + 10; }");
+    assert!(matches!(watcher.poll(), Some(Ok(_))));
+    assert!(watcher.poll().is_none());
+}
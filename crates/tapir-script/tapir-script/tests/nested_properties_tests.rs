@@ -0,0 +1,64 @@
+use tapir_script::{Fix, TapirScript};
+
+struct Position {
+    x: Fix,
+}
+
+struct Transform {
+    pos: Position,
+}
+
+#[derive(TapirScript)]
+#[tapir("tests/nested_properties.tapir")]
+#[tapir_property(x: fix = transform.pos.x)]
+#[tapir_property(health: int, get = health, set = set_health)]
+struct Player {
+    #[tapir(skip)]
+    transform: Transform,
+    #[tapir(skip)]
+    health: i32,
+    #[tapir(int, name = speed)]
+    velocity: i32,
+}
+
+impl Player {
+    fn health(&self) -> i32 {
+        self.health
+    }
+
+    fn set_health(&mut self, health: i32) {
+        self.health = health.max(0);
+    }
+}
+
+#[test]
+fn nested_fields_and_accessors_are_bound() {
+    let mut script = Player {
+        transform: Transform {
+            pos: Position { x: Fix::new(2) },
+        },
+        health: 2,
+        velocity: 5,
+    }
+    .script();
+
+    script.run();
+
+    assert_eq!(script.properties.transform.pos.x, Fix::new(7) / 2);
+    assert_eq!(script.properties.health, 0);
+    assert_eq!(script.properties.velocity, 10);
+}
+
+#[derive(TapirScript)]
+#[tapir("tests/tuple_properties.tapir")]
+struct Score(#[tapir(int, name = score)] i32, i32);
+
+#[test]
+fn tuple_struct_fields_are_bound() {
+    let mut script = Score(5, 3).script();
+
+    script.run();
+
+    assert_eq!(script.properties.0, 15);
+    assert_eq!(script.properties.1, 3);
+}
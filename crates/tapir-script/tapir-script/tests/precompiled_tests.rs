@@ -0,0 +1,186 @@
+use tapir_script::{
+    precompiled::{self, ContainerError, VerifyError},
+    LoadError, PropertyAccess, Script, TapirScript, TapirTrigger,
+};
+
+#[derive(TapirScript)]
+#[tapir("tests/runtime.tapir", trigger_type = Event)]
+struct Enemy {
+    health: i32,
+    #[tapir(readonly)]
+    frame: i32,
+}
+
+#[derive(PartialEq, Eq, Debug, TapirTrigger)]
+enum Event {
+    Hit(i32),
+    Died,
+}
+
+fn enemy() -> Enemy {
+    Enemy {
+        health: 10,
+        frame: 0,
+    }
+}
+
+fn property(name: &str, index: usize, ty: compiler::Type) -> compiler::Property {
+    compiler::Property {
+        ty,
+        index,
+        name: name.to_string(),
+        access: compiler::PropertyAccess::ReadWrite,
+    }
+}
+
+fn precompile(source: &str, properties: Vec<compiler::Property>, fix_precision: u8) -> Vec<u8> {
+    compiler::compile(
+        "precompiled.tapir",
+        source,
+        compiler::CompileSettings {
+            properties,
+            enable_optimisations: true,
+            fix_precision,
+        },
+    )
+    .unwrap()
+    .to_container(true)
+}
+
+fn enemy_properties() -> Vec<compiler::Property> {
+    vec![
+        property("health", 0, compiler::Type::Int),
+        compiler::Property {
+            access: compiler::PropertyAccess::ReadOnly,
+            ..property("frame", 1, compiler::Type::Int)
+        },
+    ]
+}
+
+#[test]
+fn precompiled_scripts_use_the_same_events_and_triggers() {
+    let source = "
+        event fn heal(amount: int) {}
+
+        event fn damage(amount: int) {
+            if health <= amount * 2 {
+                trigger Died;
+            }
+
+            health = health - amount * 2;
+            trigger Hit(amount * 2);
+        }
+    ";
+
+    let bytes = precompile(source, enemy_properties(), 8);
+    let mut script = Script::new(enemy(), precompiled::load::<Enemy>(&bytes).unwrap());
+
+    script.on_heal(5);
+    assert_eq!(script.run(), &[]);
+    assert_eq!(script.properties.health, 10);
+
+    script.on_damage(3);
+    assert_eq!(script.run(), &[Event::Hit(6)]);
+
+    script.on_damage(3);
+    assert_eq!(script.run(), &[Event::Died, Event::Hit(6)]);
+    assert_eq!(script.properties.health, -2);
+}
+
+#[test]
+fn fix_precision_must_match() {
+    let bytes = precompile("health = 3;", enemy_properties(), 4);
+
+    assert!(matches!(
+        precompiled::load::<Enemy>(&bytes),
+        Err(LoadError::FixPrecisionMismatch {
+            expected: 8,
+            actual: 4
+        })
+    ));
+}
+
+#[test]
+fn properties_must_match() {
+    let bytes = precompile(
+        "health = 3.5;",
+        vec![property("health", 0, compiler::Type::Fix)],
+        8,
+    );
+
+    assert!(matches!(
+        precompiled::load::<Enemy>(&bytes),
+        Err(LoadError::PropertyMismatch { name }) if name == "health"
+    ));
+}
+
+#[test]
+fn property_access_must_be_allowed() {
+    // frame is readonly, so this would assign to it if it was loaded
+    let bytes = precompile(
+        "frame = 3;",
+        vec![
+            property("health", 0, compiler::Type::Int),
+            property("frame", 1, compiler::Type::Int),
+        ],
+        8,
+    );
+
+    assert!(matches!(
+        precompiled::load::<Enemy>(&bytes),
+        Err(LoadError::PropertyAccessMismatch {
+            name,
+            expected: PropertyAccess::ReadOnly,
+            actual: PropertyAccess::ReadWrite,
+        }) if name == "frame"
+    ));
+}
+
+#[test]
+fn unknown_properties_are_rejected() {
+    let bytes = precompile(
+        "mana = 3;",
+        vec![property("mana", 0, compiler::Type::Int)],
+        8,
+    );
+
+    assert!(matches!(
+        precompiled::load::<Enemy>(&bytes),
+        Err(LoadError::UnknownProperty { name }) if name == "mana"
+    ));
+}
+
+#[test]
+fn unknown_triggers_are_rejected() {
+    let bytes = precompile("trigger Exploded;", enemy_properties(), 8);
+
+    assert!(matches!(
+        precompiled::load::<Enemy>(&bytes),
+        Err(LoadError::UnknownTrigger { name }) if name == "Exploded"
+    ));
+}
+
+#[test]
+fn invalid_containers_are_rejected() {
+    let mut bytes = precompile("health = 3;", enemy_properties(), 8);
+    bytes.truncate(bytes.len() - 1);
+
+    assert!(matches!(
+        precompiled::load::<Enemy>(&bytes),
+        Err(LoadError::Container(ContainerError::Truncated))
+    ));
+}
+
+#[test]
+fn corrupt_bytecode_is_rejected() {
+    let mut bytes = precompile("health = 3;", enemy_properties(), 8);
+
+    // the bytecode starts after the 10 byte header and its length, so this is the top byte of the
+    // first instruction
+    bytes[15] = 0xff;
+
+    assert!(matches!(
+        precompiled::load::<Enemy>(&bytes),
+        Err(LoadError::Verify(VerifyError::InvalidInstruction { pc: 0 }))
+    ));
+}
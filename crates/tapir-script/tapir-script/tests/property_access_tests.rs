@@ -0,0 +1,33 @@
+use tapir_script::TapirScript;
+
+#[derive(TapirScript)]
+#[tapir("tests/property_access.tapir")]
+#[tapir_property(level: int, get = level, readonly)]
+struct Sprite {
+    #[tapir(readonly)]
+    frame: i32,
+    #[tapir(int, writeonly)]
+    animation: i32,
+    speed: i32,
+}
+
+impl Sprite {
+    fn level(&self) -> i32 {
+        3
+    }
+}
+
+#[test]
+fn readonly_and_writeonly_properties_can_be_used() {
+    let mut script = Sprite {
+        frame: 12,
+        animation: 0,
+        speed: 1,
+    }
+    .script();
+
+    script.run();
+
+    assert_eq!(script.properties.animation, 2);
+    assert_eq!(script.properties.speed, 4);
+}
@@ -0,0 +1,284 @@
+use tapir_script::{
+    runtime::{self, LoadError},
+    PropertyType, ScriptWorld, TapirScript, TapirTrigger,
+};
+
+#[derive(TapirScript)]
+#[tapir("tests/runtime.tapir", trigger_type = Event)]
+struct Enemy {
+    health: i32,
+    #[tapir(readonly)]
+    frame: i32,
+}
+
+#[derive(PartialEq, Eq, Debug, TapirTrigger)]
+enum Event {
+    Hit(i32),
+    Died,
+}
+
+fn enemy() -> Enemy {
+    Enemy {
+        health: 10,
+        frame: 0,
+    }
+}
+
+#[test]
+fn runtime_scripts_use_the_same_events_and_triggers() {
+    // the triggers are called in a different order to the original script, and the event
+    // handlers are declared in a different order
+    let source = "
+        event fn heal(amount: int) {}
+
+        event fn damage(amount: int) {
+            if health <= amount * 2 {
+                trigger Died;
+            }
+
+            health = health - amount * 2;
+            trigger Hit(amount * 2);
+        }
+    ";
+
+    let mut script = runtime::compile::<Enemy>("runtime.tapir", source)
+        .unwrap()
+        .script(enemy());
+
+    script.on_heal(5);
+    assert_eq!(script.run(), &[]);
+    assert_eq!(script.properties.health, 10);
+
+    script.on_damage(3);
+    assert_eq!(script.run(), &[Event::Hit(6)]);
+
+    script.on_damage(3);
+    assert_eq!(script.run(), &[Event::Died, Event::Hit(6)]);
+    assert_eq!(script.properties.health, -2);
+}
+
+#[test]
+fn events_without_a_handler_do_nothing() {
+    let mut script = runtime::compile::<Enemy>("runtime.tapir", "health = frame + 3;")
+        .unwrap()
+        .script(enemy());
+
+    script.on_damage(3);
+    assert_eq!(script.run(), &[]);
+    assert_eq!(script.properties.health, 3);
+}
+
+#[test]
+fn unknown_triggers_are_rejected() {
+    let result = runtime::compile::<Enemy>("runtime.tapir", "trigger Exploded;");
+
+    assert!(matches!(
+        result,
+        Err(LoadError::UnknownTrigger { name }) if name == "Exploded"
+    ));
+}
+
+#[test]
+fn triggers_must_have_the_same_arguments() {
+    let result = runtime::compile::<Enemy>("runtime.tapir", "trigger Hit(1.5);");
+
+    let Err(LoadError::TriggerArgumentsMismatch {
+        name,
+        expected,
+        actual,
+    }) = result
+    else {
+        panic!("Expected an argument mismatch");
+    };
+
+    assert_eq!(name, "Hit");
+    assert_eq!(expected, [PropertyType::Int]);
+    assert_eq!(actual, [PropertyType::Fix]);
+}
+
+#[test]
+fn triggers_must_name_the_same_fields() {
+    let result = runtime::compile::<Enemy>("runtime.tapir", "trigger Hit { amount: 1 };");
+
+    let Err(LoadError::TriggerFieldsMismatch {
+        name,
+        expected,
+        actual,
+    }) = result
+    else {
+        panic!("Expected a field mismatch");
+    };
+
+    assert_eq!(name, "Hit");
+    assert_eq!(expected, None);
+    assert_eq!(actual, Some(vec!["amount".to_string()]));
+}
+
+#[test]
+fn unknown_events_are_rejected() {
+    let result = runtime::compile::<Enemy>("runtime.tapir", "event fn explode() {}");
+
+    assert!(matches!(
+        result,
+        Err(LoadError::UnknownEvent { name }) if name == "explode"
+    ));
+}
+
+#[test]
+fn events_must_have_the_same_arguments() {
+    let result = runtime::compile::<Enemy>("runtime.tapir", "event fn heal(amount: fix) {}");
+
+    assert!(matches!(
+        result,
+        Err(LoadError::EventArgumentsMismatch { name, .. }) if name == "heal"
+    ));
+}
+
+#[test]
+fn property_access_is_checked() {
+    let result = runtime::compile::<Enemy>("runtime.tapir", "frame = 5;");
+
+    let Err(error @ LoadError::Compile(_)) = result else {
+        panic!("Expected a compile error");
+    };
+
+    assert!(error
+        .to_string()
+        .contains("Cannot assign to read only property 'frame'"));
+}
+
+#[test]
+fn unknown_variables_are_compile_errors() {
+    let result = runtime::compile::<Enemy>("runtime.tapir", "health = zzz;");
+
+    assert!(matches!(result, Err(LoadError::Compile(_))));
+}
+
+#[test]
+fn events_must_have_the_same_return_types() {
+    let result = runtime::compile::<Enemy>(
+        "runtime.tapir",
+        "event fn heal(amount: int) -> bool { return true; }",
+    );
+
+    let Err(LoadError::EventReturnsMismatch {
+        name,
+        expected,
+        actual,
+    }) = result
+    else {
+        panic!("Expected a return type mismatch");
+    };
+
+    assert_eq!(name, "heal");
+    assert_eq!(expected, []);
+    assert_eq!(actual, [PropertyType::Bool]);
+}
+
+#[test]
+fn unknown_messages_are_rejected() {
+    let result = runtime::compile::<Enemy>("runtime.tapir", "send 1, explode();");
+
+    assert!(matches!(
+        result,
+        Err(LoadError::UnknownMessage { name }) if name == "explode"
+    ));
+}
+
+#[test]
+fn messages_must_have_the_same_arguments() {
+    let result = runtime::compile::<Enemy>("runtime.tapir", "send 1, heal(1.5);");
+
+    let Err(LoadError::MessageArgumentsMismatch {
+        name,
+        expected,
+        actual,
+    }) = result
+    else {
+        panic!("Expected an argument mismatch");
+    };
+
+    assert_eq!(name, "heal");
+    assert_eq!(expected, [PropertyType::Int]);
+    assert_eq!(actual, [PropertyType::Fix]);
+}
+
+#[test]
+fn runtime_scripts_can_send_messages_to_other_scripts() {
+    let mut world = ScriptWorld::new();
+    world.insert(1, enemy().script());
+    world.insert(
+        2,
+        runtime::compile::<Enemy>("runtime.tapir", "send 1, damage(4);")
+            .unwrap()
+            .script(enemy()),
+    );
+
+    assert_eq!(world.run(), &[]);
+    assert_eq!(world.run(), &[(1, Event::Hit(4))]);
+    assert_eq!(world.get(1).unwrap().properties.health, 6);
+    assert_eq!(world.get(2).unwrap().properties.health, 10);
+}
+
+#[test]
+fn runtime_scripts_can_have_generators() {
+    let mut script = runtime::compile::<Enemy>(
+        "runtime.tapir",
+        "generator fn patrol(steps: int) -> int { yield steps; yield steps * 2; }",
+    )
+    .unwrap()
+    .script(enemy());
+
+    assert_eq!(script.iter_patrol(3).collect::<Vec<_>>(), [3, 6]);
+}
+
+#[test]
+fn generators_must_yield_the_same_type() {
+    let result = runtime::compile::<Enemy>(
+        "runtime.tapir",
+        "generator fn patrol(steps: int) -> bool { yield true; }",
+    );
+
+    let Err(LoadError::EventYieldsMismatch {
+        name,
+        expected,
+        actual,
+    }) = result
+    else {
+        panic!("Expected a yield type mismatch");
+    };
+
+    assert_eq!(name, "patrol");
+    assert_eq!(expected, Some(PropertyType::Int));
+    assert_eq!(actual, Some(PropertyType::Bool));
+}
+
+#[test]
+fn generators_cant_be_event_handlers() {
+    let result = runtime::compile::<Enemy>("runtime.tapir", "event fn patrol(steps: int) {}");
+
+    assert!(matches!(
+        result,
+        Err(LoadError::EventYieldsMismatch { name, expected: Some(PropertyType::Int), actual: None })
+            if name == "patrol"
+    ));
+}
+
+#[test]
+fn messages_cant_be_sent_to_generators() {
+    let result = runtime::compile::<Enemy>("runtime.tapir", "send 1, patrol(2);");
+
+    assert!(matches!(
+        result,
+        Err(LoadError::MessageToGenerator { name }) if name == "patrol"
+    ));
+}
+
+#[test]
+fn generators_the_script_doesnt_have_yield_nothing() {
+    let mut script = runtime::compile::<Enemy>("runtime.tapir", "event fn heal(amount: int) {}")
+        .unwrap()
+        .script(enemy());
+
+    assert_eq!(script.iter_patrol(3).count(), 0);
+}
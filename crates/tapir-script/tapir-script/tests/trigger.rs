@@ -1,22 +1,31 @@
 #![deny(unfulfilled_lint_expectations)]
 
 use agb_fixnum::num;
-use tapir_script::{Fix, TapirScript};
+use tapir_script::{Fix, TapirScript, TapirTrigger};
 
 #[derive(TapirScript)]
 #[tapir("tests/trigger.tapir", trigger_type = MyEventType)]
 struct Triggers;
 
-#[derive(PartialEq, Eq, Debug)]
+#[derive(PartialEq, Eq, Debug, TapirTrigger)]
 enum MyEventType {
     IntKind(i32),
     FixKind(Fix),
     BoolKind(bool),
     TwoArguments(i32, i32),
     EmptyKind,
+    Hit {
+        damage: i32,
+        critical: bool,
+        knockback: Fix,
+    },
 
     #[expect(dead_code, reason = "This isn't referenced in the trigger.tapir code")]
     UnusedEventType,
+
+    #[tapir(skip)]
+    #[expect(dead_code, reason = "Skipped variants can't be triggered by scripts")]
+    Message(String),
 }
 
 #[test]
@@ -37,6 +46,11 @@ fn triggers() {
             IntKind(5),
         ],
         &[TwoArguments(5, 7)],
+        &[Hit {
+            damage: 3,
+            critical: true,
+            knockback: num!(1.5),
+        }],
     ];
 
     let mut script = Triggers.script();
@@ -0,0 +1,20 @@
+use tapir_script::TapirScript;
+
+#[derive(TapirScript)]
+#[tapir(
+    "tests/warnings.tapir",
+    allow(unused_variable, unused_function),
+    deny(shadowing, unreachable_code)
+)]
+struct AllowedWarnings {
+    #[tapir(int)]
+    output: i32,
+}
+
+#[test]
+fn allowed_warnings_still_compile() {
+    let mut script = AllowedWarnings { output: 0 }.script();
+    script.run();
+
+    assert_eq!(script.properties.output, 3);
+}
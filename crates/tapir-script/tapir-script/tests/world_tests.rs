@@ -0,0 +1,96 @@
+use tapir_script::{Script, ScriptWorld, TapirScript, TapirTrigger};
+
+#[derive(TapirScript)]
+#[tapir("tests/world.tapir", trigger_type = Event)]
+struct Unit {
+    #[tapir(readonly)]
+    id: i32,
+    #[tapir(readonly)]
+    leader: i32,
+    health: i32,
+}
+
+#[derive(PartialEq, Eq, Debug, TapirTrigger)]
+enum Event {
+    Reported(i32),
+}
+
+fn unit(id: i32, leader: i32) -> Script<Unit> {
+    Unit {
+        id,
+        leader,
+        health: 5,
+    }
+    .script()
+}
+
+fn world(ids: &[i32], leader: i32) -> ScriptWorld<Unit> {
+    let mut world = ScriptWorld::new();
+    for &id in ids {
+        world.insert(id, unit(id, leader));
+    }
+
+    world
+}
+
+#[test]
+fn messages_are_delivered_at_the_next_run_in_sender_order() {
+    // delivering the messages is the same as calling the event handlers from rust in the order
+    // of the sender's id
+    let mut leader = unit(0, 0);
+    leader.run();
+    leader.on_report(1);
+    leader.on_report(2);
+    leader.on_report(3);
+    let expected = leader
+        .run()
+        .into_iter()
+        .map(|event| (0, event))
+        .collect::<Vec<_>>();
+
+    // the order the scripts are inserted doesn't matter
+    for ids in [[0, 1, 2, 3], [3, 0, 1, 2], [2, 3, 1, 0]] {
+        let mut world = world(&ids, 0);
+
+        assert_eq!(world.run(), &[]);
+        assert_eq!(world.run(), expected);
+
+        assert!(!world.will_calling_run_do_anything());
+        assert_eq!(world.run(), &[]);
+    }
+}
+
+#[test]
+fn messages_sent_from_rust_events_are_delivered() {
+    let mut world = world(&[0, 1], 0);
+    world.run();
+    world.run();
+
+    world.get_mut(1).unwrap().on_attack(10);
+
+    // the event handler sends the message when it runs, so it arrives the run after
+    assert_eq!(world.run(), &[]);
+    assert!(world.will_calling_run_do_anything());
+    assert_eq!(world.run(), &[(0, Event::Reported(1))]);
+}
+
+#[test]
+fn messages_to_unknown_scripts_are_dropped() {
+    let mut world = world(&[1, 2], 7);
+
+    assert_eq!(world.run(), &[]);
+    assert_eq!(world.run(), &[]);
+    assert!(!world.will_calling_run_do_anything());
+}
+
+#[test]
+fn removed_scripts_stop_receiving_messages() {
+    let mut world = world(&[0, 1, 2], 0);
+    world.run();
+
+    let leader = world.remove(0).unwrap();
+    assert_eq!(leader.properties.id, 0);
+
+    assert_eq!(world.run(), &[]);
+    assert_eq!(world.len(), 2);
+}
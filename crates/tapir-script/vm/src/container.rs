@@ -0,0 +1,573 @@
+//! Loading scripts which were compiled ahead of time into the container format described in
+//! [`bytecode::container`]. Everything is checked as it is read, so a file from a different
+//! version of tapir-script, or one which is corrupt, fails with a [`ContainerError`] rather than
+//! misbehaving when it is run.
+
+use alloc::vec::Vec;
+use core::fmt;
+
+use bytecode::container::{FLAG_DEBUG, FORMAT_VERSION, MAGIC, UNBOUNDED_STACK_DEPTH};
+
+pub use bytecode::container::{PropertyAccess, ValueType};
+
+/// A script read from a container. This still needs matching up with the struct it will run
+/// with before it can become a [`Program`](crate::Program).
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Container<'a> {
+    pub fix_precision: u8,
+    pub bytecode: Vec<u16>,
+    /// Sorted by `bytecode_offset`
+    pub stack_depths: Vec<StackDepth>,
+    /// The properties the script was compiled against
+    pub properties: Vec<Property<'a>>,
+    pub event_handlers: Vec<EventHandler<'a>>,
+    /// In the order the bytecode refers to them
+    pub triggers: Vec<Trigger<'a>>,
+    /// In the order the bytecode refers to them
+    pub messages: Vec<Message<'a>>,
+    /// Only there if the script was written with debug info
+    pub functions: Option<Vec<Function<'a>>>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct StackDepth {
+    pub bytecode_offset: usize,
+    /// `None` for recursive functions, which could need any amount of stack
+    pub max_depth: Option<usize>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Property<'a> {
+    pub name: &'a str,
+    pub index: u8,
+    pub ty: ValueType,
+    pub access: PropertyAccess,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct EventHandler<'a> {
+    pub name: &'a str,
+    pub bytecode_offset: usize,
+    pub arguments: Vec<ValueType>,
+    pub returns: Vec<ValueType>,
+    /// The type of the values a generator yields, or `None` for an event handler
+    pub yields: Option<ValueType>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Trigger<'a> {
+    pub name: &'a str,
+    pub arguments: Vec<ValueType>,
+    /// The name of each argument, if the trigger was called like `trigger Hit { damage: 5 };`
+    pub field_names: Option<Vec<&'a str>>,
+}
+
+/// A message the script sends to other scripts, which is delivered to the event handler with the
+/// same name
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Message<'a> {
+    pub name: &'a str,
+    pub arguments: Vec<ValueType>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Function<'a> {
+    pub name: &'a str,
+    pub bytecode_offset: usize,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ContainerError {
+    /// This isn't a tapir-script container at all
+    BadMagic,
+    /// The container was written by a different version of tapir-script
+    UnsupportedVersion {
+        expected: u16,
+        actual: u16,
+    },
+    /// The bytecode was compiled for a different instruction set, so can't be run by this vm
+    IsaMismatch {
+        expected: u16,
+        actual: u16,
+    },
+    /// The data ended part way through the container
+    Truncated,
+    /// A name wasn't valid UTF-8
+    InvalidName,
+    InvalidType(u8),
+    InvalidAccess(u8),
+    /// An event handler has more than the one yield type a generator can have
+    InvalidYieldTypes(usize),
+    /// Something points outside of the bytecode
+    OffsetOutOfRange {
+        offset: usize,
+        length: usize,
+    },
+    /// There is more data after the end of the container
+    TrailingData,
+}
+
+impl fmt::Display for ContainerError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ContainerError::BadMagic => write!(f, "Not a tapir-script container"),
+            ContainerError::UnsupportedVersion { expected, actual } => write!(
+                f,
+                "Container is version {actual}, but only version {expected} is supported"
+            ),
+            ContainerError::IsaMismatch { expected, actual } => write!(
+                f,
+                "Bytecode was compiled for instruction set revision {actual}, but this vm runs revision {expected}"
+            ),
+            ContainerError::Truncated => write!(f, "Container is truncated"),
+            ContainerError::InvalidName => write!(f, "Container has a name which isn't UTF-8"),
+            ContainerError::InvalidType(ty) => write!(f, "Container has an unknown type {ty}"),
+            ContainerError::InvalidAccess(access) => {
+                write!(f, "Container has an unknown property access {access}")
+            }
+            ContainerError::InvalidYieldTypes(count) => write!(
+                f,
+                "Container has a generator which yields {count} types rather than one"
+            ),
+            ContainerError::OffsetOutOfRange { offset, length } => write!(
+                f,
+                "Offset {offset} is outside of the bytecode, which is {length} long"
+            ),
+            ContainerError::TrailingData => write!(f, "Unexpected data after the container"),
+        }
+    }
+}
+
+impl core::error::Error for ContainerError {}
+
+/// Reads a container written by the compiler's `CompileResult::to_container`
+pub fn load(bytes: &[u8]) -> Result<Container<'_>, ContainerError> {
+    let mut reader = Reader { bytes };
+
+    if reader.take(MAGIC.len())? != MAGIC {
+        return Err(ContainerError::BadMagic);
+    }
+
+    let version = reader.u16()?;
+    if version != FORMAT_VERSION {
+        return Err(ContainerError::UnsupportedVersion {
+            expected: FORMAT_VERSION,
+            actual: version,
+        });
+    }
+
+    let isa_revision = reader.u16()?;
+    if isa_revision != bytecode::ISA_REVISION {
+        return Err(ContainerError::IsaMismatch {
+            expected: bytecode::ISA_REVISION,
+            actual: isa_revision,
+        });
+    }
+
+    let fix_precision = reader.u8()?;
+    let flags = reader.u8()?;
+
+    let bytecode_length = reader.u32()?;
+    let bytecode = reader
+        .take(
+            bytecode_length
+                .checked_mul(2)
+                .ok_or(ContainerError::Truncated)?,
+        )?
+        .chunks_exact(2)
+        .map(|word| u16::from_le_bytes([word[0], word[1]]))
+        .collect::<Vec<_>>();
+
+    let offset = |offset: usize| {
+        if offset < bytecode_length {
+            Ok(offset)
+        } else {
+            Err(ContainerError::OffsetOutOfRange {
+                offset,
+                length: bytecode_length,
+            })
+        }
+    };
+
+    let mut stack_depths = reader.list(|reader| {
+        Ok(StackDepth {
+            bytecode_offset: offset(reader.u32()?)?,
+            max_depth: Some(reader.u32()?)
+                .filter(|&max_depth| max_depth != UNBOUNDED_STACK_DEPTH as usize),
+        })
+    })?;
+    stack_depths.sort_by_key(|stack_depth| stack_depth.bytecode_offset);
+
+    let properties = reader.list(|reader| {
+        Ok(Property {
+            name: reader.string()?,
+            index: reader.u8()?,
+            ty: reader.value_type()?,
+            access: reader.property_access()?,
+        })
+    })?;
+
+    let event_handlers = reader.list(|reader| {
+        Ok(EventHandler {
+            name: reader.string()?,
+            bytecode_offset: offset(reader.u32()?)?,
+            arguments: reader.types()?,
+            returns: reader.types()?,
+            yields: match *reader.types()? {
+                [] => None,
+                [ty] => Some(ty),
+                ref types => return Err(ContainerError::InvalidYieldTypes(types.len())),
+            },
+        })
+    })?;
+
+    let triggers = reader.list(|reader| {
+        let name = reader.string()?;
+        let arguments = reader.types()?;
+        let field_names = if reader.u8()? != 0 {
+            Some(
+                arguments
+                    .iter()
+                    .map(|_| reader.string())
+                    .collect::<Result<_, _>>()?,
+            )
+        } else {
+            None
+        };
+
+        Ok(Trigger {
+            name,
+            arguments,
+            field_names,
+        })
+    })?;
+
+    let messages = reader.list(|reader| {
+        Ok(Message {
+            name: reader.string()?,
+            arguments: reader.types()?,
+        })
+    })?;
+
+    let functions = if flags & FLAG_DEBUG != 0 {
+        Some(reader.list(|reader| {
+            Ok(Function {
+                name: reader.string()?,
+                bytecode_offset: offset(reader.u32()?)?,
+            })
+        })?)
+    } else {
+        None
+    };
+
+    if !reader.bytes.is_empty() {
+        return Err(ContainerError::TrailingData);
+    }
+
+    Ok(Container {
+        fix_precision,
+        bytecode,
+        stack_depths,
+        properties,
+        event_handlers,
+        triggers,
+        messages,
+        functions,
+    })
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, length: usize) -> Result<&'a [u8], ContainerError> {
+        if self.bytes.len() < length {
+            return Err(ContainerError::Truncated);
+        }
+
+        let (taken, rest) = self.bytes.split_at(length);
+        self.bytes = rest;
+        Ok(taken)
+    }
+
+    fn u8(&mut self) -> Result<u8, ContainerError> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u16(&mut self) -> Result<u16, ContainerError> {
+        let bytes = self.take(2)?;
+        Ok(u16::from_le_bytes([bytes[0], bytes[1]]))
+    }
+
+    fn u32(&mut self) -> Result<usize, ContainerError> {
+        let bytes = self.take(4)?;
+        Ok(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]) as usize)
+    }
+
+    fn string(&mut self) -> Result<&'a str, ContainerError> {
+        let length = self.u16()? as usize;
+        core::str::from_utf8(self.take(length)?).map_err(|_| ContainerError::InvalidName)
+    }
+
+    fn value_type(&mut self) -> Result<ValueType, ContainerError> {
+        let ty = self.u8()?;
+        ValueType::n(ty).ok_or(ContainerError::InvalidType(ty))
+    }
+
+    fn property_access(&mut self) -> Result<PropertyAccess, ContainerError> {
+        let access = self.u8()?;
+        PropertyAccess::n(access).ok_or(ContainerError::InvalidAccess(access))
+    }
+
+    fn types(&mut self) -> Result<Vec<ValueType>, ContainerError> {
+        let count = self.u8()?;
+        (0..count).map(|_| self.value_type()).collect()
+    }
+
+    /// Reads a `u16` count followed by that many items
+    fn list<T>(
+        &mut self,
+        mut item: impl FnMut(&mut Self) -> Result<T, ContainerError>,
+    ) -> Result<Vec<T>, ContainerError> {
+        let count = self.u16()?;
+        // not using with_capacity, since a corrupt count shouldn't cause a huge allocation
+        let mut items = Vec::new();
+        for _ in 0..count {
+            items.push(item(self)?);
+        }
+        Ok(items)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use alloc::{string::ToString, vec};
+
+    use compiler::{
+        CompileSettings, Property as CompilerProperty, PropertyAccess as CompilerPropertyAccess,
+        Type,
+    };
+
+    use super::*;
+
+    const SCRIPT: &str = r#"
+        event fn on_hit(damage: int, critical: bool) {
+            health = health - damage;
+            trigger hurt(damage);
+        }
+
+        event fn should_flee() -> bool {
+            return health < 3;
+        }
+
+        generator fn escape_route() -> fix {
+            yield 1.5;
+            yield 2.5;
+        }
+
+        fn countdown(n: int) {
+            if n > 0 {
+                countdown(n - 1);
+            }
+        }
+
+        countdown(3);
+        trigger fled { distance: 3, hiding: true };
+        send 2, on_hit(1, false);
+        wait;
+    "#;
+
+    fn compile() -> compiler::CompileResult {
+        compiler::compile(
+            "container.tapir",
+            SCRIPT,
+            CompileSettings {
+                properties: vec![CompilerProperty {
+                    ty: Type::Int,
+                    index: 0,
+                    name: "health".to_string(),
+                    access: CompilerPropertyAccess::ReadWrite,
+                }],
+                enable_optimisations: true,
+                fix_precision: 4,
+            },
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn round_trips() {
+        let compiled = compile();
+        let bytes = compiled.to_container(false);
+        let container = load(&bytes).unwrap();
+
+        assert_eq!(container.fix_precision, 4);
+        assert_eq!(container.bytecode, compiled.bytecode);
+        assert_eq!(
+            container.stack_depths,
+            compiled
+                .stack_depths
+                .iter()
+                .map(|stack_depth| StackDepth {
+                    bytecode_offset: stack_depth.bytecode_offset,
+                    max_depth: stack_depth.max_depth,
+                })
+                .collect::<Vec<_>>()
+        );
+        assert_eq!(
+            container.properties,
+            [Property {
+                name: "health",
+                index: 0,
+                ty: ValueType::Int,
+                access: PropertyAccess::ReadWrite,
+            }]
+        );
+        assert_eq!(
+            container.event_handlers,
+            [
+                EventHandler {
+                    name: "on_hit",
+                    bytecode_offset: compiled.event_handlers[0].bytecode_offset,
+                    arguments: vec![ValueType::Int, ValueType::Bool],
+                    returns: vec![],
+                    yields: None,
+                },
+                EventHandler {
+                    name: "should_flee",
+                    bytecode_offset: compiled.event_handlers[1].bytecode_offset,
+                    arguments: vec![],
+                    returns: vec![ValueType::Bool],
+                    yields: None,
+                },
+                EventHandler {
+                    name: "escape_route",
+                    bytecode_offset: compiled.event_handlers[2].bytecode_offset,
+                    arguments: vec![],
+                    returns: vec![],
+                    yields: Some(ValueType::Fix),
+                }
+            ]
+        );
+        assert_eq!(
+            container.triggers,
+            [
+                Trigger {
+                    name: "fled",
+                    arguments: vec![ValueType::Int, ValueType::Bool],
+                    field_names: Some(vec!["distance", "hiding"]),
+                },
+                Trigger {
+                    name: "hurt",
+                    arguments: vec![ValueType::Int],
+                    field_names: None,
+                }
+            ]
+        );
+        assert_eq!(
+            container.messages,
+            [Message {
+                name: "on_hit",
+                arguments: vec![ValueType::Int, ValueType::Bool],
+            }]
+        );
+        assert_eq!(container.functions, None);
+    }
+
+    #[test]
+    fn round_trips_debug_info() {
+        let compiled = compile();
+        let bytes = compiled.to_container(true);
+        let container = load(&bytes).unwrap();
+
+        let functions = container.functions.unwrap();
+        let names = functions
+            .iter()
+            .map(|function| function.name)
+            .collect::<Vec<_>>();
+        assert_eq!(
+            names,
+            [
+                "@toplevel",
+                "on_hit",
+                "should_flee",
+                "escape_route",
+                "countdown"
+            ]
+        );
+        assert_eq!(
+            functions[1].bytecode_offset,
+            compiled.event_handlers[0].bytecode_offset
+        );
+    }
+
+    #[test]
+    fn rejects_other_files() {
+        assert_eq!(load(b"\x89PNG\r\n\x1a\n"), Err(ContainerError::BadMagic));
+    }
+
+    #[test]
+    fn rejects_other_versions() {
+        let mut bytes = compile().to_container(false);
+        bytes[4..6].copy_from_slice(&(FORMAT_VERSION + 1).to_le_bytes());
+
+        assert_eq!(
+            load(&bytes),
+            Err(ContainerError::UnsupportedVersion {
+                expected: FORMAT_VERSION,
+                actual: FORMAT_VERSION + 1
+            })
+        );
+    }
+
+    #[test]
+    fn rejects_other_instruction_sets() {
+        let mut bytes = compile().to_container(false);
+        bytes[6..8].copy_from_slice(&(bytecode::ISA_REVISION + 1).to_le_bytes());
+
+        assert_eq!(
+            load(&bytes),
+            Err(ContainerError::IsaMismatch {
+                expected: bytecode::ISA_REVISION,
+                actual: bytecode::ISA_REVISION + 1
+            })
+        );
+    }
+
+    #[test]
+    fn rejects_truncated_containers() {
+        let bytes = compile().to_container(true);
+
+        for length in 0..bytes.len() {
+            assert!(load(&bytes[..length]).is_err(), "loaded {length} bytes");
+        }
+    }
+
+    #[test]
+    fn rejects_trailing_data() {
+        let mut bytes = compile().to_container(false);
+        bytes.push(0);
+
+        assert_eq!(load(&bytes), Err(ContainerError::TrailingData));
+    }
+
+    #[test]
+    fn rejects_out_of_range_offsets() {
+        let compiled = compile();
+        let mut bytes = compiled.to_container(false);
+
+        // the first stack depth's offset comes straight after its count
+        let stack_depths_start = 10 + 4 + compiled.bytecode.len() * 2;
+        let offset = stack_depths_start + 2;
+        bytes[offset..offset + 4].copy_from_slice(&u32::MAX.to_le_bytes());
+
+        assert_eq!(
+            load(&bytes),
+            Err(ContainerError::OffsetOutOfRange {
+                offset: u32::MAX as usize,
+                length: compiled.bytecode.len()
+            })
+        );
+    }
+}
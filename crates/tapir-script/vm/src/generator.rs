@@ -0,0 +1,60 @@
+//! Running a `generator fn` one `yield` at a time, so rust can read the values it produces.
+
+use core::iter::FusedIterator;
+
+use crate::{
+    state::{ObjectSafePropertiesImpl, RunResult, State},
+    Script, TapirScript, VmStorage,
+};
+
+/// The values yielded by a generator. The generator runs up to its next `yield` each time a value
+/// is asked for, outside of the script's other threads, so nothing else in the script runs while
+/// it does. Anything it spawns runs with the other threads at the next
+/// [`run`](Script::run), which is also when the events it triggers are passed on.
+///
+/// The values are the raw `i32`s the script works with. The `iter_` methods generated by the
+/// derive macro convert them to their rust types.
+///
+/// The generator finishes when it reaches the end of its function or a `return`. Reaching a
+/// `wait` in a function it calls also finishes it, since there is nothing for it to wait for.
+/// Dropping this before the generator finishes stops it where it is.
+pub struct Generator<'a, T: TapirScript> {
+    script: &'a mut Script<T>,
+    /// `None` once the generator has finished
+    state: Option<State<<T::Storage as VmStorage>::Stack>>,
+}
+
+impl<'a, T: TapirScript> Generator<'a, T> {
+    pub(crate) fn new(
+        script: &'a mut Script<T>,
+        state: Option<State<<T::Storage as VmStorage>::Stack>>,
+    ) -> Self {
+        Self { script, state }
+    }
+}
+
+impl<T: TapirScript> Iterator for Generator<'_, T> {
+    type Item = i32;
+
+    fn next(&mut self) -> Option<i32> {
+        let state = self.state.as_mut()?;
+
+        let script = &mut *self.script;
+        let pending_events = &mut script.pending_events;
+        let mut object_safe_props = ObjectSafePropertiesImpl {
+            properties: &mut script.properties,
+            on_event: |event| pending_events.push(event),
+            outbox: &mut script.outbox,
+        };
+
+        match script.vm.resume(state, &mut object_safe_props) {
+            RunResult::Yielded(value) => Some(value),
+            _ => {
+                self.state = None;
+                None
+            }
+        }
+    }
+}
+
+impl<T: TapirScript> FusedIterator for Generator<'_, T> {}
@@ -0,0 +1,136 @@
+//! Groups of threads which can be paused or slowed down separately from the rest of the script,
+//! so that for example gameplay can stop behind a pause menu while the menu's threads keep running.
+
+use crate::Storage;
+
+/// A change to how the threads in a group run, from either rust or `pause`, `resume` and
+/// `timescale` in the script
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum GroupControl {
+    Pause,
+    Resume,
+    /// Only run once every this many frames. 0 is treated as 1.
+    TimeScale(u32),
+}
+
+/// A group which has been paused or slowed down. Groups which run every frame aren't stored.
+#[derive(Debug, Default)]
+pub(crate) struct Group {
+    id: i32,
+    paused: bool,
+    /// The group runs once every this many frames
+    frames: u32,
+    /// How many more frames until the group next runs
+    countdown: u32,
+    /// Worked out at the start of each run, so changes made during a run only take effect from
+    /// the next one, whichever order the threads happen to run in
+    skip_this_frame: bool,
+}
+
+/// Why a group couldn't be paused or slowed down
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum GroupError {
+    /// The script uses [`Fixed`](crate::Fixed) storage, and as many groups as it has room for
+    /// are already paused or slowed down
+    TooManyGroups,
+}
+
+impl core::fmt::Display for GroupError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            GroupError::TooManyGroups => {
+                write!(f, "Too many groups are already paused or slowed down")
+            }
+        }
+    }
+}
+
+impl core::error::Error for GroupError {}
+
+pub(crate) struct Groups<G> {
+    groups: G,
+}
+
+impl<G: Storage<Group>> Groups<G> {
+    pub(crate) fn new() -> Self {
+        Self {
+            groups: G::with_capacity(0),
+        }
+    }
+
+    /// Decides which groups run this frame
+    pub(crate) fn start_frame(&mut self) {
+        let mut index = 0;
+        while index < self.groups.len() {
+            let group = &mut self.groups[index];
+
+            if !group.paused && group.frames == 1 {
+                // back to running every frame, so there's nothing to keep track of
+                self.groups.swap_remove(index);
+                continue;
+            }
+
+            group.skip_this_frame = group.paused || group.countdown > 0;
+            if !group.paused {
+                group.countdown = group.countdown.checked_sub(1).unwrap_or(group.frames - 1);
+            }
+
+            index += 1;
+        }
+    }
+
+    pub(crate) fn runs_this_frame(&self, id: i32) -> bool {
+        self.get(id).is_none_or(|group| !group.skip_this_frame)
+    }
+
+    /// Fails if `id` isn't already paused or slowed down and there is no room to start keeping
+    /// track of it. Controls which leave a group running every frame never need room.
+    pub(crate) fn control(&mut self, id: i32, control: GroupControl) -> Result<(), GroupError> {
+        let index = match self.groups.iter().position(|group| group.id == id) {
+            Some(index) => index,
+            None => {
+                if matches!(
+                    control,
+                    GroupControl::Resume | GroupControl::TimeScale(0 | 1)
+                ) {
+                    // the group already runs every frame, so there's nothing to change
+                    return Ok(());
+                }
+
+                self.groups
+                    .try_push(Group {
+                        id,
+                        frames: 1,
+                        ..Group::default()
+                    })
+                    .map_err(|_| GroupError::TooManyGroups)?;
+                self.groups.len() - 1
+            }
+        };
+
+        let group = &mut self.groups[index];
+        match control {
+            GroupControl::Pause => group.paused = true,
+            GroupControl::Resume => group.paused = false,
+            GroupControl::TimeScale(frames) => {
+                group.frames = frames.max(1);
+                // run on the next frame, and then every `frames` frames after that
+                group.countdown = 0;
+            }
+        }
+
+        Ok(())
+    }
+
+    pub(crate) fn is_paused(&self, id: i32) -> bool {
+        self.get(id).is_some_and(|group| group.paused)
+    }
+
+    pub(crate) fn time_scale(&self, id: i32) -> u32 {
+        self.get(id).map_or(1, |group| group.frames)
+    }
+
+    fn get(&self, id: i32) -> Option<&Group> {
+        self.groups.iter().find(|group| group.id == id)
+    }
+}
@@ -2,49 +2,322 @@
 #![no_std]
 extern crate alloc;
 
+pub mod container;
+mod generator;
+mod group;
 mod state;
+mod storage;
+pub mod verify;
+mod world;
 
-use alloc::{vec, vec::Vec};
-use state::{ObjectSafeProperties, ObjectSafePropertiesImpl, State};
+use alloc::{borrow::Cow, vec, vec::Vec};
+use group::{Group, GroupControl, Groups};
+use state::{KeepProperties, ObjectSafeProperties, ObjectSafePropertiesImpl, State};
 
-struct Vm<'a> {
-    bytecode: &'a [u16],
-    states: Vec<State>,
+pub use generator::Generator;
+pub use group::GroupError;
+pub use storage::{Fixed, FixedVec, Growable, Storage, VmStorage};
+pub use world::ScriptWorld;
+
+/// The most stack space the function starting at `bytecode_offset` could need, including
+/// everything it calls. Recursive functions have no limit, so don't get one of these.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct StackDepth {
+    pub bytecode_offset: usize,
+    pub max_depth: usize,
+}
+
+/// Some bytecode along with what's needed to run it. The derive macro builds these from the
+/// bytecode it compiles in, but they can also be built at runtime from scripts which weren't
+/// available when the game was built. Either way, they must become a [`VerifiedProgram`] to run.
+#[derive(Clone, Debug)]
+pub struct Program<'a> {
+    pub bytecode: Cow<'a, [u16]>,
+    /// Sorted by `bytecode_offset`
+    pub stack_depths: Cow<'a, [StackDepth]>,
+    /// Where each of the script's event handlers starts, in the same order as the events in the
+    /// `TapirScript` implementation. `None` if this program doesn't handle that event.
+    pub event_handlers: Cow<'a, [Option<usize>]>,
+    /// Which of the `TapirScript`'s triggers each trigger in the bytecode refers to, or `None` if
+    /// they already match. They always match for programs built by the derive macro.
+    pub triggers: Option<Cow<'a, [u8]>>,
+    /// Which of the `TapirScript`'s events each message in the bytecode is delivered to
+    pub messages: Cow<'a, [usize]>,
 }
 
-impl<'a> Vm<'a> {
-    pub fn new(bytecode: &'a [u16]) -> Self {
+impl<'a> Program<'a> {
+    pub const fn new(
+        bytecode: &'a [u16],
+        stack_depths: &'a [StackDepth],
+        event_handlers: &'a [Option<usize>],
+        messages: &'a [usize],
+    ) -> Self {
         Self {
-            bytecode,
-            states: vec![State::new(0, vec![])],
+            bytecode: Cow::Borrowed(bytecode),
+            stack_depths: Cow::Borrowed(stack_depths),
+            event_handlers: Cow::Borrowed(event_handlers),
+            triggers: None,
+            messages: Cow::Borrowed(messages),
+        }
+    }
+
+    fn trigger_index(&self, trigger: u8) -> u8 {
+        match &self.triggers {
+            Some(triggers) => triggers[trigger as usize],
+            None => trigger,
+        }
+    }
+}
+
+/// A [`Program`] which has passed [`verify::verify`], which is the only kind a [`Script`] runs.
+/// The interpreter relies on this to skip its own checks on jumps, stack offsets and returns.
+#[derive(Clone, Debug)]
+pub struct VerifiedProgram<'a> {
+    program: Program<'a>,
+}
+
+impl<'a> VerifiedProgram<'a> {
+    /// Checks that `program` is safe to run with something which provides `interface`
+    pub fn verify(
+        program: Program<'a>,
+        interface: &verify::Interface,
+    ) -> Result<Self, verify::VerifyError> {
+        verify::verify(&program, interface)?;
+        Ok(Self { program })
+    }
+
+    /// Skips verifying `program`. The derive macro uses this for the bytecode it compiles, since
+    /// it comes straight from the compiler.
+    ///
+    /// # Safety
+    ///
+    /// `program` must pass [`verify::verify`] with the interface of the [`TapirScript`] it is run
+    /// with.
+    pub const unsafe fn new_unchecked(program: Program<'a>) -> Self {
+        Self { program }
+    }
+
+    pub fn into_program(self) -> Program<'a> {
+        self.program
+    }
+}
+
+impl<'a> core::ops::Deref for VerifiedProgram<'a> {
+    type Target = Program<'a>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.program
+    }
+}
+
+struct Vm<'a, S: VmStorage> {
+    program: VerifiedProgram<'a>,
+    fix_precision: usize,
+    states: S::Threads<State<S::Stack>>,
+    groups: Groups<S::Groups<Group>>,
+    /// The last time the script couldn't pause or slow down a group, until it's taken
+    group_error: Option<GroupError>,
+    /// Set by a reload until the top level, which is always the first thread, first waits. Its
+    /// assignments to properties are skipped until then, so they keep their values.
+    keep_properties: bool,
+
+    #[cfg(test)]
+    finished_dispatch_count: usize,
+    #[cfg(test)]
+    finished_stack_usage: Vec<(usize, usize)>,
+}
+
+impl<'a, S: VmStorage> Vm<'a, S> {
+    pub fn new(program: VerifiedProgram<'a>, fix_precision: u8) -> Self {
+        let mut vm = Self {
+            program,
+            fix_precision: fix_precision as usize,
+            states: Storage::with_capacity(1),
+            groups: Groups::new(),
+            group_error: None,
+            keep_properties: false,
+            #[cfg(test)]
+            finished_dispatch_count: 0,
+            #[cfg(test)]
+            finished_stack_usage: vec![],
+        };
+
+        vm.states
+            .push(State::new(0, Storage::with_capacity(vm.stack_capacity(0))));
+        vm
+    }
+
+    /// How much stack to allocate up front for a thread starting at `pc`. If the function is
+    /// recursive, this is 0 and the stack grows as needed.
+    fn stack_capacity(&self, pc: usize) -> usize {
+        let stack_depths = &self.program.stack_depths;
+
+        stack_depths
+            .binary_search_by_key(&pc, |stack_depth| stack_depth.bytecode_offset)
+            .map_or(0, |index| stack_depths[index].max_depth)
+    }
+
+    /// The state for a new thread running the event handler at `pc`
+    fn event_state(&self, pc: usize, arguments: &[i32]) -> State<S::Stack> {
+        let mut initial_stack = S::Stack::with_capacity(self.stack_capacity(pc));
+        for &argument in arguments {
+            initial_stack.push(argument);
+        }
+        // events run in their own thread, so finish like a spawned function does
+        initial_stack.push(state::SPAWN_FINISHED);
+
+        State::new(pc, initial_stack)
+    }
+
+    /// Runs the event handler at `pc` until it returns, rather than alongside the other threads.
+    /// Returns its stack, with the return values on top. Anything it spawns runs with the other
+    /// threads.
+    fn run_to_completion(
+        &mut self,
+        pc: usize,
+        arguments: &[i32],
+        properties: &mut dyn ObjectSafeProperties,
+    ) -> Result<S::Stack, CallError> {
+        let mut state = self.event_state(pc, arguments);
+
+        match self.resume(&mut state, properties) {
+            state::RunResult::Finished => Ok(state.into_stack()),
+            // only generators yield, and those aren't called like this
+            _ => Err(CallError::Waited),
+        }
+    }
+
+    /// Runs `state`, which isn't one of the vm's threads, until it waits, yields or finishes.
+    /// Anything it spawns runs with the other threads.
+    fn resume(
+        &mut self,
+        state: &mut State<S::Stack>,
+        properties: &mut dyn ObjectSafeProperties,
+    ) -> state::RunResult {
+        loop {
+            match state.run_until_wait(&self.program, self.fix_precision, properties) {
+                state::RunResult::Spawn { pc, args, group } => {
+                    let capacity = self.stack_capacity(pc);
+                    let spawned = state.spawn(pc, args, group, capacity);
+                    self.states.push(spawned);
+                }
+                state::RunResult::ControlGroup { group, control } => {
+                    self.control_group(group, control);
+                }
+                result => return result,
+            }
+        }
+    }
+
+    /// Groups controlled by the script which there's no room for carry on running as they were,
+    /// since the script has no way to handle the error itself
+    fn control_group(&mut self, group: i32, control: GroupControl) {
+        if let Err(e) = self.groups.control(group, control) {
+            self.group_error = Some(e);
         }
     }
 
     fn run_until_wait(&mut self, properties: &mut dyn ObjectSafeProperties) {
+        self.groups.start_frame();
+
         let mut state_index = 0;
         while state_index < self.states.len() {
-            match self.states[state_index].run_until_wait(self.bytecode, properties) {
-                state::RunResult::Waiting => {
+            if !self
+                .groups
+                .runs_this_frame(self.states[state_index].group())
+            {
+                state_index += 1;
+                continue;
+            }
+
+            let keep_properties = self.keep_properties && state_index == 0;
+            let result = if keep_properties {
+                self.states[state_index].run_until_wait(
+                    &self.program,
+                    self.fix_precision,
+                    &mut KeepProperties(&mut *properties),
+                )
+            } else {
+                self.states[state_index].run_until_wait(
+                    &self.program,
+                    self.fix_precision,
+                    properties,
+                )
+            };
+
+            if keep_properties
+                && !matches!(
+                    result,
+                    state::RunResult::Spawn { .. } | state::RunResult::ControlGroup { .. }
+                )
+            {
+                self.keep_properties = false;
+            }
+
+            match result {
+                // generators are never run as threads by a verified program, so this can only
+                // happen with unchecked bytecode, where the value is dropped
+                state::RunResult::Waiting | state::RunResult::Yielded(_) => {
                     state_index += 1;
                 }
                 state::RunResult::Finished => {
-                    self.states.swap_remove(state_index);
+                    let _finished = self.states.swap_remove(state_index);
+                    #[cfg(test)]
+                    {
+                        self.finished_dispatch_count += _finished.dispatch_count;
+                        self.finished_stack_usage
+                            .push((_finished.entry_pc, _finished.max_stack_len));
+                    }
                 }
-                state::RunResult::Spawn(state) => {
-                    self.states.push(*state);
+                state::RunResult::Spawn { pc, args, group } => {
+                    let capacity = self.stack_capacity(pc);
+                    let spawned = self.states[state_index].spawn(pc, args, group, capacity);
+                    self.states.push(spawned);
                     // intentionally not increasing state_index to ensure that the spawning
                     // state continues to run.
                 }
+                state::RunResult::ControlGroup { group, control } => {
+                    self.control_group(group, control);
+                    // the same as for spawning, the state carries on running
+                }
             }
         }
     }
 }
 
+/// Why calling an event handler which returns something failed
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CallError {
+    /// The script doesn't handle this event, so has nothing to return. This can only happen with
+    /// scripts loaded at runtime.
+    Unhandled,
+    /// The event handler reached a `wait`, so couldn't return straight away. It is stopped there,
+    /// but anything it did before waiting still happened.
+    Waited,
+}
+
+impl core::fmt::Display for CallError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            CallError::Unhandled => write!(f, "The script doesn't handle this event"),
+            CallError::Waited => write!(f, "The event handler waited before returning"),
+        }
+    }
+}
+
+impl core::error::Error for CallError {}
+
 /// # Safety
 ///
 /// You should never implement this directly, and instead go through the derive macro
 pub unsafe trait TapirScript {
     type EventType;
+    /// Where the script keeps its threads. Set with `threads = ..., stack_size = ...` in the
+    /// `#[tapir(...)]` attribute to run without allocating.
+    type Storage: VmStorage;
+    /// How many fractional bits the script's `fix` values have. Set with `fix_precision = ...` in
+    /// the `#[tapir(...)]` attribute, and the default is 8.
+    const FIX_PRECISION: u8;
 
     fn script(self) -> Script<Self>
     where
@@ -53,42 +326,187 @@ pub unsafe trait TapirScript {
     fn set_prop(&mut self, index: u8, value: i32);
     fn get_prop(&self, index: u8) -> i32;
 
-    fn create_event(&self, index: u8, stack: &mut Vec<i32>) -> Self::EventType;
+    fn create_event(&self, index: u8, stack: &mut dyn Storage<i32>) -> Self::EventType;
 }
 
 pub struct Script<T: TapirScript> {
-    vm: Vm<'static>,
+    vm: Vm<'static, T::Storage>,
     pub properties: T,
+    /// Triggered by event handlers which were called synchronously, waiting for the next run
+    pending_events: Vec<T::EventType>,
+    /// Messages sent to other scripts, which a [`ScriptWorld`] delivers before its next run
+    outbox: Vec<world::Envelope>,
 }
 
 impl<T: TapirScript> Script<T> {
-    pub fn new(properties: T, bytecode: &'static [u16]) -> Self {
+    pub fn new(properties: T, program: VerifiedProgram<'static>) -> Self {
         Self {
-            vm: Vm::new(bytecode),
+            vm: Vm::new(program, T::FIX_PRECISION),
             properties,
+            pending_events: vec![],
+            outbox: vec![],
         }
     }
 
+    /// Swaps in new bytecode for the script, for example after its source has changed. Anything
+    /// running in the old bytecode is stopped, since there is no way to know where it should
+    /// carry on from, and the script starts again from the top on the next run.
+    ///
+    /// The properties keep their values. Until the top level first waits, anything it assigns to
+    /// a property, like `health = 10;`, is skipped, including in the functions it calls. It still
+    /// spawns its threads and triggers its events as normal, and anything after that first wait
+    /// runs as normal too.
+    ///
+    /// Groups stay paused or slowed down across the reload.
+    pub fn reload(&mut self, program: VerifiedProgram<'static>) {
+        let groups = core::mem::replace(&mut self.vm.groups, Groups::new());
+        self.vm = Vm::new(program, T::FIX_PRECISION);
+        self.vm.groups = groups;
+        self.vm.keep_properties = true;
+    }
+
     pub fn run(&mut self) -> Vec<T::EventType> {
+        let mut events = vec![];
+        self.run_with_callback(|event| events.push(event));
+        events
+    }
+
+    /// The same as [`run`](Self::run), but passes each event to `on_event` as it is triggered
+    /// rather than collecting them into a `Vec`. Along with [`Fixed`] storage, this means running
+    /// the script never allocates.
+    ///
+    /// Events triggered by event handlers which return something are passed on here too, before
+    /// any from this run.
+    ///
+    /// Messages sent with `send` are only delivered when the script is part of a
+    /// [`ScriptWorld`], and are otherwise dropped at the start of the next run. Sending a message
+    /// allocates, even with [`Fixed`] storage.
+    pub fn run_with_callback(&mut self, mut on_event: impl FnMut(T::EventType)) {
+        for event in self.pending_events.drain(..) {
+            on_event(event);
+        }
+
+        self.outbox.clear();
+
         let mut object_safe_props = ObjectSafePropertiesImpl {
             properties: &mut self.properties,
-            events: vec![],
+            on_event,
+            outbox: &mut self.outbox,
         };
 
         self.vm.run_until_wait(&mut object_safe_props);
-
-        object_safe_props.events
     }
 
+    /// Threads in paused groups don't count, since running won't do anything with them
     pub fn will_calling_run_do_anything(&self) -> bool {
-        !self.vm.states.is_empty()
+        self.vm
+            .states
+            .iter()
+            .any(|state| !self.vm.groups.is_paused(state.group()))
+            || !self.pending_events.is_empty()
+    }
+
+    /// Stops the threads in `group` from running, from the next [`run`](Self::run) until the
+    /// group is resumed. They stay where they are, and carry on from there once resumed.
+    ///
+    /// The script's threads start in group 0, and threads run in the same group as the thread
+    /// which spawned them unless the script chooses a group with `spawn group, function();`.
+    /// Scripts can also `pause group;`, `resume group;` and `timescale group, frames;` themselves.
+    ///
+    /// With [`Fixed`] storage, at most `GROUPS` groups can be paused or slowed down at once. Past
+    /// that, this returns [`GroupError::TooManyGroups`] and the group carries on running. When
+    /// the script is the one pausing or slowing down too many groups, the error is kept for
+    /// [`take_group_error`](Self::take_group_error) instead.
+    pub fn pause_group(&mut self, group: i32) -> Result<(), GroupError> {
+        self.vm.groups.control(group, GroupControl::Pause)
+    }
+
+    /// Lets the threads in a group which was paused with [`pause_group`](Self::pause_group) run
+    /// again from the next [`run`](Self::run). They keep the time scale they had when paused.
+    pub fn resume_group(&mut self, group: i32) {
+        // resuming never needs room for a group, so can't fail
+        let _ = self.vm.groups.control(group, GroupControl::Resume);
+    }
+
+    /// Makes the threads in `group` run once every `frames` calls to [`run`](Self::run), for
+    /// effects such as slow motion. They run on the next call, and then every `frames` calls
+    /// after that. A `frames` of 1 goes back to running every time, as does 0.
+    ///
+    /// This fails in the same way as [`pause_group`](Self::pause_group) with [`Fixed`] storage.
+    pub fn set_group_time_scale(&mut self, group: i32, frames: u32) -> Result<(), GroupError> {
+        self.vm
+            .groups
+            .control(group, GroupControl::TimeScale(frames))
+    }
+
+    /// Returns the error from the last time the script tried to `pause` or `timescale` a group
+    /// but there was no room for it, if that's happened since this was last called. The group
+    /// carried on running as it was.
+    pub fn take_group_error(&mut self) -> Option<GroupError> {
+        self.vm.group_error.take()
+    }
+
+    pub fn is_group_paused(&self, group: i32) -> bool {
+        self.vm.groups.is_paused(group)
+    }
+
+    /// How many calls to [`run`](Self::run) it takes for the threads in `group` to run once
+    pub fn group_time_scale(&self, group: i32) -> u32 {
+        self.vm.groups.time_scale(group)
+    }
+
+    #[doc(hidden)]
+    pub unsafe fn __private_trigger_event(&mut self, event_index: usize, arguments: &[i32]) {
+        let Some(pc) = self.vm.program.event_handlers[event_index] else {
+            // this script doesn't handle the event
+            return;
+        };
+
+        let state = self.vm.event_state(pc, arguments);
+        self.vm.states.push(state);
+    }
+
+    /// Runs an event handler which returns `N` values to completion
+    #[doc(hidden)]
+    pub unsafe fn __private_call_event<const N: usize>(
+        &mut self,
+        event_index: usize,
+        arguments: &[i32],
+    ) -> Result<[i32; N], CallError> {
+        let Some(pc) = self.vm.program.event_handlers[event_index] else {
+            return Err(CallError::Unhandled);
+        };
+
+        let pending_events = &mut self.pending_events;
+        let mut object_safe_props = ObjectSafePropertiesImpl {
+            properties: &mut self.properties,
+            on_event: |event| pending_events.push(event),
+            outbox: &mut self.outbox,
+        };
+
+        let stack = self
+            .vm
+            .run_to_completion(pc, arguments, &mut object_safe_props)?;
+
+        let returns = stack
+            .len()
+            .checked_sub(N)
+            .expect("Event handler finished without returning");
+        Ok(core::array::from_fn(|i| stack[returns + i]))
     }
 
+    /// Starts the generator at `event_index`, which doesn't run until its first value is asked
+    /// for. It yields nothing if this script doesn't have the generator.
     #[doc(hidden)]
-    pub unsafe fn __private_trigger_event(&mut self, mut initial_stack: Vec<i32>, pc: usize) {
-        initial_stack.push(0);
+    pub unsafe fn __private_generate(
+        &mut self,
+        event_index: usize,
+        arguments: &[i32],
+    ) -> Generator<'_, T> {
+        let state = self.vm.program.event_handlers[event_index]
+            .map(|pc| self.vm.event_state(pc, arguments));
 
-        self.vm.states.push(State::new(pc, initial_stack));
+        Generator::new(self, state)
     }
 }
 
@@ -101,7 +519,7 @@ mod test {
     use std::fs;
 
     use alloc::string::ToString;
-    use compiler::{CompileSettings, Property, Type};
+    use compiler::{CompileSettings, Property, PropertyAccess, Type};
     use insta::{assert_ron_snapshot, glob};
     use serde::Serialize;
 
@@ -119,47 +537,303 @@ mod test {
                     ty: Type::Int,
                     index: 0,
                     name: "int_prop".to_string(),
+                    access: PropertyAccess::ReadWrite,
                 }],
                 enable_optimisations: true,
+                fix_precision: CompileSettings::DEFAULT_FIX_PRECISION,
             };
 
-            let bytecode = compiler::compile(path.file_name().unwrap(), &input, compiler_settings)
-                .unwrap()
-                .bytecode;
+            let compiled =
+                compiler::compile(path.file_name().unwrap(), &input, compiler_settings).unwrap();
+            let stack_at_waits = stacks_at_waits::<Growable>(&compiled);
 
-            let mut vm = Vm::new(&bytecode);
-            let mut prop_object = PropObj { int_prop: 5 };
+            assert_ron_snapshot!(stack_at_waits);
+        });
+    }
+
+    /// Running with fixed storage shouldn't change anything as long as the script fits
+    #[test]
+    fn fixed_storage_matches_growable() {
+        glob!("snapshot_tests", "stack/**/*.tapir", |path| {
+            let input = fs::read_to_string(path).unwrap();
 
-            let mut stack_at_waits = vec![];
+            let compiler_settings = CompileSettings {
+                properties: vec![Property {
+                    ty: Type::Int,
+                    index: 0,
+                    name: "int_prop".to_string(),
+                    access: PropertyAccess::ReadWrite,
+                }],
+                enable_optimisations: true,
+                fix_precision: CompileSettings::DEFAULT_FIX_PRECISION,
+            };
 
-            let mut max_iterations = 1000;
+            let compiled =
+                compiler::compile(path.file_name().unwrap(), &input, compiler_settings).unwrap();
 
+            assert_eq!(
+                stacks_at_waits::<Fixed<16, 256>>(&compiled),
+                stacks_at_waits::<Growable>(&compiled)
+            );
+        });
+    }
+
+    #[test]
+    #[should_panic = "Exceeded the fixed capacity of 2"]
+    fn fixed_storage_panics_with_too_many_threads() {
+        let compile_settings = CompileSettings {
+            properties: vec![],
+            enable_optimisations: false,
+            fix_precision: CompileSettings::DEFAULT_FIX_PRECISION,
+        };
+
+        let compiled = compiler::compile(
+            "too_many_threads.tapir",
+            "fn forever() { loop { wait; } }\nspawn forever();\nspawn forever();",
+            compile_settings,
+        )
+        .unwrap();
+
+        stacks_at_waits::<Fixed<2, 16>>(&compiled);
+    }
+
+    type StacksAtWaits = Vec<(Vec<Vec<i32>>, PropObj)>;
+
+    fn stacks_at_waits<S: VmStorage>(compiled: &compiler::CompileResult) -> StacksAtWaits {
+        let stack_depths = bounded_stack_depths(&compiled.stack_depths);
+
+        let mut vm = Vm::<S>::new(
+            compiled_program(&compiled.bytecode, &stack_depths),
+            CompileSettings::DEFAULT_FIX_PRECISION,
+        );
+        let mut prop_object = PropObj { int_prop: 5 };
+
+        let mut stack_at_waits = vec![];
+
+        let mut max_iterations = 1000;
+
+        while !vm.states.is_empty() && max_iterations >= 0 {
+            let mut object_safe_props = ObjectSafePropertiesImpl {
+                properties: &mut prop_object,
+                on_event: |_| {},
+                outbox: &mut vec![],
+            };
+
+            vm.run_until_wait(&mut object_safe_props);
+            stack_at_waits.push((
+                vm.states
+                    .iter()
+                    .map(|state| state.stack().to_vec())
+                    .collect::<Vec<_>>(),
+                prop_object.clone(),
+            ));
+
+            max_iterations -= 1;
+        }
+
+        if max_iterations == 0 {
+            panic!("ran for over 1000 waits, something seems to have gone wrong...");
+        }
+
+        stack_at_waits
+    }
+
+    /// The superinstructions should give the same results as the existing stack snapshot tests
+    /// while dispatching fewer instructions. Dispatch count is what dominates the run time on
+    /// the GBA, so it is what this measures.
+    #[test]
+    fn superinstruction_benchmark() {
+        #[derive(Serialize)]
+        struct Benchmark {
+            without_superinstructions: usize,
+            with_superinstructions: usize,
+        }
+
+        glob!("snapshot_tests", "stack/**/*.tapir", |path| {
+            let input = fs::read_to_string(path).unwrap();
+
+            let compiler_settings = || CompileSettings {
+                properties: vec![Property {
+                    ty: Type::Int,
+                    index: 0,
+                    name: "int_prop".to_string(),
+                    access: PropertyAccess::ReadWrite,
+                }],
+                enable_optimisations: true,
+                fix_precision: CompileSettings::DEFAULT_FIX_PRECISION,
+            };
+
+            let without_superinstructions = compiler::compile_without_superinstructions(
+                path.file_name().unwrap(),
+                &input,
+                compiler_settings(),
+            )
+            .unwrap();
+            let with_superinstructions =
+                compiler::compile(path.file_name().unwrap(), &input, compiler_settings()).unwrap();
+
+            let (without_count, without_properties) = dispatch_count(&without_superinstructions);
+            let (with_count, with_properties) = dispatch_count(&with_superinstructions);
+            assert_eq!(without_properties, with_properties);
+
+            assert_ron_snapshot!(Benchmark {
+                without_superinstructions: without_count,
+                with_superinstructions: with_count,
+            });
+        });
+    }
+
+    /// Runs the script to completion, returning how many instructions were dispatched and what
+    /// the property ended up as
+    fn dispatch_count(compiled: &compiler::CompileResult) -> (usize, PropObj) {
+        let stack_depths = bounded_stack_depths(&compiled.stack_depths);
+
+        let mut vm = Vm::<Growable>::new(
+            compiled_program(&compiled.bytecode, &stack_depths),
+            CompileSettings::DEFAULT_FIX_PRECISION,
+        );
+        let mut prop_object = PropObj { int_prop: 5 };
+
+        while !vm.states.is_empty() {
+            let mut object_safe_props = ObjectSafePropertiesImpl {
+                properties: &mut prop_object,
+                on_event: |_| {},
+                outbox: &mut vec![],
+            };
+
+            vm.run_until_wait(&mut object_safe_props);
+        }
+
+        (vm.finished_dispatch_count, prop_object)
+    }
+
+    /// Every thread should stay within the stack depth the compiler worked out for where it started,
+    /// so the preallocated stacks never need to grow
+    #[test]
+    fn stack_depths_are_never_exceeded() {
+        glob!("snapshot_tests", "stack/**/*.tapir", |path| {
+            let input = fs::read_to_string(path).unwrap();
+
+            let compiler_settings = CompileSettings {
+                properties: vec![Property {
+                    ty: Type::Int,
+                    index: 0,
+                    name: "int_prop".to_string(),
+                    access: PropertyAccess::ReadWrite,
+                }],
+                enable_optimisations: true,
+                fix_precision: CompileSettings::DEFAULT_FIX_PRECISION,
+            };
+
+            let compiled =
+                compiler::compile(path.file_name().unwrap(), &input, compiler_settings).unwrap();
+            let stack_depths = bounded_stack_depths(&compiled.stack_depths);
+
+            let mut vm = Vm::<Growable>::new(
+                compiled_program(&compiled.bytecode, &stack_depths),
+                CompileSettings::DEFAULT_FIX_PRECISION,
+            );
+            let mut prop_object = PropObj { int_prop: 5 };
+
+            let mut max_iterations = 1000;
             while !vm.states.is_empty() && max_iterations >= 0 {
                 let mut object_safe_props = ObjectSafePropertiesImpl {
                     properties: &mut prop_object,
-                    events: vec![],
+                    on_event: |_| {},
+                    outbox: &mut vec![],
                 };
 
                 vm.run_until_wait(&mut object_safe_props);
-                stack_at_waits.push((
-                    vm.states
-                        .iter()
-                        .map(|state| state.stack().to_vec())
-                        .collect::<Vec<_>>(),
-                    prop_object.clone(),
-                ));
-
                 max_iterations -= 1;
             }
 
-            if max_iterations == 0 {
-                panic!("ran for over 1000 waits, something seems to have gone wrong...");
-            }
+            let still_running = vm
+                .states
+                .iter()
+                .map(|state| (state.entry_pc, state.max_stack_len));
 
-            assert_ron_snapshot!(stack_at_waits);
+            for (entry_pc, max_stack_len) in
+                vm.finished_stack_usage.iter().copied().chain(still_running)
+            {
+                let Some(stack_depth) = stack_depths
+                    .iter()
+                    .find(|stack_depth| stack_depth.bytecode_offset == entry_pc)
+                else {
+                    continue; // recursive, so there's no limit to check
+                };
+
+                assert!(
+                    max_stack_len <= stack_depth.max_depth,
+                    "thread starting at {entry_pc} used {max_stack_len} stack slots, but the limit was {}",
+                    stack_depth.max_depth
+                );
+            }
         });
     }
 
+    fn compiled_program<'a>(
+        bytecode: &'a [u16],
+        stack_depths: &'a [StackDepth],
+    ) -> VerifiedProgram<'a> {
+        // SAFETY: the bytecode comes straight from the compiler, the same as with the derive macro
+        unsafe { VerifiedProgram::new_unchecked(Program::new(bytecode, stack_depths, &[], &[])) }
+    }
+
+    fn bounded_stack_depths(stack_depths: &[compiler::StackDepth]) -> Vec<StackDepth> {
+        stack_depths
+            .iter()
+            .filter_map(|stack_depth| {
+                Some(StackDepth {
+                    bytecode_offset: stack_depth.bytecode_offset,
+                    max_depth: stack_depth.max_depth?,
+                })
+            })
+            .collect()
+    }
+
+    #[test]
+    fn fix_precision_matches_agb_fixnum() {
+        type Num12 = agb_fixnum::Num<i32, 12>;
+
+        let compile_settings = CompileSettings {
+            properties: vec![Property {
+                ty: Type::Fix,
+                index: 0,
+                name: "prop".to_string(),
+                access: PropertyAccess::ReadWrite,
+            }],
+            enable_optimisations: false,
+            fix_precision: 12,
+        };
+
+        let bytecode = compiler::compile(
+            "fix_precision.tapir",
+            "prop = prop * 1.375 / 0.3 - 0.001;",
+            compile_settings,
+        )
+        .unwrap()
+        .bytecode;
+
+        let mut vm = Vm::<Growable>::new(compiled_program(&bytecode, &[]), 12);
+        let mut prop_object = PropObj {
+            int_prop: Num12::new(3).to_raw(),
+        };
+
+        while !vm.states.is_empty() {
+            let mut object_safe_props = ObjectSafePropertiesImpl {
+                properties: &mut prop_object,
+                on_event: |_| {},
+                outbox: &mut vec![],
+            };
+
+            vm.run_until_wait(&mut object_safe_props);
+        }
+
+        let expected = Num12::new(3) * agb_fixnum::num!(1.375) / agb_fixnum::num!(0.3)
+            - agb_fixnum::num!(0.001);
+        assert_eq!(prop_object.int_prop, expected.to_raw());
+    }
+
     macro_rules! binop_test {
         ($($type: ident, $name:ident: ($code:tt, $expected:expr),)*) => {
             $(
@@ -171,8 +845,10 @@ mod test {
                                 ty: Type::$type,
                                 index: 0,
                                 name: "prop".to_string(),
+                                access: PropertyAccess::ReadWrite,
                             }],
                             enable_optimisations: false,
+                            fix_precision: CompileSettings::DEFAULT_FIX_PRECISION,
                         };
 
                         let bytecode = compiler::compile(
@@ -181,7 +857,7 @@ mod test {
                             compile_settings
                         ).unwrap().bytecode;
 
-                        let mut vm = Vm::new(&bytecode);
+                        let mut vm = Vm::<Growable>::new(compiled_program(&bytecode, &[]), CompileSettings::DEFAULT_FIX_PRECISION);
                         let mut prop_object = PropObj {
                             int_prop: if Type::$type == Type::Int { 5 } else { 1 },
                         };
@@ -189,7 +865,8 @@ mod test {
                         while !vm.states.is_empty() {
                             let mut object_safe_props = ObjectSafePropertiesImpl {
                                 properties: &mut prop_object,
-                                events: vec![],
+                                on_event: |_| {},
+                                outbox: &mut vec![],
                             };
 
                             vm.run_until_wait(&mut object_safe_props);
@@ -247,7 +924,91 @@ mod test {
         Int, then3: ("7 then prop", 5),
     );
 
-    #[derive(Serialize, Clone, Debug)]
+    #[test]
+    fn common_subexpressions_match_unoptimised() {
+        let source = "c = a * a + a * a;";
+
+        assert_eq!(
+            run_with_properties(source, true),
+            run_with_properties(source, false)
+        );
+    }
+
+    #[test]
+    fn loop_invariants_match_unoptimised() {
+        let source = "loop { a = a + b * b * b; if a > 10 { break; } }";
+
+        assert_eq!(
+            run_with_properties(source, true),
+            run_with_properties(source, false)
+        );
+    }
+
+    /// Runs `source` to completion with int properties `a`, `b`, `c` and `d`, which start at 1,
+    /// 2, 3 and 4, and returns what they end up as
+    fn run_with_properties(source: &str, enable_optimisations: bool) -> [i32; 4] {
+        let compile_settings = CompileSettings {
+            properties: ["a", "b", "c", "d"]
+                .into_iter()
+                .enumerate()
+                .map(|(index, name)| Property {
+                    ty: Type::Int,
+                    index,
+                    name: name.to_string(),
+                    access: PropertyAccess::ReadWrite,
+                })
+                .collect(),
+            enable_optimisations,
+            fix_precision: CompileSettings::DEFAULT_FIX_PRECISION,
+        };
+
+        let compiled = compiler::compile("properties.tapir", source, compile_settings).unwrap();
+        let stack_depths = bounded_stack_depths(&compiled.stack_depths);
+
+        let mut vm = Vm::<Growable>::new(
+            compiled_program(&compiled.bytecode, &stack_depths),
+            CompileSettings::DEFAULT_FIX_PRECISION,
+        );
+        let mut properties = Properties([1, 2, 3, 4]);
+
+        let mut max_iterations = 1000;
+        while !vm.states.is_empty() && max_iterations >= 0 {
+            let mut object_safe_props = ObjectSafePropertiesImpl {
+                properties: &mut properties,
+                on_event: |_| {},
+                outbox: &mut vec![],
+            };
+
+            vm.run_until_wait(&mut object_safe_props);
+            max_iterations -= 1;
+        }
+
+        properties.0
+    }
+
+    struct Properties([i32; 4]);
+
+    unsafe impl TapirScript for Properties {
+        fn set_prop(&mut self, index: u8, value: i32) {
+            self.0[index as usize] = value;
+        }
+
+        fn get_prop(&self, index: u8) -> i32 {
+            self.0[index as usize]
+        }
+
+        type EventType = ();
+        type Storage = Growable;
+        const FIX_PRECISION: u8 = CompileSettings::DEFAULT_FIX_PRECISION;
+
+        fn script(self) -> Script<Self> {
+            unimplemented!("Shouldn't create the script this way in the tests")
+        }
+
+        fn create_event(&self, _index: u8, _stack: &mut dyn Storage<i32>) -> Self::EventType {}
+    }
+
+    #[derive(Serialize, Clone, Debug, PartialEq, Eq)]
     struct PropObj {
         int_prop: i32,
     }
@@ -270,11 +1031,13 @@ mod test {
         }
 
         type EventType = ();
+        type Storage = Growable;
+        const FIX_PRECISION: u8 = CompileSettings::DEFAULT_FIX_PRECISION;
 
         fn script(self) -> Script<Self> {
             unimplemented!("Shouldn't create the script this way in the tests")
         }
 
-        fn create_event(&self, _index: u8, _stack: &mut Vec<i32>) -> Self::EventType {}
+        fn create_event(&self, _index: u8, _stack: &mut dyn Storage<i32>) -> Self::EventType {}
     }
 }
@@ -1,25 +1,92 @@
-use crate::TapirScript;
+use alloc::vec::Vec;
 
-use agb_fixnum::Num;
-use alloc::{boxed::Box, vec::Vec};
+use crate::{group::GroupControl, world::Envelope, Storage, TapirScript, VerifiedProgram};
 
-#[derive(Debug)]
-pub(crate) struct State {
+use agb_fixnum::FixedWidthUnsignedInteger;
+
+#[derive(Debug, Default)]
+pub(crate) struct State<S> {
     pc: usize,
-    stack: Vec<i32>,
+    stack: S,
+    /// The id of the group this thread runs in, which decides whether it runs each frame
+    group: i32,
+
+    /// How many instructions have been dispatched, used to benchmark the superinstructions
+    #[cfg(test)]
+    pub(crate) dispatch_count: usize,
+    #[cfg(test)]
+    pub(crate) entry_pc: usize,
+    #[cfg(test)]
+    pub(crate) max_stack_len: usize,
 }
 
-const SPAWN_FINISHED: i32 = u16::MAX as i32;
+pub(crate) const SPAWN_FINISHED: i32 = u16::MAX as i32;
 
 pub(crate) enum RunResult {
     Waiting,
     Finished,
-    Spawn(Box<State>),
+    /// A generator handed this value to rust, and carries on from here when the next one is
+    /// asked for
+    Yielded(i32),
+    /// The top `args` values on the stack are the arguments for a new thread starting at `pc`,
+    /// which runs in `group` or the spawning thread's group if that's `None`
+    Spawn {
+        pc: usize,
+        args: usize,
+        group: Option<i32>,
+    },
+    /// The thread changed how the threads in `group` run, and carries on straight away
+    ControlGroup {
+        group: i32,
+        control: GroupControl,
+    },
 }
 
-impl State {
-    pub(crate) fn new(pc: usize, stack: Vec<i32>) -> Self {
-        Self { pc, stack }
+impl<S: Storage<i32>> State<S> {
+    pub(crate) fn new(pc: usize, stack: S) -> Self {
+        Self {
+            pc,
+            stack,
+            group: 0,
+            #[cfg(test)]
+            dispatch_count: 0,
+            #[cfg(test)]
+            entry_pc: pc,
+            #[cfg(test)]
+            max_stack_len: 0,
+        }
+    }
+
+    /// Moves the top `args` values of the stack into a new thread starting at `pc`, which runs in
+    /// `group` or this thread's group if that's `None`
+    pub(crate) fn spawn(
+        &mut self,
+        pc: usize,
+        args: usize,
+        group: Option<i32>,
+        capacity: usize,
+    ) -> Self {
+        let args_start = self.stack.len() - args;
+
+        let mut new_stack = S::with_capacity(capacity);
+        for &arg in &self.stack[args_start..] {
+            new_stack.push(arg);
+        }
+        new_stack.push(SPAWN_FINISHED);
+
+        self.stack.truncate(args_start);
+
+        let mut spawned = Self::new(pc, new_stack);
+        spawned.group = group.unwrap_or(self.group);
+        spawned
+    }
+
+    pub(crate) fn group(&self) -> i32 {
+        self.group
+    }
+
+    pub(crate) fn into_stack(self) -> S {
+        self.stack
     }
 
     #[cfg(test)]
@@ -27,37 +94,62 @@ impl State {
         &self.stack
     }
 
+    /// Pops a value which the program is known to have pushed
+    fn pop(&mut self) -> i32 {
+        // SAFETY: verified programs never pop more than their function has on the stack
+        unsafe { self.stack.pop().unwrap_unchecked() }
+    }
+
+    /// Reads the value `offset` below the top of the stack
+    fn peek(&self, offset: usize) -> i32 {
+        // SAFETY: verified programs never read outside of their function's stack frame, or the
+        // return address or SPAWN_FINISHED just below it
+        unsafe { *self.stack.get_unchecked(self.stack.len() - offset - 1) }
+    }
+
+    /// Runs the thread until it waits, yields, finishes or needs the vm to do something for it.
+    /// This relies on `program` being verified, and skips checking jumps, stack offsets and
+    /// returns.
     pub(crate) fn run_until_wait(
         &mut self,
-        bytecode: &[u16],
+        program: &VerifiedProgram,
+        fix_precision: usize,
         properties: &mut dyn ObjectSafeProperties,
     ) -> RunResult {
+        let bytecode = &*program.bytecode;
+
         loop {
             let Some(instr) = bytecode.get(self.pc) else {
                 return RunResult::Finished;
             };
 
-            let parsed =
-                bytecode::Instruction::n((instr >> 8) as u8).expect("Invalid bytecode instruction");
+            // SAFETY: verified programs only contain valid instructions, and only ever jump, call
+            // or return to the start of one
+            let parsed = unsafe { bytecode::Instruction::n((instr >> 8) as u8).unwrap_unchecked() };
 
             let arg = instr & 0xff;
 
             self.pc += 1;
 
+            #[cfg(test)]
+            {
+                self.max_stack_len = self.max_stack_len.max(self.stack.len());
+                self.dispatch_count += 1;
+            }
+
             match parsed {
                 bytecode::Instruction::Push8 => {
                     self.stack.push(arg as i8 as i32);
                 }
                 bytecode::Instruction::Push32 => {
-                    let first = bytecode[self.pc].to_le_bytes();
-                    let second = bytecode[self.pc + 1].to_le_bytes();
+                    let first = operand(bytecode, self.pc).to_le_bytes();
+                    let second = operand(bytecode, self.pc + 1).to_le_bytes();
                     self.pc += 2;
                     let value = i32::from_le_bytes([first[0], first[1], second[0], second[1]]);
                     self.stack.push(value);
                 }
                 bytecode::Instruction::Dup => {
-                    self.stack
-                        .push(self.stack[self.stack.len() - arg as usize - 1]);
+                    self.stack.push(self.peek(arg as usize));
                 }
                 bytecode::Instruction::Drop => {
                     let desired_size = self.stack.len() - arg as usize;
@@ -67,76 +159,97 @@ impl State {
                     self.stack.push(properties.get_prop(arg as u8));
                 }
                 bytecode::Instruction::SetProp => {
-                    properties.set_prop(arg as u8, self.stack.pop().expect("Stack underflow"));
+                    properties.set_prop(arg as u8, self.pop());
                 }
                 bytecode::Instruction::Nop => {}
                 bytecode::Instruction::Wait => {
                     return RunResult::Waiting;
                 }
+                bytecode::Instruction::Yield => {
+                    return RunResult::Yielded(self.pop());
+                }
                 bytecode::Instruction::Move => {
                     let move_location = self.stack.len() - arg as usize - 1;
-                    self.stack[move_location] = self.stack.pop().expect("Stack underflow");
+                    let value = self.pop();
+                    // SAFETY: verified programs only move values within their function's stack
+                    // frame, and never over its return address
+                    unsafe { *self.stack.get_unchecked_mut(move_location) = value };
                 }
                 bytecode::Instruction::MathsOp => {
-                    let op = bytecode::MathsOp::n(arg as u8).expect("Invalid maths op");
-                    let rhs = self.stack.pop().expect("Stack underflow");
-                    let lhs = self.stack.pop().expect("Stack underflow");
-
-                    let result = match op {
-                        bytecode::MathsOp::Add => lhs + rhs,
-                        bytecode::MathsOp::Sub => lhs - rhs,
-                        bytecode::MathsOp::Mul => lhs * rhs,
-                        bytecode::MathsOp::RealMod => lhs.rem_euclid(rhs),
-                        bytecode::MathsOp::RealDiv => lhs / rhs, // FIXME: div_floor
-                        bytecode::MathsOp::EqEq => (lhs == rhs).into(),
-                        bytecode::MathsOp::NeEq => (lhs != rhs).into(),
-                        bytecode::MathsOp::Gt => (lhs > rhs).into(),
-                        bytecode::MathsOp::GtEq => (lhs >= rhs).into(),
-                        bytecode::MathsOp::Lt => (lhs < rhs).into(),
-                        bytecode::MathsOp::LtEq => (lhs <= rhs).into(),
-                        bytecode::MathsOp::FixMul => {
-                            (Num::<i32, 8>::from_raw(lhs) * Num::<i32, 8>::from_raw(rhs)).to_raw()
-                        }
-                        bytecode::MathsOp::FixDiv => {
-                            (Num::<i32, 8>::from_raw(lhs) / Num::<i32, 8>::from_raw(rhs)).to_raw()
-                        }
-                    };
+                    // SAFETY: verified programs only use valid maths ops
+                    let op = unsafe { bytecode::MathsOp::n(arg as u8).unwrap_unchecked() };
+                    let rhs = self.pop();
+                    let lhs = self.pop();
 
-                    self.stack.push(result);
+                    self.stack.push(maths_op(op, lhs, rhs, fix_precision));
                 }
                 bytecode::Instruction::JumpIfFalse => {
-                    let target_for_jump = bytecode[self.pc];
+                    let target_for_jump = operand(bytecode, self.pc);
                     self.pc += 1;
 
-                    if *self.stack.last().expect("Stack underflow") == 0 {
+                    if self.peek(0) == 0 {
                         self.pc = target_for_jump as usize;
                     }
                 }
                 bytecode::Instruction::Jump => {
-                    let target_for_jump = bytecode[self.pc];
+                    let target_for_jump = operand(bytecode, self.pc);
                     self.pc = target_for_jump as usize;
                 }
                 bytecode::Instruction::Call => {
-                    let target_for_jump = bytecode[self.pc];
+                    let target_for_jump = operand(bytecode, self.pc);
                     self.stack.push((self.pc + 1) as i32);
 
                     self.pc = target_for_jump as usize;
                 }
                 bytecode::Instruction::Spawn => {
-                    let target_for_spawn = bytecode[self.pc];
+                    let target_for_spawn = operand(bytecode, self.pc);
                     self.pc += 1;
 
-                    let mut new_stack = self.stack.split_off(self.stack.len() - arg as usize);
-                    new_stack.push(SPAWN_FINISHED);
+                    return RunResult::Spawn {
+                        pc: target_for_spawn as usize,
+                        args: arg as usize,
+                        group: None,
+                    };
+                }
+                bytecode::Instruction::SpawnInGroup => {
+                    let target_for_spawn = operand(bytecode, self.pc);
+                    self.pc += 1;
+
+                    // the group is below the arguments, so move them down over it
+                    let args_start = self.stack.len() - arg as usize;
+                    let group = self.stack[args_start - 1];
+                    self.stack.copy_within(args_start.., args_start - 1);
+                    self.stack.pop();
 
-                    return RunResult::Spawn(Box::new(Self::new(
-                        target_for_spawn as usize,
-                        new_stack,
-                    )));
+                    return RunResult::Spawn {
+                        pc: target_for_spawn as usize,
+                        args: arg as usize,
+                        group: Some(group),
+                    };
+                }
+                bytecode::Instruction::PauseGroup => {
+                    return RunResult::ControlGroup {
+                        group: self.pop(),
+                        control: GroupControl::Pause,
+                    };
+                }
+                bytecode::Instruction::ResumeGroup => {
+                    return RunResult::ControlGroup {
+                        group: self.pop(),
+                        control: GroupControl::Resume,
+                    };
+                }
+                bytecode::Instruction::SetGroupTimeScale => {
+                    let frames = self.pop();
+                    return RunResult::ControlGroup {
+                        group: self.pop(),
+                        // anything below 1 runs every frame, the same as 1
+                        control: GroupControl::TimeScale(frames.try_into().unwrap_or(0)),
+                    };
                 }
                 bytecode::Instruction::Return => {
                     let args = arg;
-                    let [rets, shift] = bytecode[self.pc].to_be_bytes();
+                    let [rets, shift] = operand(bytecode, self.pc).to_be_bytes();
 
                     let args = args as usize;
                     let rets = rets as usize;
@@ -146,7 +259,7 @@ impl State {
                         return RunResult::Finished;
                     }
 
-                    let new_pc = self.stack[self.stack.len() - shift - 1];
+                    let new_pc = self.peek(shift);
 
                     if new_pc == SPAWN_FINISHED {
                         // this is the end of a spawned function or event
@@ -163,32 +276,142 @@ impl State {
 
                     self.pc = new_pc as usize;
                 }
+                bytecode::Instruction::TailCall => {
+                    let target_for_jump = operand(bytecode, self.pc);
+                    let [frame_args, shift] = operand(bytecode, self.pc + 1).to_be_bytes();
+
+                    let args = arg as usize;
+                    let frame_args = frame_args as usize;
+                    let shift = shift as usize;
+
+                    // keep the current return address so the called function returns straight
+                    // to our caller
+                    let return_address_index = self.stack.len() - shift - 1;
+                    let return_address = self.peek(shift);
+                    let frame_start = return_address_index - frame_args;
+
+                    let copy_range = (self.stack.len() - args)..;
+                    self.stack.copy_within(copy_range, frame_start);
+                    self.stack.truncate(frame_start + args);
+                    self.stack.push(return_address);
+
+                    self.pc = target_for_jump as usize;
+                }
                 bytecode::Instruction::Trigger => {
-                    properties.add_event(arg as u8, &mut self.stack);
+                    properties.add_event(program.trigger_index(arg as u8), &mut self.stack);
+                }
+                bytecode::Instruction::Send => {
+                    let message = operand(bytecode, self.pc);
+                    self.pc += 1;
+
+                    let args_start = self.stack.len() - arg as usize;
+                    properties.send(
+                        self.stack[args_start - 1],
+                        program.messages[message as usize],
+                        &self.stack[args_start..],
+                    );
+                    // the target is below the arguments
+                    self.stack.truncate(args_start - 1);
+                }
+                bytecode::Instruction::AddPropImmediate => {
+                    let value = operand(bytecode, self.pc) as i16 as i32;
+                    self.pc += 1;
+
+                    let index = arg as u8;
+                    properties.set_prop(index, properties.get_prop(index) + value);
+                }
+                bytecode::Instruction::CompareJumpIfFalse => {
+                    // SAFETY: verified programs only use valid maths ops
+                    let op = unsafe { bytecode::MathsOp::n(arg as u8).unwrap_unchecked() };
+                    let target_for_jump = operand(bytecode, self.pc);
+                    self.pc += 1;
+
+                    let rhs = self.pop();
+                    let lhs = self.pop();
+
+                    // the result stays on the stack, exactly as if this was a MathsOp then JumpIfFalse
+                    let result = maths_op(op, lhs, rhs, fix_precision);
+                    self.stack.push(result);
+
+                    if result == 0 {
+                        self.pc = target_for_jump as usize;
+                    }
                 }
             }
         }
     }
 }
 
+/// Reads the word after an instruction, which is part of the instruction itself
+fn operand(bytecode: &[u16], pc: usize) -> u16 {
+    // SAFETY: verified programs never end part way through an instruction
+    unsafe { *bytecode.get_unchecked(pc) }
+}
+
+/// `fix_precision` is the number of fractional bits in a fix, so the multiplication and division
+/// are the same as for `agb_fixnum::Num<i32, fix_precision>`
+fn maths_op(op: bytecode::MathsOp, lhs: i32, rhs: i32, fix_precision: usize) -> i32 {
+    match op {
+        bytecode::MathsOp::Add => lhs + rhs,
+        bytecode::MathsOp::Sub => lhs - rhs,
+        bytecode::MathsOp::Mul => lhs * rhs,
+        bytecode::MathsOp::RealMod => lhs.rem_euclid(rhs),
+        bytecode::MathsOp::RealDiv => lhs / rhs, // FIXME: div_floor
+        bytecode::MathsOp::EqEq => (lhs == rhs).into(),
+        bytecode::MathsOp::NeEq => (lhs != rhs).into(),
+        bytecode::MathsOp::Gt => (lhs > rhs).into(),
+        bytecode::MathsOp::GtEq => (lhs >= rhs).into(),
+        bytecode::MathsOp::Lt => (lhs < rhs).into(),
+        bytecode::MathsOp::LtEq => (lhs <= rhs).into(),
+        bytecode::MathsOp::FixMul => i32::upcast_multiply(lhs, rhs, fix_precision),
+        bytecode::MathsOp::FixDiv => (lhs << fix_precision) / rhs,
+        bytecode::MathsOp::ShiftLeft => lhs << rhs,
+        bytecode::MathsOp::ShiftRight => lhs >> rhs,
+    }
+}
+
 pub(crate) trait ObjectSafeProperties {
     fn set_prop(&mut self, index: u8, value: i32);
     fn get_prop(&self, index: u8) -> i32;
 
-    fn add_event(&mut self, index: u8, stack: &mut Vec<i32>);
+    fn add_event(&mut self, index: u8, stack: &mut dyn Storage<i32>);
+    fn send(&mut self, target: i32, event_index: usize, arguments: &[i32]);
 }
 
-pub(crate) struct ObjectSafePropertiesImpl<'a, T, U>
+/// Skips assignments to properties while passing everything else on, for the top level of a
+/// script which has just been reloaded
+pub(crate) struct KeepProperties<'a>(pub &'a mut dyn ObjectSafeProperties);
+
+impl ObjectSafeProperties for KeepProperties<'_> {
+    fn set_prop(&mut self, _index: u8, _value: i32) {}
+
+    fn get_prop(&self, index: u8) -> i32 {
+        self.0.get_prop(index)
+    }
+
+    fn add_event(&mut self, index: u8, stack: &mut dyn Storage<i32>) {
+        self.0.add_event(index, stack);
+    }
+
+    fn send(&mut self, target: i32, event_index: usize, arguments: &[i32]) {
+        self.0.send(target, event_index, arguments);
+    }
+}
+
+pub(crate) struct ObjectSafePropertiesImpl<'a, T, F>
 where
-    T: TapirScript<EventType = U>,
+    T: TapirScript,
+    F: FnMut(T::EventType),
 {
     pub properties: &'a mut T,
-    pub events: Vec<U>,
+    pub on_event: F,
+    pub outbox: &'a mut Vec<Envelope>,
 }
 
-impl<'a, T, U> ObjectSafeProperties for ObjectSafePropertiesImpl<'a, T, U>
+impl<'a, T, F> ObjectSafeProperties for ObjectSafePropertiesImpl<'a, T, F>
 where
-    T: TapirScript<EventType = U>,
+    T: TapirScript,
+    F: FnMut(T::EventType),
 {
     fn set_prop(&mut self, index: u8, value: i32) {
         self.properties.set_prop(index, value);
@@ -198,7 +421,15 @@ where
         self.properties.get_prop(index)
     }
 
-    fn add_event(&mut self, index: u8, stack: &mut Vec<i32>) {
-        self.events.push(self.properties.create_event(index, stack));
+    fn add_event(&mut self, index: u8, stack: &mut dyn Storage<i32>) {
+        (self.on_event)(self.properties.create_event(index, stack));
+    }
+
+    fn send(&mut self, target: i32, event_index: usize, arguments: &[i32]) {
+        self.outbox.push(Envelope {
+            target,
+            event_index,
+            arguments: arguments.to_vec(),
+        });
     }
 }
@@ -0,0 +1,155 @@
+use core::ops::{Deref, DerefMut};
+
+use alloc::vec::Vec;
+
+/// Somewhere to keep the threads and their stacks. This is either a `Vec`, which grows as needed,
+/// or a [`FixedVec`] which never allocates.
+pub trait Storage<T>: DerefMut<Target = [T]> {
+    fn with_capacity(capacity: usize) -> Self
+    where
+        Self: Sized;
+
+    fn push(&mut self, value: T);
+    /// Pushes `value` if there is room for it, or hands it back if not
+    fn try_push(&mut self, value: T) -> Result<(), T>;
+    fn pop(&mut self) -> Option<T>;
+    fn truncate(&mut self, len: usize);
+    fn swap_remove(&mut self, index: usize) -> T;
+}
+
+impl<T> Storage<T> for Vec<T> {
+    fn with_capacity(capacity: usize) -> Self {
+        Vec::with_capacity(capacity)
+    }
+
+    fn push(&mut self, value: T) {
+        Vec::push(self, value);
+    }
+
+    fn try_push(&mut self, value: T) -> Result<(), T> {
+        Vec::push(self, value);
+        Ok(())
+    }
+
+    fn pop(&mut self) -> Option<T> {
+        Vec::pop(self)
+    }
+
+    fn truncate(&mut self, len: usize) {
+        Vec::truncate(self, len);
+    }
+
+    fn swap_remove(&mut self, index: usize) -> T {
+        Vec::swap_remove(self, index)
+    }
+}
+
+/// A list which can hold at most `N` values and never allocates. Pushing past the end panics.
+pub struct FixedVec<T, const N: usize> {
+    data: [T; N],
+    len: usize,
+}
+
+impl<T: Default, const N: usize> Default for FixedVec<T, N> {
+    fn default() -> Self {
+        Self {
+            data: core::array::from_fn(|_| T::default()),
+            len: 0,
+        }
+    }
+}
+
+impl<T, const N: usize> Deref for FixedVec<T, N> {
+    type Target = [T];
+
+    fn deref(&self) -> &Self::Target {
+        &self.data[..self.len]
+    }
+}
+
+impl<T, const N: usize> DerefMut for FixedVec<T, N> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.data[..self.len]
+    }
+}
+
+impl<T: Default, const N: usize> Storage<T> for FixedVec<T, N> {
+    fn with_capacity(_capacity: usize) -> Self {
+        Self::default()
+    }
+
+    fn push(&mut self, value: T) {
+        assert!(
+            self.try_push(value).is_ok(),
+            "Exceeded the fixed capacity of {N}"
+        );
+    }
+
+    fn try_push(&mut self, value: T) -> Result<(), T> {
+        if self.len == N {
+            return Err(value);
+        }
+
+        self.data[self.len] = value;
+        self.len += 1;
+        Ok(())
+    }
+
+    fn pop(&mut self) -> Option<T> {
+        self.len = self.len.checked_sub(1)?;
+        Some(core::mem::take(&mut self.data[self.len]))
+    }
+
+    fn truncate(&mut self, len: usize) {
+        while self.len > len {
+            self.pop();
+        }
+    }
+
+    fn swap_remove(&mut self, index: usize) -> T {
+        let last = self.len - 1;
+        self.data[..self.len].swap(index, last);
+        self.pop().unwrap()
+    }
+}
+
+/// Decides where a script keeps its threads and their stacks
+pub trait VmStorage {
+    /// The most values each thread's stack can hold, or `None` if there is no limit
+    const STACK_SIZE: Option<usize>;
+
+    type Stack: Storage<i32> + Default;
+    type Threads<T: Default>: Storage<T>;
+    /// Where the groups which have been paused or slowed down are kept
+    type Groups<T: Default>: Storage<T>;
+}
+
+/// Threads and stacks are kept in `Vec`s, so a script can spawn as many threads and recurse as
+/// deeply as it likes. This is the default.
+pub struct Growable;
+
+impl VmStorage for Growable {
+    const STACK_SIZE: Option<usize> = None;
+
+    type Stack = Vec<i32>;
+    type Threads<T: Default> = Vec<T>;
+    type Groups<T: Default> = Vec<T>;
+}
+
+/// At most `THREADS` threads can run at once, each with room for `STACK_SIZE` values on its stack.
+/// Nothing is allocated while the script runs, but going over either limit panics.
+///
+/// Separately, at most `GROUPS` groups can be paused or slowed down at once, 4 unless set with
+/// `groups = ...`. Going over that is an error rather than a panic, see
+/// [`Script::pause_group`](crate::Script::pause_group).
+pub struct Fixed<const THREADS: usize, const STACK_SIZE: usize, const GROUPS: usize = 4>;
+
+impl<const THREADS: usize, const STACK_SIZE: usize, const GROUPS: usize> VmStorage
+    for Fixed<THREADS, STACK_SIZE, GROUPS>
+{
+    const STACK_SIZE: Option<usize> = Some(STACK_SIZE);
+
+    type Stack = FixedVec<i32, STACK_SIZE>;
+    type Threads<T: Default> = FixedVec<T, THREADS>;
+    type Groups<T: Default> = FixedVec<T, GROUPS>;
+}
@@ -0,0 +1,991 @@
+//! Checks bytecode before it is run. The interpreter trusts its bytecode completely, so bytecode
+//! which didn't come straight from the compiler, such as a precompiled script loaded from disk,
+//! should be verified first. A verified program never hits an invalid instruction, never jumps
+//! into the middle of an instruction, never reads or writes outside of the current function's
+//! stack frame and never reads a write only property or assigns to a read only one.
+//!
+//! The interpreter only runs a [`VerifiedProgram`](crate::VerifiedProgram), and relies on it having
+//! passed these checks to skip its own, so it doesn't check jump targets, `Dup` and `Move` offsets
+//! or the stack frames of `Return` and `TailCall` as it runs.
+//!
+//! Functions are found from the toplevel at offset 0, the event handlers and the targets of
+//! `Call`, `Spawn`, `SpawnInGroup` and `TailCall`. Each one must be entered with the same number of
+//! arguments everywhere, and every instruction in it must always be reached with the same stack
+//! height.
+
+use alloc::{collections::BTreeMap, vec};
+use core::fmt;
+
+use bytecode::{Instruction, MathsOp};
+
+use crate::Program;
+
+/// What the rust side provides to the script, which the bytecode is checked against
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Interface<'a> {
+    /// The index of every property the script can read from
+    pub readable_properties: &'a [u8],
+    /// The index of every property the script can assign to
+    pub writable_properties: &'a [u8],
+    /// How many arguments each trigger takes, in the same order as `TapirScript::create_event`
+    pub trigger_arguments: &'a [usize],
+    /// How many arguments each event handler takes, in the same order as
+    /// [`Program::event_handlers`]
+    pub event_arguments: &'a [usize],
+    /// How many values each event handler returns, in the same order as `event_arguments`
+    pub event_returns: &'a [usize],
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum VerifyError {
+    /// The instruction, or the maths op it uses, doesn't exist
+    InvalidInstruction {
+        pc: usize,
+    },
+    /// The bytecode ends part way through the instruction
+    TruncatedInstruction {
+        pc: usize,
+    },
+    /// The instruction jumps outside of the bytecode or into the middle of another instruction
+    InvalidJumpTarget {
+        pc: usize,
+        target: usize,
+    },
+    /// An event handler starts outside of the bytecode or in the middle of an instruction
+    InvalidEventHandler {
+        index: usize,
+    },
+    InvalidProperty {
+        pc: usize,
+        index: u8,
+    },
+    /// The instruction reads from a write only property or assigns to a read only one
+    InvalidPropertyAccess {
+        pc: usize,
+        index: u8,
+    },
+    InvalidTrigger {
+        pc: usize,
+        index: u8,
+    },
+    /// The message doesn't exist, or is sent with a different number of arguments to the event
+    /// it is delivered to
+    InvalidMessage {
+        pc: usize,
+        index: u16,
+    },
+    /// The function at `function` is entered with different numbers of arguments, or returns a
+    /// different number of values, in different places
+    InconsistentSignature {
+        function: usize,
+    },
+    /// The instruction can be reached with different stack heights
+    StackMismatch {
+        pc: usize,
+        expected: usize,
+        actual: usize,
+    },
+    /// The instruction uses more of the stack than the current function has
+    StackUnderflow {
+        pc: usize,
+    },
+    /// The instruction is part of more than one function
+    SharedCode {
+        pc: usize,
+    },
+    /// A `Return` or `TailCall` which doesn't match the function's stack frame
+    InvalidReturn {
+        pc: usize,
+    },
+}
+
+impl fmt::Display for VerifyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            VerifyError::InvalidInstruction { pc } => write!(f, "Invalid instruction at {pc}"),
+            VerifyError::TruncatedInstruction { pc } => {
+                write!(f, "Instruction at {pc} is cut off by the end of the bytecode")
+            }
+            VerifyError::InvalidJumpTarget { pc, target } => {
+                write!(f, "Instruction at {pc} jumps to {target}, which isn't an instruction")
+            }
+            VerifyError::InvalidEventHandler { index } => {
+                write!(f, "Event handler {index} doesn't start at an instruction")
+            }
+            VerifyError::InvalidProperty { pc, index } => {
+                write!(f, "Instruction at {pc} uses property {index}, which doesn't exist")
+            }
+            VerifyError::InvalidPropertyAccess { pc, index } => write!(
+                f,
+                "Instruction at {pc} reads from or assigns to property {index}, which the script isn't allowed to"
+            ),
+            VerifyError::InvalidTrigger { pc, index } => {
+                write!(f, "Instruction at {pc} uses trigger {index}, which doesn't exist")
+            }
+            VerifyError::InvalidMessage { pc, index } => write!(
+                f,
+                "Instruction at {pc} sends message {index}, which doesn't exist or takes different arguments"
+            ),
+            VerifyError::InconsistentSignature { function } => write!(
+                f,
+                "Function at {function} takes or returns a different number of values in different places"
+            ),
+            VerifyError::StackMismatch {
+                pc,
+                expected,
+                actual,
+            } => write!(
+                f,
+                "Instruction at {pc} is reached with a stack height of both {expected} and {actual}"
+            ),
+            VerifyError::StackUnderflow { pc } => {
+                write!(f, "Instruction at {pc} uses more stack than its function has")
+            }
+            VerifyError::SharedCode { pc } => {
+                write!(f, "Instruction at {pc} is part of more than one function")
+            }
+            VerifyError::InvalidReturn { pc } => {
+                write!(f, "Return at {pc} doesn't match its function's stack frame")
+            }
+        }
+    }
+}
+
+impl core::error::Error for VerifyError {}
+
+/// Checks that `program` is safe to run with something which provides `interface`
+pub fn verify(program: &Program, interface: &Interface) -> Result<(), VerifyError> {
+    let bytecode = &*program.bytecode;
+    let instructions = decode(bytecode)?;
+
+    let is_boundary = |offset: usize| instructions.contains_key(&offset);
+
+    let mut functions = Functions::default();
+    functions.toplevel(0);
+
+    for (&pc, decoded) in &instructions {
+        if let Some(target) = decoded.target {
+            if !is_boundary(target) {
+                return Err(VerifyError::InvalidJumpTarget { pc, target });
+            }
+        }
+
+        match decoded.instruction {
+            Instruction::GetProp | Instruction::SetProp | Instruction::AddPropImmediate => {
+                let index = decoded.arg;
+                let readable = interface.readable_properties.contains(&index);
+                let writable = interface.writable_properties.contains(&index);
+
+                if !readable && !writable {
+                    return Err(VerifyError::InvalidProperty { pc, index });
+                }
+
+                let valid = match decoded.instruction {
+                    Instruction::GetProp => readable,
+                    Instruction::SetProp => writable,
+                    _ => readable && writable,
+                };
+                if !valid {
+                    return Err(VerifyError::InvalidPropertyAccess { pc, index });
+                }
+            }
+            Instruction::Trigger => {
+                let index = decoded.arg;
+                let valid = program
+                    .triggers
+                    .as_ref()
+                    .is_none_or(|triggers| (index as usize) < triggers.len())
+                    && (program.trigger_index(index) as usize) < interface.trigger_arguments.len();
+                if !valid {
+                    return Err(VerifyError::InvalidTrigger { pc, index });
+                }
+            }
+            Instruction::Send => {
+                let index = bytecode[pc + 1];
+                let valid = program
+                    .messages
+                    .get(index as usize)
+                    .and_then(|&event| interface.event_arguments.get(event))
+                    .is_some_and(|&arguments| arguments == decoded.arg as usize);
+                if !valid {
+                    return Err(VerifyError::InvalidMessage { pc, index });
+                }
+            }
+            Instruction::Call
+            | Instruction::Spawn
+            | Instruction::SpawnInGroup
+            | Instruction::TailCall => {
+                let target = decoded.target.unwrap();
+                // the toplevel has no stack frame, so can't be called
+                if target == 0 {
+                    return Err(VerifyError::InvalidJumpTarget { pc, target });
+                }
+
+                functions.entered_with(target, decoded.arg as usize)?;
+            }
+            _ => {}
+        }
+    }
+
+    for (index, event_handler) in program.event_handlers.iter().enumerate() {
+        let Some(offset) = *event_handler else {
+            continue;
+        };
+
+        let Some(&arguments) = interface.event_arguments.get(index) else {
+            return Err(VerifyError::InvalidEventHandler { index });
+        };
+
+        if !is_boundary(offset) || offset == 0 {
+            return Err(VerifyError::InvalidEventHandler { index });
+        }
+
+        functions.entered_with(offset, arguments)?;
+    }
+
+    functions.find_returns(&instructions)?;
+
+    for (index, event_handler) in program.event_handlers.iter().enumerate() {
+        let Some(offset) = *event_handler else {
+            continue;
+        };
+
+        // event handlers which never return are fine, since they'll either wait or finish
+        let returns = functions.signatures[&offset].returns;
+        if returns.is_some() && returns != interface.event_returns.get(index).copied() {
+            return Err(VerifyError::InconsistentSignature { function: offset });
+        }
+    }
+
+    let checker = StackChecker {
+        program,
+        interface,
+        functions: &functions,
+        instructions: &instructions,
+    };
+
+    let mut owners = vec![None; bytecode.len()];
+    for (&entry, signature) in &functions.signatures {
+        checker.check(entry, signature, &mut owners)?;
+    }
+
+    Ok(())
+}
+
+#[derive(Clone, Copy, Debug)]
+struct Decoded {
+    instruction: Instruction,
+    arg: u8,
+    /// `[returns, shift]` for a `Return`, or `[frame arguments, shift]` for a `TailCall`
+    frame: [u8; 2],
+    /// Where it jumps, calls or spawns to
+    target: Option<usize>,
+    size: usize,
+}
+
+/// Splits the bytecode into instructions, keyed by where they start
+fn decode(bytecode: &[u16]) -> Result<BTreeMap<usize, Decoded>, VerifyError> {
+    let mut instructions = BTreeMap::new();
+
+    let mut pc = 0;
+    while pc < bytecode.len() {
+        let word = bytecode[pc];
+        let instruction =
+            Instruction::n((word >> 8) as u8).ok_or(VerifyError::InvalidInstruction { pc })?;
+        let arg = (word & 0xff) as u8;
+
+        let size = match instruction {
+            Instruction::Push32 | Instruction::TailCall => 3,
+            Instruction::JumpIfFalse
+            | Instruction::Jump
+            | Instruction::Call
+            | Instruction::Return
+            | Instruction::Spawn
+            | Instruction::SpawnInGroup
+            | Instruction::Send
+            | Instruction::AddPropImmediate
+            | Instruction::CompareJumpIfFalse => 2,
+            _ => 1,
+        };
+
+        if pc + size > bytecode.len() {
+            return Err(VerifyError::TruncatedInstruction { pc });
+        }
+
+        if matches!(
+            instruction,
+            Instruction::MathsOp | Instruction::CompareJumpIfFalse
+        ) && MathsOp::n(arg).is_none()
+        {
+            return Err(VerifyError::InvalidInstruction { pc });
+        }
+
+        let frame = match instruction {
+            Instruction::Return => bytecode[pc + 1].to_be_bytes(),
+            Instruction::TailCall => bytecode[pc + 2].to_be_bytes(),
+            _ => [0, 0],
+        };
+        let target = match instruction {
+            Instruction::JumpIfFalse
+            | Instruction::Jump
+            | Instruction::Call
+            | Instruction::Spawn
+            | Instruction::SpawnInGroup
+            | Instruction::TailCall
+            | Instruction::CompareJumpIfFalse => Some(bytecode[pc + 1] as usize),
+            _ => None,
+        };
+
+        instructions.insert(
+            pc,
+            Decoded {
+                instruction,
+                arg,
+                frame,
+                target,
+                size,
+            },
+        );
+
+        pc += size;
+    }
+
+    Ok(instructions)
+}
+
+#[derive(Clone, Copy, Debug, Default)]
+struct Signature {
+    /// `None` for the toplevel, which has no arguments or return address below it
+    arguments: Option<usize>,
+    /// `None` if the function never returns
+    returns: Option<usize>,
+}
+
+#[derive(Default)]
+struct Functions {
+    signatures: BTreeMap<usize, Signature>,
+}
+
+impl Functions {
+    fn toplevel(&mut self, entry: usize) {
+        self.signatures.insert(entry, Signature::default());
+    }
+
+    fn entered_with(&mut self, entry: usize, arguments: usize) -> Result<(), VerifyError> {
+        let signature = self.signatures.entry(entry).or_insert(Signature {
+            arguments: Some(arguments),
+            returns: None,
+        });
+
+        if signature.arguments != Some(arguments) {
+            return Err(VerifyError::InconsistentSignature { function: entry });
+        }
+
+        Ok(())
+    }
+
+    /// Works out how many values each function returns from the `Return`s it can reach, ignoring
+    /// the stack. Functions which only end in tail calls return whatever they tail call returns.
+    fn find_returns(&mut self, instructions: &BTreeMap<usize, Decoded>) -> Result<(), VerifyError> {
+        let mut tail_calls = vec![];
+
+        for (&entry, signature) in &mut self.signatures {
+            let mut seen = BTreeMap::new();
+            let mut pending = vec![entry];
+
+            while let Some(pc) = pending.pop() {
+                if seen.insert(pc, ()).is_some() {
+                    continue;
+                }
+
+                let Some(decoded) = instructions.get(&pc) else {
+                    // running off the end of the bytecode finishes the thread
+                    continue;
+                };
+
+                match decoded.instruction {
+                    Instruction::Return => {
+                        let [returns, _] = decoded.frame;
+                        let returns = Some(returns as usize);
+
+                        if signature.returns.is_some() && signature.returns != returns {
+                            return Err(VerifyError::InconsistentSignature { function: entry });
+                        }
+                        signature.returns = returns;
+                    }
+                    Instruction::TailCall => tail_calls.push((entry, decoded.target.unwrap())),
+                    Instruction::Jump => pending.push(decoded.target.unwrap()),
+                    Instruction::JumpIfFalse | Instruction::CompareJumpIfFalse => {
+                        pending.push(decoded.target.unwrap());
+                        pending.push(pc + decoded.size);
+                    }
+                    _ => pending.push(pc + decoded.size),
+                }
+            }
+        }
+
+        // keep going until nothing changes, since tail calls can be chained
+        let mut changed = true;
+        while changed {
+            changed = false;
+
+            for &(caller, callee) in &tail_calls {
+                let callee_returns = self.signatures[&callee].returns;
+                let caller_signature = self.signatures.get_mut(&caller).unwrap();
+
+                match (caller_signature.returns, callee_returns) {
+                    (None, Some(_)) => {
+                        caller_signature.returns = callee_returns;
+                        changed = true;
+                    }
+                    (Some(caller_returns), Some(callee_returns))
+                        if caller_returns != callee_returns =>
+                    {
+                        return Err(VerifyError::InconsistentSignature { function: caller });
+                    }
+                    _ => {}
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+struct StackChecker<'a> {
+    program: &'a Program<'a>,
+    interface: &'a Interface<'a>,
+    functions: &'a Functions,
+    instructions: &'a BTreeMap<usize, Decoded>,
+}
+
+impl StackChecker<'_> {
+    /// Follows every path through the function starting at `entry`, tracking how many values are
+    /// on the stack above its return address. `owners` records which function each instruction
+    /// belongs to, and the stack height there.
+    fn check(
+        &self,
+        entry: usize,
+        signature: &Signature,
+        owners: &mut [Option<(usize, usize)>],
+    ) -> Result<(), VerifyError> {
+        // how much of the stack below the function's own values it can read. Arguments can be
+        // written to, but the return address can't.
+        let readable_below = signature.arguments.map_or(0, |arguments| arguments + 1);
+        let writable_below = signature.arguments.unwrap_or(0);
+
+        let mut pending = vec![(entry, 0)];
+
+        while let Some((pc, height)) = pending.pop() {
+            let Some(decoded) = self.instructions.get(&pc) else {
+                // running off the end of the bytecode finishes the thread
+                continue;
+            };
+
+            match owners[pc] {
+                Some((owner, _)) if owner != entry => return Err(VerifyError::SharedCode { pc }),
+                Some((_, expected)) if expected != height => {
+                    return Err(VerifyError::StackMismatch {
+                        pc,
+                        expected,
+                        actual: height,
+                    })
+                }
+                Some(_) => continue,
+                None => owners[pc] = Some((entry, height)),
+            }
+
+            let pops = |count: usize| {
+                height
+                    .checked_sub(count)
+                    .ok_or(VerifyError::StackUnderflow { pc })
+            };
+
+            let arg = decoded.arg as usize;
+            let next = pc + decoded.size;
+
+            match decoded.instruction {
+                Instruction::Push8 | Instruction::Push32 | Instruction::GetProp => {
+                    pending.push((next, height + 1));
+                }
+                Instruction::Dup => {
+                    if arg >= height + readable_below {
+                        return Err(VerifyError::StackUnderflow { pc });
+                    }
+                    pending.push((next, height + 1));
+                }
+                Instruction::Drop => pending.push((next, pops(arg)?)),
+                Instruction::SetProp
+                | Instruction::Yield
+                | Instruction::PauseGroup
+                | Instruction::ResumeGroup => pending.push((next, pops(1)?)),
+                Instruction::SetGroupTimeScale => pending.push((next, pops(2)?)),
+                Instruction::Nop | Instruction::Wait | Instruction::AddPropImmediate => {
+                    pending.push((next, height));
+                }
+                Instruction::Move => {
+                    // the top value is popped, then written `arg` below the new top. Skip over the
+                    // return address when writing to an argument.
+                    let popped = pops(1)?;
+                    let valid = arg >= 1
+                        && (arg <= popped
+                            || (arg >= popped + 2 && arg <= popped + 1 + writable_below));
+                    if !valid {
+                        return Err(VerifyError::StackUnderflow { pc });
+                    }
+                    pending.push((next, popped));
+                }
+                Instruction::MathsOp => pending.push((next, pops(2)? + 1)),
+                Instruction::JumpIfFalse => {
+                    pops(1)?;
+                    pending.push((decoded.target.unwrap(), height));
+                    pending.push((next, height));
+                }
+                Instruction::CompareJumpIfFalse => {
+                    let height = pops(2)? + 1;
+                    pending.push((decoded.target.unwrap(), height));
+                    pending.push((next, height));
+                }
+                Instruction::Jump => pending.push((decoded.target.unwrap(), height)),
+                Instruction::Call => {
+                    let remaining = pops(arg)?;
+                    // if the function never returns, then neither does this call
+                    if let Some(returns) =
+                        self.functions.signatures[&decoded.target.unwrap()].returns
+                    {
+                        pending.push((next, remaining + returns));
+                    }
+                }
+                Instruction::Spawn => pending.push((next, pops(arg)?)),
+                // the arguments are above the id of the group to spawn into
+                Instruction::SpawnInGroup => pending.push((next, pops(arg + 1)?)),
+                Instruction::Trigger => {
+                    let index = self.program.trigger_index(decoded.arg) as usize;
+                    let arguments = self.interface.trigger_arguments[index];
+                    pending.push((next, pops(arguments)?));
+                }
+                // the arguments are above the id of the script to send to
+                Instruction::Send => pending.push((next, pops(arg + 1)?)),
+                Instruction::Return => {
+                    let [returns, shift] = decoded.frame;
+                    let (returns, shift) = (returns as usize, shift as usize);
+
+                    let valid = shift == height
+                        && returns <= shift
+                        && signature.arguments.is_none_or(|arguments| arguments == arg);
+                    if !valid {
+                        return Err(VerifyError::InvalidReturn { pc });
+                    }
+                }
+                Instruction::TailCall => {
+                    let [frame_arguments, shift] = decoded.frame;
+                    let (frame_arguments, shift) = (frame_arguments as usize, shift as usize);
+
+                    let valid = shift == height
+                        && arg <= shift
+                        && signature.arguments == Some(frame_arguments);
+                    if !valid {
+                        return Err(VerifyError::InvalidReturn { pc });
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    extern crate std;
+
+    use std::fs;
+
+    use alloc::{string::ToString, vec::Vec};
+    use compiler::{CompileSettings, Property, PropertyAccess, Type};
+    use insta::glob;
+
+    use super::*;
+
+    fn verify_compiled(compiled: &compiler::CompileResult) -> Result<(), VerifyError> {
+        let event_handlers = compiled
+            .event_handlers
+            .iter()
+            .map(|event_handler| Some(event_handler.bytecode_offset))
+            .collect::<Vec<_>>();
+        let event_arguments = compiled
+            .event_handlers
+            .iter()
+            .map(|event_handler| event_handler.arguments.len())
+            .collect::<Vec<_>>();
+        let event_returns = compiled
+            .event_handlers
+            .iter()
+            .map(|event_handler| event_handler.return_types.len())
+            .collect::<Vec<_>>();
+        let trigger_arguments = compiled
+            .triggers
+            .iter()
+            .map(|trigger| trigger.arguments.len())
+            .collect::<Vec<_>>();
+        // scripts built by the derive macro can only send messages to their own event handlers
+        let messages = compiled
+            .messages
+            .iter()
+            .map(|message| {
+                compiled
+                    .event_handlers
+                    .iter()
+                    .position(|event_handler| event_handler.name == message.name)
+                    .unwrap_or(usize::MAX)
+            })
+            .collect::<Vec<_>>();
+
+        verify(
+            &Program::new(&compiled.bytecode, &[], &event_handlers, &messages),
+            &Interface {
+                readable_properties: &[0],
+                writable_properties: &[0],
+                trigger_arguments: &trigger_arguments,
+                event_arguments: &event_arguments,
+                event_returns: &event_returns,
+            },
+        )
+    }
+
+    /// Anything the compiler produces must pass verification
+    #[test]
+    fn compiled_scripts_verify() {
+        let check = |path: &std::path::Path| {
+            let input = fs::read_to_string(path).unwrap();
+
+            for enable_optimisations in [false, true] {
+                let compile_settings = CompileSettings {
+                    properties: vec![Property {
+                        ty: Type::Int,
+                        index: 0,
+                        name: "int_prop".to_string(),
+                        access: PropertyAccess::ReadWrite,
+                    }],
+                    enable_optimisations,
+                    fix_precision: CompileSettings::DEFAULT_FIX_PRECISION,
+                };
+
+                // some of the compiler's tests are meant to fail
+                let Ok(compiled) = compiler::compile(path, &input, compile_settings) else {
+                    continue;
+                };
+
+                if let Err(e) = verify_compiled(&compiled) {
+                    panic!("{} failed to verify: {e}", path.display());
+                }
+            }
+        };
+
+        glob!("snapshot_tests", "stack/**/*.tapir", |path| check(path));
+        glob!(
+            "../../compiler/src/snapshot_tests",
+            "{compiler,peephole,stack_depth,warnings}/*.tapir",
+            |path| { check(path) }
+        );
+    }
+
+    fn op(instruction: Instruction, arg: u8) -> u16 {
+        ((instruction as u16) << 8) | arg as u16
+    }
+
+    fn verify_bytecode(bytecode: &[u16]) -> Result<(), VerifyError> {
+        verify(
+            &Program::new(bytecode, &[], &[], &[]),
+            &Interface {
+                readable_properties: &[0],
+                writable_properties: &[0],
+                trigger_arguments: &[1],
+                event_arguments: &[],
+                event_returns: &[],
+            },
+        )
+    }
+
+    const RETURN: [u16; 2] = [(Instruction::Return as u16) << 8, 0];
+
+    #[test]
+    fn accepts_valid_bytecode() {
+        let bytecode = [
+            op(Instruction::Push8, 5),
+            op(Instruction::Trigger, 0),
+            op(Instruction::GetProp, 0),
+            op(Instruction::Drop, 1),
+            RETURN[0],
+            RETURN[1],
+        ];
+
+        assert_eq!(verify_bytecode(&bytecode), Ok(()));
+    }
+
+    #[test]
+    fn rejects_unknown_instructions() {
+        assert_eq!(
+            verify_bytecode(&[op(Instruction::Wait, 0), 0xff00]),
+            Err(VerifyError::InvalidInstruction { pc: 1 })
+        );
+    }
+
+    #[test]
+    fn rejects_unknown_maths_ops() {
+        let bytecode = [
+            op(Instruction::Push8, 1),
+            op(Instruction::Push8, 2),
+            op(Instruction::MathsOp, 0xff),
+        ];
+
+        assert_eq!(
+            verify_bytecode(&bytecode),
+            Err(VerifyError::InvalidInstruction { pc: 2 })
+        );
+    }
+
+    #[test]
+    fn rejects_truncated_instructions() {
+        assert_eq!(
+            verify_bytecode(&[op(Instruction::Push32, 0), 0]),
+            Err(VerifyError::TruncatedInstruction { pc: 0 })
+        );
+    }
+
+    #[test]
+    fn rejects_jumps_into_the_middle_of_instructions() {
+        let bytecode = [
+            op(Instruction::Jump, 0),
+            3,
+            op(Instruction::Push32, 0),
+            0,
+            0,
+        ];
+
+        assert_eq!(
+            verify_bytecode(&bytecode),
+            Err(VerifyError::InvalidJumpTarget { pc: 0, target: 3 })
+        );
+    }
+
+    #[test]
+    fn rejects_jumps_out_of_the_bytecode() {
+        assert_eq!(
+            verify_bytecode(&[op(Instruction::Jump, 0), 100]),
+            Err(VerifyError::InvalidJumpTarget { pc: 0, target: 100 })
+        );
+    }
+
+    #[test]
+    fn rejects_unknown_properties() {
+        assert_eq!(
+            verify_bytecode(&[op(Instruction::GetProp, 3)]),
+            Err(VerifyError::InvalidProperty { pc: 0, index: 3 })
+        );
+    }
+
+    #[test]
+    fn rejects_property_access_the_script_isnt_allowed() {
+        // property 0 is read only and property 1 is write only
+        let verify_properties = |bytecode: &[u16]| {
+            verify(
+                &Program::new(bytecode, &[], &[], &[]),
+                &Interface {
+                    readable_properties: &[0],
+                    writable_properties: &[1],
+                    ..Interface::default()
+                },
+            )
+        };
+
+        assert_eq!(
+            verify_properties(&[op(Instruction::GetProp, 0), op(Instruction::SetProp, 1)]),
+            Ok(())
+        );
+        assert_eq!(
+            verify_properties(&[op(Instruction::Push8, 1), op(Instruction::SetProp, 0)]),
+            Err(VerifyError::InvalidPropertyAccess { pc: 1, index: 0 })
+        );
+        assert_eq!(
+            verify_properties(&[op(Instruction::AddPropImmediate, 0), 1]),
+            Err(VerifyError::InvalidPropertyAccess { pc: 0, index: 0 })
+        );
+        assert_eq!(
+            verify_properties(&[op(Instruction::GetProp, 1)]),
+            Err(VerifyError::InvalidPropertyAccess { pc: 0, index: 1 })
+        );
+    }
+
+    #[test]
+    fn rejects_unknown_triggers() {
+        assert_eq!(
+            verify_bytecode(&[op(Instruction::Push8, 0), op(Instruction::Trigger, 1)]),
+            Err(VerifyError::InvalidTrigger { pc: 1, index: 1 })
+        );
+    }
+
+    #[test]
+    fn only_verified_programs_can_be_run() {
+        let interface = Interface::default();
+
+        let valid = [op(Instruction::Push8, 0), op(Instruction::Dup, 0)];
+        assert!(
+            crate::VerifiedProgram::verify(Program::new(&valid, &[], &[], &[]), &interface).is_ok()
+        );
+
+        let invalid = [op(Instruction::Push8, 0), op(Instruction::Dup, 1)];
+        assert_eq!(
+            crate::VerifiedProgram::verify(Program::new(&invalid, &[], &[], &[]), &interface)
+                .map(|_| ()),
+            Err(VerifyError::StackUnderflow { pc: 1 })
+        );
+    }
+
+    #[test]
+    fn rejects_reading_below_the_stack() {
+        assert_eq!(
+            verify_bytecode(&[op(Instruction::Push8, 0), op(Instruction::Dup, 1)]),
+            Err(VerifyError::StackUnderflow { pc: 1 })
+        );
+        assert_eq!(
+            verify_bytecode(&[op(Instruction::Drop, 1)]),
+            Err(VerifyError::StackUnderflow { pc: 0 })
+        );
+        // triggers take their arguments from the stack
+        assert_eq!(
+            verify_bytecode(&[op(Instruction::Trigger, 0)]),
+            Err(VerifyError::StackUnderflow { pc: 0 })
+        );
+    }
+
+    #[test]
+    fn moves_can_write_to_arguments_but_not_the_return_address() {
+        let with_move = |distance| {
+            [
+                op(Instruction::Push8, 7),
+                op(Instruction::Call, 1),
+                5,
+                RETURN[0],
+                RETURN[1],
+                // fn f(a) { a = 1; }
+                op(Instruction::Push8, 1),
+                op(Instruction::Move, distance),
+                op(Instruction::Return, 1),
+                0,
+            ]
+        };
+
+        assert_eq!(verify_bytecode(&with_move(2)), Ok(()));
+        assert_eq!(
+            verify_bytecode(&with_move(1)),
+            Err(VerifyError::StackUnderflow { pc: 6 })
+        );
+    }
+
+    #[test]
+    fn rejects_inconsistent_stack_heights() {
+        // one side of the branch pushes an extra value before they join
+        let bytecode = [
+            op(Instruction::Push8, 1),
+            op(Instruction::JumpIfFalse, 0),
+            5,
+            op(Instruction::Push8, 2),
+            op(Instruction::Nop, 0),
+            op(Instruction::Wait, 0),
+        ];
+
+        assert!(matches!(
+            verify_bytecode(&bytecode),
+            Err(VerifyError::StackMismatch { pc: 5, .. })
+        ));
+    }
+
+    #[test]
+    fn rejects_returns_which_dont_match_the_frame() {
+        assert_eq!(
+            verify_bytecode(&[
+                op(Instruction::Push8, 1),
+                (Instruction::Return as u16) << 8,
+                0
+            ]),
+            Err(VerifyError::InvalidReturn { pc: 1 })
+        );
+    }
+
+    #[test]
+    fn rejects_functions_called_with_different_arguments() {
+        let bytecode = [
+            op(Instruction::Push8, 1),
+            op(Instruction::Call, 1),
+            9,
+            op(Instruction::Push8, 1),
+            op(Instruction::Push8, 2),
+            op(Instruction::Call, 2),
+            9,
+            RETURN[0],
+            RETURN[1],
+            op(Instruction::Return, 1),
+            0x0000,
+        ];
+
+        assert_eq!(
+            verify_bytecode(&bytecode),
+            Err(VerifyError::InconsistentSignature { function: 9 })
+        );
+    }
+
+    #[test]
+    fn event_handlers_must_start_at_an_instruction() {
+        let bytecode = [op(Instruction::Push32, 0), 0, 0];
+        let program = Program::new(&bytecode, &[], &[Some(1)], &[]);
+
+        assert_eq!(
+            verify(
+                &program,
+                &Interface {
+                    event_arguments: &[0],
+                    ..Interface::default()
+                }
+            ),
+            Err(VerifyError::InvalidEventHandler { index: 0 })
+        );
+    }
+
+    #[test]
+    fn messages_must_match_their_event() {
+        let send = |args, index| {
+            [
+                op(Instruction::Push8, 1),
+                op(Instruction::Push8, 2),
+                op(Instruction::Push8, 3),
+                op(Instruction::Send, args),
+                index,
+            ]
+        };
+        let verify_send = |bytecode: &[u16]| {
+            verify(
+                &Program::new(bytecode, &[], &[], &[0]),
+                &Interface {
+                    event_arguments: &[2],
+                    ..Interface::default()
+                },
+            )
+        };
+
+        assert_eq!(verify_send(&send(2, 0)), Ok(()));
+        assert_eq!(
+            verify_send(&send(1, 0)),
+            Err(VerifyError::InvalidMessage { pc: 3, index: 0 })
+        );
+        assert_eq!(
+            verify_send(&send(2, 1)),
+            Err(VerifyError::InvalidMessage { pc: 3, index: 1 })
+        );
+        // the id of the script to send to is missing
+        assert_eq!(
+            verify_send(&send(2, 0)[1..]),
+            Err(VerifyError::StackUnderflow { pc: 2 })
+        );
+    }
+}
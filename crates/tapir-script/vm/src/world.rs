@@ -0,0 +1,124 @@
+//! Running many scripts together, so that they can send each other messages with
+//! `send target, name(arguments);`.
+
+use alloc::{collections::BTreeMap, vec, vec::Vec};
+
+use crate::{Script, TapirScript};
+
+/// A message sent by a script, waiting to be delivered
+#[derive(Debug)]
+pub(crate) struct Envelope {
+    pub target: i32,
+    /// The index of the event it is delivered to, in the same order as the events in the
+    /// `TapirScript` implementation
+    pub event_index: usize,
+    pub arguments: Vec<i32>,
+}
+
+/// A collection of scripts which can send messages to each other, each with an id which other
+/// scripts use as the target of `send`. The ids are whatever makes sense for the game, such as
+/// the id of the entity which the script controls.
+///
+/// Messages are delivered at the start of the next [`run`](Self::run), to the event handler with
+/// the same name in the target script, exactly as if it had been called from rust. Everything
+/// happens in a deterministic order. Messages are delivered in the order of the id of the script
+/// which sent them, and then in the order they were sent, before any script runs. Then each script
+/// runs in order of its id.
+///
+/// Messages sent to an id which isn't in the world, or to a script which doesn't handle the
+/// event, are dropped. Anything returned by an event handler which receives a message is
+/// discarded.
+pub struct ScriptWorld<T: TapirScript> {
+    scripts: BTreeMap<i32, Script<T>>,
+    /// Reused between runs to collect every script's messages
+    mailbox: Vec<Envelope>,
+}
+
+impl<T: TapirScript> Default for ScriptWorld<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T: TapirScript> ScriptWorld<T> {
+    pub fn new() -> Self {
+        Self {
+            scripts: BTreeMap::new(),
+            mailbox: vec![],
+        }
+    }
+
+    /// Adds `script` with the given `id`, returning the script which previously had that id
+    pub fn insert(&mut self, id: i32, script: Script<T>) -> Option<Script<T>> {
+        self.scripts.insert(id, script)
+    }
+
+    /// Removes the script with the given `id`. Any messages it sent which haven't been delivered
+    /// yet go with it.
+    pub fn remove(&mut self, id: i32) -> Option<Script<T>> {
+        self.scripts.remove(&id)
+    }
+
+    pub fn get(&self, id: i32) -> Option<&Script<T>> {
+        self.scripts.get(&id)
+    }
+
+    pub fn get_mut(&mut self, id: i32) -> Option<&mut Script<T>> {
+        self.scripts.get_mut(&id)
+    }
+
+    /// Every script in the world, in order of their ids
+    pub fn iter(&self) -> impl Iterator<Item = (i32, &Script<T>)> {
+        self.scripts.iter().map(|(&id, script)| (id, script))
+    }
+
+    /// Every script in the world, in order of their ids
+    pub fn iter_mut(&mut self) -> impl Iterator<Item = (i32, &mut Script<T>)> {
+        self.scripts.iter_mut().map(|(&id, script)| (id, script))
+    }
+
+    pub fn len(&self) -> usize {
+        self.scripts.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.scripts.is_empty()
+    }
+
+    /// Delivers the messages sent since the last run and then runs every script, returning the
+    /// events they triggered along with the id of the script which triggered them
+    pub fn run(&mut self) -> Vec<(i32, T::EventType)> {
+        let mut events = vec![];
+        self.run_with_callback(|id, event| events.push((id, event)));
+        events
+    }
+
+    /// The same as [`run`](Self::run), but passes each event to `on_event` as it is triggered
+    /// rather than collecting them into a `Vec`
+    pub fn run_with_callback(&mut self, mut on_event: impl FnMut(i32, T::EventType)) {
+        for script in self.scripts.values_mut() {
+            self.mailbox.append(&mut script.outbox);
+        }
+
+        for envelope in self.mailbox.drain(..) {
+            let Some(script) = self.scripts.get_mut(&envelope.target) else {
+                continue;
+            };
+
+            // SAFETY: the event index came from the program's messages, which are either built
+            // by the derive macro or checked against `T` when the program was loaded, and every
+            // script in the world runs with a `T`
+            unsafe { script.__private_trigger_event(envelope.event_index, &envelope.arguments) };
+        }
+
+        for (&id, script) in &mut self.scripts {
+            script.run_with_callback(|event| on_event(id, event));
+        }
+    }
+
+    pub fn will_calling_run_do_anything(&self) -> bool {
+        self.scripts
+            .values()
+            .any(|script| script.will_calling_run_do_anything() || !script.outbox.is_empty())
+    }
+}